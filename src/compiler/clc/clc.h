@@ -56,6 +56,8 @@ struct clc_optional_features {
    bool images;
    bool images_read_write;
    bool images_write_3d;
+   bool images_mipmap;
+   bool images_write_srgb;
    bool integer_dot_product;
    bool intel_subgroups;
    /* OpenCL core subgroups */
@@ -23,7 +23,9 @@
 // ARISING FROM, OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR
 // OTHER DEALINGS IN THE SOFTWARE.
 
+#include <cctype>
 #include <cstdlib>
+#include <cstring>
 #include <filesystem>
 #include <sstream>
 #include <mutex>
@@ -115,7 +117,8 @@ public:
    SPIRVKernelArg(uint32_t id, uint32_t typeId) : id(id), typeId(typeId),
                                                   addrQualifier(CLC_KERNEL_ARG_ADDRESS_PRIVATE),
                                                   accessQualifier(0),
-                                                  typeQualifier(0) { }
+                                                  typeQualifier(0),
+                                                  zeroExtended(false) { }
    ~SPIRVKernelArg() { }
 
    uint32_t id;
@@ -125,6 +128,185 @@ public:
    enum clc_kernel_arg_address_qualifier addrQualifier;
    unsigned accessQualifier;
    unsigned typeQualifier;
+   bool zeroExtended;
+};
+
+/* Demangles the parameter types of an Itanium mangled kernel name into
+ * OpenCL C type names. Only covers what's needed for kernel arguments.
+ */
+class SPIRVParamDemangler {
+public:
+   SPIRVParamDemangler(const std::string &name) : str(name), pos(0) { }
+
+   bool demangle(std::vector<std::string> &params)
+   {
+      if (str.compare(0, 2, "_Z") != 0)
+         return false;
+
+      /* skip the function name */
+      size_t len;
+      pos = 2;
+      if (!parseNumber(len) || pos + len > str.size())
+         return false;
+      pos += len;
+
+      while (pos < str.size()) {
+         std::string type;
+         if (!parseType(type))
+            return false;
+         params.push_back(type);
+      }
+
+      /* functions without parameters are mangled with a single void */
+      if (params.size() == 1 && params[0] == "void")
+         params.clear();
+
+      return true;
+   }
+
+private:
+   bool parseNumber(size_t &num)
+   {
+      size_t start = pos;
+      num = 0;
+      while (pos < str.size() && isdigit(str[pos]))
+         num = num * 10 + (str[pos++] - '0');
+      return pos != start;
+   }
+
+   static std::string sourceTypeName(const std::string &name)
+   {
+      if (name.find("ocl_image") == 0) {
+         /* e.g. ocl_image2d_ro, the access qualifier is reported separately */
+         std::string typeName = name.substr(sizeof("ocl_") - 1);
+         size_t access = typeName.rfind('_');
+         if (access != std::string::npos && typeName.size() - access == 3)
+            typeName.erase(access);
+         return typeName + "_t";
+      }
+      if (name == "ocl_sampler")
+         return "sampler_t";
+      if (name == "ocl_event")
+         return "event_t";
+      if (name == "ocl_queue")
+         return "queue_t";
+      if (name == "ocl_clkevent")
+         return "clk_event_t";
+      return name;
+   }
+
+   bool parseSubstitution(std::string &type)
+   {
+      /* S_ is the first substitution, S<seq-id>_ the ones following it */
+      size_t idx = 0;
+      if (pos < str.size() && str[pos] != '_') {
+         size_t seq = 0;
+         while (pos < str.size() && (isdigit(str[pos]) || isupper(str[pos]))) {
+            seq = seq * 36 + (isdigit(str[pos]) ? str[pos] - '0' : str[pos] - 'A' + 10);
+            pos++;
+         }
+         idx = seq + 1;
+      }
+
+      if (pos >= str.size() || str[pos] != '_' || idx >= substitutions.size())
+         return false;
+
+      pos++;
+      type = substitutions[idx];
+      return true;
+   }
+
+   bool parseType(std::string &type)
+   {
+      if (pos >= str.size())
+         return false;
+
+      const char *builtin = NULL;
+      switch (str[pos]) {
+      case 'v': builtin = "void"; break;
+      case 'b': builtin = "bool"; break;
+      case 'a':
+      case 'c': builtin = "char"; break;
+      case 'h': builtin = "uchar"; break;
+      case 's': builtin = "short"; break;
+      case 't': builtin = "ushort"; break;
+      case 'i': builtin = "int"; break;
+      case 'j': builtin = "uint"; break;
+      case 'l':
+      case 'x': builtin = "long"; break;
+      case 'm':
+      case 'y': builtin = "ulong"; break;
+      case 'f': builtin = "float"; break;
+      case 'd': builtin = "double"; break;
+      }
+
+      /* builtin types aren't substitution candidates */
+      if (builtin) {
+         pos++;
+         type = builtin;
+         return true;
+      }
+      if (str.compare(pos, 2, "Dh") == 0) {
+         pos += 2;
+         type = "half";
+         return true;
+      }
+      if (str[pos] == 'S') {
+         pos++;
+         return parseSubstitution(type);
+      }
+
+      size_t num;
+      switch (str[pos]) {
+      case 'P':
+         pos++;
+         if (!parseType(type))
+            return false;
+         type += "*";
+         break;
+      case 'K':
+      case 'V':
+      case 'r':
+      case 'U':
+         /* address spaces and other qualifiers are reported separately, all
+          * qualifiers of a type form a single substitution candidate.
+          */
+         while (pos < str.size() && strchr("KVrU", str[pos])) {
+            if (str[pos++] != 'U')
+               continue;
+            if (!parseNumber(num) || pos + num > str.size())
+               return false;
+            pos += num;
+         }
+         if (!parseType(type))
+            return false;
+         break;
+      case 'D':
+         if (str.compare(pos, 2, "Dv") != 0)
+            return false;
+         pos += 2;
+         if (!parseNumber(num) || pos >= str.size() || str[pos] != '_')
+            return false;
+         pos++;
+         if (!parseType(type))
+            return false;
+         type += std::to_string(num);
+         break;
+      default:
+         if (!parseNumber(num) || pos + num > str.size())
+            return false;
+         type = sourceTypeName(str.substr(pos, num));
+         pos += num;
+         break;
+      }
+
+      substitutions.push_back(type);
+      return true;
+   }
+
+   const std::string &str;
+   size_t pos;
+   std::vector<std::string> substitutions;
 };
 
 class SPIRVKernelInfo {
@@ -234,6 +416,126 @@ public:
 	    }
          }
       }
+
+      names.emplace(id, name);
+   }
+
+   /* Records the OpenCL C name of types kernel arguments might have, so type
+    * names are also available for SPIR-V without the kernel_arg_type strings.
+    */
+   void parseTypeName(const spv_parsed_instruction_t *ins)
+   {
+      uint32_t typeId = ins->words[ins->operands[0].offset];
+      std::string typeName;
+
+      switch (ins->opcode) {
+      case SpvOpTypeBool:
+         typeName = "bool";
+         break;
+      /* The OpenCL environment requires a signedness of 0, so integers are
+       * named as signed. Scalar arguments get corrected through their
+       * FuncParamAttr decorations and mangled kernel names carry the actual
+       * types.
+       */
+      case SpvOpTypeInt:
+         switch (ins->words[ins->operands[1].offset]) {
+         case 8:
+            typeName = "char";
+            break;
+         case 16:
+            typeName = "short";
+            break;
+         case 32:
+            typeName = "int";
+            break;
+         case 64:
+            typeName = "long";
+            break;
+         default:
+            return;
+         }
+         break;
+      case SpvOpTypeFloat:
+         switch (ins->words[ins->operands[1].offset]) {
+         case 16:
+            typeName = "half";
+            break;
+         case 32:
+            typeName = "float";
+            break;
+         case 64:
+            typeName = "double";
+            break;
+         default:
+            return;
+         }
+         break;
+      case SpvOpTypeVector: {
+         auto iter = typeNames.find(ins->words[ins->operands[1].offset]);
+         if (iter == typeNames.end())
+            return;
+         typeName = iter->second +
+            std::to_string(ins->words[ins->operands[2].offset]);
+         break;
+      }
+      case SpvOpTypePointer: {
+         auto iter = typeNames.find(ins->words[ins->operands[2].offset]);
+         if (iter == typeNames.end())
+            return;
+         typeName = iter->second + "*";
+         break;
+      }
+      case SpvOpTypeStruct: {
+         auto iter = names.find(typeId);
+         if (iter == names.end())
+            return;
+         /* LLVM prefixes struct names with "struct." */
+         typeName = iter->second;
+         if (typeName.find("struct.") == 0)
+            typeName.replace(0, sizeof("struct.") - 1, "struct ");
+         break;
+      }
+      case SpvOpTypeImage: {
+         uint32_t dim = ins->words[ins->operands[2].offset];
+         bool depth = ins->words[ins->operands[3].offset] == 1;
+         bool arrayed = ins->words[ins->operands[4].offset];
+         switch (dim) {
+         case SpvDim1D:
+            typeName = "image1d";
+            break;
+         case SpvDim2D:
+            typeName = "image2d";
+            break;
+         case SpvDim3D:
+            typeName = "image3d";
+            break;
+         case SpvDimBuffer:
+            typeName = "image1d_buffer";
+            break;
+         default:
+            return;
+         }
+         if (arrayed)
+            typeName += "_array";
+         if (depth)
+            typeName += "_depth";
+         typeName += "_t";
+         break;
+      }
+      case SpvOpTypeSampler:
+         typeName = "sampler_t";
+         break;
+      case SpvOpTypeEvent:
+         typeName = "event_t";
+         break;
+      case SpvOpTypeQueue:
+         typeName = "queue_t";
+         break;
+      default:
+         return;
+      }
+
+      typeNames[typeId] = typeName;
    }
 
    void parseTypePointer(const spv_parsed_instruction_t *ins)
@@ -389,6 +691,9 @@ public:
                   case SpvFunctionParameterAttributeNoWrite:
                      arg.typeQualifier |= CLC_KERNEL_ARG_TYPE_CONST;
                      break;
+                  case SpvFunctionParameterAttributeZext:
+                     arg.zeroExtended = true;
+                     break;
                   }
                   break;
                }
@@ -621,9 +926,18 @@ public:
          break;
       case SpvOpTypePointer:
          parser->parseTypePointer(ins);
+         parser->parseTypeName(ins);
          break;
       case SpvOpTypeImage:
          parser->parseOpTypeImage(ins);
+         parser->parseTypeName(ins);
+         break;
+      case SpvOpTypeVector:
+      case SpvOpTypeStruct:
+      case SpvOpTypeSampler:
+      case SpvOpTypeEvent:
+      case SpvOpTypeQueue:
+         parser->parseTypeName(ins);
          break;
       case SpvOpString:
          parser->parseOpString(ins);
@@ -641,6 +955,7 @@ public:
       case SpvOpTypeInt:
       case SpvOpTypeFloat:
          parser->parseLiteralType(ins);
+         parser->parseTypeName(ins);
          break;
       case SpvOpSpecConstant:
       case SpvOpSpecConstantFalse:
@@ -681,6 +996,8 @@ public:
    std::vector<std::pair<uint32_t, clc_parsed_spec_constant>> specConstants;
    std::map<uint32_t, enum clc_spec_constant_type> literalTypes;
    std::map<uint32_t, std::vector<uint32_t>> decorationGroups;
+   std::map<uint32_t, std::string> names;
+   std::map<uint32_t, std::string> typeNames;
    SPIRVKernelInfo *curKernel;
    spv_context ctx;
 };
@@ -718,6 +1035,10 @@ clc_spirv_get_kernels_info(const struct clc_binary *spvbin,
             continue;
 
          struct clc_kernel_arg *args;
+         std::vector<std::string> mangledTypes;
+         bool mangled =
+            SPIRVParamDemangler(parser.kernels[i].name).demangle(mangledTypes) &&
+            mangledTypes.size() == parser.kernels[i].args.size();
 
          args = reinterpret_cast<struct clc_kernel_arg *>(calloc(kernels[i].num_args,
                                                                  sizeof(*kernels->args)));
@@ -726,7 +1047,21 @@ clc_spirv_get_kernels_info(const struct clc_binary *spvbin,
          for (unsigned j = 0; j < kernels[i].num_args; j++) {
             if (!parser.kernels[i].args[j].name.empty())
                args[j].name = strdup(parser.kernels[i].args[j].name.c_str());
-            args[j].type_name = strdup(parser.kernels[i].args[j].typeName.c_str());
+            std::string typeName = parser.kernels[i].args[j].typeName;
+            if (typeName.empty() && mangled) {
+               typeName = mangledTypes[j];
+            } else if (typeName.empty()) {
+               /* Fall back to the SPIR-V type if the module doesn't carry the
+                * OpenCL C type names.
+                */
+               auto iter = parser.typeNames.find(parser.kernels[i].args[j].typeId);
+               if (iter != parser.typeNames.end()) {
+                  typeName = iter->second;
+                  if (parser.kernels[i].args[j].zeroExtended)
+                     typeName = "u" + typeName;
+               }
+            }
+            args[j].type_name = strdup(typeName.c_str());
             args[j].address_qualifier = parser.kernels[i].args[j].addrQualifier;
             args[j].type_qualifier = parser.kernels[i].args[j].typeQualifier;
             args[j].access_qualifier = parser.kernels[i].args[j].accessQualifier;
@@ -943,6 +1278,12 @@ clc_compile_to_llvm_module(LLVMContext &llvm_ctx,
       c->getTargetOpts().OpenCLExtensionsAsWritten.push_back("+cl_khr_3d_image_writes");
       c->getTargetOpts().OpenCLExtensionsAsWritten.push_back("+__opencl_c_3d_image_writes");
    }
+   if (args->features.images_mipmap) {
+      c->getTargetOpts().OpenCLExtensionsAsWritten.push_back("+cl_khr_mipmap_image");
+   }
+   if (args->features.images_write_srgb) {
+      c->getTargetOpts().OpenCLExtensionsAsWritten.push_back("+cl_khr_srgb_image_writes");
+   }
    if (args->features.intel_subgroups) {
       c->getTargetOpts().OpenCLExtensionsAsWritten.push_back("+cl_intel_subgroups");
       needs_opencl_c_h = true;
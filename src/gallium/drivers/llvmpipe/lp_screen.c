@@ -374,6 +374,7 @@ llvmpipe_get_param(struct pipe_screen *screen, enum pipe_cap param)
    case PIPE_CAP_PACKED_UNIFORMS:
       return 1;
    case PIPE_CAP_SYSTEM_SVM:
+   case PIPE_CAP_SYSTEM_SVM_ATOMICS:
       return 1;
    case PIPE_CAP_ATOMIC_FLOAT_MINMAX:
       return LLVM_VERSION_MAJOR >= 15;
@@ -1,11 +1,19 @@
+pub(super) mod capture;
+mod command_buffer;
 mod context;
 mod device;
+pub(super) mod error;
 mod event;
 pub mod icd;
 mod kernel;
+mod leaks;
 mod memory;
+pub(super) mod panic;
 mod platform;
 mod program;
 mod queue;
+mod semaphore;
+pub(super) mod strict;
+pub(super) mod trace;
 pub(super) mod types;
 pub(super) mod util;
@@ -0,0 +1,269 @@
+use crate::api::icd::*;
+use crate::api::util::*;
+use crate::core::kernel::*;
+
+use mesa_rust_util::capture::*;
+use mesa_rust_util::log_error;
+use rusticl_opencl_gen::*;
+
+use std::env;
+use std::ffi::c_char;
+use std::ffi::CStr;
+use std::fs::File;
+use std::io::Write;
+use std::mem::size_of;
+use std::slice;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+struct Capture {
+    out: Mutex<File>,
+    reads: bool,
+}
+
+static CAPTURE: OnceLock<Option<Capture>> = OnceLock::new();
+
+fn capture() -> Option<&'static Capture> {
+    CAPTURE
+        .get_or_init(|| {
+            let path = env::var("RUSTICL_CAPTURE_FILE").ok()?;
+            let out = File::create(&path)
+                .and_then(|mut file| write_header(&mut file).map(|_| file))
+                .map_err(|err| log_error!("Failed to create RUSTICL_CAPTURE_FILE {path}: {err}"))
+                .ok()?;
+
+            let mut reads = false;
+            if let Ok(snapshots) = env::var("RUSTICL_CAPTURE_SNAPSHOTS") {
+                for snapshot in snapshots.split(',') {
+                    match snapshot {
+                        "reads" => reads = true,
+                        "" => (),
+                        _ => log_error!("Unknown RUSTICL_CAPTURE_SNAPSHOTS flag: {snapshot}"),
+                    }
+                }
+            }
+
+            Some(Capture {
+                out: Mutex::new(out),
+                reads: reads,
+            })
+        })
+        .as_ref()
+}
+
+pub fn is_enabled() -> bool {
+    capture().is_some()
+}
+
+/// Converts API arguments into the scalar representation stored inside captures.
+pub trait CaptureArg {
+    fn capture_value(&self) -> u64;
+}
+
+impl<T> CaptureArg for *const T {
+    fn capture_value(&self) -> u64 {
+        *self as usize as u64
+    }
+}
+
+impl<T> CaptureArg for *mut T {
+    fn capture_value(&self) -> u64 {
+        *self as usize as u64
+    }
+}
+
+/// Callbacks can't be replayed, so we only record whether one was set.
+impl<T> CaptureArg for Option<T> {
+    fn capture_value(&self) -> u64 {
+        self.is_some().into()
+    }
+}
+
+macro_rules! capture_arg_int_impl {
+    ($($ty:ty),+) => {
+        $(
+            impl CaptureArg for $ty {
+                fn capture_value(&self) -> u64 {
+                    *self as u64
+                }
+            }
+        )+
+    };
+}
+
+capture_arg_int_impl!(i32, i64, isize, u32, u64, usize);
+
+macro_rules! capture_arg_newtype_impl {
+    ($($ty:ty),+) => {
+        $(
+            impl CaptureArg for $ty {
+                fn capture_value(&self) -> u64 {
+                    (**self).into()
+                }
+            }
+        )+
+    };
+}
+
+capture_arg_newtype_impl!(
+    cl_event_info,
+    cl_gl_texture_info,
+    cl_image_info,
+    cl_kernel_arg_info,
+    cl_kernel_work_group_info,
+    cl_mem_info,
+    cl_pipe_info,
+    cl_profiling_info
+);
+
+fn blob(arg: usize, ptr: u64, len: u64) -> Option<CaptureBlob> {
+    if ptr == 0 || len == 0 {
+        return None;
+    }
+
+    // SAFETY: the API requires the application to pass in valid memory of that size.
+    let data = unsafe { slice::from_raw_parts(ptr as usize as *const u8, len as usize) };
+    Some(CaptureBlob {
+        arg: arg as u32,
+        data: data.to_vec(),
+    })
+}
+
+fn string_blob(arg: usize, ptr: u64) -> Option<CaptureBlob> {
+    if ptr == 0 {
+        return None;
+    }
+
+    // SAFETY: the API requires the application to pass in a valid C string.
+    let len = unsafe { CStr::from_ptr(ptr as usize as *const c_char) }
+        .to_bytes()
+        .len();
+    blob(arg, ptr, len as u64)
+}
+
+/// Whether the value passed to `clSetKernelArg` is a memory object or sampler handle.
+fn kernel_arg_is_object(kernel: u64, arg_index: u64) -> bool {
+    let Ok(k) = Kernel::ref_from_raw(kernel as usize as cl_kernel) else {
+        return false;
+    };
+
+    k.kernel_info
+        .args
+        .get(arg_index as usize)
+        .is_some_and(|arg| {
+            matches!(
+                arg.kind,
+                KernelArgType::MemGlobal
+                    | KernelArgType::MemConstant
+                    | KernelArgType::Image
+                    | KernelArgType::RWImage
+                    | KernelArgType::Texture
+                    | KernelArgType::Sampler
+            )
+        })
+}
+
+/// Collects the memory referenced by the arguments of calls the replayer needs to know about.
+fn blobs(capture: &Capture, name: &str, args: &[u64], err: cl_int) -> Vec<CaptureBlob> {
+    const HANDLE_SIZE: u64 = size_of::<usize>() as u64;
+    let mut res = Vec::new();
+
+    // failed calls don't get replayed and we can't trust any of the pointers either
+    if err != CL_SUCCESS as cl_int {
+        return res;
+    }
+
+    match name {
+        "clGetDeviceIDs" if args[4] != 0 => {
+            // SAFETY: the call succeeded, so the pointer is valid
+            let num_devices = unsafe { *(args[4] as usize as *const cl_uint) };
+            let cnt = num_devices.min(args[2] as cl_uint) as u64;
+            res.extend(blob(3, args[3], cnt * HANDLE_SIZE));
+        }
+        "clCreateContext" => res.extend(blob(2, args[2], args[1] * HANDLE_SIZE)),
+        "clCreateCommandQueueWithProperties" if args[2] != 0 => {
+            // SAFETY: the properties list is terminated by 0
+            let props = unsafe {
+                let props = args[2] as usize as *const cl_queue_properties;
+                let mut len = 0;
+                while *props.add(len) != 0 {
+                    len += 2;
+                }
+                len + 1
+            };
+            res.extend(blob(2, args[2], props as u64 * HANDLE_SIZE));
+        }
+        "clCreateBuffer" if bit_check(args[1], CL_MEM_COPY_HOST_PTR | CL_MEM_USE_HOST_PTR) => {
+            res.extend(blob(3, args[3], args[2]))
+        }
+        "clCreateProgramWithSource" if args[2] != 0 => {
+            // all sources get concatenated into one blob
+            let strings = args[2] as usize as *const *const c_char;
+            let lengths = args[3] as usize as *const usize;
+            let mut data = Vec::new();
+            for i in 0..args[1] as usize {
+                // SAFETY: we have `count` valid strings
+                let (string, len) = unsafe {
+                    let string = *strings.add(i);
+                    let len = if lengths.is_null() || *lengths.add(i) == 0 {
+                        CStr::from_ptr(string).to_bytes().len()
+                    } else {
+                        *lengths.add(i)
+                    };
+                    (string, len)
+                };
+                if let Some(blob) = blob(2, string as usize as u64, len as u64) {
+                    data.extend(blob.data);
+                }
+            }
+            res.push(CaptureBlob { arg: 2, data: data });
+        }
+        "clCreateProgramWithIL" => res.extend(blob(1, args[1], args[2])),
+        "clBuildProgram" => res.extend(string_blob(3, args[3])),
+        "clCreateKernel" => res.extend(string_blob(1, args[1])),
+        "clSetKernelArg" => {
+            res.extend(blob(3, args[3], args[2]));
+            if args[3] != 0 && kernel_arg_is_object(args[0], args[1]) {
+                res.push(CaptureBlob {
+                    arg: KERNEL_ARG_OBJECT_BLOB,
+                    data: Vec::new(),
+                });
+            }
+        }
+        "clEnqueueNDRangeKernel" => {
+            let size = args[2] * size_of::<usize>() as u64;
+            res.extend(blob(3, args[3], size));
+            res.extend(blob(4, args[4], size));
+            res.extend(blob(5, args[5], size));
+        }
+        "clEnqueueWriteBuffer" => res.extend(blob(5, args[5], args[4])),
+        "clEnqueueReadBuffer" if capture.reads && args[2] == CL_TRUE.into() => {
+            res.extend(blob(5, args[5], args[4]))
+        }
+        _ => {}
+    }
+
+    res
+}
+
+/// Records a single API call. Called by the stubs generated through `cl_entrypoint` and
+/// `cl_info_entrypoint` after the call returned.
+pub fn capture_call(name: &str, args: &[u64], err: cl_int, ret: u64) {
+    let Some(capture) = capture() else {
+        return;
+    };
+
+    let record = CaptureRecord {
+        name: name.to_string(),
+        args: args.to_vec(),
+        ret: ret,
+        err: err,
+        blobs: blobs(capture, name, args, err),
+    };
+
+    // serialize first, so we hold the lock as short as possible and records don't interleave.
+    let mut data = Vec::new();
+    if record.write_to(&mut data).is_ok() {
+        let _ = capture.out.lock().unwrap().write_all(&data);
+    }
+}
@@ -0,0 +1,458 @@
+use crate::api::icd::*;
+use crate::api::kernel::ndrange_kernel_cmd;
+use crate::api::memory::*;
+use crate::api::types::*;
+use crate::api::util::*;
+use crate::core::command_buffer::*;
+use crate::core::event::*;
+use crate::core::queue::*;
+
+use mesa_rust_util::properties::PropertyReader;
+use mesa_rust_util::ptr::*;
+use rusticl_opencl_gen::*;
+use rusticl_proc_macros::cl_entrypoint;
+use rusticl_proc_macros::cl_info_entrypoint;
+
+use std::ffi::c_void;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+
+#[cl_info_entrypoint(clGetCommandBufferInfoKHR)]
+impl CLInfo<cl_command_buffer_info_khr> for cl_command_buffer_khr {
+    fn query(&self, q: cl_command_buffer_info_khr, _: &[u8]) -> CLResult<Vec<MaybeUninit<u8>>> {
+        let cb = CommandBuffer::ref_from_raw(*self)?;
+        Ok(match q {
+            CL_COMMAND_BUFFER_NUM_QUEUES_KHR => cl_prop::<cl_uint>(1),
+            CL_COMMAND_BUFFER_PROPERTIES_ARRAY_KHR => {
+                cl_prop::<&Vec<cl_command_buffer_properties_khr>>(&cb.props)
+            }
+            CL_COMMAND_BUFFER_QUEUES_KHR => {
+                // Note we use as_ptr here which doesn't increase the reference count.
+                let ptr = Arc::as_ptr(&cb.queue);
+                cl_prop::<Vec<cl_command_queue>>(vec![cl_command_queue::from_ptr(ptr)])
+            }
+            CL_COMMAND_BUFFER_REFERENCE_COUNT_KHR => {
+                cl_prop::<cl_uint>(CommandBuffer::refcnt(*self)?)
+            }
+            CL_COMMAND_BUFFER_STATE_KHR => cl_prop::<cl_command_buffer_state_khr>(cb.state()),
+            // CL_INVALID_VALUE if param_name is not one of the supported values
+            _ => return Err(CL_INVALID_VALUE.into()),
+        })
+    }
+}
+
+#[cl_entrypoint(clCreateCommandBufferKHR)]
+fn create_command_buffer(
+    num_queues: cl_uint,
+    queues: *const cl_command_queue,
+    properties: *const cl_command_buffer_properties_khr,
+) -> CLResult<cl_command_buffer_khr> {
+    // CL_INVALID_VALUE if queues is NULL, or if num_queues is 0, or if num_queues is > 1 and the
+    // cl_khr_command_buffer_multi_device extension is not supported.
+    if queues.is_null() || num_queues != 1 {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    // CL_INVALID_COMMAND_QUEUE if any command-queue in queues is not a valid command-queue.
+    // SAFETY: we checked for NULL and num_queues is 1.
+    let q = Queue::arc_from_raw(unsafe { *queues })?;
+
+    let mut flags = None;
+    // SAFETY: the list is required to be terminated
+    let mut reader = unsafe { PropertyReader::new(properties) };
+    while let Some(key) = reader.key() {
+        // CL_INVALID_VALUE if values specified in properties are not valid, or if the same
+        // property name is specified more than once.
+        match u32::try_from(key) {
+            Ok(CL_COMMAND_BUFFER_FLAGS_KHR) if flags.is_none() => {
+                flags = Some(reader.value());
+            }
+            _ => return Err(CL_INVALID_VALUE.into()),
+        }
+    }
+
+    let flags = flags.unwrap_or_default();
+    if flags & !cl_bitfield::from(CL_COMMAND_BUFFER_SIMULTANEOUS_USE_KHR) != 0 {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    Ok(CommandBuffer::new(q, reader.raw(), flags).into_cl())
+
+    //• CL_INCOMPATIBLE_COMMAND_QUEUE_KHR if the properties of any command-queue in queues does not contain the minimum properties specified by CL_DEVICE_COMMAND_BUFFER_REQUIRED_QUEUE_PROPERTIES_KHR.
+    //• CL_INVALID_PROPERTY if values specified in properties are valid but are not supported by all the devices associated with command-queues in queues.
+}
+
+#[cl_entrypoint(clFinalizeCommandBufferKHR)]
+fn finalize_command_buffer(command_buffer: cl_command_buffer_khr) -> CLResult<()> {
+    // CL_INVALID_OPERATION if command_buffer has already been finalized.
+    CommandBuffer::ref_from_raw(command_buffer)?.finalize()
+}
+
+#[cl_entrypoint(clRetainCommandBufferKHR)]
+fn retain_command_buffer(command_buffer: cl_command_buffer_khr) -> CLResult<()> {
+    CommandBuffer::retain(command_buffer)
+}
+
+#[cl_entrypoint(clReleaseCommandBufferKHR)]
+fn release_command_buffer(command_buffer: cl_command_buffer_khr) -> CLResult<()> {
+    CommandBuffer::release(command_buffer)
+}
+
+#[cl_entrypoint(clEnqueueCommandBufferKHR)]
+fn enqueue_command_buffer(
+    num_queues: cl_uint,
+    queues: *mut cl_command_queue,
+    command_buffer: cl_command_buffer_khr,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let cb = CommandBuffer::arc_from_raw(command_buffer)?;
+
+    // CL_INVALID_VALUE if queues is NULL and num_queues is > 0, or queues is not NULL and
+    // num_queues is 0, or num_queues is > 0 and not equal to CL_COMMAND_BUFFER_NUM_QUEUES_KHR.
+    let q = match (queues.is_null(), num_queues) {
+        (true, 0) => Arc::clone(&cb.queue),
+        // SAFETY: we checked for NULL and num_queues is 1.
+        (false, 1) => Queue::arc_from_raw(unsafe { *queues })?,
+        _ => return Err(CL_INVALID_VALUE.into()),
+    };
+
+    // CL_INVALID_CONTEXT if any element of queues does not have the same OpenCL context as the
+    // command-queue set on command_buffer creation at the same list index.
+    if q.context != cb.queue.context {
+        return Err(CL_INVALID_CONTEXT.into());
+    }
+
+    // CL_INCOMPATIBLE_COMMAND_QUEUE_KHR if any element of queues is not compatible with the
+    // command-queue set on command_buffer creation at the same list index.
+    if q.device != cb.queue.device || q.props != cb.queue.props {
+        return Err(CL_INCOMPATIBLE_COMMAND_QUEUE_KHR.into());
+    }
+
+    // CL_INVALID_OPERATION if command_buffer has not been finalized.
+    // CL_INVALID_OPERATION if command_buffer was not created with the
+    // CL_COMMAND_BUFFER_SIMULTANEOUS_USE_KHR flag and is in the Pending state.
+    if !cb.is_finalized()
+        || !bit_check(cb.flags, CL_COMMAND_BUFFER_SIMULTANEOUS_USE_KHR) && cb.is_pending()
+    {
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let e = cb.exec(&q, evs);
+    if !event.is_null() {
+        // SAFETY: we check for null and valid API use is to pass in a valid pointer
+        unsafe {
+            event.write(Arc::clone(&e).into_cl());
+        }
+    }
+    q.queue(e);
+    Ok(())
+}
+
+/// Validates the parts common to all commands and records the command created by `cmd`.
+fn record_command(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+    cmd: impl FnOnce(&Arc<Queue>) -> CLResult<CommandSig>,
+) -> CLResult<()> {
+    let cb = CommandBuffer::ref_from_raw(command_buffer)?;
+
+    // CL_INVALID_COMMAND_QUEUE if command_queue is not NULL.
+    if !command_queue.is_null() {
+        return Err(CL_INVALID_COMMAND_QUEUE.into());
+    }
+
+    // CL_INVALID_VALUE if mutable_handle is not NULL.
+    if !mutable_handle.is_null() {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    // CL_INVALID_OPERATION if command_buffer has been finalized.
+    if cb.is_finalized() {
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    // CL_INVALID_SYNC_POINT_WAIT_LIST_KHR if sync_point_wait_list is NULL and
+    // num_sync_points_in_wait_list is > 0, or sync_point_wait_list is not NULL and
+    // num_sync_points_in_wait_list is 0, or if synchronization-point objects in
+    // sync_point_wait_list are not valid synchronization-points.
+    if sync_point_wait_list.is_null() != (num_sync_points_in_wait_list == 0) {
+        return Err(CL_INVALID_SYNC_POINT_WAIT_LIST_KHR.into());
+    }
+
+    if num_sync_points_in_wait_list > 0 {
+        // SAFETY: the application has to pass in a list of num_sync_points_in_wait_list entries.
+        let sync_points = unsafe {
+            cl_slice::from_raw_parts(sync_point_wait_list, num_sync_points_in_wait_list as usize)
+        }
+        .map_err(|_| CL_INVALID_SYNC_POINT_WAIT_LIST_KHR)?;
+
+        if !cb.sync_points_valid(sync_points) {
+            return Err(CL_INVALID_SYNC_POINT_WAIT_LIST_KHR.into());
+        }
+    }
+
+    let sp = cb.record(cmd(&cb.queue)?)?;
+    sync_point.write_checked(sp);
+    Ok(())
+}
+
+#[cl_entrypoint(clCommandBarrierWithWaitListKHR)]
+fn command_barrier_with_wait_list(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    // commands are executed in order, so there is nothing to wait on.
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |_| Ok(Box::new(|_, _| Ok(()))),
+    )
+}
+
+#[cl_entrypoint(clCommandCopyBufferKHR)]
+fn command_copy_buffer(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    src_buffer: cl_mem,
+    dst_buffer: cl_mem,
+    src_offset: usize,
+    dst_offset: usize,
+    size: usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| copy_buffer_cmd(q, src_buffer, dst_buffer, src_offset, dst_offset, size),
+    )
+}
+
+#[cl_entrypoint(clCommandCopyBufferRectKHR)]
+fn command_copy_buffer_rect(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    src_buffer: cl_mem,
+    dst_buffer: cl_mem,
+    src_origin: *const usize,
+    dst_origin: *const usize,
+    region: *const usize,
+    src_row_pitch: usize,
+    src_slice_pitch: usize,
+    dst_row_pitch: usize,
+    dst_slice_pitch: usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| {
+            copy_buffer_rect_cmd(
+                q,
+                src_buffer,
+                dst_buffer,
+                src_origin,
+                dst_origin,
+                region,
+                src_row_pitch,
+                src_slice_pitch,
+                dst_row_pitch,
+                dst_slice_pitch,
+            )
+        },
+    )
+}
+
+#[cl_entrypoint(clCommandCopyBufferToImageKHR)]
+fn command_copy_buffer_to_image(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    src_buffer: cl_mem,
+    dst_image: cl_mem,
+    src_offset: usize,
+    dst_origin: *const usize,
+    region: *const usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| copy_buffer_to_image_cmd(q, src_buffer, dst_image, src_offset, dst_origin, region),
+    )
+}
+
+#[cl_entrypoint(clCommandCopyImageKHR)]
+fn command_copy_image(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    src_image: cl_mem,
+    dst_image: cl_mem,
+    src_origin: *const usize,
+    dst_origin: *const usize,
+    region: *const usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| copy_image_cmd(q, src_image, dst_image, src_origin, dst_origin, region),
+    )
+}
+
+#[cl_entrypoint(clCommandCopyImageToBufferKHR)]
+fn command_copy_image_to_buffer(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    src_image: cl_mem,
+    dst_buffer: cl_mem,
+    src_origin: *const usize,
+    region: *const usize,
+    dst_offset: usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| copy_image_to_buffer_cmd(q, src_image, dst_buffer, src_origin, region, dst_offset),
+    )
+}
+
+#[cl_entrypoint(clCommandFillBufferKHR)]
+fn command_fill_buffer(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    buffer: cl_mem,
+    pattern: *const c_void,
+    pattern_size: usize,
+    offset: usize,
+    size: usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| fill_buffer_cmd(q, buffer, pattern, pattern_size, offset, size),
+    )
+}
+
+#[cl_entrypoint(clCommandFillImageKHR)]
+fn command_fill_image(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    image: cl_mem,
+    fill_color: *const c_void,
+    origin: *const usize,
+    region: *const usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| fill_image_cmd(q, image, fill_color, origin.cast(), region.cast()),
+    )
+}
+
+#[cl_entrypoint(clCommandNDRangeKernelKHR)]
+fn command_ndrange_kernel(
+    command_buffer: cl_command_buffer_khr,
+    command_queue: cl_command_queue,
+    properties: *const cl_ndrange_kernel_command_properties_khr,
+    kernel: cl_kernel,
+    work_dim: cl_uint,
+    global_work_offset: *const usize,
+    global_work_size: *const usize,
+    local_work_size: *const usize,
+    num_sync_points_in_wait_list: cl_uint,
+    sync_point_wait_list: *const cl_sync_point_khr,
+    sync_point: *mut cl_sync_point_khr,
+    mutable_handle: *mut cl_mutable_command_khr,
+) -> CLResult<()> {
+    // CL_INVALID_VALUE if values specified in properties are not valid. There are no properties
+    // without cl_khr_command_buffer_mutable_dispatch.
+    // SAFETY: the list is required to be terminated
+    if unsafe { PropertyReader::new(properties) }.key().is_some() {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    record_command(
+        command_buffer,
+        command_queue,
+        num_sync_points_in_wait_list,
+        sync_point_wait_list,
+        sync_point,
+        mutable_handle,
+        |q| {
+            ndrange_kernel_cmd(
+                q,
+                kernel,
+                work_dim,
+                global_work_offset,
+                global_work_size,
+                local_work_size,
+            )
+        },
+    )
+}
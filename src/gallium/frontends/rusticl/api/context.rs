@@ -1,4 +1,5 @@
 use crate::api::icd::*;
+use crate::api::leaks;
 use crate::api::types::*;
 use crate::api::util::*;
 use crate::core::context::*;
@@ -18,6 +19,11 @@ use std::mem::transmute;
 use std::mem::MaybeUninit;
 use std::ptr;
 use std::slice;
+use std::sync::Arc;
+
+// cl_mesa_memory_stats: returns a cl_ulong[3] array of bytes allocated for buffers, images and
+// mapping shadows of the context.
+const CL_CONTEXT_MEMORY_STATS_MESA: cl_context_info = 0x10002;
 
 #[cl_info_entrypoint(clGetContextInfo)]
 impl CLInfo<cl_context_info> for cl_context {
@@ -33,8 +39,9 @@ impl CLInfo<cl_context_info> for cl_context {
             CL_CONTEXT_NUM_DEVICES => cl_prop::<cl_uint>(ctx.devs.len() as u32),
             CL_CONTEXT_PROPERTIES => cl_prop::<&Properties<cl_context_properties>>(&ctx.properties),
             CL_CONTEXT_REFERENCE_COUNT => cl_prop::<cl_uint>(Context::refcnt(*self)?),
+            CL_CONTEXT_MEMORY_STATS_MESA => cl_prop::<[cl_ulong; 3]>(ctx.mem_stats.snapshot()),
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -59,7 +66,7 @@ impl CLInfo<cl_gl_context_info> for GLCtxManager {
                     .collect();
                 cl_prop::<&Vec<cl_device_id>>(&devs)
             }
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -94,7 +101,7 @@ pub fn get_gl_context_info_khr(
                 glx_display = p.1 as *mut _;
             }
             // CL_INVALID_PROPERTY if context property name in properties is not a supported property name
-            _ => return Err(CL_INVALID_PROPERTY),
+            _ => return Err(CL_INVALID_PROPERTY.into()),
         }
     }
 
@@ -117,19 +124,18 @@ fn create_context(
     pfn_notify: Option<FuncCreateContextCB>,
     user_data: *mut ::std::os::raw::c_void,
 ) -> CLResult<cl_context> {
-    // TODO: Actually hook this callback up so it gets called when appropriate.
     // SAFETY: The requirements on `CreateContextCB::try_new` match the requirements
     // imposed by the OpenCL specification. It is the caller's duty to uphold them.
-    let _cb_opt = unsafe { CreateContextCB::try_new(pfn_notify, user_data)? };
+    let cb_opt = unsafe { CreateContextCB::try_new(pfn_notify, user_data)? };
 
     // CL_INVALID_VALUE if devices is NULL.
     if devices.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if num_devices is equal to zero.
     if num_devices == 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let mut egl_display: EGLDisplay = ptr::null_mut();
@@ -157,7 +163,7 @@ fn create_context(
                 glx_display = p.1 as *mut _;
             }
             // CL_INVALID_PROPERTY if context property name in properties is not a supported property name
-            _ => return Err(CL_INVALID_PROPERTY),
+            _ => return Err(CL_INVALID_PROPERTY.into()),
         }
     }
 
@@ -177,11 +183,11 @@ fn create_context(
         // share the data store of an OpenGL object.
 
         let [dev] = devs.as_slice() else {
-            return Err(CL_INVALID_OPERATION);
+            return Err(CL_INVALID_OPERATION.into());
         };
 
         if !dev.is_gl_sharing_supported() {
-            return Err(CL_INVALID_OPERATION);
+            return Err(CL_INVALID_OPERATION.into());
         }
 
         // gl sharing is only supported on devices with an UUID, so we can simply unwrap it
@@ -189,11 +195,11 @@ fn create_context(
             unsafe { transmute(dev.screen().device_uuid().unwrap_or_default()) };
         if gl_ctx_manager.interop_dev_info.device_uuid != dev_uuid {
             // we only support gl_sharing on the same device
-            return Err(CL_INVALID_OPERATION);
+            return Err(CL_INVALID_OPERATION.into());
         }
     }
 
-    Ok(Context::new(devs, props, gl_ctx_manager).into_cl())
+    Ok(Context::new(devs, props, gl_ctx_manager, cb_opt).into_cl())
 }
 
 #[cl_entrypoint(clCreateContextFromType)]
@@ -213,7 +219,7 @@ fn create_context_from_type(
 
     // CL_DEVICE_NOT_FOUND if no devices that match device_type and property values specified in properties were found.
     if devs.is_empty() {
-        return Err(CL_DEVICE_NOT_FOUND);
+        return Err(CL_DEVICE_NOT_FOUND.into());
     }
 
     // errors are essentially the same and we will always pass in a valid
@@ -234,7 +240,10 @@ fn retain_context(context: cl_context) -> CLResult<()> {
 
 #[cl_entrypoint(clReleaseContext)]
 fn release_context(context: cl_context) -> CLResult<()> {
-    Context::release(context)
+    let ctx = Arc::downgrade(&Context::arc_from_raw(context)?);
+    Context::release(context)?;
+    leaks::context_released(&ctx);
+    Ok(())
 }
 
 #[cl_entrypoint(clSetContextDestructorCallback)]
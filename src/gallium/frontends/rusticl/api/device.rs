@@ -26,6 +26,10 @@ const SPIRV_SUPPORT: [cl_name_version; 5] = [
 ];
 type ClDevIdpAccelProps = cl_device_integer_dot_product_acceleration_properties_khr;
 
+// cl_mesa_memory_stats: returns a cl_ulong[4] array of bytes allocated for buffers, images, mapping
+// shadows and idle staging buffers of the device.
+const CL_DEVICE_MEMORY_STATS_MESA: cl_device_info = 0x10003;
+
 #[cl_info_entrypoint(clGetDeviceInfo)]
 impl CLInfo<cl_device_info> for cl_device_id {
     fn query(&self, q: cl_device_info, _: &[u8]) -> CLResult<Vec<MaybeUninit<u8>>> {
@@ -48,15 +52,25 @@ impl CLInfo<cl_device_info> for cl_device_id {
             CL_DEVICE_AVAILABLE => cl_prop::<bool>(true),
             CL_DEVICE_BUILT_IN_KERNELS => cl_prop::<&str>(""),
             CL_DEVICE_BUILT_IN_KERNELS_WITH_VERSION => cl_prop::<Vec<cl_name_version>>(Vec::new()),
+            CL_DEVICE_COMMAND_BUFFER_CAPABILITIES_KHR => {
+                cl_prop::<cl_device_command_buffer_capabilities_khr>(
+                    (CL_COMMAND_BUFFER_CAPABILITY_KERNEL_PRINTF_KHR
+                        | CL_COMMAND_BUFFER_CAPABILITY_SIMULTANEOUS_USE_KHR)
+                        .into(),
+                )
+            }
+            CL_DEVICE_COMMAND_BUFFER_REQUIRED_QUEUE_PROPERTIES_KHR => {
+                cl_prop::<cl_command_queue_properties>(0)
+            }
             CL_DEVICE_COMPILER_AVAILABLE => cl_prop::<bool>(true),
             CL_DEVICE_CROSS_DEVICE_SHARED_MEM_CAPABILITIES_INTEL => {
-                cl_prop::<cl_device_device_enqueue_capabilities>(0)
+                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(dev.usm_caps())
             }
             CL_DEVICE_DEVICE_ENQUEUE_CAPABILITIES => {
                 cl_prop::<cl_device_device_enqueue_capabilities>(0)
             }
             CL_DEVICE_DEVICE_MEM_CAPABILITIES_INTEL => {
-                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(0)
+                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(dev.usm_caps())
             }
             CL_DEVICE_DOUBLE_FP_CONFIG => cl_prop::<cl_device_fp_config>(
                 if dev.fp64_supported() {
@@ -82,6 +96,11 @@ impl CLInfo<cl_device_info> for cl_device_id {
             }
             CL_DEVICE_EXTENSIONS => cl_prop::<&str>(&dev.extension_string),
             CL_DEVICE_EXTENSIONS_WITH_VERSION => cl_prop::<&Vec<cl_name_version>>(&dev.extensions),
+            CL_DEVICE_EXTERNAL_MEMORY_IMPORT_HANDLE_TYPES_KHR => {
+                cl_prop::<Vec<cl_external_memory_handle_type_khr>>(
+                    dev.external_memory_import_handle_types(),
+                )
+            }
             CL_DEVICE_GENERIC_ADDRESS_SPACE_SUPPORT => cl_prop::<bool>(false),
             CL_DEVICE_GLOBAL_MEM_CACHE_TYPE => cl_prop::<cl_device_mem_cache_type>(CL_NONE),
             CL_DEVICE_GLOBAL_MEM_CACHE_SIZE => cl_prop::<cl_ulong>(0),
@@ -97,7 +116,7 @@ impl CLInfo<cl_device_info> for cl_device_id {
                 .into(),
             ),
             CL_DEVICE_HOST_MEM_CAPABILITIES_INTEL => {
-                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(0)
+                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(dev.usm_caps())
             }
             CL_DEVICE_HOST_UNIFIED_MEMORY => cl_prop::<bool>(dev.unified_memory()),
             CL_DEVICE_IL_VERSION => cl_prop::<&str>(SPIRV_SUPPORT_STRING),
@@ -195,8 +214,7 @@ impl CLInfo<cl_device_info> for cl_device_id {
             CL_DEVICE_MAX_WORK_ITEM_DIMENSIONS => cl_prop::<cl_uint>(dev.max_grid_dimensions()),
             CL_DEVICE_MAX_WORK_ITEM_SIZES => cl_prop::<Vec<usize>>(dev.max_block_sizes()),
             CL_DEVICE_MAX_WRITE_IMAGE_ARGS => cl_prop::<cl_uint>(dev.caps.max_write_images),
-            // TODO proper retrival from devices
-            CL_DEVICE_MEM_BASE_ADDR_ALIGN => cl_prop::<cl_uint>(0x1000),
+            CL_DEVICE_MEM_BASE_ADDR_ALIGN => cl_prop::<cl_uint>(dev.mem_base_addr_align()),
             CL_DEVICE_MIN_DATA_TYPE_ALIGN_SIZE => {
                 cl_prop::<cl_uint>(size_of::<cl_ulong16>() as cl_uint)
             }
@@ -268,11 +286,24 @@ impl CLInfo<cl_device_info> for cl_device_id {
                 cl_prop::<cl_command_queue_properties>(CL_QUEUE_PROFILING_ENABLE.into())
             }
             CL_DEVICE_REFERENCE_COUNT => cl_prop::<cl_uint>(1),
+            CL_DEVICE_SEMAPHORE_EXPORT_HANDLE_TYPES_KHR => {
+                cl_prop::<Vec<cl_external_semaphore_handle_type_khr>>(
+                    dev.semaphore_export_handle_types(),
+                )
+            }
+            CL_DEVICE_SEMAPHORE_IMPORT_HANDLE_TYPES_KHR => {
+                cl_prop::<Vec<cl_external_semaphore_handle_type_khr>>(
+                    dev.semaphore_import_handle_types(),
+                )
+            }
+            CL_DEVICE_SEMAPHORE_TYPES_KHR => {
+                cl_prop::<Vec<cl_semaphore_type_khr>>(vec![CL_SEMAPHORE_TYPE_BINARY_KHR])
+            }
             CL_DEVICE_SHARED_SYSTEM_MEM_CAPABILITIES_INTEL => {
-                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(0)
+                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(dev.usm_caps())
             }
             CL_DEVICE_SINGLE_DEVICE_SHARED_MEM_CAPABILITIES_INTEL => {
-                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(0)
+                cl_prop::<cl_device_unified_shared_memory_capabilities_intel>(dev.usm_caps())
             }
             CL_DEVICE_SINGLE_FP_CONFIG => cl_prop::<cl_device_fp_config>(
                 (CL_FP_ROUND_TO_NEAREST | CL_FP_INF_NAN) as cl_device_fp_config,
@@ -286,16 +317,16 @@ impl CLInfo<cl_device_info> for cl_device_id {
                 })
             }
             CL_DEVICE_SVM_CAPABILITIES | CL_DEVICE_SVM_CAPABILITIES_ARM => {
-                cl_prop::<cl_device_svm_capabilities>(
-                    if dev.svm_supported() {
-                        CL_DEVICE_SVM_COARSE_GRAIN_BUFFER
-                            | CL_DEVICE_SVM_FINE_GRAIN_BUFFER
-                            | CL_DEVICE_SVM_FINE_GRAIN_SYSTEM
-                    } else {
-                        0
-                    }
-                    .into(),
-                )
+                let mut caps = 0;
+                if dev.svm_supported() {
+                    caps |= CL_DEVICE_SVM_COARSE_GRAIN_BUFFER
+                        | CL_DEVICE_SVM_FINE_GRAIN_BUFFER
+                        | CL_DEVICE_SVM_FINE_GRAIN_SYSTEM;
+                }
+                if dev.svm_atomics_supported() {
+                    caps |= CL_DEVICE_SVM_ATOMICS;
+                }
+                cl_prop::<cl_device_svm_capabilities>(caps.into())
             }
             CL_DEVICE_TYPE => cl_prop::<cl_device_type>(dev.device_type(false)),
             CL_DEVICE_UUID_KHR => cl_prop::<[cl_uchar; CL_UUID_SIZE_KHR as usize]>(
@@ -309,9 +340,10 @@ impl CLInfo<cl_device_info> for cl_device_id {
             ),
             CL_DRIVER_VERSION => cl_prop::<&CStr>(unsafe { CStr::from_ptr(mesa_version_string()) }),
             CL_DEVICE_WORK_GROUP_COLLECTIVE_FUNCTIONS_SUPPORT => cl_prop::<bool>(false),
+            CL_DEVICE_MEMORY_STATS_MESA => cl_prop::<[cl_ulong; 4]>(dev.memory_stats()),
             // CL_INVALID_VALUE if param_name is not one of the supported values
             // CL_INVALID_VALUE [...] if param_name is a value that is available as an extension and the corresponding extension is not supported by the device.
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -332,18 +364,18 @@ fn get_device_ids(
 
     // CL_INVALID_VALUE if num_entries is equal to zero and devices is not NULL
     if num_entries == 0 && !devices.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE [...] if both num_devices and devices are NULL.
     if num_devices.is_null() && devices.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let devs = get_devs_for_type(device_type);
     // CL_DEVICE_NOT_FOUND if no OpenCL devices that matched device_type were found
     if devs.is_empty() {
-        return Err(CL_DEVICE_NOT_FOUND);
+        return Err(CL_DEVICE_NOT_FOUND.into());
     }
 
     // num_devices returns the number of OpenCL devices available that match device_type. If
@@ -382,7 +414,7 @@ fn get_device_and_host_timer(
 ) -> CLResult<()> {
     if device_timestamp.is_null() {
         // CL_INVALID_VALUE if host_timestamp or device_timestamp is NULL
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     get_host_timer(device, host_timestamp)?;
@@ -404,14 +436,14 @@ fn get_device_and_host_timer(
 fn get_host_timer(device_id: cl_device_id, host_timestamp: *mut cl_ulong) -> CLResult<()> {
     if host_timestamp.is_null() {
         // CL_INVALID_VALUE if host_timestamp is NULL
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let device = Device::ref_from_raw(device_id)?;
 
     if !device.caps.has_timestamp {
         // CL_INVALID_OPERATION if the platform associated with device does not support device and host timer synchronization
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // Currently the best clock we have for the host_timestamp
@@ -426,5 +458,5 @@ fn set_default_device_command_queue(
     _device: cl_device_id,
     _command_queue: cl_command_queue,
 ) -> CLResult<()> {
-    Err(CL_INVALID_OPERATION)
+    Err(CL_INVALID_OPERATION.into())
 }
@@ -0,0 +1,113 @@
+//! Errors carrying more context than the bare CL error code.
+//!
+//! Internal code can return a [CLError] describing what exactly failed. The description travels
+//! up to the entry point returning the error to the application, which logs it at the debug
+//! level.
+
+use crate::core::device::*;
+
+use mesa_rust_util::log_debug;
+use rusticl_opencl_gen::*;
+
+use std::cell::RefCell;
+use std::fmt;
+
+#[derive(Debug)]
+pub struct CLError {
+    pub code: cl_int,
+    op: &'static str,
+    object: Option<String>,
+    device: Option<String>,
+    msg: Option<String>,
+}
+
+impl CLError {
+    pub fn new(code: cl_int, op: &'static str) -> Self {
+        Self {
+            code: code,
+            op: op,
+            object: None,
+            device: None,
+            msg: None,
+        }
+    }
+
+    pub fn object<T>(mut self, obj: *const T) -> Self {
+        self.object = Some(format!("{obj:p}"));
+        self
+    }
+
+    pub fn device(mut self, dev: &Device) -> Self {
+        self.device = Some(dev.screen().name());
+        self
+    }
+
+    pub fn msg(mut self, msg: impl Into<String>) -> Self {
+        self.msg = Some(msg.into());
+        self
+    }
+}
+
+impl fmt::Display for CLError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} failed with {}", self.op, self.code)?;
+        if let Some(object) = &self.object {
+            write!(f, " on {object}")?;
+        }
+        if let Some(device) = &self.device {
+            write!(f, " for device {device}")?;
+        }
+        if let Some(msg) = &self.msg {
+            write!(f, ": {msg}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Allows returning plain error codes.
+impl From<cl_int> for CLError {
+    fn from(code: cl_int) -> Self {
+        Self::new(code, "")
+    }
+}
+
+/// Used where errors get turned into an event status or similar. The description is kept around
+/// in case the code ends up being returned by the current API call after all.
+impl From<CLError> for cl_int {
+    fn from(err: CLError) -> Self {
+        let code = err.code;
+        // errors converted from plain codes don't carry anything worth keeping
+        if !err.op.is_empty() {
+            PENDING.with(|pending| *pending.borrow_mut() = Some(err));
+        }
+        code
+    }
+}
+
+thread_local! {
+    /// The last detailed error turned into a plain code, which hasn't been returned by an API call
+    /// yet.
+    static PENDING: RefCell<Option<CLError>> = const { RefCell::new(None) };
+}
+
+/// Called by the stubs generated through `cl_entrypoint` and `cl_info_entrypoint` before calling
+/// into the implementation, so details left over by earlier calls aren't attached to this one.
+pub fn begin_call() {
+    PENDING.with(|pending| pending.borrow_mut().take());
+}
+
+/// Called by the stubs generated through `cl_entrypoint` and `cl_info_entrypoint` if the call
+/// failed. Returns the code to hand to the application.
+pub fn finish_call(name: &str, err: CLError) -> cl_int {
+    let pending = PENDING.with(|pending| pending.borrow_mut().take());
+    let code = err.code;
+    let detail = match pending {
+        _ if !err.op.is_empty() => format!("{name}: {err}"),
+        // the error might have been handled internally, so only use it if the code still matches
+        Some(detail) if detail.code == code => format!("{name}: {detail}"),
+        _ => format!("{name} failed with {code}"),
+    };
+
+    log_debug!("{detail}");
+    code
+}
@@ -1,4 +1,5 @@
 use crate::api::icd::*;
+use crate::api::strict;
 use crate::api::types::*;
 use crate::api::util::*;
 use crate::core::context::*;
@@ -35,7 +36,7 @@ impl CLInfo<cl_event_info> for cl_event {
             }
             CL_EVENT_REFERENCE_COUNT => cl_prop::<cl_uint>(Event::refcnt(*self)?),
             CL_EVENT_COMMAND_TYPE => cl_prop::<cl_command_type>(event.cmd_type),
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -46,7 +47,7 @@ impl CLInfo<cl_profiling_info> for cl_event {
         let event = Event::ref_from_raw(*self)?;
         if event.cmd_type == CL_COMMAND_USER {
             // CL_PROFILING_INFO_NOT_AVAILABLE [...] if event is a user event object.
-            return Err(CL_PROFILING_INFO_NOT_AVAILABLE);
+            return Err(CL_PROFILING_INFO_NOT_AVAILABLE.into());
         }
 
         Ok(match *q {
@@ -56,7 +57,7 @@ impl CLInfo<cl_profiling_info> for cl_event {
             CL_PROFILING_COMMAND_END => cl_prop::<cl_ulong>(event.get_time(EventTimes::End)),
             // For now, we treat Complete the same as End
             CL_PROFILING_COMMAND_COMPLETE => cl_prop::<cl_ulong>(event.get_time(EventTimes::End)),
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -83,13 +84,13 @@ fn wait_for_events(num_events: cl_uint, event_list: *const cl_event) -> CLResult
 
     // CL_INVALID_VALUE if num_events is zero or event_list is NULL.
     if evs.is_empty() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_CONTEXT if events specified in event_list do not belong to the same context.
     let contexts: HashSet<_> = evs.iter().map(|e| &e.context).collect();
     if contexts.len() != 1 {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // find all queues we have to flush
@@ -106,7 +107,7 @@ fn wait_for_events(num_events: cl_uint, event_list: *const cl_event) -> CLResult
     // CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST if the execution status of any of the events
     // in event_list is a negative integer value.
     if err {
-        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST);
+        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST.into());
     }
 
     Ok(())
@@ -123,7 +124,7 @@ fn set_event_callback(
 
     // CL_INVALID_VALUE [...] if command_exec_callback_type is not CL_SUBMITTED, CL_RUNNING, or CL_COMPLETE.
     if ![CL_SUBMITTED, CL_RUNNING, CL_COMPLETE].contains(&(command_exec_callback_type as cl_uint)) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // SAFETY: The requirements on `EventCB::new` match the requirements
@@ -141,13 +142,13 @@ fn set_user_event_status(event: cl_event, execution_status: cl_int) -> CLResult<
 
     // CL_INVALID_VALUE if the execution_status is not CL_COMPLETE or a negative integer value.
     if execution_status != CL_COMPLETE as cl_int && execution_status > 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_OPERATION if the execution_status for event has already been changed by a
     // previous call to clSetUserEventStatus.
     if e.status() != CL_SUBMITTED as cl_int {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     e.set_user_status(execution_status);
@@ -162,7 +163,25 @@ pub fn create_and_queue(
     block: bool,
     work: EventSig,
 ) -> CLResult<()> {
-    let e = Event::new(&q, cmd_type, deps, work);
+    let host_access = HostAccess::for_cmd(&q, cmd_type);
+    create_and_queue_with_host_access(q, cmd_type, deps, event, block, host_access, work)
+}
+
+/// Like [create_and_queue], for commands accessing application memory through a host pointer.
+pub fn create_and_queue_with_host_access(
+    q: Arc<Queue>,
+    cmd_type: cl_command_type,
+    deps: Vec<Arc<Event>>,
+    event: *mut cl_event,
+    block: bool,
+    host_access: HostAccess,
+    work: EventSig,
+) -> CLResult<()> {
+    if block {
+        strict::check_blocking_deps(&deps, cmd_type);
+    }
+
+    let e = Event::new_with_host_access(&q, cmd_type, deps, host_access, work);
     if !event.is_null() {
         // SAFETY: we check for null and valid API use is to pass in a valid pointer
         unsafe {
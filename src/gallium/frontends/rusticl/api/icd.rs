@@ -1,14 +1,19 @@
 #![allow(non_snake_case)]
 
+use crate::api::command_buffer::*;
 use crate::api::context::*;
 use crate::api::device::*;
+use crate::api::error;
 use crate::api::event::*;
 use crate::api::kernel::*;
+use crate::api::leaks;
 use crate::api::memory::*;
+use crate::api::panic;
 use crate::api::platform;
 use crate::api::platform::*;
 use crate::api::program::*;
 use crate::api::queue::*;
+use crate::api::semaphore::*;
 use crate::api::types::*;
 use crate::api::util::*;
 
@@ -21,6 +26,8 @@ use std::ffi::CStr;
 use std::ptr;
 use std::sync::Arc;
 
+pub use crate::api::error::CLError;
+
 pub static DISPATCH: cl_icd_dispatch = cl_icd_dispatch {
     clGetPlatformIDs: Some(clGetPlatformIDs),
     clGetPlatformInfo: Some(clGetPlatformInfo),
@@ -173,7 +180,6 @@ pub static DISPATCH: cl_icd_dispatch = cl_icd_dispatch {
     clSetContextDestructorCallback: Some(clSetContextDestructorCallback),
 };
 
-pub type CLError = cl_int;
 pub type CLResult<T> = Result<T, CLError>;
 
 #[derive(Clone, Copy, PartialEq)]
@@ -189,6 +195,8 @@ pub enum RusticlTypes {
     Program,
     Queue,
     Sampler,
+    Semaphore,
+    CommandBuffer,
 }
 
 impl RusticlTypes {
@@ -207,6 +215,8 @@ impl RusticlTypes {
             0xec4cf9af => Self::Program,
             0xec4cf9b0 => Self::Queue,
             0xec4cf9b1 => Self::Sampler,
+            0xec4cf9b2 => Self::Semaphore,
+            0xec4cf9b3 => Self::CommandBuffer,
             _ => return None,
         };
         debug_assert!(result.u32() == val);
@@ -218,6 +228,7 @@ impl RusticlTypes {
 pub struct CLObjectBase<const ERR: i32> {
     dispatch: &'static cl_icd_dispatch,
     rusticl_type: u32,
+    leak_id: u64,
 }
 
 impl<const ERR: i32> CLObjectBase<ERR> {
@@ -225,21 +236,22 @@ impl<const ERR: i32> CLObjectBase<ERR> {
         Self {
             dispatch: &DISPATCH,
             rusticl_type: t.u32(),
+            leak_id: leaks::track(t),
         }
     }
 
     pub fn check_ptr(ptr: *const Self) -> CLResult<RusticlTypes> {
         if ptr.is_null() {
-            return Err(ERR);
+            return Err(ERR.into());
         }
 
         unsafe {
             if !::std::ptr::eq((*ptr).dispatch, &DISPATCH) {
-                return Err(ERR);
+                return Err(ERR.into());
             }
 
             let Some(ty) = RusticlTypes::from_u32((*ptr).rusticl_type) else {
-                return Err(ERR);
+                return Err(ERR.into());
             };
 
             Ok(ty)
@@ -247,7 +259,13 @@ impl<const ERR: i32> CLObjectBase<ERR> {
     }
 
     pub fn get_type(&self) -> CLResult<RusticlTypes> {
-        RusticlTypes::from_u32(self.rusticl_type).ok_or(ERR)
+        RusticlTypes::from_u32(self.rusticl_type).ok_or(ERR.into())
+    }
+}
+
+impl<const ERR: i32> Drop for CLObjectBase<ERR> {
+    fn drop(&mut self) {
+        leaks::untrack(self.leak_id);
     }
 }
 
@@ -275,7 +293,7 @@ pub trait BaseCLObject<'a, const ERR: i32, CL: ReferenceCountedAPIPointer<Self,
     {
         // CL spec requires validation for obj arrays, both values have to make sense
         if objs.is_null() && count > 0 || !objs.is_null() && count == 0 {
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
 
         let mut res = Vec::new();
@@ -310,7 +328,7 @@ pub trait ArcedCLObject<'a, const ERR: i32, CL: ReferenceCountedAPIPointer<Self,
     {
         // CL spec requires validation for obj arrays, both values have to make sense
         if objs.is_null() && count > 0 || !objs.is_null() && count == 0 {
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
 
         let mut res = Vec::new();
@@ -363,7 +381,7 @@ macro_rules! impl_cl_type_trait_base {
                 type Base = $crate::api::icd::CLObjectBase<$err>;
                 let t = Base::check_ptr(self.cast())?;
                 if ![$($crate::api::icd::RusticlTypes::$types),+].contains(&t) {
-                    return Err($err);
+                    return Err($err.into());
                 }
 
                 let offset = ::mesa_rust_util::offset_of!($t, $($field).+);
@@ -481,7 +499,24 @@ extern "C" fn clGetExtensionFunctionAddress(
     if function_name.is_null() {
         return ptr::null_mut();
     }
-    match unsafe { CStr::from_ptr(function_name) }.to_str().unwrap() {
+    match unsafe { CStr::from_ptr(function_name) }.to_str().unwrap_or_default() {
+        // cl_khr_command_buffer
+        "clCommandBarrierWithWaitListKHR" => cl_ext_func!(clCommandBarrierWithWaitListKHR: clCommandBarrierWithWaitListKHR_fn),
+        "clCommandCopyBufferKHR" => cl_ext_func!(clCommandCopyBufferKHR: clCommandCopyBufferKHR_fn),
+        "clCommandCopyBufferRectKHR" => cl_ext_func!(clCommandCopyBufferRectKHR: clCommandCopyBufferRectKHR_fn),
+        "clCommandCopyBufferToImageKHR" => cl_ext_func!(clCommandCopyBufferToImageKHR: clCommandCopyBufferToImageKHR_fn),
+        "clCommandCopyImageKHR" => cl_ext_func!(clCommandCopyImageKHR: clCommandCopyImageKHR_fn),
+        "clCommandCopyImageToBufferKHR" => cl_ext_func!(clCommandCopyImageToBufferKHR: clCommandCopyImageToBufferKHR_fn),
+        "clCommandFillBufferKHR" => cl_ext_func!(clCommandFillBufferKHR: clCommandFillBufferKHR_fn),
+        "clCommandFillImageKHR" => cl_ext_func!(clCommandFillImageKHR: clCommandFillImageKHR_fn),
+        "clCommandNDRangeKernelKHR" => cl_ext_func!(clCommandNDRangeKernelKHR: clCommandNDRangeKernelKHR_fn),
+        "clCreateCommandBufferKHR" => cl_ext_func!(clCreateCommandBufferKHR: clCreateCommandBufferKHR_fn),
+        "clEnqueueCommandBufferKHR" => cl_ext_func!(clEnqueueCommandBufferKHR: clEnqueueCommandBufferKHR_fn),
+        "clFinalizeCommandBufferKHR" => cl_ext_func!(clFinalizeCommandBufferKHR: clFinalizeCommandBufferKHR_fn),
+        "clGetCommandBufferInfoKHR" => cl_ext_func!(clGetCommandBufferInfoKHR: clGetCommandBufferInfoKHR_fn),
+        "clReleaseCommandBufferKHR" => cl_ext_func!(clReleaseCommandBufferKHR: clReleaseCommandBufferKHR_fn),
+        "clRetainCommandBufferKHR" => cl_ext_func!(clRetainCommandBufferKHR: clRetainCommandBufferKHR_fn),
+
         // cl_khr_create_command_queue
         "clCreateCommandQueueWithPropertiesKHR" => cl_ext_func!(clCreateCommandQueueWithProperties: clCreateCommandQueueWithPropertiesKHR_fn),
 
@@ -492,6 +527,10 @@ extern "C" fn clGetExtensionFunctionAddress(
         // cl_khr_il_program
         "clCreateProgramWithILKHR" => cl_ext_func!(clCreateProgramWithIL: clCreateProgramWithILKHR_fn),
 
+        // cl_khr_external_memory
+        "clEnqueueAcquireExternalMemObjectsKHR" => cl_ext_func!(clEnqueueAcquireExternalMemObjectsKHR: clEnqueueAcquireExternalMemObjectsKHR_fn),
+        "clEnqueueReleaseExternalMemObjectsKHR" => cl_ext_func!(clEnqueueReleaseExternalMemObjectsKHR: clEnqueueReleaseExternalMemObjectsKHR_fn),
+
         // cl_khr_gl_sharing
         "clCreateFromGLBuffer" => cl_ext_func!(clCreateFromGLBuffer: cl_api_clCreateFromGLBuffer),
         "clCreateFromGLRenderbuffer" => cl_ext_func!(clCreateFromGLRenderbuffer: cl_api_clCreateFromGLRenderbuffer),
@@ -504,6 +543,15 @@ extern "C" fn clGetExtensionFunctionAddress(
         "clGetGLObjectInfo" => cl_ext_func!(clGetGLObjectInfo: cl_api_clGetGLObjectInfo),
         "clGetGLTextureInfo" => cl_ext_func!(clGetGLTextureInfo: cl_api_clGetGLTextureInfo),
 
+        // cl_khr_semaphore and cl_khr_external_semaphore
+        "clCreateSemaphoreWithPropertiesKHR" => cl_ext_func!(clCreateSemaphoreWithPropertiesKHR: clCreateSemaphoreWithPropertiesKHR_fn),
+        "clEnqueueSignalSemaphoresKHR" => cl_ext_func!(clEnqueueSignalSemaphoresKHR: clEnqueueSignalSemaphoresKHR_fn),
+        "clEnqueueWaitSemaphoresKHR" => cl_ext_func!(clEnqueueWaitSemaphoresKHR: clEnqueueWaitSemaphoresKHR_fn),
+        "clGetSemaphoreHandleForTypeKHR" => cl_ext_func!(clGetSemaphoreHandleForTypeKHR: clGetSemaphoreHandleForTypeKHR_fn),
+        "clGetSemaphoreInfoKHR" => cl_ext_func!(clGetSemaphoreInfoKHR: clGetSemaphoreInfoKHR_fn),
+        "clReleaseSemaphoreKHR" => cl_ext_func!(clReleaseSemaphoreKHR: clReleaseSemaphoreKHR_fn),
+        "clRetainSemaphoreKHR" => cl_ext_func!(clRetainSemaphoreKHR: clRetainSemaphoreKHR_fn),
+
         // cl_khr_suggested_local_work_size
         "clGetKernelSuggestedLocalWorkSizeKHR" => cl_ext_func!(clGetKernelSuggestedLocalWorkSizeKHR: clGetKernelSuggestedLocalWorkSizeKHR_fn),
 
@@ -518,6 +566,22 @@ extern "C" fn clGetExtensionFunctionAddress(
         "clSVMAllocARM" => cl_ext_func!(clSVMAlloc: cl_api_clSVMAlloc),
         "clSVMFreeARM" => cl_ext_func!(clSVMFree: cl_api_clSVMFree),
 
+        // cl_intel_unified_shared_memory
+        "clDeviceMemAllocINTEL" => cl_ext_func!(clDeviceMemAllocINTEL: clDeviceMemAllocINTEL_fn),
+        "clEnqueueMemAdviseINTEL" => cl_ext_func!(clEnqueueMemAdviseINTEL: clEnqueueMemAdviseINTEL_fn),
+        "clEnqueueMemcpyINTEL" => cl_ext_func!(clEnqueueMemcpyINTEL: clEnqueueMemcpyINTEL_fn),
+        "clEnqueueMemFillINTEL" => cl_ext_func!(clEnqueueMemFillINTEL: clEnqueueMemFillINTEL_fn),
+        "clEnqueueMigrateMemINTEL" => cl_ext_func!(clEnqueueMigrateMemINTEL: clEnqueueMigrateMemINTEL_fn),
+        "clGetMemAllocInfoINTEL" => cl_ext_func!(clGetMemAllocInfoINTEL: clGetMemAllocInfoINTEL_fn),
+        "clHostMemAllocINTEL" => cl_ext_func!(clHostMemAllocINTEL: clHostMemAllocINTEL_fn),
+        "clMemBlockingFreeINTEL" => cl_ext_func!(clMemBlockingFreeINTEL: clMemBlockingFreeINTEL_fn),
+        "clMemFreeINTEL" => cl_ext_func!(clMemFreeINTEL: clMemFreeINTEL_fn),
+        "clSetKernelArgMemPointerINTEL" => cl_ext_func!(clSetKernelArgMemPointerINTEL: clSetKernelArgMemPointerINTEL_fn),
+        "clSharedMemAllocINTEL" => cl_ext_func!(clSharedMemAllocINTEL: clSharedMemAllocINTEL_fn),
+
+        // cl_mesa_dma_buf_export
+        "clGetMemObjectDmaBufMESA" => clGetMemObjectDmaBufMESA as *mut c_void,
+
         // DPCPP bug https://github.com/intel/llvm/issues/9964
         "clSetProgramSpecializationConstant" => cl_ext_func!(clSetProgramSpecializationConstant: cl_api_clSetProgramSpecializationConstant),
 
@@ -536,18 +600,21 @@ extern "C" fn clLinkProgram(
     user_data: *mut ::std::os::raw::c_void,
     errcode_ret: *mut cl_int,
 ) -> cl_program {
-    let (ptr, err) = match link_program(
-        context,
-        num_devices,
-        device_list,
-        options,
-        num_input_programs,
-        input_programs,
-        pfn_notify,
-        user_data,
-    ) {
+    error::begin_call();
+    let (ptr, err) = match panic::catch("clLinkProgram", || {
+        link_program(
+            context,
+            num_devices,
+            device_list,
+            options,
+            num_input_programs,
+            input_programs,
+            pfn_notify,
+            user_data,
+        )
+    }) {
         Ok((prog, code)) => (prog, code),
-        Err(e) => (ptr::null_mut(), e),
+        Err(e) => (ptr::null_mut(), error::finish_call("clLinkProgram", e)),
     };
 
     errcode_ret.write_checked(err);
@@ -567,11 +634,35 @@ extern "C" fn clSVMAlloc(
     size: usize,
     alignment: ::std::os::raw::c_uint,
 ) -> *mut ::std::os::raw::c_void {
-    svm_alloc(context, flags, size, alignment).unwrap_or(ptr::null_mut())
+    panic::catch("clSVMAlloc", || svm_alloc(context, flags, size, alignment))
+        .unwrap_or(ptr::null_mut())
 }
 
 extern "C" fn clSVMFree(context: cl_context, svm_pointer: *mut ::std::os::raw::c_void) {
-    svm_free(context, svm_pointer as usize).ok();
+    panic::catch("clSVMFree", || svm_free(context, svm_pointer as usize)).ok();
+}
+
+extern "C" fn clGetMemAllocInfoINTEL(
+    context: cl_context,
+    ptr: *const ::std::os::raw::c_void,
+    param_name: cl_mem_info_intel,
+    param_value_size: usize,
+    param_value: *mut ::std::os::raw::c_void,
+    param_value_size_ret: *mut usize,
+) -> cl_int {
+    error::begin_call();
+    match panic::catch("clGetMemAllocInfoINTEL", || {
+        context.get_info_obj(
+            ptr,
+            param_name,
+            param_value_size,
+            param_value,
+            param_value_size_ret,
+        )
+    }) {
+        Ok(_) => CL_SUCCESS as cl_int,
+        Err(e) => error::finish_call("clGetMemAllocInfoINTEL", e),
+    }
 }
 
 extern "C" fn clGetKernelSubGroupInfo(
@@ -584,14 +675,17 @@ extern "C" fn clGetKernelSubGroupInfo(
     param_value: *mut ::std::os::raw::c_void,
     param_value_size_ret: *mut usize,
 ) -> cl_int {
-    match kernel.get_info_obj(
-        (device, input_value_size, input_value, param_value_size),
-        param_name,
-        param_value_size,
-        param_value,
-        param_value_size_ret,
-    ) {
+    error::begin_call();
+    match panic::catch("clGetKernelSubGroupInfo", || {
+        kernel.get_info_obj(
+            (device, input_value_size, input_value, param_value_size),
+            param_name,
+            param_value_size,
+            param_value,
+            param_value_size_ret,
+        )
+    }) {
         Ok(_) => CL_SUCCESS as cl_int,
-        Err(e) => e,
+        Err(e) => error::finish_call("clGetKernelSubGroupInfo", e),
     }
 }
@@ -1,5 +1,6 @@
 use crate::api::event::create_and_queue;
 use crate::api::icd::*;
+use crate::api::strict;
 use crate::api::util::*;
 use crate::core::device::*;
 use crate::core::event::*;
@@ -39,7 +40,7 @@ impl CLInfo<cl_kernel_info> for cl_kernel {
             }
             CL_KERNEL_REFERENCE_COUNT => cl_prop::<cl_uint>(Kernel::refcnt(*self)?),
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -51,7 +52,7 @@ impl CLInfoObj<cl_kernel_arg_info, cl_uint> for cl_kernel {
 
         // CL_INVALID_ARG_INDEX if arg_index is not a valid argument index.
         if idx as usize >= kernel.kernel_info.args.len() {
-            return Err(CL_INVALID_ARG_INDEX);
+            return Err(CL_INVALID_ARG_INDEX.into());
         }
 
         Ok(match *q {
@@ -67,7 +68,7 @@ impl CLInfoObj<cl_kernel_arg_info, cl_uint> for cl_kernel {
                 cl_prop::<cl_kernel_arg_type_qualifier>(kernel.type_qualifier(idx))
             }
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -84,7 +85,7 @@ impl CLInfoObj<cl_kernel_work_group_info, cl_device_id> for cl_kernel {
         // CL_INVALID_DEVICE [..] if device is NULL but there is more than one device associated with kernel.
         let dev = if dev.is_null() {
             if kernel.prog.devs.len() > 1 {
-                return Err(CL_INVALID_DEVICE);
+                return Err(CL_INVALID_DEVICE.into());
             } else {
                 kernel.prog.devs[0]
             }
@@ -94,7 +95,7 @@ impl CLInfoObj<cl_kernel_work_group_info, cl_device_id> for cl_kernel {
 
         // CL_INVALID_DEVICE if device is not in the list of devices associated with kernel
         if !kernel.prog.devs.contains(&dev) {
-            return Err(CL_INVALID_DEVICE);
+            return Err(CL_INVALID_DEVICE.into());
         }
 
         Ok(match *q {
@@ -106,7 +107,7 @@ impl CLInfoObj<cl_kernel_work_group_info, cl_device_id> for cl_kernel {
             CL_KERNEL_PRIVATE_MEM_SIZE => cl_prop::<cl_ulong>(kernel.priv_mem_size(dev)),
             CL_KERNEL_WORK_GROUP_SIZE => cl_prop::<usize>(kernel.max_threads_per_block(dev)),
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -130,7 +131,7 @@ impl CLInfoObj<cl_kernel_sub_group_info, (cl_device_id, usize, *const c_void, us
         // with kernel.
         let dev = if dev.is_null() {
             if kernel.prog.devs.len() > 1 {
-                return Err(CL_INVALID_DEVICE);
+                return Err(CL_INVALID_DEVICE.into());
             } else {
                 kernel.prog.devs[0]
             }
@@ -140,12 +141,12 @@ impl CLInfoObj<cl_kernel_sub_group_info, (cl_device_id, usize, *const c_void, us
 
         // CL_INVALID_DEVICE if device is not in the list of devices associated with kernel
         if !kernel.prog.devs.contains(&dev) {
-            return Err(CL_INVALID_DEVICE);
+            return Err(CL_INVALID_DEVICE.into());
         }
 
         // CL_INVALID_OPERATION if device does not support subgroups.
         if !dev.subgroups_supported() {
-            return Err(CL_INVALID_OPERATION);
+            return Err(CL_INVALID_OPERATION.into());
         }
 
         let usize_byte = mem::size_of::<usize>();
@@ -156,7 +157,7 @@ impl CLInfoObj<cl_kernel_sub_group_info, (cl_device_id, usize, *const c_void, us
                 // CL_KERNEL_SUB_GROUP_COUNT_FOR_NDRANGE or ... and the size in bytes specified by
                 // input_value_size is not valid or if input_value is NULL.
                 if ![usize_byte, 2 * usize_byte, 3 * usize_byte].contains(&input_value_size) {
-                    return Err(CL_INVALID_VALUE);
+                    return Err(CL_INVALID_VALUE.into());
                 }
                 // SAFETY: we verified the size as best as possible, with the rest we trust the client
                 unsafe { slice::from_raw_parts(input_value.cast(), input_value_size / usize_byte) }
@@ -166,7 +167,7 @@ impl CLInfoObj<cl_kernel_sub_group_info, (cl_device_id, usize, *const c_void, us
                 // and the size in bytes specified by input_value_size is not valid or if
                 // input_value is NULL.
                 if input_value_size != usize_byte || input_value.is_null() {
-                    return Err(CL_INVALID_VALUE);
+                    return Err(CL_INVALID_VALUE.into());
                 }
                 // SAFETY: we trust the client here
                 unsafe { slice::from_raw_parts(input_value.cast(), 1) }
@@ -218,7 +219,7 @@ impl CLInfoObj<cl_kernel_sub_group_info, (cl_device_id, usize, *const c_void, us
             CL_KERNEL_COMPILE_NUM_SUB_GROUPS => cl_prop::<usize>(kernel.num_subgroups()),
             CL_KERNEL_COMPILE_SUB_GROUP_SIZE_INTEL => cl_prop::<usize>(kernel.subgroup_size()),
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -257,25 +258,25 @@ fn create_kernel(
 
     // CL_INVALID_VALUE if kernel_name is NULL.
     if kernel_name.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let build = p.build_info();
     // CL_INVALID_PROGRAM_EXECUTABLE if there is no successfully built executable for program.
     if build.kernels().is_empty() {
-        return Err(CL_INVALID_PROGRAM_EXECUTABLE);
+        return Err(CL_INVALID_PROGRAM_EXECUTABLE.into());
     }
 
     // CL_INVALID_KERNEL_NAME if kernel_name is not found in program.
     if !build.kernels().contains(&name) {
-        return Err(CL_INVALID_KERNEL_NAME);
+        return Err(CL_INVALID_KERNEL_NAME.into());
     }
 
     // CL_INVALID_KERNEL_DEFINITION if the function definition for __kernel function given by
     // kernel_name such as the number of arguments, the argument types are not the same for all
     // devices for which the program executable has been built.
     if !p.has_unique_kernel_signatures(&name) {
-        return Err(CL_INVALID_KERNEL_DEFINITION);
+        return Err(CL_INVALID_KERNEL_DEFINITION.into());
     }
 
     Ok(Kernel::new(name, Arc::clone(&p), &build).into_cl())
@@ -304,13 +305,13 @@ fn create_kernels_in_program(
     // CL_INVALID_PROGRAM_EXECUTABLE if there is no successfully built executable for any device in
     // program.
     if build.kernels().is_empty() {
-        return Err(CL_INVALID_PROGRAM_EXECUTABLE);
+        return Err(CL_INVALID_PROGRAM_EXECUTABLE.into());
     }
 
     // CL_INVALID_VALUE if kernels is not NULL and num_kernels is less than the number of kernels
     // in program.
     if !kernels.is_null() && build.kernels().len() > num_kernels as usize {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let mut num_kernels = 0;
@@ -355,7 +356,7 @@ fn set_kernel_arg(
         match arg.kind {
             KernelArgType::MemLocal => {
                 if arg_size == 0 {
-                    return Err(CL_INVALID_ARG_SIZE);
+                    return Err(CL_INVALID_ARG_SIZE.into());
                 }
             }
             KernelArgType::MemGlobal
@@ -364,12 +365,12 @@ fn set_kernel_arg(
             | KernelArgType::RWImage
             | KernelArgType::Texture => {
                 if arg_size != std::mem::size_of::<cl_mem>() {
-                    return Err(CL_INVALID_ARG_SIZE);
+                    return Err(CL_INVALID_ARG_SIZE.into());
                 }
             }
             _ => {
                 if arg.size != arg_size {
-                    return Err(CL_INVALID_ARG_SIZE);
+                    return Err(CL_INVALID_ARG_SIZE.into());
                 }
             }
         }
@@ -380,14 +381,14 @@ fn set_kernel_arg(
             // NULL.
             KernelArgType::MemLocal => {
                 if !arg_value.is_null() {
-                    return Err(CL_INVALID_ARG_VALUE);
+                    return Err(CL_INVALID_ARG_VALUE.into());
                 }
             }
             // If the argument is of type sampler_t, the arg_value entry must be a pointer to the
             // sampler object.
             KernelArgType::Constant | KernelArgType::Sampler => {
                 if arg_value.is_null() {
-                    return Err(CL_INVALID_ARG_VALUE);
+                    return Err(CL_INVALID_ARG_VALUE.into());
                 }
             }
             _ => {}
@@ -424,7 +425,7 @@ fn set_kernel_arg(
         };
         k.set_kernel_arg(arg_index, arg)
     } else {
-        Err(CL_INVALID_ARG_INDEX)
+        Err(CL_INVALID_ARG_INDEX.into())
     }
 
     //• CL_INVALID_DEVICE_QUEUE for an argument declared to be of type queue_t when the specified arg_value is not a valid device queue object. This error code is missing before version 2.0.
@@ -443,7 +444,7 @@ fn set_kernel_arg_svm_pointer(
     let arg_value = arg_value as usize;
 
     if !kernel.has_svm_devs() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     if let Some(arg) = kernel.kernel_info.args.get(arg_index) {
@@ -451,18 +452,28 @@ fn set_kernel_arg_svm_pointer(
             arg.kind,
             KernelArgType::MemConstant | KernelArgType::MemGlobal
         ) {
-            return Err(CL_INVALID_ARG_INDEX);
+            return Err(CL_INVALID_ARG_INDEX.into());
         }
 
         let arg_value = KernelArgValue::Constant(arg_value.to_ne_bytes().to_vec());
         kernel.set_kernel_arg(arg_index, arg_value)
     } else {
-        Err(CL_INVALID_ARG_INDEX)
+        Err(CL_INVALID_ARG_INDEX.into())
     }
 
     // CL_INVALID_ARG_VALUE if arg_value specified is not a valid value.
 }
 
+#[cl_entrypoint(clSetKernelArgMemPointerINTEL)]
+fn set_kernel_arg_mem_pointer(
+    kernel: cl_kernel,
+    arg_index: cl_uint,
+    arg_value: *const ::std::os::raw::c_void,
+) -> CLResult<()> {
+    // USM pointers are system pointers just like SVM ones.
+    set_kernel_arg_svm_pointer(kernel, arg_index, arg_value)
+}
+
 #[cl_entrypoint(clSetKernelExecInfo)]
 fn set_kernel_exec_info(
     kernel: cl_kernel,
@@ -474,30 +485,35 @@ fn set_kernel_exec_info(
 
     // CL_INVALID_OPERATION if no devices in the context associated with kernel support SVM.
     if !k.prog.devs.iter().any(|dev| dev.svm_supported()) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_VALUE ... if param_value is NULL
     if param_value.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE ... if the size specified by param_value_size is not valid.
     match param_name {
-        CL_KERNEL_EXEC_INFO_SVM_PTRS | CL_KERNEL_EXEC_INFO_SVM_PTRS_ARM => {
+        CL_KERNEL_EXEC_INFO_SVM_PTRS
+        | CL_KERNEL_EXEC_INFO_SVM_PTRS_ARM
+        | CL_KERNEL_EXEC_INFO_USM_PTRS_INTEL => {
             // it's a list of pointers
             if param_value_size % mem::size_of::<*const c_void>() != 0 {
-                return Err(CL_INVALID_VALUE);
+                return Err(CL_INVALID_VALUE.into());
             }
         }
         CL_KERNEL_EXEC_INFO_SVM_FINE_GRAIN_SYSTEM
-        | CL_KERNEL_EXEC_INFO_SVM_FINE_GRAIN_SYSTEM_ARM => {
+        | CL_KERNEL_EXEC_INFO_SVM_FINE_GRAIN_SYSTEM_ARM
+        | CL_KERNEL_EXEC_INFO_INDIRECT_HOST_ACCESS_INTEL
+        | CL_KERNEL_EXEC_INFO_INDIRECT_DEVICE_ACCESS_INTEL
+        | CL_KERNEL_EXEC_INFO_INDIRECT_SHARED_ACCESS_INTEL => {
             if param_value_size != mem::size_of::<cl_bool>() {
-                return Err(CL_INVALID_VALUE);
+                return Err(CL_INVALID_VALUE.into());
             }
         }
         // CL_INVALID_VALUE if param_name is not valid
-        _ => return Err(CL_INVALID_VALUE),
+        _ => return Err(CL_INVALID_VALUE.into()),
     }
 
     Ok(())
@@ -505,42 +521,38 @@ fn set_kernel_exec_info(
     // CL_INVALID_OPERATION if param_name is CL_KERNEL_EXEC_INFO_SVM_FINE_GRAIN_SYSTEM and param_value is CL_TRUE but no devices in context associated with kernel support fine-grain system SVM allocations.
 }
 
-#[cl_entrypoint(clEnqueueNDRangeKernel)]
-fn enqueue_ndrange_kernel(
-    command_queue: cl_command_queue,
+/// Validates a kernel launch on `q` and returns its work, which is shared between
+/// clEnqueueNDRangeKernel and command buffers.
+pub fn ndrange_kernel_cmd(
+    q: &Arc<Queue>,
     kernel: cl_kernel,
     work_dim: cl_uint,
     global_work_offset: *const usize,
     global_work_size: *const usize,
     local_work_size: *const usize,
-    num_events_in_wait_list: cl_uint,
-    event_wait_list: *const cl_event,
-    event: *mut cl_event,
-) -> CLResult<()> {
-    let q = Queue::arc_from_raw(command_queue)?;
+) -> CLResult<CommandSig> {
     let k = Kernel::arc_from_raw(kernel)?;
-    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_CONTEXT if context associated with command_queue and kernel are not the same
     if q.context != k.prog.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_PROGRAM_EXECUTABLE if there is no successfully built program executable available
     // for device associated with command_queue.
     if k.prog.status(q.device) != CL_BUILD_SUCCESS as cl_build_status {
-        return Err(CL_INVALID_PROGRAM_EXECUTABLE);
+        return Err(CL_INVALID_PROGRAM_EXECUTABLE.into());
     }
 
     // CL_INVALID_KERNEL_ARGS if the kernel argument values have not been specified.
     if k.arg_values().iter().any(|v| v.is_none()) {
-        return Err(CL_INVALID_KERNEL_ARGS);
+        return Err(CL_INVALID_KERNEL_ARGS.into());
     }
 
     // CL_INVALID_WORK_DIMENSION if work_dim is not a valid value (i.e. a value between 1 and
     // CL_DEVICE_MAX_WORK_ITEM_DIMENSIONS).
     if work_dim == 0 || work_dim > q.device.max_grid_dimensions() {
-        return Err(CL_INVALID_WORK_DIMENSION);
+        return Err(CL_INVALID_WORK_DIMENSION.into());
     }
 
     // we assume the application gets it right and doesn't pass shorter arrays then actually needed.
@@ -564,27 +576,27 @@ fn enqueue_ndrange_kernel(
         // values specified by
         // CL_DEVICE_MAX_WORK_ITEM_SIZES[0], …, CL_DEVICE_MAX_WORK_ITEM_SIZES[work_dim - 1].
         if lws > q.device.max_block_sizes()[i] {
-            return Err(CL_INVALID_WORK_ITEM_SIZE);
+            return Err(CL_INVALID_WORK_ITEM_SIZE.into());
         }
 
         // CL_INVALID_WORK_GROUP_SIZE if the work-group size must be uniform and the
         // local_work_size is not NULL, [...] if the global_work_size is not evenly divisible by
         // the local_work_size.
         if lws != 0 && gws % lws != 0 {
-            return Err(CL_INVALID_WORK_GROUP_SIZE);
+            return Err(CL_INVALID_WORK_GROUP_SIZE.into());
         }
 
         // CL_INVALID_WORK_GROUP_SIZE if local_work_size is specified and does not match the
         // required work-group size for kernel in the program source.
         if lws != 0 && k.work_group_size()[i] != 0 && lws != k.work_group_size()[i] {
-            return Err(CL_INVALID_WORK_GROUP_SIZE);
+            return Err(CL_INVALID_WORK_GROUP_SIZE.into());
         }
 
         // CL_INVALID_GLOBAL_WORK_SIZE if any of the values specified in global_work_size[0], …
         // global_work_size[work_dim - 1] exceed the maximum value representable by size_t on
         // the device on which the kernel-instance will be enqueued.
         if gws as u64 > device_max {
-            return Err(CL_INVALID_GLOBAL_WORK_SIZE);
+            return Err(CL_INVALID_GLOBAL_WORK_SIZE.into());
         }
 
         // CL_INVALID_GLOBAL_OFFSET if the value specified in global_work_size + the
@@ -595,7 +607,7 @@ fn enqueue_ndrange_kernel(
             .filter(|&x| x <= device_max)
             .is_none()
         {
-            return Err(CL_INVALID_GLOBAL_OFFSET);
+            return Err(CL_INVALID_GLOBAL_OFFSET.into());
         }
     }
 
@@ -604,25 +616,82 @@ fn enqueue_ndrange_kernel(
     // than the value specified by CL_KERNEL_WORK_GROUP_SIZE in the Kernel Object Device Queries
     // table.
     if threads != 0 && threads > k.max_threads_per_block(q.device) {
-        return Err(CL_INVALID_WORK_GROUP_SIZE);
+        return Err(CL_INVALID_WORK_GROUP_SIZE.into());
+    }
+
+    // we don't know which arguments the kernel writes to, so only catch mappings for writing and
+    // treat all memory objects as written afterwards.
+    if strict::enabled() {
+        let arg_values = k.arg_values();
+        let buffers: Vec<&Buffer> = arg_values
+            .iter()
+            .flatten()
+            .filter_map(|val| match val {
+                KernelArgValue::Buffer(buffer) => Some(buffer.as_ref()),
+                _ => None,
+            })
+            .collect();
+        strict::check_overlapping_buffers(&buffers, "clEnqueueNDRangeKernel")?;
+
+        for val in arg_values.iter().flatten() {
+            let mem: &MemBase = match val {
+                KernelArgValue::Buffer(buffer) => buffer,
+                KernelArgValue::Image(image) => image,
+                _ => continue,
+            };
+
+            strict::check_unmapped(mem, "clEnqueueNDRangeKernel", false)?;
+            strict::mark_written(mem);
+        }
     }
 
     // If global_work_size is NULL, or the value in any passed dimension is 0 then the kernel
     // command will trivially succeed after its event dependencies are satisfied and subsequently
     // update its completion event.
-    let cb: EventSig = if global_work_size.contains(&0) {
-        Box::new(|_, _| Ok(()))
+    if global_work_size.contains(&0) {
+        Ok(Box::new(|_, _| Ok(())))
     } else {
         k.launch(
-            &q,
+            q,
             work_dim,
             local_work_size,
             global_work_size,
             global_work_offset,
-        )?
-    };
+        )
+    }
+}
 
-    create_and_queue(q, CL_COMMAND_NDRANGE_KERNEL, evs, event, false, cb)
+#[cl_entrypoint(clEnqueueNDRangeKernel)]
+fn enqueue_ndrange_kernel(
+    command_queue: cl_command_queue,
+    kernel: cl_kernel,
+    work_dim: cl_uint,
+    global_work_offset: *const usize,
+    global_work_size: *const usize,
+    local_work_size: *const usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = ndrange_kernel_cmd(
+        &q,
+        kernel,
+        work_dim,
+        global_work_offset,
+        global_work_size,
+        local_work_size,
+    )?;
+
+    create_and_queue(
+        q,
+        CL_COMMAND_NDRANGE_KERNEL,
+        evs,
+        event,
+        false,
+        Box::new(cmd),
+    )
 
     //• CL_INVALID_WORK_GROUP_SIZE if local_work_size is specified and is not consistent with the required number of sub-groups for kernel in the program source.
     //• CL_MISALIGNED_SUB_BUFFER_OFFSET if a sub-buffer object is specified as the value for an argument that is a buffer object and the offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue. This error code
@@ -675,16 +744,16 @@ fn get_kernel_suggested_local_work_size_khr(
     // CL_INVALID_GLOBAL_WORK_SIZE if global_work_size is NULL or if any of the values specified in
     // global_work_size are 0.
     if global_work_size.is_null() {
-        return Err(CL_INVALID_GLOBAL_WORK_SIZE);
+        return Err(CL_INVALID_GLOBAL_WORK_SIZE.into());
     }
 
     if global_work_offset.is_null() {
-        return Err(CL_INVALID_GLOBAL_OFFSET);
+        return Err(CL_INVALID_GLOBAL_OFFSET.into());
     }
 
     // CL_INVALID_VALUE if suggested_local_work_size is NULL.
     if suggested_local_work_size.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_COMMAND_QUEUE if command_queue is not a valid host command-queue.
@@ -696,24 +765,24 @@ fn get_kernel_suggested_local_work_size_khr(
     // CL_INVALID_CONTEXT if the context associated with kernel is not the same as the context
     // associated with command_queue.
     if queue.context != kernel.prog.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_PROGRAM_EXECUTABLE if there is no successfully built program executable available
     // for kernel for the device associated with command_queue.
     if kernel.prog.status(queue.device) != CL_BUILD_SUCCESS as cl_build_status {
-        return Err(CL_INVALID_PROGRAM_EXECUTABLE);
+        return Err(CL_INVALID_PROGRAM_EXECUTABLE.into());
     }
 
     // CL_INVALID_KERNEL_ARGS if all argument values for kernel have not been set.
     if kernel.arg_values().iter().any(|v| v.is_none()) {
-        return Err(CL_INVALID_KERNEL_ARGS);
+        return Err(CL_INVALID_KERNEL_ARGS.into());
     }
 
     // CL_INVALID_WORK_DIMENSION if work_dim is not a valid value (i.e. a value between 1 and
     // CL_DEVICE_MAX_WORK_ITEM_DIMENSIONS).
     if work_dim == 0 || work_dim > queue.device.max_grid_dimensions() {
-        return Err(CL_INVALID_WORK_DIMENSION);
+        return Err(CL_INVALID_WORK_DIMENSION.into());
     }
 
     let mut global_work_size =
@@ -734,12 +803,12 @@ fn get_kernel_suggested_local_work_size_khr(
         // CL_INVALID_GLOBAL_WORK_SIZE if global_work_size is NULL or if any of the values specified
         // in global_work_size are 0.
         if gws == 0 {
-            return Err(CL_INVALID_GLOBAL_WORK_SIZE);
+            return Err(CL_INVALID_GLOBAL_WORK_SIZE.into());
         }
         // CL_INVALID_GLOBAL_WORK_SIZE if any of the values specified in global_work_size exceed the
         // maximum value representable by size_t on the device associated with command_queue.
         if gws as u64 > device_max {
-            return Err(CL_INVALID_GLOBAL_WORK_SIZE);
+            return Err(CL_INVALID_GLOBAL_WORK_SIZE.into());
         }
         // CL_INVALID_GLOBAL_OFFSET if the value specified in global_work_size plus the
         // corresponding value in global_work_offset for dimension exceeds the maximum value
@@ -748,7 +817,7 @@ fn get_kernel_suggested_local_work_size_khr(
             .filter(|&x| x <= device_max)
             .is_none()
         {
-            return Err(CL_INVALID_GLOBAL_OFFSET);
+            return Err(CL_INVALID_GLOBAL_OFFSET.into());
         }
     }
 
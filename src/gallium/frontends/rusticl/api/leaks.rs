@@ -0,0 +1,156 @@
+//! Tracking of live API objects, so leaks can be reported when the application exits or releases
+//! its last context. Enabled in debug builds and through `RUSTICL_DEBUG=leaks`, which also
+//! captures a backtrace for every created object.
+
+use crate::api::icd::RusticlTypes;
+use crate::core::context::Context;
+use crate::core::platform::*;
+
+use libc_rust_gen::atexit;
+use mesa_rust_util::log_warn;
+
+use std::backtrace::Backtrace;
+use std::backtrace::BacktraceStatus;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+use std::sync::Mutex;
+use std::sync::Once;
+use std::sync::Weak;
+
+struct LiveObject {
+    ty: RusticlTypes,
+    backtrace: Backtrace,
+}
+
+// ordered by id, so the report lists objects in creation order
+static LIVE_OBJECTS: Mutex<BTreeMap<u64, LiveObject>> = Mutex::new(BTreeMap::new());
+static NEXT_ID: AtomicU64 = AtomicU64::new(1);
+static REPORT_AT_EXIT: Once = Once::new();
+
+fn enabled() -> bool {
+    cfg!(debug_assertions) || Platform::dbg().leaks
+}
+
+fn type_name(ty: RusticlTypes) -> &'static str {
+    match ty {
+        RusticlTypes::Buffer => "buffer",
+        RusticlTypes::Context => "context",
+        RusticlTypes::Device => "device",
+        RusticlTypes::Event => "event",
+        RusticlTypes::Image => "image",
+        RusticlTypes::Kernel => "kernel",
+        RusticlTypes::Program => "program",
+        RusticlTypes::Queue => "queue",
+        RusticlTypes::Sampler => "sampler",
+        RusticlTypes::Semaphore => "semaphore",
+        RusticlTypes::CommandBuffer => "command buffer",
+    }
+}
+
+/// Formats the report, returns `None` if nothing leaked.
+fn report(objects: &BTreeMap<u64, LiveObject>, reason: &str) -> Option<String> {
+    if objects.is_empty() {
+        return None;
+    }
+
+    let mut counts = BTreeMap::new();
+    for obj in objects.values() {
+        *counts.entry(type_name(obj.ty)).or_insert(0) += 1;
+    }
+
+    let mut res = format!("{} API object(s) still alive {reason}:", objects.len());
+    for (name, cnt) in counts {
+        let _ = write!(res, "\n  {name}: {cnt}");
+    }
+
+    for (id, obj) in objects {
+        if obj.backtrace.status() == BacktraceStatus::Captured {
+            let _ = write!(
+                res,
+                "\n{} #{id} created at:\n{}",
+                type_name(obj.ty),
+                obj.backtrace
+            );
+        }
+    }
+
+    Some(res)
+}
+
+extern "C" fn report_at_exit() {
+    // don't panic while the process is going down, even if some thread poisoned the lock.
+    let objects = match LIVE_OBJECTS.lock() {
+        Ok(objects) => objects,
+        Err(err) => err.into_inner(),
+    };
+
+    if let Some(report) = report(&objects, "at exit") {
+        log_warn!("{report}");
+    }
+}
+
+/// Registers a newly created API object, returns the id to pass into [untrack] once the object
+/// gets destroyed.
+pub fn track(ty: RusticlTypes) -> u64 {
+    // devices live as long as the platform and are never destroyed
+    if ty == RusticlTypes::Device || !enabled() {
+        return 0;
+    }
+
+    REPORT_AT_EXIT.call_once(|| {
+        // SAFETY: report_at_exit doesn't depend on any state which gets destroyed before atexit
+        //         handlers are run.
+        unsafe {
+            atexit(Some(report_at_exit));
+        }
+    });
+
+    // capturing backtraces is expensive, so debug builds only do it if requested through the
+    // usual RUST_BACKTRACE environment variable.
+    let backtrace = if Platform::dbg().leaks {
+        Backtrace::force_capture()
+    } else {
+        Backtrace::capture()
+    };
+
+    let id = NEXT_ID.fetch_add(1, Ordering::Relaxed);
+    LIVE_OBJECTS.lock().unwrap().insert(
+        id,
+        LiveObject {
+            ty: ty,
+            backtrace: backtrace,
+        },
+    );
+    id
+}
+
+pub fn untrack(id: u64) {
+    if id != 0 {
+        LIVE_OBJECTS.lock().unwrap().remove(&id);
+    }
+}
+
+/// Called after the application released a context. If it was the last one, but it's still
+/// alive, objects the application never released are keeping it alive.
+pub fn context_released(ctx: &Weak<Context>) {
+    if !enabled() || ctx.strong_count() == 0 {
+        return;
+    }
+
+    let objects = LIVE_OBJECTS.lock().unwrap();
+    let contexts = objects
+        .values()
+        .filter(|obj| obj.ty == RusticlTypes::Context)
+        .count();
+    if contexts > 1 {
+        return;
+    }
+
+    let report = report(&objects, "after the last context was released");
+    drop(objects);
+    if let Some(report) = report {
+        log_warn!("{report}");
+    }
+}
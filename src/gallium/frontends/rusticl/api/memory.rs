@@ -1,18 +1,26 @@
 #![allow(non_upper_case_globals)]
 
 use crate::api::event::create_and_queue;
+use crate::api::event::create_and_queue_with_host_access;
 use crate::api::icd::*;
+use crate::api::strict;
 use crate::api::types::*;
 use crate::api::util::*;
 use crate::core::context::Context;
+use crate::core::context::UsmAlloc;
 use crate::core::device::*;
+use crate::core::event::CommandSig;
 use crate::core::event::EventSig;
+use crate::core::event::HostAccess;
 use crate::core::format::*;
 use crate::core::gl::*;
 use crate::core::memory::*;
+use crate::core::platform::*;
 use crate::core::queue::*;
 
+use mesa_rust_util::flags::Flags;
 use mesa_rust_util::properties::Properties;
+use mesa_rust_util::properties::PropertyReader;
 use mesa_rust_util::ptr::*;
 use mesa_rust_util::static_assert;
 use rusticl_opencl_gen::*;
@@ -24,18 +32,21 @@ use std::alloc::Layout;
 use std::cmp;
 use std::cmp::Ordering;
 use std::mem::{self, MaybeUninit};
+use std::os::raw::c_int;
 use std::os::raw::c_void;
 use std::ptr;
 use std::slice;
 use std::sync::Arc;
 
 fn validate_mem_flags(flags: cl_mem_flags, images: bool) -> CLResult<()> {
-    let mut valid_flags = cl_bitfield::from(
+    let flags = Flags::<cl_mem_flags>::new(flags);
+    let mut valid_flags = Flags::<cl_mem_flags>::new(
         CL_MEM_READ_WRITE | CL_MEM_WRITE_ONLY | CL_MEM_READ_ONLY | CL_MEM_KERNEL_READ_AND_WRITE,
     );
+    valid_flags.insert(CL_MEM_PROTECTED_ALLOC_ARM);
 
     if !images {
-        valid_flags |= cl_bitfield::from(
+        valid_flags.insert(
             CL_MEM_USE_HOST_PTR
                 | CL_MEM_ALLOC_HOST_PTR
                 | CL_MEM_COPY_HOST_PTR
@@ -45,38 +56,54 @@ fn validate_mem_flags(flags: cl_mem_flags, images: bool) -> CLResult<()> {
         );
     }
 
-    let read_write_group =
-        cl_bitfield::from(CL_MEM_READ_WRITE | CL_MEM_WRITE_ONLY | CL_MEM_READ_ONLY);
-
-    let alloc_host_group = cl_bitfield::from(CL_MEM_ALLOC_HOST_PTR | CL_MEM_USE_HOST_PTR);
+    let read_write_group = CL_MEM_READ_WRITE | CL_MEM_WRITE_ONLY | CL_MEM_READ_ONLY;
+    let alloc_host_group = CL_MEM_ALLOC_HOST_PTR | CL_MEM_USE_HOST_PTR;
+    let copy_host_group = CL_MEM_COPY_HOST_PTR | CL_MEM_USE_HOST_PTR;
+    let host_read_write_group =
+        CL_MEM_HOST_WRITE_ONLY | CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_NO_ACCESS;
 
-    let copy_host_group = cl_bitfield::from(CL_MEM_COPY_HOST_PTR | CL_MEM_USE_HOST_PTR);
+    if !flags.is_subset_of(valid_flags.bits())
+        || flags.count(read_write_group) > 1
+        || flags.count(alloc_host_group) > 1
+        || flags.count(copy_host_group) > 1
+        || flags.count(host_read_write_group) > 1
+    {
+        return Err(CL_INVALID_VALUE.into());
+    }
+    Ok(())
+}
 
-    let host_read_write_group =
-        cl_bitfield::from(CL_MEM_HOST_WRITE_ONLY | CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_NO_ACCESS);
+/// Validates the use of `CL_MEM_PROTECTED_ALLOC_ARM` for memory objects allocated by us.
+fn validate_protected_flags(c: &Context, flags: cl_mem_flags) -> CLResult<()> {
+    if !bit_check(flags, CL_MEM_PROTECTED_ALLOC_ARM) {
+        return Ok(());
+    }
 
-    if (flags & !valid_flags != 0)
-        || (flags & read_write_group).count_ones() > 1
-        || (flags & alloc_host_group).count_ones() > 1
-        || (flags & copy_host_group).count_ones() > 1
-        || (flags & host_read_write_group).count_ones() > 1
+    // The content of protected memory can neither be initialized from nor migrated through the
+    // host, so it's only supported in single device contexts and without host pointers.
+    if c.devs.len() > 1
+        || !c.devs[0].protected_memory_supported()
+        || bit_check(
+            flags,
+            CL_MEM_USE_HOST_PTR | CL_MEM_ALLOC_HOST_PTR | CL_MEM_COPY_HOST_PTR,
+        )
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
+
     Ok(())
 }
 
 fn validate_map_flags_common(map_flags: cl_mem_flags) -> CLResult<()> {
     // CL_INVALID_VALUE ... if values specified in map_flags are not valid.
-    let valid_flags =
-        cl_bitfield::from(CL_MAP_READ | CL_MAP_WRITE | CL_MAP_WRITE_INVALIDATE_REGION);
-    let read_write_group = cl_bitfield::from(CL_MAP_READ | CL_MAP_WRITE);
-    let invalidate_group = cl_bitfield::from(CL_MAP_WRITE_INVALIDATE_REGION);
+    let map_flags = Flags::<cl_map_flags>::new(map_flags);
+    let valid_flags = CL_MAP_READ | CL_MAP_WRITE | CL_MAP_WRITE_INVALIDATE_REGION;
 
-    if (map_flags & !valid_flags != 0)
-        || ((map_flags & read_write_group != 0) && (map_flags & invalidate_group != 0))
+    if !map_flags.is_subset_of(valid_flags)
+        || map_flags.intersects(CL_MAP_READ | CL_MAP_WRITE)
+            && map_flags.intersects(CL_MAP_WRITE_INVALIDATE_REGION)
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     Ok(())
@@ -94,7 +121,12 @@ fn validate_map_flags(m: &MemBase, map_flags: cl_mem_flags) -> CLResult<()> {
       bit_check(m.flags, CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_NO_ACCESS) &&
       bit_check(map_flags, CL_MAP_WRITE | CL_MAP_WRITE_INVALIDATE_REGION)
     {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    // Protected memory can't be accessed by the host at all.
+    if bit_check(m.flags, CL_MEM_PROTECTED_ALLOC_ARM) {
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     Ok(())
@@ -142,6 +174,9 @@ fn inherit_mem_flags(mut flags: cl_mem_flags, mem: &MemBase) -> cl_mem_flags {
         flags |= mem.flags & host_mask;
     }
 
+    // memory created from protected memory is protected as well
+    flags |= mem.flags & CL_MEM_PROTECTED_ALLOC_ARM;
+
     flags
 }
 
@@ -156,14 +191,14 @@ fn validate_addressing_mode(addressing_mode: cl_addressing_mode) -> CLResult<()>
         | CL_ADDRESS_CLAMP
         | CL_ADDRESS_REPEAT
         | CL_ADDRESS_MIRRORED_REPEAT => Ok(()),
-        _ => Err(CL_INVALID_VALUE),
+        _ => Err(CL_INVALID_VALUE.into()),
     }
 }
 
 fn validate_filter_mode(filter_mode: cl_filter_mode) -> CLResult<()> {
     match filter_mode {
         CL_FILTER_NEAREST | CL_FILTER_LINEAR => Ok(()),
-        _ => Err(CL_INVALID_VALUE),
+        _ => Err(CL_INVALID_VALUE.into()),
     }
 }
 
@@ -173,7 +208,7 @@ fn validate_host_ptr(host_ptr: *mut ::std::os::raw::c_void, flags: cl_mem_flags)
     if host_ptr.is_null()
         && flags & (cl_mem_flags::from(CL_MEM_USE_HOST_PTR | CL_MEM_COPY_HOST_PTR)) != 0
     {
-        return Err(CL_INVALID_HOST_PTR);
+        return Err(CL_INVALID_HOST_PTR.into());
     }
 
     // or if host_ptr is not NULL but CL_MEM_COPY_HOST_PTR or CL_MEM_USE_HOST_PTR are not set in
@@ -181,7 +216,7 @@ fn validate_host_ptr(host_ptr: *mut ::std::os::raw::c_void, flags: cl_mem_flags)
     if !host_ptr.is_null()
         && flags & (cl_mem_flags::from(CL_MEM_USE_HOST_PTR | CL_MEM_COPY_HOST_PTR)) == 0
     {
-        return Err(CL_INVALID_HOST_PTR);
+        return Err(CL_INVALID_HOST_PTR.into());
     }
 
     Ok(())
@@ -207,7 +242,7 @@ fn validate_matching_buffer_flags(mem: &MemBase, flags: cl_mem_flags) -> CLResul
       // or if mem_object was created with CL_MEM_HOST_NO_ACCESS and_flags_ specifies CL_MEM_HOST_READ_ONLY or CL_MEM_HOST_WRITE_ONLY.
       bit_check(mem.flags, CL_MEM_HOST_NO_ACCESS) && bit_check(flags, CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_WRITE_ONLY)
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     Ok(())
@@ -252,11 +287,88 @@ impl CLInfo<cl_mem_info> for cl_mem {
             CL_MEM_USES_SVM_POINTER | CL_MEM_USES_SVM_POINTER_ARM => {
                 cl_prop::<cl_bool>(mem.is_svm().into())
             }
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
 
+/// Properties passed into clCreateBufferWithProperties and clCreateImageWithProperties.
+#[derive(Default)]
+struct MemProps {
+    /// The properties as passed in, reported back through `CL_MEM_PROPERTIES`.
+    raw: Vec<cl_mem_properties>,
+    dma_buf: Option<i32>,
+    devs: Option<Vec<&'static Device>>,
+}
+
+impl MemProps {
+    fn from_ptr(c: &Context, p: *const cl_mem_properties) -> CLResult<Self> {
+        let mut res = Self::default();
+        // SAFETY: the list is required to be terminated
+        let mut reader = unsafe { PropertyReader::new(p) };
+
+        while let Some(key) = reader.key() {
+            // CL_INVALID_PROPERTY if a property name in properties is not a supported property
+            // name, if the value specified for a supported property name is not valid, or if the
+            // same property name is specified more than once.
+            match u32::try_from(key) {
+                Ok(CL_EXTERNAL_MEMORY_HANDLE_DMA_BUF_KHR) if res.dma_buf.is_none() => {
+                    res.dma_buf = Some(reader.value() as i32);
+                }
+                Ok(CL_DEVICE_HANDLE_LIST_KHR) if res.devs.is_none() => {
+                    let list = reader.list(CL_DEVICE_HANDLE_LIST_END_KHR.into());
+                    res.devs = Some(devs_from_handle_list(c, &list)?);
+                }
+                _ => return Err(CL_INVALID_PROPERTY.into()),
+            }
+        }
+
+        res.raw = reader.raw();
+        Ok(res)
+    }
+
+    /// Validates the memory to import, if there is any.
+    fn external(
+        &self,
+        c: &Context,
+        flags: cl_mem_flags,
+        host_ptr: *mut c_void,
+    ) -> CLResult<Option<ExternalMemory>> {
+        let Some(fd) = self.dma_buf else {
+            // a device list is only valid together with an external memory handle
+            if self.devs.is_some() {
+                return Err(CL_INVALID_PROPERTY.into());
+            }
+            return Ok(None);
+        };
+
+        // without a device list the memory gets imported on all devices of the context
+        let devs = self.devs.clone().unwrap_or_else(|| c.devs.clone());
+        if devs.is_empty() || devs.iter().any(|d| !d.dma_buf_import_supported()) {
+            return Err(CL_INVALID_PROPERTY.into());
+        }
+
+        // imported memory is backed by the external memory and nothing else.
+        if bit_check(
+            flags,
+            CL_MEM_USE_HOST_PTR | CL_MEM_ALLOC_HOST_PTR | CL_MEM_COPY_HOST_PTR,
+        ) || bit_check(flags, CL_MEM_PROTECTED_ALLOC_ARM)
+        {
+            return Err(CL_INVALID_VALUE.into());
+        }
+
+        if !host_ptr.is_null() {
+            return Err(CL_INVALID_HOST_PTR.into());
+        }
+
+        Ok(Some(ExternalMemory {
+            handle_type: CL_EXTERNAL_MEMORY_HANDLE_DMA_BUF_KHR,
+            handle: fd,
+            devs: devs,
+        }))
+    }
+}
+
 #[cl_entrypoint(clCreateBufferWithProperties)]
 fn create_buffer_with_properties(
     context: cl_context,
@@ -272,12 +384,12 @@ fn create_buffer_with_properties(
 
     // CL_INVALID_BUFFER_SIZE if size is 0
     if size == 0 {
-        return Err(CL_INVALID_BUFFER_SIZE);
+        return Err(CL_INVALID_BUFFER_SIZE.into());
     }
 
     // ... or if size is greater than CL_DEVICE_MAX_MEM_ALLOC_SIZE for all devices in context,
     if checked_compare(size, Ordering::Greater, c.max_mem_alloc()) {
-        return Err(CL_INVALID_BUFFER_SIZE);
+        return Err(CL_INVALID_BUFFER_SIZE.into());
     }
 
     validate_host_ptr(host_ptr, flags)?;
@@ -291,20 +403,16 @@ fn create_buffer_with_properties(
 
         // technically we don't have to account for the offset, but it's almost for free.
         if size > svm_layout.size() - diff {
-            return Err(CL_INVALID_BUFFER_SIZE);
+            return Err(CL_INVALID_BUFFER_SIZE.into());
         }
     }
 
-    let props = Properties::from_ptr_raw(properties);
-    // CL_INVALID_PROPERTY if a property name in properties is not a supported property name, if
-    // the value specified for a supported property name is not valid, or if the same property name
-    // is specified more than once.
-    if props.len() > 1 {
-        // we don't support any properties besides the 0 property
-        return Err(CL_INVALID_PROPERTY);
-    }
+    validate_protected_flags(&c, flags)?;
+
+    let props = MemProps::from_ptr(&c, properties)?;
+    let external = props.external(&c, flags, host_ptr)?;
 
-    Ok(MemBase::new_buffer(c, flags, size, host_ptr, props)?.into_cl())
+    Ok(MemBase::new_buffer(c, flags, size, host_ptr, props.raw, external)?.into_cl())
 }
 
 #[cl_entrypoint(clCreateBuffer)]
@@ -328,7 +436,7 @@ fn create_sub_buffer(
 
     // CL_INVALID_MEM_OBJECT if buffer ... is a sub-buffer object.
     if b.parent.is_some() {
-        return Err(CL_INVALID_MEM_OBJECT);
+        return Err(CL_INVALID_MEM_OBJECT.into());
     }
 
     validate_matching_buffer_flags(&b, flags)?;
@@ -348,25 +456,36 @@ fn create_sub_buffer(
             // CL_INVALID_BUFFER_SIZE if the size field of the cl_buffer_region structure passed in
             // buffer_create_info is 0.
             if region.size == 0 {
-                return Err(CL_INVALID_BUFFER_SIZE);
+                return Err(CL_INVALID_BUFFER_SIZE.into());
             }
 
             // CL_INVALID_VALUE if the region specified by the cl_buffer_region structure passed in
             // buffer_create_info is out of bounds in buffer.
-            if region.origin + region.size > b.size {
-                return Err(CL_INVALID_VALUE);
+            if region
+                .origin
+                .checked_add(region.size)
+                .map_or(true, |end| end > b.size)
+            {
+                return Err(CL_INVALID_VALUE.into());
+            }
+
+            // CL_MISALIGNED_SUB_BUFFER_OFFSET if there are no devices in context associated with
+            // buffer for which the origin field of the cl_buffer_region structure passed in
+            // buffer_create_info is aligned to the CL_DEVICE_MEM_BASE_ADDR_ALIGN value.
+            if !b.context.devs.iter().any(|dev| {
+                let align = dev.mem_base_addr_align() as usize / 8;
+                region.origin % align == 0
+            }) {
+                return Err(CL_MISALIGNED_SUB_BUFFER_OFFSET.into());
             }
 
             (region.origin, region.size)
         }
         // CL_INVALID_VALUE if the value specified in buffer_create_type is not valid.
-        _ => return Err(CL_INVALID_VALUE),
+        _ => return Err(CL_INVALID_VALUE.into()),
     };
 
     Ok(MemBase::new_sub_buffer(b, flags, offset, size).into_cl())
-
-    // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if there are no devices in context associated with buffer for which the origin field of the cl_buffer_region structure passed in buffer_create_info is aligned to the CL_DEVICE_MEM_BASE_ADDR_ALIGN value.
 }
 
 #[cl_entrypoint(clSetMemObjectDestructorCallback)]
@@ -403,7 +522,7 @@ fn validate_image_format<'a>(
         _ => true,
     };
     if !valid_combination {
-        return Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR);
+        return Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR.into());
     }
 
     Ok((format, pixel_size))
@@ -425,7 +544,7 @@ fn validate_image_desc(
     // CL_MEM_OBJECT_IMAGE1D_BUFFER, CL_MEM_OBJECT_IMAGE1D_ARRAY, CL_MEM_OBJECT_IMAGE2D,
     // CL_MEM_OBJECT_IMAGE2D_ARRAY, or CL_MEM_OBJECT_IMAGE3D.
     if !CL_IMAGE_TYPES.contains(&desc.image_type) {
-        return Err(err);
+        return Err(err.into());
     }
 
     let (dims, array) = desc.type_info();
@@ -448,7 +567,7 @@ fn validate_image_desc(
         || desc.image_depth < 1 && dims >= 3
         || desc.image_array_size < 1 && array
     {
-        return Err(err);
+        return Err(err.into());
     }
 
     let max_size = if dims == 3 {
@@ -468,12 +587,23 @@ fn validate_image_desc(
         || desc.image_depth > max_size && dims >= 3
         || desc.image_array_size > max_array && array
     {
-        return Err(CL_INVALID_IMAGE_SIZE);
+        return Err(CL_INVALID_IMAGE_SIZE.into());
+    }
+
+    // num_samples must be 0.
+    if desc.num_samples != 0 {
+        return Err(err.into());
     }
 
-    // num_mip_levels and num_samples must be 0.
-    if desc.num_mip_levels != 0 || desc.num_samples != 0 {
-        return Err(err);
+    // cl_khr_mipmap_image: num_mip_levels can't exceed the number of levels of a full mip chain of
+    // the image.
+    let max_dim = [desc.image_width, desc.image_height, desc.image_depth][..usize::from(dims)]
+        .iter()
+        .copied()
+        .max()
+        .unwrap();
+    if desc.num_mip_levels > usize::BITS - max_dim.leading_zeros() {
+        return Err(err.into());
     }
 
     // mem_object may refer to a valid buffer or image memory object. mem_object can be a buffer
@@ -493,13 +623,18 @@ fn validate_image_desc(
             }
             _ => false,
         } {
-            return Err(CL_INVALID_OPERATION);
+            return Err(CL_INVALID_OPERATION.into());
         }
         Some(p)
     } else {
         None
     };
 
+    // Mipmapped images can neither be created from another memory object, nor from host memory.
+    if desc.num_mip_levels > 1 && (parent.is_some() || !host_ptr.is_null()) {
+        return Err(err.into());
+    }
+
     // image_row_pitch is the scan-line pitch in bytes. This must be 0 if host_ptr is NULL and can
     // be either 0 or ≥ image_width × size of element in bytes if host_ptr is not NULL. If host_ptr
     // is not NULL and image_row_pitch = 0, image_row_pitch is calculated as image_width × size of
@@ -519,7 +654,7 @@ fn validate_image_desc(
     let has_buf_parent = parent.as_ref().map_or(false, |p| p.is_buffer());
     if host_ptr.is_null() {
         if (desc.image_row_pitch != 0 || desc.image_slice_pitch != 0) && !has_buf_parent {
-            return Err(err);
+            return Err(err.into());
         }
 
         if desc.image_row_pitch == 0 {
@@ -535,15 +670,22 @@ fn validate_image_desc(
                 .map(|d| d.image_pitch_alignment())
                 .max()
                 .unwrap() as usize;
-            if desc.image_row_pitch % (pitch_alignment * elem_size) != 0 {
-                return Err(err);
+            if desc.image_row_pitch < desc.image_width * elem_size
+                || desc.image_row_pitch % (pitch_alignment * elem_size) != 0
+            {
+                return Err(err.into());
+            }
+
+            // The row pitch in pixels is passed to the driver as a 16 bit value.
+            if desc.image_row_pitch / elem_size > u16::MAX as usize {
+                return Err(CL_INVALID_IMAGE_SIZE.into());
             }
         }
     } else {
         if desc.image_row_pitch == 0 {
             desc.image_row_pitch = desc.image_width * elem_size;
         } else if desc.image_row_pitch % elem_size != 0 {
-            return Err(err);
+            return Err(err.into());
         }
 
         if dims == 3 || array {
@@ -553,7 +695,7 @@ fn validate_image_desc(
             } else if desc.image_slice_pitch < valid_slice_pitch
                 || desc.image_slice_pitch % desc.image_row_pitch != 0
             {
-                return Err(err);
+                return Err(err.into());
             }
         }
     }
@@ -561,25 +703,58 @@ fn validate_image_desc(
     Ok((desc, parent))
 }
 
-fn validate_image_bounds(i: &Image, origin: CLVec<usize>, region: CLVec<usize>) -> CLResult<()> {
+/// Reads the `origin` of an operation on `i` and returns it together with the mip level.
+/// cl_khr_mipmap_image passes the mip level of mipmapped images in the first coordinate not used
+/// by the image type, which for 2D image arrays and 3D images is a fourth element of `origin`.
+///
+/// # Safety
+///
+/// `origin` has to point to four elements for mipmapped 2D image arrays and 3D images and to three
+/// elements otherwise.
+unsafe fn image_origin(i: &Image, origin: *const usize) -> CLResult<(CLVec<usize>, u32)> {
+    let mut o = unsafe { CLVec::from_raw(origin) };
+    if i.image_desc.num_levels() == 1 {
+        return Ok((o, 0));
+    }
+
+    let idx: usize = i.image_desc.dims_with_array().into();
+    let level = if idx < 3 {
+        mem::take(&mut o[idx])
+    } else {
+        unsafe { *origin.add(3) }
+    };
+
+    Ok((o, level.try_into().map_err(|_| CL_INVALID_VALUE)?))
+}
+
+fn validate_image_bounds(
+    i: &Image,
+    origin: CLVec<usize>,
+    level: u32,
+    region: CLVec<usize>,
+) -> CLResult<()> {
+    if level >= i.image_desc.num_levels() {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
     let dims = i.image_desc.dims_with_array();
     let bound = region + origin;
-    if bound > i.image_desc.size() {
-        return Err(CL_INVALID_VALUE);
+    if bound > i.image_desc.level_size(level) {
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If image is a 2D image object, origin[2] must be 0. If image is a 1D image or 1D image buffer
     // object, origin[1] and origin[2] must be 0. If image is a 1D image array object, origin[2]
     // must be 0.
     if dims < 3 && origin[2] != 0 || dims < 2 && origin[1] != 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If image is a 2D image object, region[2] must be 1. If image is a 1D image or 1D image buffer
     // object, region[1] and region[2] must be 1. If image is a 1D image array object, region[2]
     // must be 1. The values in region cannot be 0.
     if dims < 3 && region[2] != 1 || dims < 2 && region[1] != 1 || region.contains(&0) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     Ok(())
@@ -601,7 +776,6 @@ fn validate_buffer(
     desc: &cl_image_desc,
     mut flags: cl_mem_flags,
     format: &cl_image_format,
-    host_ptr: *mut ::std::os::raw::c_void,
     elem_size: usize,
 ) -> CLResult<cl_mem_flags> {
     // CL_INVALID_IMAGE_DESCRIPTOR if values specified in image_desc are not valid
@@ -624,19 +798,36 @@ fn validate_buffer(
                     // element in bytes must be ≤ size of the buffer object.
                     CL_MEM_OBJECT_IMAGE1D_BUFFER => {
                         if desc.image_width * elem_size > mem.size {
-                            return Err(err);
+                            return Err(err.into());
                         }
                     }
                     // For a 2D image created from a buffer object, the image_row_pitch × image_height
                     // must be ≤ size of the buffer object specified by mem_object.
                     CL_MEM_OBJECT_IMAGE2D => {
-                        //TODO
-                        //• CL_INVALID_IMAGE_FORMAT_DESCRIPTOR if a 2D image is created from a buffer and the row pitch and base address alignment does not follow the rules described for creating a 2D image from a buffer.
                         if desc.image_row_pitch * desc.image_height > mem.size {
-                            return Err(err);
+                            return Err(err.into());
+                        }
+
+                        // If the buffer object specified by mem_object was created with
+                        // CL_MEM_USE_HOST_PTR, the host_ptr specified to clCreateBuffer or
+                        // clCreateBufferWithProperties must be aligned to the maximum of the
+                        // CL_DEVICE_IMAGE_BASE_ADDRESS_ALIGNMENT value for all devices in the
+                        // context associated with the buffer specified by mem_object that support
+                        // images.
+                        //
+                        // CL_INVALID_IMAGE_FORMAT_DESCRIPTOR if a 2D image is created from a buffer
+                        // and the row pitch and base address alignment does not follow the rules
+                        // described for creating a 2D image from a buffer.
+                        if bit_check(mem.flags, CL_MEM_USE_HOST_PTR) {
+                            // the alignment is in pixels
+                            let addr_alignment =
+                                mem.context.image_base_address_alignment() as usize * elem_size;
+                            if mem.host_ptr() as usize % addr_alignment != 0 {
+                                return Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR.into());
+                            }
                         }
                     }
-                    _ => return Err(err),
+                    _ => return Err(err.into()),
                 }
             }
             // For an image object created from another image object, the values specified in the
@@ -645,7 +836,7 @@ fn validate_buffer(
             CL_MEM_OBJECT_IMAGE2D => {
                 let image = Image::ref_from_raw(mem_object).unwrap();
                 if desc.image_type != mem.mem_type || !desc_eq_no_buffer(desc, &image.image_desc) {
-                    return Err(err);
+                    return Err(err.into());
                 }
 
                 // CL_INVALID_IMAGE_FORMAT_DESCRIPTOR if a 2D image is created from a 2D image object
@@ -658,7 +849,7 @@ fn validate_buffer(
                 // The image channel data type specified in image_format must match the image channel
                 // data type associated with mem_object.
                 if format.image_channel_data_type != image.image_format.image_channel_data_type {
-                    return Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR);
+                    return Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR.into());
                 }
 
                 // The image channel order specified in image_format must be compatible with the image
@@ -687,26 +878,11 @@ fn validate_buffer(
                         | (CL_sRGBx, CL_RGBx)
                         | (CL_RGBx, CL_sRGBx)
                         | (CL_DEPTH, CL_R) => (),
-                        _ => return Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR),
+                        _ => return Err(CL_INVALID_IMAGE_FORMAT_DESCRIPTOR.into()),
                     }
                 }
             }
-            _ => return Err(err),
-        }
-
-        // If the buffer object specified by mem_object was created with CL_MEM_USE_HOST_PTR, the
-        // host_ptr specified to clCreateBuffer or clCreateBufferWithProperties must be aligned to
-        // the maximum of the CL_DEVICE_IMAGE_BASE_ADDRESS_ALIGNMENT value for all devices in the
-        // context associated with the buffer specified by mem_object that support images.
-        if mem.flags & CL_MEM_USE_HOST_PTR as cl_mem_flags != 0 {
-            for dev in &mem.context.devs {
-                let addr_alignment = dev.image_base_address_alignment();
-                if addr_alignment == 0 {
-                    return Err(CL_INVALID_OPERATION);
-                } else if !is_alligned(host_ptr, addr_alignment as usize) {
-                    return Err(err);
-                }
-            }
+            _ => return Err(err.into()),
         }
 
         validate_matching_buffer_flags(mem, flags)?;
@@ -714,7 +890,7 @@ fn validate_buffer(
         flags = inherit_mem_flags(flags, mem);
     // implied by spec
     } else if desc.image_type == CL_MEM_OBJECT_IMAGE1D_BUFFER {
-        return Err(err);
+        return Err(err.into());
     }
 
     Ok(flags)
@@ -740,7 +916,7 @@ impl CLInfo<cl_image_info> for cl_mem {
                 mem.image_desc.image_slice_pitch
             }),
             CL_IMAGE_WIDTH => cl_prop::<usize>(mem.image_desc.image_width),
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -769,7 +945,7 @@ fn create_image_with_properties(
     // validate host_ptr before merging flags
     validate_host_ptr(host_ptr, flags)?;
 
-    flags = validate_buffer(&desc, flags, format, host_ptr, elem_size.into())?;
+    flags = validate_buffer(&desc, flags, format, elem_size.into())?;
 
     // For all image types except CL_MEM_OBJECT_IMAGE1D_BUFFER, if the value specified for flags is 0, the
     // default is used which is CL_MEM_READ_WRITE.
@@ -788,13 +964,14 @@ fn create_image_with_properties(
         .find(|f| *f & filtered_flags == filtered_flags)
         .ok_or(CL_IMAGE_FORMAT_NOT_SUPPORTED)?;
 
-    let props = Properties::from_ptr_raw(properties);
-    // CL_INVALID_PROPERTY if a property name in properties is not a supported property name, if
-    // the value specified for a supported property name is not valid, or if the same property name
-    // is specified more than once.
-    if props.len() > 1 {
-        // we don't support any properties besides the 0 property
-        return Err(CL_INVALID_PROPERTY);
+    validate_protected_flags(&c, flags)?;
+
+    let props = MemProps::from_ptr(&c, properties)?;
+    let external = props.external(&c, flags, host_ptr)?;
+
+    // images created from other memory objects can't import memory themselves
+    if external.is_some() && parent.is_some() {
+        return Err(CL_INVALID_PROPERTY.into());
     }
 
     Ok(MemBase::new_image(
@@ -806,7 +983,8 @@ fn create_image_with_properties(
         desc,
         elem_size,
         host_ptr,
-        props,
+        props.raw,
+        external,
     )?
     .into_cl())
 }
@@ -891,12 +1069,12 @@ fn get_supported_image_formats(
 
     // or image_type are not valid
     if !image_type_valid(image_type) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE ... if num_entries is 0 and image_formats is not NULL.
     if num_entries == 0 && !image_formats.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let mut res = Vec::<cl_image_format>::new();
@@ -932,13 +1110,16 @@ impl CLInfo<cl_sampler_info> for cl_sampler {
                 cl_prop::<cl_context>(cl_context::from_ptr(ptr))
             }
             CL_SAMPLER_FILTER_MODE => cl_prop::<cl_filter_mode>(sampler.filter_mode),
+            CL_SAMPLER_LOD_MAX_KHR => cl_prop::<cl_float>(sampler.lod_max),
+            CL_SAMPLER_LOD_MIN_KHR => cl_prop::<cl_float>(sampler.lod_min),
+            CL_SAMPLER_MIP_FILTER_MODE_KHR => cl_prop::<cl_filter_mode>(sampler.mip_filter_mode),
             CL_SAMPLER_NORMALIZED_COORDS => cl_prop::<bool>(sampler.normalized_coords),
             CL_SAMPLER_REFERENCE_COUNT => cl_prop::<cl_uint>(Sampler::refcnt(*self)?),
             CL_SAMPLER_PROPERTIES => {
                 cl_prop::<&Option<Properties<cl_sampler_properties>>>(&sampler.props)
             }
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -948,6 +1129,9 @@ fn create_sampler_impl(
     normalized_coords: cl_bool,
     addressing_mode: cl_addressing_mode,
     filter_mode: cl_filter_mode,
+    mip_filter_mode: cl_filter_mode,
+    lod_min: f32,
+    lod_max: f32,
     props: Option<Properties<cl_sampler_properties>>,
 ) -> CLResult<cl_sampler> {
     let c = Context::arc_from_raw(context)?;
@@ -963,12 +1147,21 @@ fn create_sampler_impl(
     // arguements are not valid.
     validate_addressing_mode(addressing_mode)?;
     validate_filter_mode(filter_mode)?;
+    validate_filter_mode(mip_filter_mode)?;
+
+    // cl_khr_mipmap_image: the LOD range has to be a valid range of non negative values.
+    if !(0.0..=lod_max).contains(&lod_min) {
+        return Err(CL_INVALID_VALUE.into());
+    }
 
     let sampler = Sampler::new(
         c,
         check_cl_bool(normalized_coords).ok_or(CL_INVALID_VALUE)?,
         addressing_mode,
         filter_mode,
+        mip_filter_mode,
+        lod_min,
+        lod_max,
         props,
     );
     Ok(sampler.into_cl())
@@ -986,6 +1179,9 @@ fn create_sampler(
         normalized_coords,
         addressing_mode,
         filter_mode,
+        CL_FILTER_NEAREST,
+        0.0,
+        f32::MAX,
         None,
     )
 }
@@ -998,6 +1194,9 @@ fn create_sampler_with_properties(
     let mut normalized_coords = CL_TRUE;
     let mut addressing_mode = CL_ADDRESS_CLAMP;
     let mut filter_mode = CL_FILTER_NEAREST;
+    let mut mip_filter_mode = CL_FILTER_NEAREST;
+    let mut lod_min = 0.0;
+    let mut lod_max = f32::MAX;
 
     // CL_INVALID_VALUE if the same property name is specified more than once.
     let sampler_properties = if sampler_properties.is_null() {
@@ -1010,9 +1209,13 @@ fn create_sampler_with_properties(
                 CL_SAMPLER_ADDRESSING_MODE => addressing_mode = p.1 as u32,
                 CL_SAMPLER_FILTER_MODE => filter_mode = p.1 as u32,
                 CL_SAMPLER_NORMALIZED_COORDS => normalized_coords = p.1 as u32,
+                // the LOD values are passed as the bits of a cl_float
+                CL_SAMPLER_LOD_MAX_KHR => lod_max = f32::from_bits(p.1 as u32),
+                CL_SAMPLER_LOD_MIN_KHR => lod_min = f32::from_bits(p.1 as u32),
+                CL_SAMPLER_MIP_FILTER_MODE_KHR => mip_filter_mode = p.1 as u32,
                 // CL_INVALID_VALUE if the property name in sampler_properties is not a supported
                 // property name
-                _ => return Err(CL_INVALID_VALUE),
+                _ => return Err(CL_INVALID_VALUE.into()),
             }
         }
         Some(sampler_properties)
@@ -1023,6 +1226,9 @@ fn create_sampler_with_properties(
         normalized_coords,
         addressing_mode,
         filter_mode,
+        mip_filter_mode,
+        lod_min,
+        lod_max,
         sampler_properties,
     )
 }
@@ -1057,35 +1263,49 @@ fn enqueue_read_buffer(
     // CL_INVALID_VALUE if the region being read or written specified by (offset, size) is out of
     // bounds or if ptr is a NULL value.
     if offset + cb > b.size || ptr.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and buffer are not the same
     if b.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST if the read and write operations are blocking
     // and the execution status of any of the events in event_wait_list is a negative integer value.
     if block && evs.iter().any(|e| e.is_error()) {
-        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST);
+        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST.into());
     }
 
     // CL_INVALID_OPERATION if clEnqueueReadBuffer is called on buffer which has been created with
     // CL_MEM_HOST_WRITE_ONLY or CL_MEM_HOST_NO_ACCESS.
     if bit_check(b.flags, CL_MEM_HOST_WRITE_ONLY | CL_MEM_HOST_NO_ACCESS) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
+    strict::check_unmapped(&b, "clEnqueueReadBuffer", false)?;
+    strict::check_written(&b, "clEnqueueReadBuffer")?;
+
+    let host_access = HostAccess::range(ptr, 0, cb);
+
     // SAFETY: it's required that applications do not cause data races
     let ptr = unsafe { MutMemoryPtr::from_ptr(ptr) };
-    create_and_queue(
+    create_and_queue_with_host_access(
         q,
         CL_COMMAND_READ_BUFFER,
         evs,
         event,
         block,
-        Box::new(move |q, ctx| b.read(q, ctx, offset, ptr, cb)),
+        host_access,
+        Box::new(move |q, ctx| {
+            if block {
+                b.read(q, ctx, offset, ptr, cb)
+            } else {
+                // don't stall the queue thread, the data only has to be there once the event
+                // completes
+                b.read_async(q, ctx, offset, ptr, cb)
+            }
+        }),
     )
 
     // TODO
@@ -1112,34 +1332,40 @@ fn enqueue_write_buffer(
     // CL_INVALID_VALUE if the region being read or written specified by (offset, size) is out of
     // bounds or if ptr is a NULL value.
     if offset + cb > b.size || ptr.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and buffer are not the same
     if b.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST if the read and write operations are blocking
     // and the execution status of any of the events in event_wait_list is a negative integer value.
     if block && evs.iter().any(|e| e.is_error()) {
-        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST);
+        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST.into());
     }
 
     // CL_INVALID_OPERATION if clEnqueueWriteBuffer is called on buffer which has been created with
     // CL_MEM_HOST_READ_ONLY or CL_MEM_HOST_NO_ACCESS.
     if bit_check(b.flags, CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_NO_ACCESS) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
+    strict::check_unmapped(&b, "clEnqueueWriteBuffer", true)?;
+    strict::mark_written(&b);
+
+    let host_access = HostAccess::range(ptr, 0, cb);
+
     // SAFETY: it's required that applications do not cause data races
     let ptr = unsafe { ConstMemoryPtr::from_ptr(ptr) };
-    create_and_queue(
+    create_and_queue_with_host_access(
         q,
         CL_COMMAND_WRITE_BUFFER,
         evs,
         event,
         block,
+        host_access,
         Box::new(move |q, ctx| b.write(q, ctx, offset, ptr, cb)),
     )
 
@@ -1147,33 +1373,27 @@ fn enqueue_write_buffer(
     // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
 }
 
-#[cl_entrypoint(clEnqueueCopyBuffer)]
-fn enqueue_copy_buffer(
-    command_queue: cl_command_queue,
+pub fn copy_buffer_cmd(
+    q: &Queue,
     src_buffer: cl_mem,
     dst_buffer: cl_mem,
     src_offset: usize,
     dst_offset: usize,
     size: usize,
-    num_events_in_wait_list: cl_uint,
-    event_wait_list: *const cl_event,
-    event: *mut cl_event,
-) -> CLResult<()> {
-    let q = Queue::arc_from_raw(command_queue)?;
+) -> CLResult<CommandSig> {
     let src = Buffer::arc_from_raw(src_buffer)?;
     let dst = Buffer::arc_from_raw(dst_buffer)?;
-    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_CONTEXT if the context associated with command_queue, src_buffer and dst_buffer
     // are not the same
     if q.context != src.context || q.context != dst.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if src_offset, dst_offset, size, src_offset + size or dst_offset + size
     // require accessing elements outside the src_buffer and dst_buffer buffer objects respectively.
     if src_offset + size > src.size || dst_offset + size > dst.size {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_MEM_COPY_OVERLAP if src_buffer and dst_buffer are the same buffer or sub-buffer object
@@ -1187,18 +1407,18 @@ fn enqueue_copy_buffer(
         if (src_offset <= dst_offset && dst_offset < src_offset + size)
             || (dst_offset <= src_offset && src_offset < dst_offset + size)
         {
-            return Err(CL_MEM_COPY_OVERLAP);
+            return Err(CL_MEM_COPY_OVERLAP.into());
         }
     }
 
-    create_and_queue(
-        q,
-        CL_COMMAND_COPY_BUFFER,
-        evs,
-        event,
-        false,
-        Box::new(move |q, ctx| src.copy_to_buffer(q, ctx, &dst, src_offset, dst_offset, size)),
-    )
+    strict::check_unmapped(&src, "clEnqueueCopyBuffer", false)?;
+    strict::check_written(&src, "clEnqueueCopyBuffer")?;
+    strict::check_unmapped(&dst, "clEnqueueCopyBuffer", true)?;
+    strict::mark_written(&dst);
+
+    Ok(Box::new(move |q, ctx| {
+        src.copy_to_buffer(q, ctx, &dst, src_offset, dst_offset, size)
+    }))
 
     // TODO
     //• CL_MISALIGNED_SUB_BUFFER_OFFSET if src_buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
@@ -1206,6 +1426,24 @@ fn enqueue_copy_buffer(
     //• CL_MEM_OBJECT_ALLOCATION_FAILURE if there is a failure to allocate memory for data store associated with src_buffer or dst_buffer.
 }
 
+#[cl_entrypoint(clEnqueueCopyBuffer)]
+fn enqueue_copy_buffer(
+    command_queue: cl_command_queue,
+    src_buffer: cl_mem,
+    dst_buffer: cl_mem,
+    src_offset: usize,
+    dst_offset: usize,
+    size: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = copy_buffer_cmd(&q, src_buffer, dst_buffer, src_offset, dst_offset, size)?;
+    create_and_queue(q, CL_COMMAND_COPY_BUFFER, evs, event, false, Box::new(cmd))
+}
+
 #[cl_entrypoint(clEnqueueReadBufferRect)]
 fn enqueue_read_buffer_rect(
     command_queue: cl_command_queue,
@@ -1231,13 +1469,13 @@ fn enqueue_read_buffer_rect(
     // CL_INVALID_OPERATION if clEnqueueReadBufferRect is called on buffer which has been created
     // with CL_MEM_HOST_WRITE_ONLY or CL_MEM_HOST_NO_ACCESS.
     if bit_check(buf.flags, CL_MEM_HOST_WRITE_ONLY | CL_MEM_HOST_NO_ACCESS) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST if the read and write operations are blocking
     // and the execution status of any of the events in event_wait_list is a negative integer value.
     if block && evs.iter().any(|e| e.is_error()) {
-        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST);
+        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST.into());
     }
 
     // CL_INVALID_VALUE if buffer_origin, host_origin, or region is NULL.
@@ -1247,7 +1485,7 @@ fn enqueue_read_buffer_rect(
       // CL_INVALID_VALUE if ptr is NULL.
       ptr.is_null()
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let r = unsafe { CLVec::from_raw(region) };
@@ -1261,7 +1499,7 @@ fn enqueue_read_buffer_rect(
       // CL_INVALID_VALUE if host_row_pitch is not 0 and is less than region[0].
       host_row_pitch != 0 && host_row_pitch < r[0]
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If buffer_row_pitch is 0, buffer_row_pitch is computed as region[0].
@@ -1279,7 +1517,7 @@ fn enqueue_read_buffer_rect(
       // CL_INVALID_VALUE if host_slice_pitch is not 0 and is less than region[1] × host_row_pitch and not a multiple of host_row_pitch.
       host_slice_pitch != 0 && host_slice_pitch < r[1] * host_row_pitch && host_slice_pitch % host_row_pitch != 0
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If buffer_slice_pitch is 0, buffer_slice_pitch is computed as region[1] × buffer_row_pitch.
@@ -1295,22 +1533,30 @@ fn enqueue_read_buffer_rect(
     // CL_INVALID_VALUE if the region being read or written specified by (buffer_origin, region,
     // buffer_row_pitch, buffer_slice_pitch) is out of bounds.
     if CLVec::calc_size(r + buf_ori, [1, buffer_row_pitch, buffer_slice_pitch]) > buf.size {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and buffer are not the same
     if q.context != buf.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
+    strict::check_unmapped(&buf, "clEnqueueReadBufferRect", false)?;
+    strict::check_written(&buf, "clEnqueueReadBufferRect")?;
+
+    let (host_offset, host_size) =
+        CLVec::calc_offset_size(&host_ori, &r, [1, host_row_pitch, host_slice_pitch]);
+    let host_access = HostAccess::range(ptr, host_offset, host_size);
+
     // SAFETY: it's required that applications do not cause data races
     let ptr = unsafe { MutMemoryPtr::from_ptr(ptr) };
-    create_and_queue(
+    create_and_queue_with_host_access(
         q,
         CL_COMMAND_READ_BUFFER_RECT,
         evs,
         event,
         block,
+        host_access,
         Box::new(move |q, ctx| {
             buf.read_rect(
                 ptr,
@@ -1356,13 +1602,13 @@ fn enqueue_write_buffer_rect(
     // CL_INVALID_OPERATION if clEnqueueWriteBufferRect is called on buffer which has been created
     // with CL_MEM_HOST_READ_ONLY or CL_MEM_HOST_NO_ACCESS.
     if bit_check(buf.flags, CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_NO_ACCESS) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST if the read and write operations are blocking
     // and the execution status of any of the events in event_wait_list is a negative integer value.
     if block && evs.iter().any(|e| e.is_error()) {
-        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST);
+        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST.into());
     }
 
     // CL_INVALID_VALUE if buffer_origin, host_origin, or region is NULL.
@@ -1372,7 +1618,7 @@ fn enqueue_write_buffer_rect(
       // CL_INVALID_VALUE if ptr is NULL.
       ptr.is_null()
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let r = unsafe { CLVec::from_raw(region) };
@@ -1386,7 +1632,7 @@ fn enqueue_write_buffer_rect(
       // CL_INVALID_VALUE if host_row_pitch is not 0 and is less than region[0].
       host_row_pitch != 0 && host_row_pitch < r[0]
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If buffer_row_pitch is 0, buffer_row_pitch is computed as region[0].
@@ -1404,7 +1650,7 @@ fn enqueue_write_buffer_rect(
       // CL_INVALID_VALUE if host_slice_pitch is not 0 and is less than region[1] × host_row_pitch and not a multiple of host_row_pitch.
       host_slice_pitch != 0 && host_slice_pitch < r[1] * host_row_pitch && host_slice_pitch % host_row_pitch != 0
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If buffer_slice_pitch is 0, buffer_slice_pitch is computed as region[1] × buffer_row_pitch.
@@ -1420,22 +1666,30 @@ fn enqueue_write_buffer_rect(
     // CL_INVALID_VALUE if the region being read or written specified by (buffer_origin, region,
     // buffer_row_pitch, buffer_slice_pitch) is out of bounds.
     if CLVec::calc_size(r + buf_ori, [1, buffer_row_pitch, buffer_slice_pitch]) > buf.size {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and buffer are not the same
     if q.context != buf.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
+    strict::check_unmapped(&buf, "clEnqueueWriteBufferRect", true)?;
+    strict::mark_written(&buf);
+
+    let (host_offset, host_size) =
+        CLVec::calc_offset_size(&host_ori, &r, [1, host_row_pitch, host_slice_pitch]);
+    let host_access = HostAccess::range(ptr, host_offset, host_size);
+
     // SAFETY: it's required that applications do not cause data races
     let ptr = unsafe { ConstMemoryPtr::from_ptr(ptr) };
-    create_and_queue(
+    create_and_queue_with_host_access(
         q,
         CL_COMMAND_WRITE_BUFFER_RECT,
         evs,
         event,
         block,
+        host_access,
         Box::new(move |q, ctx| {
             buf.write_rect(
                 ptr,
@@ -1456,9 +1710,8 @@ fn enqueue_write_buffer_rect(
     // CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
 }
 
-#[cl_entrypoint(clEnqueueCopyBufferRect)]
-fn enqueue_copy_buffer_rect(
-    command_queue: cl_command_queue,
+pub fn copy_buffer_rect_cmd(
+    q: &Queue,
     src_buffer: cl_mem,
     dst_buffer: cl_mem,
     src_origin: *const usize,
@@ -1468,18 +1721,13 @@ fn enqueue_copy_buffer_rect(
     mut src_slice_pitch: usize,
     mut dst_row_pitch: usize,
     mut dst_slice_pitch: usize,
-    num_events_in_wait_list: cl_uint,
-    event_wait_list: *const cl_event,
-    event: *mut cl_event,
-) -> CLResult<()> {
-    let q = Queue::arc_from_raw(command_queue)?;
+) -> CLResult<CommandSig> {
     let src = Buffer::arc_from_raw(src_buffer)?;
     let dst = Buffer::arc_from_raw(dst_buffer)?;
-    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_VALUE if src_origin, dst_origin, or region is NULL.
     if src_origin.is_null() || dst_origin.is_null() || region.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let r = unsafe { CLVec::from_raw(region) };
@@ -1493,7 +1741,7 @@ fn enqueue_copy_buffer_rect(
       // CL_INVALID_VALUE if dst_row_pitch is not 0 and is less than region[0].
       dst_row_pitch != 0 && dst_row_pitch < r[0]
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If src_row_pitch is 0, src_row_pitch is computed as region[0].
@@ -1515,7 +1763,7 @@ fn enqueue_copy_buffer_rect(
       // if dst_slice_pitch is not 0 and is not a multiple of dst_row_pitch.
       dst_slice_pitch != 0 && dst_slice_pitch % dst_row_pitch != 0
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // If src_slice_pitch is 0, src_slice_pitch is computed as region[1] × src_row_pitch.
@@ -1534,7 +1782,7 @@ fn enqueue_copy_buffer_rect(
         && src_slice_pitch != dst_slice_pitch
         && src_row_pitch != dst_row_pitch
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if (src_origin, region, src_row_pitch, src_slice_pitch) or (dst_origin,
@@ -1543,7 +1791,7 @@ fn enqueue_copy_buffer_rect(
     if CLVec::calc_size(r + src_ori, [1, src_row_pitch, src_slice_pitch]) > src.size
         || CLVec::calc_size(r + dst_ori, [1, dst_row_pitch, dst_slice_pitch]) > dst.size
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_MEM_COPY_OVERLAP if src_buffer and dst_buffer are the same buffer or sub-buffer object and
@@ -1560,95 +1808,143 @@ fn enqueue_copy_buffer_rect(
             src_slice_pitch,
         )
     {
-        return Err(CL_MEM_COPY_OVERLAP);
+        return Err(CL_MEM_COPY_OVERLAP.into());
     }
 
     // CL_INVALID_CONTEXT if the context associated with command_queue, src_buffer and dst_buffer
     // are not the same
     if src.context != q.context || dst.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
+    strict::check_unmapped(&src, "clEnqueueCopyBufferRect", false)?;
+    strict::check_written(&src, "clEnqueueCopyBufferRect")?;
+    strict::check_unmapped(&dst, "clEnqueueCopyBufferRect", true)?;
+    strict::mark_written(&dst);
+
+    Ok(Box::new(move |q, ctx| {
+        src.copy_rect(
+            &dst,
+            q,
+            ctx,
+            &r,
+            &src_ori,
+            src_row_pitch,
+            src_slice_pitch,
+            &dst_ori,
+            dst_row_pitch,
+            dst_slice_pitch,
+        )
+    }))
+
+    // TODO
+    // CL_MISALIGNED_SUB_BUFFER_OFFSET if src_buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
+}
+
+#[cl_entrypoint(clEnqueueCopyBufferRect)]
+fn enqueue_copy_buffer_rect(
+    command_queue: cl_command_queue,
+    src_buffer: cl_mem,
+    dst_buffer: cl_mem,
+    src_origin: *const usize,
+    dst_origin: *const usize,
+    region: *const usize,
+    src_row_pitch: usize,
+    src_slice_pitch: usize,
+    dst_row_pitch: usize,
+    dst_slice_pitch: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = copy_buffer_rect_cmd(
+        &q,
+        src_buffer,
+        dst_buffer,
+        src_origin,
+        dst_origin,
+        region,
+        src_row_pitch,
+        src_slice_pitch,
+        dst_row_pitch,
+        dst_slice_pitch,
+    )?;
     create_and_queue(
         q,
         CL_COMMAND_COPY_BUFFER_RECT,
         evs,
         event,
         false,
-        Box::new(move |q, ctx| {
-            src.copy_rect(
-                &dst,
-                q,
-                ctx,
-                &r,
-                &src_ori,
-                src_row_pitch,
-                src_slice_pitch,
-                &dst_ori,
-                dst_row_pitch,
-                dst_slice_pitch,
-            )
-        }),
+        Box::new(cmd),
     )
-
-    // TODO
-    // CL_MISALIGNED_SUB_BUFFER_OFFSET if src_buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
 }
 
-#[cl_entrypoint(clEnqueueFillBuffer)]
-fn enqueue_fill_buffer(
-    command_queue: cl_command_queue,
+pub fn fill_buffer_cmd(
+    q: &Queue,
     buffer: cl_mem,
     pattern: *const ::std::os::raw::c_void,
     pattern_size: usize,
     offset: usize,
     size: usize,
-    num_events_in_wait_list: cl_uint,
-    event_wait_list: *const cl_event,
-    event: *mut cl_event,
-) -> CLResult<()> {
-    let q = Queue::arc_from_raw(command_queue)?;
+) -> CLResult<CommandSig> {
     let b = Buffer::arc_from_raw(buffer)?;
-    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_VALUE if offset or offset + size require accessing elements outside the buffer
     // buffer object respectively.
     if offset + size > b.size {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if pattern is NULL or if pattern_size is 0 or if pattern_size is not one of
     // { 1, 2, 4, 8, 16, 32, 64, 128 }.
     if pattern.is_null() || pattern_size.count_ones() != 1 || pattern_size > 128 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if offset and size are not a multiple of pattern_size.
     if offset % pattern_size != 0 || size % pattern_size != 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and buffer are not the same
     if b.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
+    strict::check_unmapped(&b, "clEnqueueFillBuffer", true)?;
+    strict::mark_written(&b);
+
     // we have to copy memory
     let pattern = unsafe { slice::from_raw_parts(pattern.cast(), pattern_size).to_vec() };
-    create_and_queue(
-        q,
-        CL_COMMAND_FILL_BUFFER,
-        evs,
-        event,
-        false,
-        Box::new(move |q, ctx| b.fill(q, ctx, &pattern, offset, size)),
-    )
+    Ok(Box::new(move |q, ctx| {
+        b.fill(q, ctx, &pattern, offset, size)
+    }))
 
     // TODO
     //• CL_MISALIGNED_SUB_BUFFER_OFFSET if buffer is a sub-buffer object and offset specified when the sub-buffer object is created is not aligned to CL_DEVICE_MEM_BASE_ADDR_ALIGN value for device associated with queue.
     //• CL_MEM_OBJECT_ALLOCATION_FAILURE if there is a failure to allocate memory for data store associated with buffer.
 }
 
+#[cl_entrypoint(clEnqueueFillBuffer)]
+fn enqueue_fill_buffer(
+    command_queue: cl_command_queue,
+    buffer: cl_mem,
+    pattern: *const ::std::os::raw::c_void,
+    pattern_size: usize,
+    offset: usize,
+    size: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = fill_buffer_cmd(&q, buffer, pattern, pattern_size, offset, size)?;
+    create_and_queue(q, CL_COMMAND_FILL_BUFFER, evs, event, false, Box::new(cmd))
+}
+
 #[cl_entrypoint(clEnqueueMapBuffer)]
 fn enqueue_map_buffer(
     command_queue: cl_command_queue,
@@ -1663,7 +1959,8 @@ fn enqueue_map_buffer(
 ) -> CLResult<*mut c_void> {
     let q = Queue::arc_from_raw(command_queue)?;
     let b = Buffer::arc_from_raw(buffer)?;
-    let block = check_cl_bool(blocking_map).ok_or(CL_INVALID_VALUE)?;
+    let block =
+        check_cl_bool(blocking_map).ok_or(CL_INVALID_VALUE)? || Platform::conf().serialize_maps;
     let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     validate_map_flags(&b, map_flags)?;
@@ -1671,28 +1968,32 @@ fn enqueue_map_buffer(
     // CL_INVALID_VALUE if region being mapped given by (offset, size) is out of bounds or if size
     // is 0
     if offset + size > b.size || size == 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST if the map operation is blocking and the
     // execution status of any of the events in event_wait_list is a negative integer value.
     if block && evs.iter().any(|e| e.is_error()) {
-        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST);
+        return Err(CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST.into());
     }
 
     // CL_INVALID_CONTEXT if context associated with command_queue and buffer are not the same
     if b.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
+    strict::check_map_buffer(&b, map_flags, offset, size)?;
+
     let ptr = b.map(q.device, offset)?;
+    strict::add_map_buffer(&b, ptr.as_ptr(), map_flags, offset, size);
+
     create_and_queue(
         q,
         CL_COMMAND_MAP_BUFFER,
         evs,
         event,
         block,
-        Box::new(move |q, ctx| b.sync_shadow(q, ctx, ptr)),
+        Box::new(move |q, ctx| b.sync_shadow(q, ctx, ptr, offset, size)),
     )?;
 
     Ok(ptr.as_ptr())
@@ -1725,34 +2026,34 @@ fn enqueue_read_image(
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and image are not the same
     if i.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_OPERATION if clEnqueueReadImage is called on image which has been created with
     // CL_MEM_HOST_WRITE_ONLY or CL_MEM_HOST_NO_ACCESS.
     if bit_check(i.flags, CL_MEM_HOST_WRITE_ONLY | CL_MEM_HOST_NO_ACCESS) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_VALUE if origin or region is NULL.
     // CL_INVALID_VALUE if ptr is NULL.
     if origin.is_null() || region.is_null() || ptr.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if image is a 1D or 2D image and slice_pitch or input_slice_pitch is not 0.
     if !i.image_desc.has_slice() && slice_pitch != 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let r = unsafe { CLVec::from_raw(region) };
-    let o = unsafe { CLVec::from_raw(origin) };
+    let (o, level) = unsafe { image_origin(&i, origin)? };
 
     // CL_INVALID_VALUE if the region being read or written specified by origin and region is out of
     // bounds.
     // CL_INVALID_VALUE if values in origin and region do not follow rules described in the argument
     // description for origin and region.
-    validate_image_bounds(&i, o, r)?;
+    validate_image_bounds(&i, o, level, r)?;
 
     // If row_pitch (or input_row_pitch) is set to 0, the appropriate row pitch is calculated based
     // on the size of each element in bytes multiplied by width.
@@ -1766,15 +2067,25 @@ fn enqueue_read_image(
         slice_pitch = row_pitch * r[1];
     }
 
+    strict::check_unmapped(&i, "clEnqueueReadImage", false)?;
+    strict::check_written(&i, "clEnqueueReadImage")?;
+
+    let host_size = CLVec::calc_size(
+        CLVec::new([r[0] * pixel_size, r[1], r[2]]),
+        [1, row_pitch, slice_pitch],
+    );
+    let host_access = HostAccess::range(ptr, 0, host_size);
+
     // SAFETY: it's required that applications do not cause data races
     let ptr = unsafe { MutMemoryPtr::from_ptr(ptr) };
-    create_and_queue(
+    create_and_queue_with_host_access(
         q,
         CL_COMMAND_READ_IMAGE,
         evs,
         event,
         block,
-        Box::new(move |q, ctx| i.read(ptr, q, ctx, &r, &o, row_pitch, slice_pitch)),
+        host_access,
+        Box::new(move |q, ctx| i.read(ptr, q, ctx, &r, &o, level, row_pitch, slice_pitch)),
     )
 
     //• CL_INVALID_IMAGE_SIZE if image dimensions (image width, height, specified or compute row and/or slice pitch) for image are not supported by device associated with queue.
@@ -1805,34 +2116,34 @@ fn enqueue_write_image(
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and image are not the same
     if i.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_OPERATION if clEnqueueWriteImage is called on image which has been created with
     // CL_MEM_HOST_READ_ONLY or CL_MEM_HOST_NO_ACCESS.
     if bit_check(i.flags, CL_MEM_HOST_READ_ONLY | CL_MEM_HOST_NO_ACCESS) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_VALUE if origin or region is NULL.
     // CL_INVALID_VALUE if ptr is NULL.
     if origin.is_null() || region.is_null() || ptr.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if image is a 1D or 2D image and slice_pitch or input_slice_pitch is not 0.
     if !i.image_desc.has_slice() && slice_pitch != 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let r = unsafe { CLVec::from_raw(region) };
-    let o = unsafe { CLVec::from_raw(origin) };
+    let (o, level) = unsafe { image_origin(&i, origin)? };
 
     // CL_INVALID_VALUE if the region being read or written specified by origin and region is out of
     // bounds.
     // CL_INVALID_VALUE if values in origin and region do not follow rules described in the argument
     // description for origin and region.
-    validate_image_bounds(&i, o, r)?;
+    validate_image_bounds(&i, o, level, r)?;
 
     // If row_pitch (or input_row_pitch) is set to 0, the appropriate row pitch is calculated based
     // on the size of each element in bytes multiplied by width.
@@ -1846,15 +2157,25 @@ fn enqueue_write_image(
         slice_pitch = row_pitch * r[1];
     }
 
+    strict::check_unmapped(&i, "clEnqueueWriteImage", true)?;
+    strict::mark_written(&i);
+
+    let host_size = CLVec::calc_size(
+        CLVec::new([r[0] * pixel_size, r[1], r[2]]),
+        [1, row_pitch, slice_pitch],
+    );
+    let host_access = HostAccess::range(ptr, 0, host_size);
+
     // SAFETY: it's required that applications do not cause data races
     let ptr = unsafe { ConstMemoryPtr::from_ptr(ptr) };
-    create_and_queue(
+    create_and_queue_with_host_access(
         q,
         CL_COMMAND_WRITE_BUFFER_RECT,
         evs,
         event,
         block,
-        Box::new(move |q, ctx| i.write(ptr, q, ctx, &r, row_pitch, slice_pitch, &o)),
+        host_access,
+        Box::new(move |q, ctx| i.write(ptr, q, ctx, &r, row_pitch, slice_pitch, &o, level)),
     )
 
     //• CL_INVALID_IMAGE_SIZE if image dimensions (image width, height, specified or compute row and/or slice pitch) for image are not supported by device associated with queue.
@@ -1863,60 +2184,63 @@ fn enqueue_write_image(
     //• CL_EXEC_STATUS_ERROR_FOR_EVENTS_IN_WAIT_LIST if the read and write operations are blocking and the execution status of any of the events in event_wait_list is a negative integer value.
 }
 
-#[cl_entrypoint(clEnqueueCopyImage)]
-fn enqueue_copy_image(
-    command_queue: cl_command_queue,
+pub fn copy_image_cmd(
+    q: &Queue,
     src_image: cl_mem,
     dst_image: cl_mem,
     src_origin: *const usize,
     dst_origin: *const usize,
     region: *const usize,
-    num_events_in_wait_list: cl_uint,
-    event_wait_list: *const cl_event,
-    event: *mut cl_event,
-) -> CLResult<()> {
-    let q = Queue::arc_from_raw(command_queue)?;
+) -> CLResult<CommandSig> {
     let src_image = Image::arc_from_raw(src_image)?;
     let dst_image = Image::arc_from_raw(dst_image)?;
-    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_CONTEXT if the context associated with command_queue, src_image and dst_image are not the same
     if src_image.context != q.context || dst_image.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_IMAGE_FORMAT_MISMATCH if src_image and dst_image do not use the same image format.
     if src_image.image_format != dst_image.image_format {
-        return Err(CL_IMAGE_FORMAT_MISMATCH);
+        return Err(CL_IMAGE_FORMAT_MISMATCH.into());
     }
 
     // CL_INVALID_VALUE if src_origin, dst_origin, or region is NULL.
     if src_origin.is_null() || dst_origin.is_null() || region.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let region = unsafe { CLVec::from_raw(region) };
-    let dst_origin = unsafe { CLVec::from_raw(dst_origin) };
-    let src_origin = unsafe { CLVec::from_raw(src_origin) };
+    let (dst_origin, dst_level) = unsafe { image_origin(&dst_image, dst_origin)? };
+    let (src_origin, src_level) = unsafe { image_origin(&src_image, src_origin)? };
 
     // CL_INVALID_VALUE if the 2D or 3D rectangular region specified by src_origin and
     // src_origin + region refers to a region outside src_image, or if the 2D or 3D rectangular
     // region specified by dst_origin and dst_origin + region refers to a region outside dst_image.
     // CL_INVALID_VALUE if values in src_origin, dst_origin and region do not follow rules described
     // in the argument description for src_origin, dst_origin and region.
-    validate_image_bounds(&src_image, src_origin, region)?;
-    validate_image_bounds(&dst_image, dst_origin, region)?;
-
-    create_and_queue(
-        q,
-        CL_COMMAND_COPY_IMAGE,
-        evs,
-        event,
-        false,
-        Box::new(move |q, ctx| {
-            src_image.copy_to_image(q, ctx, &dst_image, src_origin, dst_origin, &region)
-        }),
-    )
+    validate_image_bounds(&src_image, src_origin, src_level, region)?;
+    validate_image_bounds(&dst_image, dst_origin, dst_level, region)?;
+
+    strict::check_copy_image(
+        &src_image,
+        &dst_image,
+        &src_origin,
+        src_level,
+        &dst_origin,
+        dst_level,
+        &region,
+    )?;
+    strict::check_unmapped(&src_image, "clEnqueueCopyImage", false)?;
+    strict::check_written(&src_image, "clEnqueueCopyImage")?;
+    strict::check_unmapped(&dst_image, "clEnqueueCopyImage", true)?;
+    strict::mark_written(&dst_image);
+
+    Ok(Box::new(move |q, ctx| {
+        src_image.copy_to_image(
+            q, ctx, &dst_image, src_origin, src_level, dst_origin, dst_level, &region,
+        )
+    }))
 
     //• CL_INVALID_IMAGE_SIZE if image dimensions (image width, height, specified or compute row and/or slice pitch) for src_image or dst_image are not supported by device associated with queue.
     //• CL_IMAGE_FORMAT_NOT_SUPPORTED if image format (image channel order and data type) for src_image or dst_image are not supported by device associated with queue.
@@ -1924,103 +2248,125 @@ fn enqueue_copy_image(
     //• CL_MEM_COPY_OVERLAP if src_image and dst_image are the same image object and the source and destination regions overlap.
 }
 
-#[cl_entrypoint(clEnqueueFillImage)]
-fn enqueue_fill_image(
+#[cl_entrypoint(clEnqueueCopyImage)]
+fn enqueue_copy_image(
     command_queue: cl_command_queue,
-    image: cl_mem,
-    fill_color: *const ::std::os::raw::c_void,
-    origin: *const [usize; 3],
-    region: *const [usize; 3],
+    src_image: cl_mem,
+    dst_image: cl_mem,
+    src_origin: *const usize,
+    dst_origin: *const usize,
+    region: *const usize,
     num_events_in_wait_list: cl_uint,
     event_wait_list: *const cl_event,
     event: *mut cl_event,
 ) -> CLResult<()> {
     let q = Queue::arc_from_raw(command_queue)?;
-    let i = Image::arc_from_raw(image)?;
     let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = copy_image_cmd(&q, src_image, dst_image, src_origin, dst_origin, region)?;
+    create_and_queue(q, CL_COMMAND_COPY_IMAGE, evs, event, false, Box::new(cmd))
+}
+
+pub fn fill_image_cmd(
+    q: &Queue,
+    image: cl_mem,
+    fill_color: *const ::std::os::raw::c_void,
+    origin: *const [usize; 3],
+    region: *const [usize; 3],
+) -> CLResult<CommandSig> {
+    let i = Image::arc_from_raw(image)?;
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and image are not the same
     if i.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if fill_color is NULL.
     // CL_INVALID_VALUE if origin or region is NULL.
     if fill_color.is_null() || origin.is_null() || region.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let region = unsafe { CLVec::from_raw(region.cast()) };
-    let origin = unsafe { CLVec::from_raw(origin.cast()) };
+    let (origin, level) = unsafe { image_origin(&i, origin.cast())? };
 
     // CL_INVALID_VALUE if the region being filled as specified by origin and region is out of
     // bounds.
     // CL_INVALID_VALUE if values in origin and region do not follow rules described in the argument
     // description for origin and region.
-    validate_image_bounds(&i, origin, region)?;
+    validate_image_bounds(&i, origin, level, region)?;
+
+    strict::check_unmapped(&i, "clEnqueueFillImage", true)?;
+    strict::mark_written(&i);
 
     // we have to copy memory and it's always a 4 component int value
     // TODO but not for CL_DEPTH
     let fill_color = unsafe { slice::from_raw_parts(fill_color.cast(), 4).to_vec() };
-    create_and_queue(
-        q,
-        CL_COMMAND_FILL_BUFFER,
-        evs,
-        event,
-        false,
-        Box::new(move |q, ctx| i.fill(q, ctx, &fill_color, &origin, &region)),
-    )
+    Ok(Box::new(move |q, ctx| {
+        i.fill(q, ctx, &fill_color, &origin, level, &region)
+    }))
 
     //• CL_INVALID_IMAGE_SIZE if image dimensions (image width, height, specified or compute row and/or slice pitch) for image are not supported by device associated with queue.
     //• CL_IMAGE_FORMAT_NOT_SUPPORTED if image format (image channel order and data type) for
     //image are not supported by device associated with queue.
 }
 
-#[cl_entrypoint(clEnqueueCopyBufferToImage)]
-fn enqueue_copy_buffer_to_image(
+#[cl_entrypoint(clEnqueueFillImage)]
+fn enqueue_fill_image(
     command_queue: cl_command_queue,
-    src_buffer: cl_mem,
-    dst_image: cl_mem,
-    src_offset: usize,
-    dst_origin: *const usize,
-    region: *const usize,
+    image: cl_mem,
+    fill_color: *const ::std::os::raw::c_void,
+    origin: *const [usize; 3],
+    region: *const [usize; 3],
     num_events_in_wait_list: cl_uint,
     event_wait_list: *const cl_event,
     event: *mut cl_event,
 ) -> CLResult<()> {
     let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = fill_image_cmd(&q, image, fill_color, origin, region)?;
+    create_and_queue(q, CL_COMMAND_FILL_BUFFER, evs, event, false, Box::new(cmd))
+}
+
+pub fn copy_buffer_to_image_cmd(
+    q: &Queue,
+    src_buffer: cl_mem,
+    dst_image: cl_mem,
+    src_offset: usize,
+    dst_origin: *const usize,
+    region: *const usize,
+) -> CLResult<CommandSig> {
     let src = Buffer::arc_from_raw(src_buffer)?;
     let dst = Image::arc_from_raw(dst_image)?;
-    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_CONTEXT if the context associated with command_queue, src_buffer and dst_image
     // are not the same
     if q.context != src.context || q.context != dst.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if dst_origin or region is NULL.
     if dst_origin.is_null() || region.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let region = unsafe { CLVec::from_raw(region) };
-    let dst_origin = unsafe { CLVec::from_raw(dst_origin) };
+    let (dst_origin, dst_level) = unsafe { image_origin(&dst, dst_origin)? };
 
     // CL_INVALID_VALUE if values in dst_origin and region do not follow rules described in the
     // argument description for dst_origin and region.
     // CL_INVALID_VALUE if the 1D, 2D or 3D rectangular region specified by dst_origin and
     // dst_origin + region refer to a region outside dst_image,
-    validate_image_bounds(&dst, dst_origin, region)?;
+    validate_image_bounds(&dst, dst_origin, dst_level, region)?;
 
-    create_and_queue(
-        q,
-        CL_COMMAND_COPY_BUFFER_TO_IMAGE,
-        evs,
-        event,
-        false,
-        Box::new(move |q, ctx| src.copy_to_image(q, ctx, &dst, src_offset, dst_origin, &region)),
-    )
+    strict::check_copy_shared_storage(&src, &dst, "clEnqueueCopyBufferToImage")?;
+    strict::check_unmapped(&src, "clEnqueueCopyBufferToImage", false)?;
+    strict::check_written(&src, "clEnqueueCopyBufferToImage")?;
+    strict::check_unmapped(&dst, "clEnqueueCopyBufferToImage", true)?;
+    strict::mark_written(&dst);
+
+    Ok(Box::new(move |q, ctx| {
+        src.copy_to_image(q, ctx, &dst, src_offset, dst_origin, dst_level, &region)
+    }))
 
     //• CL_INVALID_MEM_OBJECT if src_buffer is not a valid buffer object or dst_image is not a valid image object or if dst_image is a 1D image buffer object created from src_buffer.
     //• CL_INVALID_VALUE ... if the region specified by src_offset and src_offset + src_cb refer to a region outside src_buffer.
@@ -2031,52 +2377,72 @@ fn enqueue_copy_buffer_to_image(
     //• CL_INVALID_OPERATION if the device associated with command_queue does not support images (i.e. CL_DEVICE_IMAGE_SUPPORT specified in the Device Queries table is CL_FALSE).
 }
 
-#[cl_entrypoint(clEnqueueCopyImageToBuffer)]
-fn enqueue_copy_image_to_buffer(
+#[cl_entrypoint(clEnqueueCopyBufferToImage)]
+fn enqueue_copy_buffer_to_image(
     command_queue: cl_command_queue,
-    src_image: cl_mem,
-    dst_buffer: cl_mem,
-    src_origin: *const usize,
+    src_buffer: cl_mem,
+    dst_image: cl_mem,
+    src_offset: usize,
+    dst_origin: *const usize,
     region: *const usize,
-    dst_offset: usize,
     num_events_in_wait_list: cl_uint,
     event_wait_list: *const cl_event,
     event: *mut cl_event,
 ) -> CLResult<()> {
     let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = copy_buffer_to_image_cmd(&q, src_buffer, dst_image, src_offset, dst_origin, region)?;
+    create_and_queue(
+        q,
+        CL_COMMAND_COPY_BUFFER_TO_IMAGE,
+        evs,
+        event,
+        false,
+        Box::new(cmd),
+    )
+}
+
+pub fn copy_image_to_buffer_cmd(
+    q: &Queue,
+    src_image: cl_mem,
+    dst_buffer: cl_mem,
+    src_origin: *const usize,
+    region: *const usize,
+    dst_offset: usize,
+) -> CLResult<CommandSig> {
     let src = Image::arc_from_raw(src_image)?;
     let dst = Buffer::arc_from_raw(dst_buffer)?;
-    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_CONTEXT if the context associated with command_queue, src_image and dst_buffer
     // are not the same
     if q.context != src.context || q.context != dst.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if src_origin or region is NULL.
     if src_origin.is_null() || region.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let region = unsafe { CLVec::from_raw(region) };
-    let src_origin = unsafe { CLVec::from_raw(src_origin) };
+    let (src_origin, src_level) = unsafe { image_origin(&src, src_origin)? };
 
     // CL_INVALID_VALUE if values in src_origin and region do not follow rules described in the
     // argument description for src_origin and region.
     // CL_INVALID_VALUE if the 1D, 2D or 3D rectangular region specified by src_origin and
     // src_origin + region refers to a region outside src_image, or if the region specified by
     // dst_offset and dst_offset + dst_cb to a region outside dst_buffer.
-    validate_image_bounds(&src, src_origin, region)?;
+    validate_image_bounds(&src, src_origin, src_level, region)?;
 
-    create_and_queue(
-        q,
-        CL_COMMAND_COPY_IMAGE_TO_BUFFER,
-        evs,
-        event,
-        false,
-        Box::new(move |q, ctx| src.copy_to_buffer(q, ctx, &dst, src_origin, dst_offset, &region)),
-    )
+    strict::check_copy_shared_storage(&src, &dst, "clEnqueueCopyImageToBuffer")?;
+    strict::check_unmapped(&src, "clEnqueueCopyImageToBuffer", false)?;
+    strict::check_written(&src, "clEnqueueCopyImageToBuffer")?;
+    strict::check_unmapped(&dst, "clEnqueueCopyImageToBuffer", true)?;
+    strict::mark_written(&dst);
+
+    Ok(Box::new(move |q, ctx| {
+        src.copy_to_buffer(q, ctx, &dst, src_origin, src_level, dst_offset, &region)
+    }))
 
     //• CL_INVALID_MEM_OBJECT if src_image is not a valid image object or dst_buffer is not a valid buffer object or if src_image is a 1D image buffer object created from dst_buffer.
     //• CL_INVALID_VALUE ... if the region specified by dst_offset and dst_offset + dst_cb to a region outside dst_buffer.
@@ -2087,6 +2453,31 @@ fn enqueue_copy_image_to_buffer(
     //• CL_INVALID_OPERATION if the device associated with command_queue does not support images (i.e. CL_DEVICE_IMAGE_SUPPORT specified in the Device Queries table is CL_FALSE).
 }
 
+#[cl_entrypoint(clEnqueueCopyImageToBuffer)]
+fn enqueue_copy_image_to_buffer(
+    command_queue: cl_command_queue,
+    src_image: cl_mem,
+    dst_buffer: cl_mem,
+    src_origin: *const usize,
+    region: *const usize,
+    dst_offset: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let cmd = copy_image_to_buffer_cmd(&q, src_image, dst_buffer, src_origin, region, dst_offset)?;
+    create_and_queue(
+        q,
+        CL_COMMAND_COPY_IMAGE_TO_BUFFER,
+        evs,
+        event,
+        false,
+        Box::new(cmd),
+    )
+}
+
 #[cl_entrypoint(clEnqueueMapImage)]
 fn enqueue_map_image(
     command_queue: cl_command_queue,
@@ -2103,7 +2494,8 @@ fn enqueue_map_image(
 ) -> CLResult<*mut ::std::os::raw::c_void> {
     let q = Queue::arc_from_raw(command_queue)?;
     let i = Image::arc_from_raw(image)?;
-    let block = check_cl_bool(blocking_map).ok_or(CL_INVALID_VALUE)?;
+    let block =
+        check_cl_bool(blocking_map).ok_or(CL_INVALID_VALUE)? || Platform::conf().serialize_maps;
     let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
 
     // CL_INVALID_VALUE ... or if values specified in map_flags are not valid.
@@ -2111,43 +2503,48 @@ fn enqueue_map_image(
 
     // CL_INVALID_CONTEXT if context associated with command_queue and image are not the same
     if i.context != q.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if origin or region is NULL.
     // CL_INVALID_VALUE if image_row_pitch is NULL.
     if origin.is_null() || region.is_null() || image_row_pitch.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let region = unsafe { CLVec::from_raw(region) };
-    let origin = unsafe { CLVec::from_raw(origin) };
+    let (origin, level) = unsafe { image_origin(&i, origin)? };
 
     // CL_INVALID_VALUE if region being mapped given by (origin, origin + region) is out of bounds
     // CL_INVALID_VALUE if values in origin and region do not follow rules described in the argument
     // description for origin and region.
-    validate_image_bounds(&i, origin, region)?;
+    validate_image_bounds(&i, origin, level, region)?;
 
     let mut dummy_slice_pitch: usize = 0;
     let image_slice_pitch = if image_slice_pitch.is_null() {
         // CL_INVALID_VALUE if image is a 3D image, 1D or 2D image array object and
         // image_slice_pitch is NULL.
         if i.image_desc.is_array() || i.image_desc.image_type == CL_MEM_OBJECT_IMAGE3D {
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
         &mut dummy_slice_pitch
     } else {
         unsafe { image_slice_pitch.as_mut().unwrap() }
     };
 
+    strict::check_map_image(&i, map_flags, origin, level, region)?;
+
     let ptr = i.map(
         q.device,
         &origin,
+        level,
+        &region,
         unsafe { image_row_pitch.as_mut().unwrap() },
         image_slice_pitch,
     )?;
 
-    // SAFETY: it's required that applications do not cause data races
+    strict::add_map_image(&i, ptr, map_flags, origin, level, region);
+
     let sync_ptr = unsafe { MutMemoryPtr::from_ptr(ptr) };
     create_and_queue(
         q,
@@ -2155,7 +2552,7 @@ fn enqueue_map_image(
         evs,
         event,
         block,
-        Box::new(move |q, ctx| i.sync_shadow(q, ctx, sync_ptr)),
+        Box::new(move |q, ctx| i.sync_shadow(q, ctx, sync_ptr, origin, level, region)),
     )?;
 
     Ok(ptr)
@@ -2174,7 +2571,7 @@ fn retain_mem_object(mem: cl_mem) -> CLResult<()> {
     match m.base.get_type()? {
         RusticlTypes::Buffer => Buffer::retain(mem),
         RusticlTypes::Image => Image::retain(mem),
-        _ => Err(CL_INVALID_MEM_OBJECT),
+        _ => Err(CL_INVALID_MEM_OBJECT.into()),
     }
 }
 
@@ -2184,7 +2581,7 @@ fn release_mem_object(mem: cl_mem) -> CLResult<()> {
     match m.base.get_type()? {
         RusticlTypes::Buffer => Buffer::release(mem),
         RusticlTypes::Image => Image::release(mem),
-        _ => Err(CL_INVALID_MEM_OBJECT),
+        _ => Err(CL_INVALID_MEM_OBJECT.into()),
     }
 }
 
@@ -2203,15 +2600,17 @@ fn enqueue_unmap_mem_object(
 
     // CL_INVALID_CONTEXT if context associated with command_queue and memobj are not the same
     if q.context != m.context {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if mapped_ptr is not a valid pointer returned by clEnqueueMapBuffer or
     // clEnqueueMapImage for memobj.
     if !m.is_mapped_ptr(mapped_ptr) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
+    strict::remove_map(&m, mapped_ptr);
+
     // SAFETY: it's required that applications do not cause data races
     let mapped_ptr = unsafe { MutMemoryPtr::from_ptr(mapped_ptr) };
     create_and_queue(
@@ -2240,13 +2639,13 @@ fn enqueue_migrate_mem_objects(
 
     // CL_INVALID_VALUE if num_mem_objects is zero or if mem_objects is NULL.
     if bufs.is_empty() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_CONTEXT if the context associated with command_queue and memory objects in
     // mem_objects are not the same
     if bufs.iter().any(|b| b.context != q.context) {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if flags is not 0 or is not any of the values described in the table above.
@@ -2256,17 +2655,29 @@ fn enqueue_migrate_mem_objects(
             !(CL_MIGRATE_MEM_OBJECT_HOST | CL_MIGRATE_MEM_OBJECT_CONTENT_UNDEFINED),
         )
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
-    // we should do something, but it's legal to not do anything at all
+    let mems = MemBase::arcs_from_arr(mem_objects, num_mem_objects)?;
     create_and_queue(
         q,
         CL_COMMAND_MIGRATE_MEM_OBJECTS,
         evs,
         event,
         false,
-        Box::new(|_, _| Ok(())),
+        Box::new(move |q, _| {
+            // mapping already goes through the device holding the content, so there is nothing to
+            // do for host migrations.
+            if bit_check(flags, CL_MIGRATE_MEM_OBJECT_HOST) {
+                return Ok(());
+            }
+
+            let content_undefined = bit_check(flags, CL_MIGRATE_MEM_OBJECT_CONTENT_UNDEFINED);
+            for mem in &mems {
+                mem.migrate(q.device, content_undefined)?;
+            }
+            Ok(())
+        }),
     )
 
     //• CL_MEM_OBJECT_ALLOCATION_FAILURE if there is a failure to allocate memory for the specified set of memory objects in mem_objects.
@@ -2276,7 +2687,7 @@ fn enqueue_migrate_mem_objects(
 impl CLInfo<cl_pipe_info> for cl_mem {
     fn query(&self, _q: cl_pipe_info, _: &[u8]) -> CLResult<Vec<MaybeUninit<u8>>> {
         // CL_INVALID_MEM_OBJECT if pipe is a not a valid pipe object.
-        Err(CL_INVALID_MEM_OBJECT)
+        Err(CL_INVALID_MEM_OBJECT.into())
     }
 }
 
@@ -2293,17 +2704,23 @@ pub fn svm_alloc(
 
     // or no devices in context support SVM.
     if !c.has_svm_devs() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // flags does not contain CL_MEM_SVM_FINE_GRAIN_BUFFER but does contain CL_MEM_SVM_ATOMICS.
     if !bit_check(flags, CL_MEM_SVM_FINE_GRAIN_BUFFER) && bit_check(flags, CL_MEM_SVM_ATOMICS) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    // CL_MEM_SVM_ATOMICS is specified in flags and these are not supported by at least one device in
+    // context.
+    if bit_check(flags, CL_MEM_SVM_ATOMICS) && !c.svm_atomics_supported() {
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // size is 0 or > CL_DEVICE_MAX_MEM_ALLOC_SIZE value for any device in context.
     if size == 0 || checked_compare(size, Ordering::Greater, c.max_mem_alloc()) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     if alignment == 0 {
@@ -2312,7 +2729,7 @@ pub fn svm_alloc(
 
     // alignment is not a power of two
     if !alignment.is_power_of_two() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let layout;
@@ -2326,14 +2743,13 @@ pub fn svm_alloc(
     }
 
     if ptr.is_null() {
-        return Err(CL_OUT_OF_HOST_MEMORY);
+        return Err(CL_OUT_OF_HOST_MEMORY.into());
     }
 
-    c.add_svm_ptr(ptr as usize, layout);
+    c.add_svm_ptr(ptr as usize, layout, bit_check(flags, CL_MEM_SVM_ATOMICS));
     Ok(ptr.cast())
 
     // Values specified in flags do not follow rules described for supported values in the SVM Memory Flags table.
-    // CL_MEM_SVM_FINE_GRAIN_BUFFER or CL_MEM_SVM_ATOMICS is specified in flags and these are not supported by at least one device in context.
     // The values specified in flags are not valid, i.e. don’t match those defined in the SVM Memory Flags table.
     // the OpenCL implementation cannot support the specified alignment for at least one device in context.
     // There was a failure to allocate resources.
@@ -2374,12 +2790,12 @@ fn enqueue_svm_free_impl(
     if num_svm_pointers == 0 && !svm_pointers.is_null()
         || num_svm_pointers != 0 && svm_pointers.is_null()
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_OPERATION if the device associated with command queue does not support SVM.
     if !q.device.svm_supported() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // The application is allowed to reuse or free the memory referenced by `svm_pointers` after this
@@ -2476,7 +2892,7 @@ fn enqueue_svm_memcpy_impl(
 
     // CL_INVALID_OPERATION if the device associated with command queue does not support SVM.
     if !q.device.svm_supported() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_MEM_COPY_OVERLAP if the values specified for dst_ptr, src_ptr and size result in an
@@ -2486,7 +2902,7 @@ fn enqueue_svm_memcpy_impl(
     if (src_ptr_addr <= dst_ptr_addr && dst_ptr_addr < src_ptr_addr + size)
         || (dst_ptr_addr <= src_ptr_addr && src_ptr_addr < dst_ptr_addr + size)
     {
-        return Err(CL_MEM_COPY_OVERLAP);
+        return Err(CL_MEM_COPY_OVERLAP.into());
     }
 
     // CAST: We have no idea about the type or initialization status of these bytes.
@@ -2583,17 +2999,17 @@ fn enqueue_svm_mem_fill_impl(
 
     // CL_INVALID_OPERATION if the device associated with command queue does not support SVM.
     if !q.device.svm_supported() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_VALUE if pattern is NULL [...]
     if pattern.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if size is not a multiple of pattern_size.
     if size % pattern_size != 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // The provided `$bytesize` must equal `pattern_size`.
@@ -2692,7 +3108,7 @@ fn enqueue_svm_mem_fill_impl(
         _ => {
             // CL_INVALID_VALUE if [...] pattern_size is 0 or if pattern_size is not one of
             // {1, 2, 4, 8, 16, 32, 64, 128}.
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
     };
 
@@ -2760,21 +3176,22 @@ fn enqueue_svm_map_impl(
 ) -> CLResult<()> {
     let q = Queue::arc_from_raw(command_queue)?;
     let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
-    let block = check_cl_bool(blocking_map).ok_or(CL_INVALID_VALUE)?;
+    let block =
+        check_cl_bool(blocking_map).ok_or(CL_INVALID_VALUE)? || Platform::conf().serialize_maps;
 
     // CL_INVALID_OPERATION if the device associated with command queue does not support SVM.
     if !q.device.svm_supported() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_VALUE if svm_ptr is NULL.
     if svm_ptr.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if size is 0 ...
     if size == 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // ... or if values specified in map_flags are not valid.
@@ -2844,12 +3261,12 @@ fn enqueue_svm_unmap_impl(
 
     // CL_INVALID_OPERATION if the device associated with command queue does not support SVM.
     if !q.device.svm_supported() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_VALUE if svm_ptr is NULL.
     if svm_ptr.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     create_and_queue(q, cmd_type, evs, event, false, Box::new(|_, _| Ok(())))
@@ -2907,12 +3324,12 @@ fn enqueue_svm_migrate_mem(
 
     // CL_INVALID_OPERATION if the device associated with command queue does not support SVM.
     if !q.device.svm_supported() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_VALUE if num_svm_pointers is zero
     if num_svm_pointers == 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let num_svm_pointers = num_svm_pointers as usize;
@@ -2944,7 +3361,7 @@ fn enqueue_svm_migrate_mem(
             }
         }
 
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let to_device = !bit_check(flags, CL_MIGRATE_MEM_OBJECT_HOST);
@@ -2963,6 +3380,319 @@ fn enqueue_svm_migrate_mem(
     )
 }
 
+// cl_intel_unified_shared_memory: USM allocations are plain system allocations like the ones of
+// clSVMAlloc, which requires devices supporting system SVM.
+
+fn validate_usm_props(properties: *const cl_mem_properties_intel) -> CLResult<()> {
+    let mut alloc_flags = None;
+    // SAFETY: the list is required to be terminated
+    let mut reader = unsafe { PropertyReader::new(properties) };
+    while let Some(key) = reader.key() {
+        // CL_INVALID_PROPERTY if a memory property name in properties is not a supported property
+        // name, if the value specified for a supported property name is not valid, or if the same
+        // property name is specified more than once.
+        match u32::try_from(key) {
+            Ok(CL_MEM_ALLOC_FLAGS_INTEL) if alloc_flags.is_none() => {
+                alloc_flags = Some(reader.value());
+            }
+            _ => return Err(CL_INVALID_PROPERTY.into()),
+        }
+    }
+
+    // all flags are hints we are free to ignore, but the placement hints contradict each other.
+    let placement =
+        CL_MEM_ALLOC_INITIAL_PLACEMENT_DEVICE_INTEL | CL_MEM_ALLOC_INITIAL_PLACEMENT_HOST_INTEL;
+    let alloc_flags = Flags::<cl_mem_alloc_flags_intel>::new(alloc_flags.unwrap_or_default());
+    if !alloc_flags.is_subset_of(CL_MEM_ALLOC_WRITE_COMBINED_INTEL | placement)
+        || alloc_flags.count(placement) > 1
+    {
+        return Err(CL_INVALID_PROPERTY.into());
+    }
+
+    Ok(())
+}
+
+fn usm_alloc(
+    c: &Context,
+    dev: Option<&'static Device>,
+    properties: *const cl_mem_properties_intel,
+    size: usize,
+    alignment: cl_uint,
+    mem_type: cl_unified_shared_memory_type_intel,
+) -> CLResult<*mut c_void> {
+    // CL_INVALID_DEVICE if device is not a valid device or is not associated with context.
+    if dev.is_some_and(|dev| !c.devs.contains(&dev)) {
+        return Err(CL_INVALID_DEVICE.into());
+    }
+
+    // CL_INVALID_OPERATION if device does not support the allocation type, or if no devices in
+    // context support it.
+    let devs = dev.as_ref().map_or(c.devs.as_slice(), slice::from_ref);
+    if devs.iter().all(|dev| dev.usm_caps() == 0) {
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    validate_usm_props(properties)?;
+
+    // CL_INVALID_BUFFER_SIZE if size is zero or greater than CL_DEVICE_MAX_MEM_ALLOC_SIZE.
+    let max_size = dev.map_or_else(|| c.max_mem_alloc(), |dev| dev.max_mem_alloc());
+    if size == 0 || checked_compare(size, Ordering::Greater, max_size) {
+        return Err(CL_INVALID_BUFFER_SIZE.into());
+    }
+
+    // CL_INVALID_VALUE if alignment is not zero or a power of two.
+    let alignment = match alignment {
+        0 => mem::size_of::<[u64; 16]>(),
+        _ if alignment.is_power_of_two() => alignment as usize,
+        _ => return Err(CL_INVALID_VALUE.into()),
+    };
+
+    let layout = Layout::from_size_align(size, alignment).map_err(|_| CL_INVALID_BUFFER_SIZE)?;
+    // SAFETY: layout is of non zero size
+
+    // SAFETY: it's required that applications do not cause data races
+    let ptr = unsafe { alloc::alloc(layout) };
+    if ptr.is_null() {
+        return Err(CL_OUT_OF_HOST_MEMORY.into());
+    }
+
+    c.add_usm_ptr(
+        ptr as usize,
+        UsmAlloc {
+            layout: layout,
+            mem_type: mem_type,
+            dev: dev,
+        },
+    );
+    Ok(ptr.cast())
+}
+
+#[cl_entrypoint(clHostMemAllocINTEL)]
+fn host_mem_alloc(
+    context: cl_context,
+    properties: *const cl_mem_properties_intel,
+    size: usize,
+    alignment: cl_uint,
+) -> CLResult<*mut c_void> {
+    let c = Context::ref_from_raw(context)?;
+    usm_alloc(c, None, properties, size, alignment, CL_MEM_TYPE_HOST_INTEL)
+}
+
+#[cl_entrypoint(clDeviceMemAllocINTEL)]
+fn device_mem_alloc(
+    context: cl_context,
+    device: cl_device_id,
+    properties: *const cl_mem_properties_intel,
+    size: usize,
+    alignment: cl_uint,
+) -> CLResult<*mut c_void> {
+    let c = Context::ref_from_raw(context)?;
+    let dev = Device::ref_from_raw(device)?;
+    usm_alloc(
+        c,
+        Some(dev),
+        properties,
+        size,
+        alignment,
+        CL_MEM_TYPE_DEVICE_INTEL,
+    )
+}
+
+#[cl_entrypoint(clSharedMemAllocINTEL)]
+fn shared_mem_alloc(
+    context: cl_context,
+    device: cl_device_id,
+    properties: *const cl_mem_properties_intel,
+    size: usize,
+    alignment: cl_uint,
+) -> CLResult<*mut c_void> {
+    let c = Context::ref_from_raw(context)?;
+    // shared allocations don't have to be associated with a device.
+    let dev = if device.is_null() {
+        None
+    } else {
+        Some(Device::ref_from_raw(device)?)
+    };
+    usm_alloc(
+        c,
+        dev,
+        properties,
+        size,
+        alignment,
+        CL_MEM_TYPE_SHARED_INTEL,
+    )
+}
+
+fn usm_free(c: &Context, ptr: *mut c_void) -> CLResult<()> {
+    // freeing NULL is allowed and does nothing.
+    if ptr.is_null() {
+        return Ok(());
+    }
+
+    let alloc = c.remove_usm_ptr(ptr as usize).ok_or(CL_INVALID_VALUE)?;
+    // SAFETY: ptr is a valid allocation and we reuse the same layout from the allocation
+    unsafe {
+        alloc::dealloc(ptr.cast(), alloc.layout);
+    }
+    Ok(())
+}
+
+#[cl_entrypoint(clMemFreeINTEL)]
+fn mem_free(context: cl_context, ptr: *mut c_void) -> CLResult<()> {
+    let c = Context::ref_from_raw(context)?;
+    usm_free(c, ptr)
+}
+
+#[cl_entrypoint(clMemBlockingFreeINTEL)]
+fn mem_blocking_free(context: cl_context, ptr: *mut c_void) -> CLResult<()> {
+    let c = Context::ref_from_raw(context)?;
+    // we don't know which commands use the allocation, so wait on all of them.
+    c.finish_queues()?;
+    usm_free(c, ptr)
+}
+
+impl CLInfoObj<cl_mem_info_intel, *const c_void> for cl_context {
+    fn query(&self, ptr: *const c_void, q: cl_mem_info_intel) -> CLResult<Vec<MaybeUninit<u8>>> {
+        let c = Context::ref_from_raw(*self)?;
+        // queries on pointers not pointing into a USM allocation are valid, but return nothing.
+        let alloc = c.find_usm_alloc(ptr as usize);
+        Ok(match q {
+            CL_MEM_ALLOC_BASE_PTR_INTEL => cl_prop::<*const c_void>(
+                alloc.map_or(ptr::null(), |(base, _)| base as *const c_void),
+            ),
+            CL_MEM_ALLOC_DEVICE_INTEL => {
+                let dev = alloc.and_then(|(_, alloc)| alloc.dev);
+                cl_prop::<cl_device_id>(
+                    dev.map_or(ptr::null_mut(), |dev| cl_device_id::from_ptr(dev)),
+                )
+            }
+            CL_MEM_ALLOC_SIZE_INTEL => {
+                cl_prop::<usize>(alloc.map_or(0, |(_, alloc)| alloc.layout.size()))
+            }
+            CL_MEM_ALLOC_TYPE_INTEL => cl_prop::<cl_unified_shared_memory_type_intel>(
+                alloc.map_or(CL_MEM_TYPE_UNKNOWN_INTEL, |(_, alloc)| alloc.mem_type),
+            ),
+            // CL_INVALID_VALUE if param_name is not one of the supported values
+            _ => return Err(CL_INVALID_VALUE.into()),
+        })
+    }
+}
+
+#[cl_entrypoint(clEnqueueMemcpyINTEL)]
+fn enqueue_mem_copy_intel(
+    command_queue: cl_command_queue,
+    blocking: cl_bool,
+    dst_ptr: *mut c_void,
+    src_ptr: *const c_void,
+    size: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    enqueue_svm_memcpy_impl(
+        command_queue,
+        blocking,
+        dst_ptr,
+        src_ptr,
+        size,
+        num_events_in_wait_list,
+        event_wait_list,
+        event,
+        CL_COMMAND_MEMCPY_INTEL,
+    )
+}
+
+#[cl_entrypoint(clEnqueueMemFillINTEL)]
+fn enqueue_mem_fill_intel(
+    command_queue: cl_command_queue,
+    dst_ptr: *mut c_void,
+    pattern: *const c_void,
+    pattern_size: usize,
+    size: usize,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    enqueue_svm_mem_fill_impl(
+        command_queue,
+        dst_ptr,
+        pattern,
+        pattern_size,
+        size,
+        num_events_in_wait_list,
+        event_wait_list,
+        event,
+        CL_COMMAND_MEMFILL_INTEL,
+    )
+}
+
+#[cl_entrypoint(clEnqueueMigrateMemINTEL)]
+fn enqueue_migrate_mem_intel(
+    command_queue: cl_command_queue,
+    ptr: *const c_void,
+    size: usize,
+    flags: cl_mem_migration_flags,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+
+    // CL_INVALID_VALUE if the range isn't contained within a USM allocation.
+    let ptr = ptr as usize;
+    let (base, alloc) = q.context.find_usm_alloc(ptr).ok_or(CL_INVALID_VALUE)?;
+    if size == 0 || size > alloc.layout.size() - (ptr - base) {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    // CL_INVALID_VALUE if flags is not 0 or any of the values described in the table.
+    if !Flags::<cl_mem_migration_flags>::new(flags)
+        .is_subset_of(CL_MIGRATE_MEM_OBJECT_HOST | CL_MIGRATE_MEM_OBJECT_CONTENT_UNDEFINED)
+    {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    let to_device = !bit_check(flags, CL_MIGRATE_MEM_OBJECT_HOST);
+    let content_undefined = bit_check(flags, CL_MIGRATE_MEM_OBJECT_CONTENT_UNDEFINED);
+
+    create_and_queue(
+        q,
+        CL_COMMAND_MIGRATEMEM_INTEL,
+        evs,
+        event,
+        false,
+        Box::new(move |_, ctx| {
+            ctx.svm_migrate(&[ptr], &[size], to_device, content_undefined);
+            Ok(())
+        }),
+    )
+}
+
+#[cl_entrypoint(clEnqueueMemAdviseINTEL)]
+fn enqueue_mem_advise_intel(
+    command_queue: cl_command_queue,
+    _ptr: *const c_void,
+    _size: usize,
+    _advice: cl_mem_advice_intel,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+
+    // advice is a hint, which we have no use for.
+    create_and_queue(
+        q,
+        CL_COMMAND_MEMADVISE_INTEL,
+        evs,
+        event,
+        false,
+        Box::new(|_, _| Ok(())),
+    )
+}
+
 #[cl_entrypoint(clCreatePipe)]
 fn create_pipe(
     _context: cl_context,
@@ -2971,7 +3701,7 @@ fn create_pipe(
     _pipe_max_packets: cl_uint,
     _properties: *const cl_pipe_properties,
 ) -> CLResult<cl_mem> {
-    Err(CL_INVALID_OPERATION)
+    Err(CL_INVALID_OPERATION.into())
 }
 
 #[cl_info_entrypoint(clGetGLTextureInfo)]
@@ -2986,7 +3716,7 @@ impl CLInfo<cl_gl_texture_info> for cl_mem {
                     .ok_or(CL_INVALID_GL_OBJECT)?
                     .gl_object_target,
             ),
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -3003,17 +3733,22 @@ fn create_from_gl(
 
     // CL_INVALID_CONTEXT if context associated with command_queue was not created from an OpenGL context
     if gl_ctx_manager.is_none() {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_VALUE if values specified in flags are not valid or if value specified in
     // texture_target is not one of the values specified in the description of texture_target.
     validate_mem_flags(flags, target == GL_ARRAY_BUFFER)?;
 
+    // OpenGL objects are never protected.
+    if bit_check(flags, CL_MEM_PROTECTED_ALLOC_ARM) {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
     // CL_INVALID_MIP_LEVEL if miplevel is greather than zero and the OpenGL
     // implementation does not support creating from non-zero mipmap levels.
     if miplevel > 0 {
-        return Err(CL_INVALID_MIP_LEVEL);
+        return Err(CL_INVALID_MIP_LEVEL.into());
     }
 
     // CL_INVALID_CONTEXT if context [..] was not created from a GL context.
@@ -3023,7 +3758,7 @@ fn create_from_gl(
 
         Ok(MemBase::from_gl(c, flags, &gl_export_manager)?)
     } else {
-        Err(CL_INVALID_CONTEXT)
+        Err(CL_INVALID_CONTEXT.into())
     }
 }
 
@@ -3038,7 +3773,7 @@ fn create_from_gl_texture(
     // CL_INVALID_VALUE if values specified in flags are not valid or if value specified in
     // texture_target is not one of the values specified in the description of texture_target.
     if !is_valid_gl_texture(target) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     create_from_gl(context, flags, target, miplevel, texture)
@@ -3055,7 +3790,7 @@ fn create_from_gl_texture_2d(
     // CL_INVALID_VALUE if values specified in flags are not valid or if value specified in
     // texture_target is not one of the values specified in the description of texture_target.
     if !is_valid_gl_texture_2d(target) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     create_from_gl(context, flags, target, miplevel, texture)
@@ -3072,7 +3807,7 @@ fn create_from_gl_texture_3d(
     // CL_INVALID_VALUE if values specified in flags are not valid or if value specified in
     // texture_target is not one of the values specified in the description of texture_target.
     if target != GL_TEXTURE_3D {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     create_from_gl(context, flags, target, miplevel, texture)
@@ -3111,7 +3846,7 @@ fn get_gl_object_info(
         }
         None => {
             // CL_INVALID_GL_OBJECT if there is no GL object associated with memobj.
-            return Err(CL_INVALID_GL_OBJECT);
+            return Err(CL_INVALID_GL_OBJECT.into());
         }
     }
 
@@ -3134,12 +3869,12 @@ fn enqueue_acquire_gl_objects(
 
     // CL_INVALID_CONTEXT if context associated with command_queue was not created from an OpenGL context
     if gl_ctx_manager.is_none() {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_GL_OBJECT if memory objects in mem_objects have not been created from a GL object(s).
     if objs.iter().any(|o| o.gl_obj.is_none()) {
-        return Err(CL_INVALID_GL_OBJECT);
+        return Err(CL_INVALID_GL_OBJECT.into());
     }
 
     create_and_queue(
@@ -3168,12 +3903,12 @@ fn enqueue_release_gl_objects(
 
     // CL_INVALID_CONTEXT if context associated with command_queue was not created from an OpenGL context
     if gl_ctx_manager.is_none() {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     // CL_INVALID_GL_OBJECT if memory objects in mem_objects have not been created from a GL object(s).
     if objs.iter().any(|o| o.gl_obj.is_none()) {
-        return Err(CL_INVALID_GL_OBJECT);
+        return Err(CL_INVALID_GL_OBJECT.into());
     }
 
     create_and_queue(
@@ -3185,3 +3920,126 @@ fn enqueue_release_gl_objects(
         Box::new(move |q, ctx| copy_slice_to_cube(q, ctx, &objs)),
     )
 }
+
+fn enqueue_external_mem_objects(
+    command_queue: cl_command_queue,
+    num_mem_objects: cl_uint,
+    mem_objects: *const cl_mem,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+    cmd_type: cl_command_type,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+
+    // CL_INVALID_VALUE if num_mem_objects is zero and mem_objects is not a NULL value or if
+    // num_mem_objects > 0 and mem_objects is NULL.
+    let objs = MemBase::refs_from_arr(mem_objects, num_mem_objects)?;
+    for obj in objs {
+        // CL_INVALID_MEM_OBJECT if any of the memory objects in mem_objects is not a valid OpenCL
+        // memory object created using an external memory handle.
+        let external = obj.external().ok_or(CL_INVALID_MEM_OBJECT)?;
+
+        // CL_INVALID_COMMAND_QUEUE if the device associated with command_queue is not one of the
+        // devices specified by CL_DEVICE_HANDLE_LIST_KHR at the time of creating one or more of
+        // mem_objects.
+        if !external.devs.contains(&q.device) {
+            return Err(CL_INVALID_COMMAND_QUEUE.into());
+        }
+    }
+
+    // dma-bufs are implicitly synchronized by the kernel, so all we have to do is to order the
+    // command against the other commands of the queue.
+    create_and_queue(q, cmd_type, evs, event, false, Box::new(|_, _| Ok(())))
+}
+
+#[cl_entrypoint(clEnqueueAcquireExternalMemObjectsKHR)]
+fn enqueue_acquire_external_mem_objects(
+    command_queue: cl_command_queue,
+    num_mem_objects: cl_uint,
+    mem_objects: *const cl_mem,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    enqueue_external_mem_objects(
+        command_queue,
+        num_mem_objects,
+        mem_objects,
+        num_events_in_wait_list,
+        event_wait_list,
+        event,
+        CL_COMMAND_ACQUIRE_EXTERNAL_MEM_OBJECTS_KHR,
+    )
+}
+
+#[cl_entrypoint(clEnqueueReleaseExternalMemObjectsKHR)]
+fn enqueue_release_external_mem_objects(
+    command_queue: cl_command_queue,
+    num_mem_objects: cl_uint,
+    mem_objects: *const cl_mem,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    enqueue_external_mem_objects(
+        command_queue,
+        num_mem_objects,
+        mem_objects,
+        num_events_in_wait_list,
+        event_wait_list,
+        event,
+        CL_COMMAND_RELEASE_EXTERNAL_MEM_OBJECTS_KHR,
+    )
+}
+
+// cl_mesa_dma_buf_export: exports the resource backing a memory object on a device as a dma-buf.
+// The returned fd is owned by the application.
+#[cl_entrypoint(clGetMemObjectDmaBufMESA)]
+fn get_mem_object_dma_buf(
+    memobj: cl_mem,
+    device: cl_device_id,
+    fd: *mut c_int,
+    modifier: *mut cl_ulong,
+    row_pitch: *mut usize,
+    offset: *mut usize,
+) -> CLResult<()> {
+    let mem = MemBase::arc_from_raw(memobj)?;
+    let devs = &mem.context.devs;
+
+    // device can only be NULL if there is only one device to pick from.
+    let dev = if device.is_null() {
+        match devs.as_slice() {
+            [dev] => *dev,
+            _ => return Err(CL_INVALID_DEVICE.into()),
+        }
+    } else {
+        Device::ref_from_raw(device)?
+    };
+
+    if !devs.contains(&dev) {
+        return Err(CL_INVALID_DEVICE.into());
+    }
+
+    if fd.is_null() {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    if !dev.dma_buf_export_supported() {
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    let export = mem.export_dma_buf(dev)?;
+    // sub buffers are exported as their parent
+    let mem_offset = match &mem {
+        Mem::Buffer(b) => b.offset,
+        Mem::Image(_) => 0,
+    };
+
+    fd.write_checked(export.fd);
+    modifier.write_checked(export.modifier);
+    row_pitch.write_checked(export.stride as usize);
+    offset.write_checked(export.offset as usize + mem_offset);
+    Ok(())
+}
@@ -0,0 +1,71 @@
+//! Keeps panics from unwinding into the application.
+//!
+//! Unwinding out of an `extern "C"` function aborts the process, which takes down the entire
+//! application because of a bug in rusticl. Instead, all entry points run through [catch], which
+//! turns panics into `CL_OUT_OF_RESOURCES` after logging them together with a backtrace of where
+//! the panic happened.
+
+use crate::api::icd::CLResult;
+
+use mesa_rust_util::log_error;
+use rusticl_opencl_gen::*;
+
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::Cell;
+use std::cell::RefCell;
+use std::panic;
+use std::panic::AssertUnwindSafe;
+use std::sync::Once;
+
+static INSTALL_HOOK: Once = Once::new();
+
+thread_local! {
+    /// Number of nested [catch] calls, e.g. through callbacks calling back into the API.
+    static CATCHING: Cell<u32> = const { Cell::new(0) };
+    static BACKTRACE: RefCell<Option<Backtrace>> = const { RefCell::new(None) };
+}
+
+/// The backtrace needs to be captured inside the panic hook, as it's already unwound by the time
+/// [catch] sees the panic. Panics outside of rusticl are forwarded to the previous hook.
+fn install_hook() {
+    let prev = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        if CATCHING.with(Cell::get) > 0 {
+            BACKTRACE.with(|bt| *bt.borrow_mut() = Some(Backtrace::force_capture()));
+        } else {
+            prev(info);
+        }
+    }));
+}
+
+fn panic_msg(payload: &(dyn Any + Send)) -> &str {
+    if let Some(msg) = payload.downcast_ref::<&str>() {
+        msg
+    } else if let Some(msg) = payload.downcast_ref::<String>() {
+        msg
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Calls `f`, turning any panic into an error.
+pub fn catch<T>(name: &str, f: impl FnOnce() -> CLResult<T>) -> CLResult<T> {
+    INSTALL_HOOK.call_once(install_hook);
+
+    CATCHING.with(|c| c.set(c.get() + 1));
+    // objects touched by a panicking call might be left in an inconsistent state, but that's
+    // still better than aborting.
+    let res = panic::catch_unwind(AssertUnwindSafe(f));
+    CATCHING.with(|c| c.set(c.get() - 1));
+
+    res.unwrap_or_else(|payload| {
+        let backtrace = BACKTRACE.with(|bt| bt.borrow_mut().take());
+        log_error!(
+            "{name} panicked: {}\n{}",
+            panic_msg(payload.as_ref()),
+            backtrace.map_or(String::new(), |bt| bt.to_string()),
+        );
+        Err(CL_OUT_OF_RESOURCES.into())
+    })
+}
@@ -1,5 +1,6 @@
 use crate::api::icd::CLResult;
 use crate::api::util::*;
+use crate::core::device::Device;
 use crate::core::platform::*;
 use crate::core::version::*;
 
@@ -10,26 +11,54 @@ use rusticl_proc_macros::cl_info_entrypoint;
 
 use std::mem::MaybeUninit;
 
+/// Returns the handle types all devices support.
+fn common_handle_types(platform: &Platform, f: fn(&Device) -> Vec<cl_uint>) -> Vec<cl_uint> {
+    platform
+        .devs
+        .iter()
+        .map(f)
+        .reduce(|a, b| a.into_iter().filter(|t| b.contains(t)).collect())
+        .unwrap_or_default()
+}
+
 #[cl_info_entrypoint(clGetPlatformInfo)]
 impl CLInfo<cl_platform_info> for cl_platform_id {
     fn query(&self, q: cl_platform_info, _: &[u8]) -> CLResult<Vec<MaybeUninit<u8>>> {
-        self.get_ref()?;
+        let platform = self.get_ref()?;
         Ok(match q {
             // TODO spirv
             CL_PLATFORM_EXTENSIONS => cl_prop(PLATFORM_EXTENSION_STR),
             CL_PLATFORM_EXTENSIONS_WITH_VERSION => {
                 cl_prop::<Vec<cl_name_version>>(PLATFORM_EXTENSIONS.to_vec())
             }
+            CL_PLATFORM_EXTERNAL_MEMORY_IMPORT_HANDLE_TYPES_KHR => {
+                let handle_types =
+                    common_handle_types(platform, Device::external_memory_import_handle_types);
+                cl_prop::<Vec<cl_external_memory_handle_type_khr>>(handle_types)
+            }
             CL_PLATFORM_HOST_TIMER_RESOLUTION => cl_prop::<cl_ulong>(1),
             CL_PLATFORM_ICD_SUFFIX_KHR => cl_prop("MESA"),
             CL_PLATFORM_NAME => cl_prop("rusticl"),
             CL_PLATFORM_NUMERIC_VERSION => cl_prop::<cl_version>(CLVersion::Cl3_0 as u32),
             CL_PLATFORM_PROFILE => cl_prop("FULL_PROFILE"),
+            CL_PLATFORM_SEMAPHORE_EXPORT_HANDLE_TYPES_KHR => {
+                let handle_types =
+                    common_handle_types(platform, Device::semaphore_export_handle_types);
+                cl_prop::<Vec<cl_external_semaphore_handle_type_khr>>(handle_types)
+            }
+            CL_PLATFORM_SEMAPHORE_IMPORT_HANDLE_TYPES_KHR => {
+                let handle_types =
+                    common_handle_types(platform, Device::semaphore_import_handle_types);
+                cl_prop::<Vec<cl_external_semaphore_handle_type_khr>>(handle_types)
+            }
+            CL_PLATFORM_SEMAPHORE_TYPES_KHR => {
+                cl_prop::<Vec<cl_semaphore_type_khr>>(vec![CL_SEMAPHORE_TYPE_BINARY_KHR])
+            }
             CL_PLATFORM_VENDOR => cl_prop("Mesa/X.org"),
             // OpenCL<space><major_version.minor_version><space><platform-specific information>
             CL_PLATFORM_VERSION => cl_prop("OpenCL 3.0 "),
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -42,12 +71,12 @@ fn get_platform_ids(
 ) -> CLResult<()> {
     // CL_INVALID_VALUE if num_entries is equal to zero and platforms is not NULL
     if num_entries == 0 && !platforms.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // or if both num_platforms and platforms are NULL."
     if num_platforms.is_null() && platforms.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // run initialization code once
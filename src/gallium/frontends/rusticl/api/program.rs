@@ -3,10 +3,12 @@ use crate::api::types::*;
 use crate::api::util::*;
 use crate::core::context::*;
 use crate::core::device::*;
-use crate::core::platform::*;
 use crate::core::program::*;
 
 use mesa_rust::compiler::clc::*;
+use mesa_rust_util::log::LogLevel;
+use mesa_rust_util::log_debug;
+use mesa_rust_util::log_enabled;
 use mesa_rust_util::string::*;
 use rusticl_opencl_gen::*;
 use rusticl_proc_macros::cl_entrypoint;
@@ -55,7 +57,7 @@ impl CLInfo<cl_program_info> for cl_program {
                 _ => Vec::new(),
             },
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -72,7 +74,7 @@ impl CLInfoObj<cl_program_build_info, cl_device_id> for cl_program {
             CL_PROGRAM_BUILD_OPTIONS => cl_prop::<&str>(&prog.options(dev)),
             CL_PROGRAM_BUILD_STATUS => cl_prop::<cl_build_status>(prog.status(dev)),
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -104,13 +106,13 @@ fn create_program_with_source(
 
     // CL_INVALID_VALUE if count is zero or if strings ...
     if count == 0 || strings.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // ... or any entry in strings is NULL.
     let srcs = unsafe { slice::from_raw_parts(strings, count as usize) };
     if srcs.contains(&ptr::null()) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // "lengths argument is an array with the number of chars in each string
@@ -186,18 +188,18 @@ fn create_program_with_binary(
 
     // CL_INVALID_VALUE if device_list is NULL or num_devices is zero.
     if devs.is_empty() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_VALUE if lengths or binaries is NULL
     if lengths.is_null() || binaries.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_DEVICE if any device in device_list is not in the list of devices associated with
     // context.
     if !devs.iter().all(|d| c.devs.contains(d)) {
-        return Err(CL_INVALID_DEVICE);
+        return Err(CL_INVALID_DEVICE.into());
     }
 
     let lengths = unsafe { slice::from_raw_parts(lengths, num_devices as usize) };
@@ -205,32 +207,42 @@ fn create_program_with_binary(
 
     // now device specific stuff
     let mut err = 0;
-    let mut bins: Vec<&[u8]> = vec![&[]; num_devices as usize];
-    for i in 0..num_devices as usize {
+    let mut bins = Vec::with_capacity(num_devices as usize);
+    for (i, &dev) in devs.iter().enumerate() {
         let mut dev_err = 0;
 
         // CL_INVALID_VALUE if lengths[i] is zero or if binaries[i] is a NULL value
         if lengths[i] == 0 || binaries[i].is_null() {
             dev_err = CL_INVALID_VALUE;
+        } else {
+            // SAFETY: have to trust the application here
+            let bin = unsafe { slice::from_raw_parts(binaries[i], lengths[i]) };
+
+            // CL_INVALID_BINARY if an invalid program binary was encountered for any device.
+            // binary_status will return specific status for each device.
+            match parse_bin(dev, bin) {
+                Some(bin) => bins.push(bin),
+                None => dev_err = CL_INVALID_BINARY,
+            }
         }
 
         if !binary_status.is_null() {
             unsafe { binary_status.add(i).write(dev_err) };
         }
 
-        // just return the last one
-        err = dev_err;
-        bins[i] = unsafe { slice::from_raw_parts(binaries[i], lengths[i]) };
+        // just return the last error
+        if dev_err != 0 {
+            err = dev_err;
+        }
     }
 
     if err != 0 {
-        return Err(err);
+        return Err(err.into());
     }
 
     let prog = Program::from_bins(c, devs, &bins);
 
     Ok(prog.into_cl())
-    //• CL_INVALID_BINARY if an invalid program binary was encountered for any device. binary_status will return specific status for each device.
 }
 
 #[cl_entrypoint(clCreateProgramWithIL)]
@@ -243,7 +255,7 @@ fn create_program_with_il(
 
     // CL_INVALID_VALUE if il is NULL or if length is zero.
     if il.is_null() || length == 0 {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // SAFETY: according to API spec
@@ -262,11 +274,11 @@ fn release_program(program: cl_program) -> CLResult<()> {
 }
 
 fn debug_logging(p: &Program, devs: &[&Device]) {
-    if Platform::dbg().program {
+    if log_enabled!(LogLevel::Debug) {
         for dev in devs {
             let msg = p.log(dev);
             if !msg.is_empty() {
-                eprintln!("{}", msg);
+                log_debug!("{}", msg);
             }
         }
     }
@@ -291,7 +303,7 @@ fn build_program(
 
     // CL_INVALID_OPERATION if there are kernel objects attached to program.
     if p.active_kernels() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_BUILD_PROGRAM_FAILURE if there is a failure to build the program executable. This error
@@ -313,7 +325,7 @@ fn build_program(
     if res {
         Ok(())
     } else {
-        Err(CL_BUILD_PROGRAM_FAILURE)
+        Err(CL_BUILD_PROGRAM_FAILURE.into())
     }
 }
 
@@ -343,7 +355,7 @@ fn compile_program(
     if num_input_headers == 0 && (!header_include_names.is_null() || !input_headers.is_null())
         || num_input_headers != 0 && (header_include_names.is_null() || input_headers.is_null())
     {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     let mut headers = Vec::new();
@@ -360,7 +372,7 @@ fn compile_program(
                     name: unsafe { CStr::from_ptr(*header_include_names.add(h)).to_owned() },
                     source: src,
                 }),
-                _ => return Err(CL_INVALID_OPERATION),
+                _ => return Err(CL_INVALID_OPERATION.into()),
             }
         }
     }
@@ -368,12 +380,12 @@ fn compile_program(
     // CL_INVALID_OPERATION if program has no source or IL available, i.e. it has not been created
     // with clCreateProgramWithSource or clCreateProgramWithIL.
     if !(p.is_src() || p.is_il()) {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_INVALID_OPERATION if there are kernel objects attached to program.
     if p.active_kernels() {
-        return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_OPERATION.into());
     }
 
     // CL_COMPILE_PROGRAM_FAILURE if there is a failure to compile the program source. This error
@@ -393,7 +405,7 @@ fn compile_program(
     if res {
         Ok(())
     } else {
-        Err(CL_COMPILE_PROGRAM_FAILURE)
+        Err(CL_COMPILE_PROGRAM_FAILURE.into())
     }
 }
 
@@ -417,31 +429,39 @@ pub fn link_program(
 
     // CL_INVALID_VALUE if num_input_programs is zero and input_programs is NULL
     if progs.is_empty() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_DEVICE if any device in device_list is not in the list of devices associated with
     // context.
     if !devs.iter().all(|d| c.devs.contains(d)) {
-        return Err(CL_INVALID_DEVICE);
-    }
-
-    // CL_INVALID_OPERATION if the compilation or build of a program executable for any of the
-    // devices listed in device_list by a previous call to clCompileProgram or clBuildProgram for
-    // program has not completed.
-    for d in &devs {
-        if progs
-            .iter()
-            .map(|p| p.status(d))
-            .any(|s| s != CL_BUILD_SUCCESS as cl_build_status)
-        {
-            return Err(CL_INVALID_OPERATION);
+        return Err(CL_INVALID_DEVICE.into());
+    }
+
+    // CL_INVALID_LINKER_OPTIONS if the linker options specified by options are invalid.
+    let options = c_string_to_string(options);
+    validate_link_options(&options)?;
+
+    // For each device in device_list, [...]
+    // • All programs specified by input_programs contain a compiled binary or library for the
+    //   device. In this case, a link is performed to generate a program executable for this
+    //   device.
+    // • None of the programs contain a compiled binary or library for that device. In this case,
+    //   no link is performed and there will be no program executable generated for this device.
+    // • All other cases will return a CL_INVALID_OPERATION error.
+    let mut linked_devs = Vec::new();
+    for &d in &devs {
+        let linkable = progs.iter().filter(|p| p.is_linkable(d)).count();
+        if linkable == progs.len() {
+            linked_devs.push(d);
+        } else if linkable != 0 {
+            return Err(CL_INVALID_OPERATION.into());
         }
     }
 
     // CL_LINK_PROGRAM_FAILURE if there is a failure to link the compiled binaries and/or libraries.
-    let res = Program::link(c, &devs, &progs, c_string_to_string(options));
-    let code = if devs
+    let res = Program::link(c, &devs, &progs, options);
+    let code = if linked_devs
         .iter()
         .map(|d| res.status(d))
         .all(|s| s == CL_BUILD_SUCCESS as cl_build_status)
@@ -457,9 +477,32 @@ pub fn link_program(
 
     debug_logging(&res, &devs);
     Ok((res.into_cl(), code))
+}
 
-    //• CL_INVALID_LINKER_OPTIONS if the linker options specified by options are invalid.
-    //• CL_INVALID_OPERATION if the rules for devices containing compiled binaries or libraries as described in input_programs argument above are not followed.
+fn validate_link_options(options: &str) -> CLResult<()> {
+    let mut lib = false;
+    let mut link_options = false;
+    for option in options.split_whitespace() {
+        match option {
+            "-create-library" => lib = true,
+            "-enable-link-options" => link_options = true,
+            // accepted, but math options only take effect through clCompileProgram
+            "-cl-denorms-are-zero"
+            | "-cl-no-signed-zeros"
+            | "-cl-unsafe-math-optimizations"
+            | "-cl-finite-math-only"
+            | "-cl-fast-relaxed-math"
+            | "-cl-no-subgroup-ifp" => (),
+            _ => return Err(CL_INVALID_LINKER_OPTIONS.into()),
+        }
+    }
+
+    // -enable-link-options [...] This option must be specified with the -create-library option.
+    if link_options && !lib {
+        return Err(CL_INVALID_LINKER_OPTIONS.into());
+    }
+
+    Ok(())
 }
 
 #[cl_entrypoint(clSetProgramSpecializationConstant)]
@@ -475,18 +518,18 @@ fn set_program_specialization_constant(
     // language (e.g. SPIR-V)
     // TODO: or if the intermediate language does not support specialization constants.
     if !program.is_il() {
-        return Err(CL_INVALID_PROGRAM);
+        return Err(CL_INVALID_PROGRAM.into());
     }
 
     if spec_size != program.get_spec_constant_size(spec_id).into() {
         // CL_INVALID_VALUE if spec_size does not match the size of the specialization constant in
         // the module,
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // or if spec_value is NULL.
     if spec_value.is_null() {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // SAFETY: according to API spec
@@ -503,5 +546,5 @@ fn set_program_release_callback(
     _pfn_notify: ::std::option::Option<FuncProgramCB>,
     _user_data: *mut ::std::os::raw::c_void,
 ) -> CLResult<()> {
-    Err(CL_INVALID_OPERATION)
+    Err(CL_INVALID_OPERATION.into())
 }
@@ -4,6 +4,7 @@ use crate::api::util::*;
 use crate::core::context::*;
 use crate::core::device::*;
 use crate::core::event::*;
+use crate::core::platform::*;
 use crate::core::queue::*;
 
 use mesa_rust_util::properties::*;
@@ -34,9 +35,9 @@ impl CLInfo<cl_command_queue_info> for cl_command_queue {
             CL_QUEUE_REFERENCE_COUNT => cl_prop::<cl_uint>(Queue::refcnt(*self)?),
             // clGetCommandQueueInfo, passing CL_QUEUE_SIZE Returns CL_INVALID_COMMAND_QUEUE since
             // command_queue cannot be a valid device command-queue.
-            CL_QUEUE_SIZE => return Err(CL_INVALID_COMMAND_QUEUE),
+            CL_QUEUE_SIZE => return Err(CL_INVALID_COMMAND_QUEUE.into()),
             // CL_INVALID_VALUE if param_name is not one of the supported values
-            _ => return Err(CL_INVALID_VALUE),
+            _ => return Err(CL_INVALID_VALUE.into()),
         })
     }
 }
@@ -81,19 +82,26 @@ pub fn create_command_queue_impl(
 
     // CL_INVALID_DEVICE if device [...] is not associated with context.
     if !c.devs.contains(&d) {
-        return Err(CL_INVALID_DEVICE);
+        return Err(CL_INVALID_DEVICE.into());
     }
 
     // CL_INVALID_VALUE if values specified in properties are not valid.
     if !valid_command_queue_properties(properties) {
-        return Err(CL_INVALID_VALUE);
+        return Err(CL_INVALID_VALUE.into());
     }
 
     // CL_INVALID_QUEUE_PROPERTIES if values specified in properties are valid but are not supported by the device.
     if !supported_command_queue_properties(d, properties) {
-        return Err(CL_INVALID_QUEUE_PROPERTIES);
+        return Err(CL_INVALID_QUEUE_PROPERTIES.into());
     }
 
+    // some applications break with out of order queues
+    let properties = if Platform::conf().force_in_order_queue {
+        properties & !cl_command_queue_properties::from(CL_QUEUE_OUT_OF_ORDER_EXEC_MODE_ENABLE)
+    } else {
+        properties
+    };
+
     Ok(Queue::new(c, d, properties, properties_v2)?.into_cl())
 }
 
@@ -123,8 +131,8 @@ fn create_command_queue_with_properties(
                 CL_QUEUE_PROPERTIES => queue_properties = *v,
                 // CL_INVALID_QUEUE_PROPERTIES if values specified in properties are valid but are not
                 // supported by the device.
-                CL_QUEUE_SIZE => return Err(CL_INVALID_QUEUE_PROPERTIES),
-                _ => return Err(CL_INVALID_PROPERTY),
+                CL_QUEUE_SIZE => return Err(CL_INVALID_QUEUE_PROPERTIES.into()),
+                _ => return Err(CL_INVALID_PROPERTY.into()),
             }
         }
 
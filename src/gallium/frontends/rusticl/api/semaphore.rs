@@ -0,0 +1,308 @@
+use crate::api::event::create_and_queue;
+use crate::api::icd::*;
+use crate::api::types::*;
+use crate::api::util::*;
+use crate::core::context::*;
+use crate::core::device::*;
+use crate::core::event::*;
+use crate::core::queue::*;
+use crate::core::semaphore::*;
+
+use mesa_rust::pipe::fence::FenceFd;
+use mesa_rust_util::properties::PropertyReader;
+use mesa_rust_util::ptr::*;
+use rusticl_opencl_gen::*;
+use rusticl_proc_macros::cl_entrypoint;
+use rusticl_proc_macros::cl_info_entrypoint;
+
+use std::ffi::c_int;
+use std::ffi::c_void;
+use std::mem;
+use std::mem::MaybeUninit;
+use std::sync::Arc;
+
+#[cl_info_entrypoint(clGetSemaphoreInfoKHR)]
+impl CLInfo<cl_semaphore_info_khr> for cl_semaphore_khr {
+    fn query(&self, q: cl_semaphore_info_khr, _: &[u8]) -> CLResult<Vec<MaybeUninit<u8>>> {
+        let sema = Semaphore::ref_from_raw(*self)?;
+        Ok(match q {
+            CL_SEMAPHORE_EXPORT_HANDLE_TYPES_KHR => {
+                cl_prop::<&Vec<cl_external_semaphore_handle_type_khr>>(&sema.export_types)
+            }
+            CL_SEMAPHORE_CONTEXT_KHR => {
+                // Note we use as_ptr here which doesn't increase the reference count.
+                let ptr = Arc::as_ptr(&sema.context);
+                cl_prop::<cl_context>(cl_context::from_ptr(ptr))
+            }
+            CL_SEMAPHORE_PAYLOAD_KHR => {
+                cl_prop::<cl_semaphore_payload_khr>(sema.is_signaled().into())
+            }
+            CL_SEMAPHORE_PROPERTIES_KHR => {
+                cl_prop::<&Vec<cl_semaphore_properties_khr>>(&sema.props)
+            }
+            CL_SEMAPHORE_REFERENCE_COUNT_KHR => cl_prop::<cl_uint>(Semaphore::refcnt(*self)?),
+            CL_SEMAPHORE_TYPE_KHR => cl_prop::<cl_semaphore_type_khr>(CL_SEMAPHORE_TYPE_BINARY_KHR),
+            // CL_INVALID_VALUE if param_name is not one of the supported values
+            _ => return Err(CL_INVALID_VALUE.into()),
+        })
+    }
+}
+
+#[cl_entrypoint(clCreateSemaphoreWithPropertiesKHR)]
+fn create_semaphore_with_properties(
+    context: cl_context,
+    sema_props: *const cl_semaphore_properties_khr,
+) -> CLResult<cl_semaphore_khr> {
+    let c = Context::arc_from_raw(context)?;
+
+    // CL_INVALID_VALUE if sema_props is NULL
+    if sema_props.is_null() {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    let mut sema_type = None;
+    let mut devs = None;
+    let mut export_types = None;
+    let mut sync_fd = None;
+    // SAFETY: the list is required to be terminated
+    let mut reader = unsafe { PropertyReader::new(sema_props) };
+    while let Some(key) = reader.key() {
+        // CL_INVALID_PROPERTY if a property name in sema_props is not a supported property name,
+        // if the value specified for a supported property name is not valid, or if the same
+        // property name is specified more than once.
+        match u32::try_from(key) {
+            Ok(CL_SEMAPHORE_TYPE_KHR) if sema_type.is_none() => {
+                sema_type = Some(reader.value());
+            }
+            Ok(CL_DEVICE_HANDLE_LIST_KHR) if devs.is_none() => {
+                let list = reader.list(CL_DEVICE_HANDLE_LIST_END_KHR.into());
+                devs = Some(devs_from_handle_list(&c, &list)?);
+            }
+            Ok(CL_SEMAPHORE_EXPORT_HANDLE_TYPES_KHR) if export_types.is_none() => {
+                let list = reader.list(CL_SEMAPHORE_EXPORT_HANDLE_TYPES_LIST_END_KHR.into());
+                let list: Result<Vec<_>, _> = list.into_iter().map(u32::try_from).collect();
+                export_types = Some(list.map_err(|_| CL_INVALID_PROPERTY)?);
+            }
+            Ok(CL_SEMAPHORE_HANDLE_SYNC_FD_KHR) if sync_fd.is_none() => {
+                sync_fd = Some(reader.value() as i32);
+            }
+            _ => return Err(CL_INVALID_PROPERTY.into()),
+        }
+    }
+
+    match sema_type {
+        Some(t) if t == CL_SEMAPHORE_TYPE_BINARY_KHR.into() => {}
+        Some(_) => return Err(CL_INVALID_PROPERTY.into()),
+        // CL_INVALID_VALUE if sema_props does not specify <property, value> pairs for minimum set
+        // of properties (i.e. CL_SEMAPHORE_TYPE_KHR) required for successful creation of a
+        // cl_semaphore_khr
+        None => return Err(CL_INVALID_VALUE.into()),
+    }
+
+    // CL_INVALID_DEVICE if CL_DEVICE_HANDLE_LIST_KHR is specified as an empty list
+    let devs = devs.unwrap_or_else(|| c.devs.clone());
+    if devs.is_empty() {
+        return Err(CL_INVALID_DEVICE.into());
+    }
+
+    // only handle types all devices support can be used.
+    let export_types = export_types.unwrap_or_default();
+    let export_supported = devs.iter().all(|dev| {
+        let supported = dev.semaphore_export_handle_types();
+        export_types.iter().all(|t| supported.contains(t))
+    });
+    if !export_supported {
+        return Err(CL_INVALID_PROPERTY.into());
+    }
+
+    let fence = if let Some(fd) = sync_fd {
+        if !devs.iter().all(|dev| dev.sync_fd_import_supported()) {
+            return Err(CL_INVALID_PROPERTY.into());
+        }
+
+        // Like in Vulkan, importing a sync file transfers its ownership to us. Fences are screen
+        // objects, so it's enough to import it on one device.
+        Some(devs[0].helper_ctx().import_fence(&FenceFd { fd: fd }))
+    } else {
+        None
+    };
+
+    let sema = Semaphore::new(c, devs, reader.raw(), export_types);
+    if let Some(fence) = fence {
+        sema.signal_with_fence(fence);
+    }
+
+    Ok(sema.into_cl())
+}
+
+/// Validates the semaphores used in a command enqueued to `q`.
+fn semaphores_from_cl(
+    q: &Queue,
+    num_sema_objects: cl_uint,
+    sema_objects: *const cl_semaphore_khr,
+) -> CLResult<Vec<Arc<Semaphore>>> {
+    // CL_INVALID_VALUE if num_sema_objects is 0.
+    if num_sema_objects == 0 {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    // CL_INVALID_SEMAPHORE_KHR if any of the semaphore objects specified by sema_objects is not
+    // valid.
+    let semas = Semaphore::arcs_from_arr(sema_objects, num_sema_objects)?;
+    for sema in &semas {
+        // CL_INVALID_CONTEXT if context associated with command_queue and any of the semaphore
+        // objects in sema_objects are not the same
+        if sema.context != q.context {
+            return Err(CL_INVALID_CONTEXT.into());
+        }
+
+        // CL_INVALID_COMMAND_QUEUE if the device associated with command_queue is not same as one
+        // of the devices specified by CL_DEVICE_HANDLE_LIST_KHR at the time of creating one or
+        // more of sema_objects.
+        if !sema.devs.contains(&q.device) {
+            return Err(CL_INVALID_COMMAND_QUEUE.into());
+        }
+    }
+
+    Ok(semas)
+}
+
+#[cl_entrypoint(clEnqueueSignalSemaphoresKHR)]
+fn enqueue_signal_semaphores(
+    command_queue: cl_command_queue,
+    num_sema_objects: cl_uint,
+    sema_objects: *const cl_semaphore_khr,
+    _sema_payload_list: *const cl_semaphore_payload_khr,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let semas = semaphores_from_cl(&q, num_sema_objects, sema_objects)?;
+
+    // binary semaphores don't have a payload, so sema_payload_list is ignored.
+    let fence = Arc::new(SignalFence::default());
+    let work_fence = Arc::clone(&fence);
+    let exportable = semas.iter().any(|sema| sema.is_exportable());
+    let e = Event::new(
+        &q,
+        CL_COMMAND_SEMAPHORE_SIGNAL_KHR,
+        evs,
+        Box::new(move |_, ctx| {
+            work_fence.set(if exportable {
+                ctx.flush_exportable()
+            } else {
+                ctx.flush()
+            });
+            Ok(())
+        }),
+    );
+
+    // the semaphores need to be signaled before the command executes, so waits enqueued right
+    // after this command can already depend on it.
+    for sema in semas {
+        sema.signal(Arc::clone(&e), Arc::clone(&fence));
+    }
+
+    if !event.is_null() {
+        // SAFETY: we check for null and valid API use is to pass in a valid pointer
+        unsafe {
+            event.write(Arc::clone(&e).into_cl());
+        }
+    }
+    q.queue(e);
+    Ok(())
+}
+
+#[cl_entrypoint(clEnqueueWaitSemaphoresKHR)]
+fn enqueue_wait_semaphores(
+    command_queue: cl_command_queue,
+    num_sema_objects: cl_uint,
+    sema_objects: *const cl_semaphore_khr,
+    _sema_payload_list: *const cl_semaphore_payload_khr,
+    num_events_in_wait_list: cl_uint,
+    event_wait_list: *const cl_event,
+    event: *mut cl_event,
+) -> CLResult<()> {
+    let q = Queue::arc_from_raw(command_queue)?;
+    let mut evs = event_list_from_cl(&q, num_events_in_wait_list, event_wait_list)?;
+    let semas = semaphores_from_cl(&q, num_sema_objects, sema_objects)?;
+
+    // Semaphores already signaled are consumed right away and the signaling commands become
+    // dependencies. The others stall the queue until they get signaled.
+    let signals: Vec<_> = semas.iter().map(|sema| sema.take_signal()).collect();
+    evs.extend(
+        signals
+            .iter()
+            .flatten()
+            .filter_map(|signal| signal.event.clone()),
+    );
+
+    create_and_queue(
+        q,
+        CL_COMMAND_SEMAPHORE_WAIT_KHR,
+        evs,
+        event,
+        false,
+        Box::new(move |_, ctx| {
+            for (sema, signal) in semas.iter().zip(signals) {
+                signal.unwrap_or_else(|| sema.wait_for_signal()).wait(ctx);
+            }
+            Ok(())
+        }),
+    )
+}
+
+#[cl_entrypoint(clGetSemaphoreHandleForTypeKHR)]
+fn get_semaphore_handle_for_type(
+    sema_object: cl_semaphore_khr,
+    device: cl_device_id,
+    handle_type: cl_external_semaphore_handle_type_khr,
+    handle_size: usize,
+    handle_ptr: *mut c_void,
+    handle_size_ret: *mut usize,
+) -> CLResult<()> {
+    let sema = Semaphore::ref_from_raw(sema_object)?;
+    let dev = Device::ref_from_raw(device)?;
+
+    // CL_INVALID_DEVICE if device is not one of the devices specified by CL_DEVICE_HANDLE_LIST_KHR
+    // at the time of creating sema_object.
+    if !sema.devs.contains(&dev) {
+        return Err(CL_INVALID_DEVICE.into());
+    }
+
+    // CL_INVALID_VALUE if the requested external semaphore handle type was not specified in
+    // CL_SEMAPHORE_EXPORT_HANDLE_TYPES_KHR at the time of creating sema_object.
+    if !sema.export_types.contains(&handle_type) {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    // CL_INVALID_VALUE if handle_size is less than the size needed to store the returned handle.
+    if !handle_ptr.is_null() && handle_size < mem::size_of::<c_int>() {
+        return Err(CL_INVALID_VALUE.into());
+    }
+
+    handle_size_ret.write_checked(mem::size_of::<c_int>());
+    if handle_ptr.is_null() {
+        return Ok(());
+    }
+
+    // Exporting a sync file consumes the pending signal operation like a wait does, and a sync
+    // file of -1 is already signaled.
+    let signal = sema.take_signal().ok_or(CL_INVALID_OPERATION)?;
+    let fd = signal.export_fd().map_or(-1, FenceFd::into_raw);
+    handle_ptr.cast::<c_int>().write_checked(fd);
+
+    Ok(())
+}
+
+#[cl_entrypoint(clRetainSemaphoreKHR)]
+fn retain_semaphore(sema_object: cl_semaphore_khr) -> CLResult<()> {
+    Semaphore::retain(sema_object)
+}
+
+#[cl_entrypoint(clReleaseSemaphoreKHR)]
+fn release_semaphore(sema_object: cl_semaphore_khr) -> CLResult<()> {
+    Semaphore::release(sema_object)
+}
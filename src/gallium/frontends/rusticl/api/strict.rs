@@ -0,0 +1,378 @@
+//! Additional validation enabled through `RUSTICL_STRICT`.
+//!
+//! The checks in here catch API usage the spec leaves undefined instead of turning it into an
+//! error, or which is too expensive to be validated on every call. Violations get logged with a
+//! description of what went wrong and the call fails, so application bugs show up at the call
+//! causing them and not as corrupted data later on.
+
+use crate::api::types::*;
+use crate::core::event::*;
+use crate::core::memory::*;
+use crate::core::platform::*;
+
+use mesa_rust_util::log_error;
+use mesa_rust_util::log_warn;
+use rusticl_opencl_gen::*;
+
+use std::collections::HashSet;
+use std::os::raw::c_void;
+use std::ptr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+
+struct StrictMapping {
+    /// The memory object the mapping was created for, as sub-buffers and images created from
+    /// buffers share the state of their parent.
+    mem: usize,
+    buffer: bool,
+    ptr: usize,
+    origin: CLVec<usize>,
+    /// The mip level of image mappings, mappings of different levels never overlap.
+    level: u32,
+    region: CLVec<usize>,
+    write: bool,
+}
+
+/// Per memory object state, only updated when strict mode is enabled. Lives on the parent
+/// memory object if there is one.
+pub struct StrictState {
+    written: AtomicBool,
+    maps: Mutex<Vec<StrictMapping>>,
+}
+
+impl StrictState {
+    pub fn new(written: bool) -> Self {
+        Self {
+            written: AtomicBool::new(written),
+            maps: Mutex::new(Vec::new()),
+        }
+    }
+}
+
+pub fn enabled() -> bool {
+    Platform::dbg().strict
+}
+
+fn boxes_overlap(
+    a_origin: &CLVec<usize>,
+    a_region: &CLVec<usize>,
+    b_origin: &CLVec<usize>,
+    b_region: &CLVec<usize>,
+) -> bool {
+    (0..3)
+        .all(|i| a_origin[i] < b_origin[i] + b_region[i] && b_origin[i] < a_origin[i] + a_region[i])
+}
+
+/// Buffers are tracked in the address space of their parent so overlapping sub-buffers are
+/// detected as well.
+fn buffer_box(b: &Buffer, offset: usize, size: usize) -> (CLVec<usize>, CLVec<usize>) {
+    (
+        CLVec::new([b.offset + offset, 0, 0]),
+        CLVec::new([size, 1, 1]),
+    )
+}
+
+/// Fails if `m` or any memory object sharing its storage is currently mapped in a conflicting
+/// way. Reads are fine while the memory is only mapped for reading, but everything else is
+/// undefined.
+pub fn check_unmapped(m: &MemBase, cmd: &str, write: bool) -> CLResult<()> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    let maps = m.strict_state().maps.lock().unwrap();
+    let conflicts = maps.iter().filter(|map| write || map.write).count();
+    if conflicts > 0 {
+        log_error!(
+            "{cmd}: memory object {:p} is {} while {} conflicting mapping(s) are active",
+            m,
+            if write { "written to" } else { "read from" },
+            conflicts,
+        );
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    Ok(())
+}
+
+/// Fails if `m` gets read before anything was ever written to it, as the content is undefined.
+pub fn check_written(m: &MemBase, cmd: &str) -> CLResult<()> {
+    if enabled() && !m.strict_state().written.load(Ordering::Relaxed) {
+        log_error!(
+            "{cmd}: memory object {:p} is read before it was ever written to",
+            m
+        );
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    Ok(())
+}
+
+pub fn mark_written(m: &MemBase) {
+    if enabled() {
+        m.strict_state().written.store(true, Ordering::Relaxed);
+    }
+}
+
+fn check_map(
+    m: &MemBase,
+    cmd: &str,
+    map_flags: cl_map_flags,
+    origin: CLVec<usize>,
+    level: u32,
+    region: CLVec<usize>,
+) -> CLResult<()> {
+    let write = bit_check(map_flags, CL_MAP_WRITE | CL_MAP_WRITE_INVALIDATE_REGION);
+    let maps = m.strict_state().maps.lock().unwrap();
+
+    // CL_INVALID_OPERATION if mapping would lead to overlapping regions being mapped for writing.
+    let key = m as *const MemBase as usize;
+    let overlap = maps.iter().any(|map| {
+        // images created from buffers use a different coordinate space, so only compare mappings
+        // of the same object or between buffers.
+        let comparable = map.mem == key || map.buffer && m.is_buffer();
+        comparable
+            && (write || map.write)
+            && map.level == level
+            && boxes_overlap(&map.origin, &map.region, &origin, &region)
+    });
+
+    if overlap {
+        log_error!(
+            "{cmd}: mapping memory object {:p} at {:?} with size {:?} for {} overlaps a mapping \
+             for writing",
+            m,
+            *origin,
+            *region,
+            if write { "writing" } else { "reading" },
+        );
+        return Err(CL_INVALID_OPERATION.into());
+    }
+
+    // reading back content never written is just as undefined through a mapping
+    if bit_check(map_flags, CL_MAP_READ) {
+        check_written(m, cmd)?;
+    }
+
+    Ok(())
+}
+
+fn add_map(
+    m: &MemBase,
+    ptr: *mut c_void,
+    map_flags: cl_map_flags,
+    origin: CLVec<usize>,
+    level: u32,
+    region: CLVec<usize>,
+) {
+    let write = bit_check(map_flags, CL_MAP_WRITE | CL_MAP_WRITE_INVALIDATE_REGION);
+    let state = m.strict_state();
+    if write {
+        state.written.store(true, Ordering::Relaxed);
+    }
+
+    state.maps.lock().unwrap().push(StrictMapping {
+        mem: m as *const MemBase as usize,
+        buffer: m.is_buffer(),
+        ptr: ptr as usize,
+        origin: origin,
+        level: level,
+        region: region,
+        write: write,
+    });
+}
+
+pub fn check_map_buffer(
+    b: &Buffer,
+    map_flags: cl_map_flags,
+    offset: usize,
+    size: usize,
+) -> CLResult<()> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    let (origin, region) = buffer_box(b, offset, size);
+    check_map(b, "clEnqueueMapBuffer", map_flags, origin, 0, region)
+}
+
+pub fn add_map_buffer(
+    b: &Buffer,
+    ptr: *mut c_void,
+    map_flags: cl_map_flags,
+    offset: usize,
+    size: usize,
+) {
+    if enabled() {
+        let (origin, region) = buffer_box(b, offset, size);
+        add_map(b, ptr, map_flags, origin, 0, region);
+    }
+}
+
+pub fn check_map_image(
+    i: &Image,
+    map_flags: cl_map_flags,
+    origin: CLVec<usize>,
+    level: u32,
+    region: CLVec<usize>,
+) -> CLResult<()> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    check_map(i, "clEnqueueMapImage", map_flags, origin, level, region)
+}
+
+pub fn add_map_image(
+    i: &Image,
+    ptr: *mut c_void,
+    map_flags: cl_map_flags,
+    origin: CLVec<usize>,
+    level: u32,
+    region: CLVec<usize>,
+) {
+    if enabled() {
+        add_map(i, ptr, map_flags, origin, level, region);
+    }
+}
+
+pub fn remove_map(m: &MemBase, ptr: *mut c_void) {
+    if !enabled() {
+        return;
+    }
+
+    let key = m as *const MemBase as usize;
+    let mut maps = m.strict_state().maps.lock().unwrap();
+    if let Some(idx) = maps
+        .iter()
+        .position(|map| map.mem == key && map.ptr == ptr as usize)
+    {
+        maps.swap_remove(idx);
+    }
+}
+
+/// Besides overlapping regions within the same image, this also catches copies between different
+/// images created from the same buffer, which share storage as well.
+pub fn check_copy_image(
+    src: &Image,
+    dst: &Image,
+    src_origin: &CLVec<usize>,
+    src_level: u32,
+    dst_origin: &CLVec<usize>,
+    dst_level: u32,
+    region: &CLVec<usize>,
+) -> CLResult<()> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    // CL_MEM_COPY_OVERLAP if src_image and dst_image are the same image object and the source and
+    // destination regions overlap.
+    let overlap = if src == dst {
+        src_level == dst_level && boxes_overlap(src_origin, region, dst_origin, region)
+    } else {
+        src.has_same_parent(dst)
+    };
+
+    if overlap {
+        log_error!(
+            "clEnqueueCopyImage: source {:?} and destination {:?} regions of size {:?} overlap",
+            **src_origin,
+            **dst_origin,
+            **region,
+        );
+        return Err(CL_MEM_COPY_OVERLAP.into());
+    }
+
+    Ok(())
+}
+
+/// Copies between buffers and images are undefined if both share the same storage.
+pub fn check_copy_shared_storage(src: &MemBase, dst: &MemBase, cmd: &str) -> CLResult<()> {
+    if enabled() && src.has_same_parent(dst) {
+        log_error!(
+            "{cmd}: source {:p} and destination {:p} share the same storage",
+            src,
+            dst,
+        );
+        return Err(CL_MEM_COPY_OVERLAP.into());
+    }
+
+    Ok(())
+}
+
+/// Accessing a buffer and its sub-buffers, or overlapping sub-buffers of the same buffer,
+/// concurrently is undefined, unless all of them are only read.
+pub fn check_overlapping_buffers(buffers: &[&Buffer], cmd: &str) -> CLResult<()> {
+    if !enabled() {
+        return Ok(());
+    }
+
+    for (i, a) in buffers.iter().enumerate() {
+        for b in &buffers[i + 1..] {
+            // passing the same buffer multiple times is fine
+            if ptr::eq(*a, *b)
+                || !a.has_same_parent(b)
+                || bit_check(a.flags, CL_MEM_READ_ONLY) && bit_check(b.flags, CL_MEM_READ_ONLY)
+            {
+                continue;
+            }
+
+            let (a_origin, a_region) = buffer_box(a, 0, a.size);
+            let (b_origin, b_region) = buffer_box(b, 0, b.size);
+            if boxes_overlap(&a_origin, &a_region, &b_origin, &b_region) {
+                log_error!(
+                    "{cmd}: buffers {:p} and {:p} overlap within the same parent buffer and one \
+                     of them might be written to",
+                    *a,
+                    *b,
+                );
+                return Err(CL_INVALID_OPERATION.into());
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn find_unset_user_events<'a>(
+    e: &'a Event,
+    seen: &mut HashSet<&'a Event>,
+    res: &mut Vec<&'a Event>,
+) {
+    if e.status() <= CL_COMPLETE as cl_int || !seen.insert(e) {
+        return;
+    }
+
+    if e.is_user() {
+        res.push(e);
+    }
+
+    for dep in &e.deps {
+        find_unset_user_events(dep, seen, res);
+    }
+}
+
+/// A blocking call waiting on a user event which is not yet set can only ever return if
+/// another thread sets it. This is legal, but a common source of hangs, so only warn about it.
+pub fn check_blocking_deps(deps: &[Arc<Event>], cmd_type: cl_command_type) {
+    if !enabled() {
+        return;
+    }
+
+    let mut seen = HashSet::new();
+    let mut user_events = Vec::new();
+    for e in deps {
+        find_unset_user_events(e, &mut seen, &mut user_events);
+    }
+
+    for e in user_events {
+        log_warn!(
+            "blocking command {cmd_type:#x} waits on the unset user event {:p}, this only returns \
+             if another thread sets its status",
+            e,
+        );
+    }
+}
@@ -0,0 +1,85 @@
+use crate::core::platform::*;
+
+use mesa_rust_util::log_error;
+use rusticl_opencl_gen::*;
+
+use std::cell::Cell;
+use std::env;
+use std::fmt::Debug;
+use std::fmt::Write as _;
+use std::fs::File;
+use std::io;
+use std::io::Write;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::thread;
+
+struct TraceOutput {
+    out: Box<dyn Write + Send>,
+    seq: u64,
+}
+
+static TRACE_OUTPUT: OnceLock<Mutex<TraceOutput>> = OnceLock::new();
+
+thread_local! {
+    static THREAD_SEQ: Cell<u64> = const { Cell::new(0) };
+}
+
+fn output() -> &'static Mutex<TraceOutput> {
+    TRACE_OUTPUT.get_or_init(|| {
+        let out: Box<dyn Write + Send> = env::var("RUSTICL_TRACE_FILE")
+            .ok()
+            .and_then(|path| match File::create(&path) {
+                Ok(file) => Some(Box::new(file) as Box<dyn Write + Send>),
+                Err(err) => {
+                    log_error!("Failed to open RUSTICL_TRACE_FILE {path}: {err}");
+                    None
+                }
+            })
+            .unwrap_or_else(|| Box::new(io::stderr()));
+
+        Mutex::new(TraceOutput { out: out, seq: 0 })
+    })
+}
+
+pub fn is_enabled() -> bool {
+    Platform::init_env_once();
+    Platform::dbg().trace
+}
+
+/// Logs a single API call. Called by the stubs generated through `cl_entrypoint` and
+/// `cl_info_entrypoint` after the call returned.
+///
+/// Every line carries a global sequence number and a per thread sequence number, so the calls
+/// of each application thread can be put back into order even if the output gets interleaved.
+pub fn trace_call(name: &str, args: &[(&str, &dyn Debug)], err: cl_int, ret: Option<&dyn Debug>) {
+    let thread_seq = THREAD_SEQ.with(|seq| {
+        let res = seq.get();
+        seq.set(res + 1);
+        res
+    });
+
+    let mut line = String::new();
+    let args = args
+        .iter()
+        .map(|(name, val)| format!("{name}: {val:?}"))
+        .collect::<Vec<_>>()
+        .join(", ");
+    let _ = write!(line, "{name}({args})");
+    if let Some(ret) = ret {
+        let _ = write!(line, " = {ret:?}");
+    }
+    let _ = write!(line, " -> {err}");
+
+    let thread = thread::current();
+    let thread_name = thread.name().unwrap_or("");
+
+    let mut output = output().lock().unwrap();
+    let seq = output.seq;
+    output.seq += 1;
+    let _ = writeln!(
+        output.out,
+        "rusticl trace: {seq} [{thread_name}{:?}:{thread_seq}] {line}",
+        thread.id(),
+    );
+}
@@ -60,7 +60,7 @@ macro_rules! cl_callback {
             /// [`clEnqueueSVMFree`]: https://registry.khronos.org/OpenCL/specs/3.0-unified/html/OpenCL_API.html#clEnqueueSVMFree
             pub unsafe fn new(func: Option<$fn_alias>, data: *mut c_void) -> CLResult<Self> {
                 let Some(func) = func else {
-                    return Err(CL_INVALID_VALUE);
+                    return Err(CL_INVALID_VALUE.into());
                 };
                 Ok(Self { func, data })
             }
@@ -78,7 +78,7 @@ macro_rules! cl_callback {
                     return if data.is_null() {
                         Ok(None)
                     } else {
-                        Err(CL_INVALID_VALUE)
+                        Err(CL_INVALID_VALUE.into())
                     };
                 };
                 Ok(Some(Self { func, data }))
@@ -100,7 +100,7 @@ cl_callback!(
 );
 
 impl CreateContextCB {
-    pub fn _call(self, err_msg: &CStr, private_info: &[u8]) {
+    pub fn call(&self, err_msg: &CStr, private_info: &[u8]) {
         let err_msg_ptr = err_msg.as_ptr();
         let private_info_ptr = private_info.as_ptr().cast::<c_void>();
         // SAFETY: The first parameter must be a valid pointer to a NUL-terminated C string. We
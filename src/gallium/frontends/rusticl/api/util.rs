@@ -1,8 +1,11 @@
-use crate::api::icd::{ArcedCLObject, CLResult};
+use crate::api::icd::{ArcedCLObject, BaseCLObject, CLResult};
 use crate::api::types::*;
+use crate::core::context::Context;
+use crate::core::device::Device;
 use crate::core::event::*;
 use crate::core::queue::*;
 
+use mesa_rust_util::flags::*;
 use mesa_rust_util::properties::Properties;
 use mesa_rust_util::ptr::CheckedPtr;
 use rusticl_opencl_gen::*;
@@ -12,7 +15,6 @@ use std::convert::TryInto;
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::mem::{size_of, MaybeUninit};
-use std::ops::BitAnd;
 use std::slice;
 use std::sync::Arc;
 
@@ -38,7 +40,7 @@ pub trait CLInfo<I> {
         // CL_INVALID_VALUE [...] if size in bytes specified by param_value_size is < size of return
         // type as specified in the Context Attributes table and param_value is not a NULL value.
         if param_value_size < size && !param_value.is_null() {
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
 
         // param_value_size_ret returns the actual size in bytes of data being queried by param_name.
@@ -72,7 +74,7 @@ pub trait CLInfoObj<I, O> {
         // CL_INVALID_VALUE [...] if size in bytes specified by param_value_size is < size of return
         // type as specified in the Context Attributes table and param_value is not a NULL value.
         if param_value_size < size && !param_value.is_null() {
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
 
         // param_value_size_ret returns the actual size in bytes of data being queried by param_name.
@@ -110,6 +112,7 @@ cl_prop_for_type!(cl_ushort);
 cl_prop_for_type!(cl_int);
 cl_prop_for_type!(cl_uint);
 cl_prop_for_type!(cl_ulong);
+cl_prop_for_type!(cl_float);
 cl_prop_for_type!(isize);
 cl_prop_for_type!(usize);
 
@@ -238,11 +241,11 @@ const CL_DEVICE_TYPES: u32 = CL_DEVICE_TYPE_ACCELERATOR
     | CL_DEVICE_TYPE_DEFAULT;
 
 pub fn check_cl_device_type(val: cl_device_type) -> CLResult<()> {
-    let v: u32 = val.try_into().or(Err(CL_INVALID_DEVICE_TYPE))?;
+    let v: u32 = val.try_into().map_err(|_| CL_INVALID_DEVICE_TYPE)?;
     if v == CL_DEVICE_TYPE_ALL || v & CL_DEVICE_TYPES == v {
         return Ok(());
     }
-    Err(CL_INVALID_DEVICE_TYPE)
+    Err(CL_INVALID_DEVICE_TYPE.into())
 }
 
 pub const CL_IMAGE_TYPES: [cl_mem_object_type; 6] = [
@@ -276,12 +279,29 @@ pub fn event_list_from_cl(
     // CL_INVALID_CONTEXT if context associated with command_queue and events in event_list are not
     // the same.
     if res.iter().any(|e| e.context != q.context) {
-        return Err(CL_INVALID_CONTEXT);
+        return Err(CL_INVALID_CONTEXT.into());
     }
 
     Ok(res)
 }
 
+/// Turns the values of a `CL_DEVICE_HANDLE_LIST_KHR` property into devices of `c`.
+pub fn devs_from_handle_list(
+    c: &Context,
+    list: &[cl_properties],
+) -> CLResult<Vec<&'static Device>> {
+    list.iter()
+        .map(|&dev| -> CLResult<_> {
+            let dev = Device::ref_from_raw(dev as cl_device_id)?;
+            // CL_INVALID_DEVICE if a device in the list isn't associated with the context
+            if !c.devs.contains(&dev) {
+                return Err(CL_INVALID_DEVICE.into());
+            }
+            Ok(dev)
+        })
+        .collect()
+}
+
 pub fn to_maybeuninit_vec<T: Copy>(v: Vec<T>) -> Vec<MaybeUninit<T>> {
     // In my tests the compiler was smart enough to turn this into a noop
     v.into_iter().map(MaybeUninit::new).collect()
@@ -299,8 +319,8 @@ pub fn is_alligned<T>(ptr: *const T, alignment: usize) -> bool {
     ptr as usize & (alignment - 1) == 0
 }
 
-pub fn bit_check<A: BitAnd<Output = A> + PartialEq + Default, B: Into<A>>(a: A, b: B) -> bool {
-    a & b.into() != A::default()
+pub fn bit_check<A: Bits, B: Into<A>>(a: A, b: B) -> bool {
+    Flags::new(a).intersects(b)
 }
 
 // Taken from "Appendix D: Checking for Memory Copy Overlap"
@@ -379,7 +399,7 @@ pub mod cl_slice {
     #[inline]
     pub unsafe fn from_raw_parts<'a, T>(data: *const T, len: usize) -> CLResult<&'a [T]> {
         if allocation_obviously_invalid(data, len) {
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
 
         // SAFETY: We've checked that `data` is not null and properly aligned. We've also checked
@@ -402,7 +422,7 @@ pub mod cl_slice {
     #[inline]
     pub unsafe fn from_raw_parts_mut<'a, T>(data: *mut T, len: usize) -> CLResult<&'a mut [T]> {
         if allocation_obviously_invalid(data, len) {
-            return Err(CL_INVALID_VALUE);
+            return Err(CL_INVALID_VALUE.into());
         }
 
         // SAFETY: We've checked that `data` is not null and properly aligned. We've also checked
@@ -1,3 +1,4 @@
+pub mod command_buffer;
 pub mod context;
 pub mod device;
 pub mod event;
@@ -8,5 +9,7 @@ pub mod memory;
 pub mod platform;
 pub mod program;
 pub mod queue;
+pub mod semaphore;
+pub mod stats;
 pub mod util;
 pub mod version;
@@ -0,0 +1,122 @@
+use crate::api::icd::*;
+use crate::core::event::*;
+use crate::core::queue::*;
+use crate::impl_cl_type_trait;
+
+use rusticl_opencl_gen::*;
+
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use std::sync::Weak;
+
+/// Command buffer of cl_khr_command_buffer.
+///
+/// Validation, argument snapshots and work size calculations are done while recording. Replaying
+/// still goes through the same code paths as regular enqueues, so e.g. kernel inputs get uploaded
+/// and state gets bound again on every execution. Pre-baking that state is left for later.
+pub struct CommandBuffer {
+    pub base: CLObjectBase<CL_INVALID_COMMAND_BUFFER_KHR>,
+    pub queue: Arc<Queue>,
+    pub props: Vec<cl_command_buffer_properties_khr>,
+    pub flags: cl_command_buffer_flags_khr,
+    /// Commands recorded so far, moved into `cmds` once the command buffer gets finalized.
+    recording: Mutex<Vec<CommandSig>>,
+    cmds: OnceLock<Vec<CommandSig>>,
+    /// The last enqueued execution of this command buffer.
+    last_exec: Mutex<Weak<Event>>,
+}
+
+impl_cl_type_trait!(
+    cl_command_buffer_khr,
+    CommandBuffer,
+    CL_INVALID_COMMAND_BUFFER_KHR
+);
+
+impl CommandBuffer {
+    pub fn new(
+        queue: Arc<Queue>,
+        props: Vec<cl_command_buffer_properties_khr>,
+        flags: cl_command_buffer_flags_khr,
+    ) -> Arc<CommandBuffer> {
+        Arc::new(Self {
+            base: CLObjectBase::new(RusticlTypes::CommandBuffer),
+            queue: queue,
+            props: props,
+            flags: flags,
+            recording: Mutex::new(Vec::new()),
+            cmds: OnceLock::new(),
+            last_exec: Mutex::new(Weak::new()),
+        })
+    }
+
+    /// Records a command and returns its sync point.
+    pub fn record(&self, cmd: CommandSig) -> CLResult<cl_sync_point_khr> {
+        let mut recording = self.recording.lock().unwrap();
+
+        // CL_INVALID_OPERATION if command_buffer has been finalized.
+        if self.is_finalized() {
+            return Err(CL_INVALID_OPERATION.into());
+        }
+
+        recording.push(cmd);
+        Ok(recording.len() as cl_sync_point_khr)
+    }
+
+    /// Checks that all `sync_points` were returned by previously recorded commands.
+    pub fn sync_points_valid(&self, sync_points: &[cl_sync_point_khr]) -> bool {
+        let recorded = self.recording.lock().unwrap().len();
+        sync_points
+            .iter()
+            .all(|&sp| sp != 0 && sp as usize <= recorded)
+    }
+
+    pub fn finalize(&self) -> CLResult<()> {
+        let mut recording = self.recording.lock().unwrap();
+        self.cmds
+            .set(recording.drain(..).collect())
+            .map_err(|_| CL_INVALID_OPERATION.into())
+    }
+
+    pub fn is_finalized(&self) -> bool {
+        self.cmds.get().is_some()
+    }
+
+    pub fn is_pending(&self) -> bool {
+        self.last_exec
+            .lock()
+            .unwrap()
+            .upgrade()
+            .is_some_and(|e| e.status() > CL_COMPLETE as cl_int)
+    }
+
+    pub fn state(&self) -> cl_command_buffer_state_khr {
+        if !self.is_finalized() {
+            CL_COMMAND_BUFFER_STATE_RECORDING_KHR
+        } else if self.is_pending() {
+            CL_COMMAND_BUFFER_STATE_PENDING_KHR
+        } else {
+            CL_COMMAND_BUFFER_STATE_EXECUTABLE_KHR
+        }
+    }
+
+    /// Creates the event executing all recorded commands in order on `q`. Commands within a
+    /// command buffer are executed in order, which trivially satisfies all sync points.
+    pub fn exec(self: &Arc<Self>, q: &Arc<Queue>, deps: Vec<Arc<Event>>) -> Arc<Event> {
+        let cb = Arc::clone(self);
+        let e = Event::new(
+            q,
+            CL_COMMAND_COMMAND_BUFFER_KHR,
+            deps,
+            Box::new(move |q, ctx| {
+                for cmd in cb.cmds.get().unwrap() {
+                    cmd(q, ctx)?;
+                }
+                Ok(())
+            }),
+        );
+
+        *self.last_exec.lock().unwrap() = Arc::downgrade(&e);
+        e
+    }
+}
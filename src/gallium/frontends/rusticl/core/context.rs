@@ -1,26 +1,45 @@
+use crate::api::error::*;
 use crate::api::icd::*;
+use crate::api::types::CreateContextCB;
 use crate::api::types::DeleteContextCB;
 use crate::core::device::*;
 use crate::core::format::*;
 use crate::core::gl::*;
 use crate::core::memory::*;
+use crate::core::platform::*;
+use crate::core::queue::*;
+use crate::core::stats::*;
 use crate::core::util::*;
 use crate::impl_cl_type_trait;
 
 use mesa_rust::pipe::resource::*;
 use mesa_rust::pipe::screen::ResourceType;
 use mesa_rust_gen::*;
+use mesa_rust_util::log_warn;
+use mesa_rust_util::math::align;
+use mesa_rust_util::per_device::PerDevice;
 use mesa_rust_util::properties::Properties;
 use rusticl_opencl_gen::*;
 
 use std::alloc::Layout;
 use std::collections::BTreeMap;
-use std::collections::HashMap;
+use std::collections::HashSet;
 use std::convert::TryInto;
+use std::ffi::CString;
 use std::mem;
 use std::os::raw::c_void;
+use std::ptr;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::Weak;
+
+#[derive(Clone, Copy)]
+pub struct UsmAlloc {
+    pub layout: Layout,
+    pub mem_type: cl_unified_shared_memory_type_intel,
+    /// The device the allocation was made for, `None` for host allocations.
+    pub dev: Option<&'static Device>,
+}
 
 pub struct Context {
     pub base: CLObjectBase<CL_INVALID_CONTEXT>,
@@ -28,7 +47,14 @@ pub struct Context {
     pub properties: Properties<cl_context_properties>,
     pub dtors: Mutex<Vec<DeleteContextCB>>,
     pub svm_ptrs: Mutex<BTreeMap<usize, Layout>>,
+    /// SVM allocations made with `CL_MEM_SVM_ATOMICS`, which need coherent mappings.
+    svm_atomics_ptrs: Mutex<HashSet<usize>>,
+    pub usm_ptrs: Mutex<BTreeMap<usize, UsmAlloc>>,
+    queues: Mutex<Vec<Weak<Queue>>>,
     pub gl_ctx_manager: Option<GLCtxManager>,
+    /// Callback passed to clCreateContext for reporting errors happening in this context.
+    notify: Option<CreateContextCB>,
+    pub mem_stats: MemoryStats,
 }
 
 impl_cl_type_trait!(cl_context, Context, CL_INVALID_CONTEXT);
@@ -38,6 +64,7 @@ impl Context {
         devs: Vec<&'static Device>,
         properties: Properties<cl_context_properties>,
         gl_ctx_manager: Option<GLCtxManager>,
+        notify: Option<CreateContextCB>,
     ) -> Arc<Context> {
         Arc::new(Self {
             base: CLObjectBase::new(RusticlTypes::Context),
@@ -45,38 +72,118 @@ impl Context {
             properties: properties,
             dtors: Mutex::new(Vec::new()),
             svm_ptrs: Mutex::new(BTreeMap::new()),
+            svm_atomics_ptrs: Mutex::new(HashSet::new()),
+            usm_ptrs: Mutex::new(BTreeMap::new()),
+            queues: Mutex::new(Vec::new()),
             gl_ctx_manager: gl_ctx_manager,
+            notify: notify,
+            mem_stats: MemoryStats::default(),
         })
     }
 
+    /// Accounts `size` bytes of `kind` memory allocated on `dev` for this context.
+    pub fn add_allocation(&self, dev: &Device, kind: MemoryKind, size: usize) {
+        dev.stats.memory.add(kind, size);
+        self.mem_stats.add(kind, size);
+    }
+
+    pub fn remove_allocation(&self, dev: &Device, kind: MemoryKind, size: usize) {
+        dev.stats.memory.remove(kind, size);
+        self.mem_stats.remove(kind, size);
+    }
+
+    /// Dumps the memory usage of this context and `dev` with `RUSTICL_DEBUG=memory`, so it's
+    /// visible why an allocation failed.
+    fn dump_memory_stats(&self, dev: &Device) {
+        if Platform::dbg().memory {
+            log_warn!(
+                "allocation failed on {}\n  context: {}\n  device: {}, idle staging: {} bytes",
+                dev.screen().name(),
+                self.mem_stats,
+                dev.stats.memory,
+                dev.idle_staging_size()
+            );
+        }
+    }
+
+    /// Reports an error to the application through the callback passed to clCreateContext.
+    pub fn notify(&self, msg: &str) {
+        if let Some(notify) = &self.notify {
+            let msg = CString::new(msg).unwrap_or_default();
+            notify.call(&msg, &[]);
+        }
+    }
+
+    /// Creates a buffer of `size` bytes on each device of `devs`.
     pub fn create_buffer(
         &self,
+        devs: &[&'static Device],
         size: usize,
         user_ptr: *mut c_void,
         copy: bool,
         res_type: ResourceType,
-    ) -> CLResult<HashMap<&'static Device, Arc<PipeResource>>> {
+    ) -> CLResult<PerDevice<&'static Device, Arc<PipeResource>>> {
         let adj_size: u32 = size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
-        let mut res = HashMap::new();
-        for &dev in &self.devs {
+
+        // leave room for the guard area behind the buffer, user memory can't be padded though.
+        let guard_size = if Platform::dbg().bounds {
+            BOUNDS_GUARD_SIZE
+        } else {
+            0
+        };
+        let alloc_size: u32 = (size + guard_size)
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+
+        let mut res = PerDevice::new();
+        for &dev in devs {
             let mut resource = None;
 
-            if !user_ptr.is_null() && !copy {
+            if !user_ptr.is_null() && !copy && dev.caps.has_user_memory {
+                // Drivers usually can only import entire pages, so we import all pages the host
+                // memory lives in. Buffers apply the offset into the first page themselves.
+                let page_size = host_page_size();
+                let page_offset = user_ptr as usize % page_size;
+                let import_size = align(size + page_offset, page_size)
+                    .try_into()
+                    .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+
                 resource = dev.screen().resource_create_buffer_from_user(
-                    adj_size,
-                    user_ptr,
+                    import_size,
+                    user_ptr.cast::<u8>().wrapping_sub(page_offset).cast(),
+                    res_type,
                     PIPE_BIND_GLOBAL,
                 )
             }
 
             if resource.is_none() {
-                resource = dev
-                    .screen()
-                    .resource_create_buffer(adj_size, res_type, PIPE_BIND_GLOBAL)
+                resource =
+                    dev.screen()
+                        .resource_create_buffer(alloc_size, res_type, PIPE_BIND_GLOBAL)
             }
 
-            let resource = resource.ok_or(CL_OUT_OF_RESOURCES);
-            res.insert(dev, Arc::new(resource?));
+            let resource = resource.ok_or_else(|| {
+                self.dump_memory_stats(dev);
+                CLError::new(CL_OUT_OF_RESOURCES, "resource_create_buffer")
+                    .device(dev)
+                    .msg(format!("failed to allocate a buffer of {adj_size} bytes"))
+            })?;
+
+            if guard_size != 0 && !resource.is_user {
+                let guard = [BOUNDS_GUARD_PATTERN; BOUNDS_GUARD_SIZE];
+                dev.helper_ctx()
+                    .exec(|ctx| {
+                        ctx.buffer_subdata(
+                            &resource,
+                            adj_size,
+                            guard.as_ptr().cast(),
+                            alloc_size - adj_size,
+                        )
+                    })
+                    .wait();
+            }
+
+            res.insert(dev, Arc::new(resource));
         }
 
         if !user_ptr.is_null() {
@@ -92,14 +199,17 @@ impl Context {
         Ok(res)
     }
 
+    /// Creates a texture described by `desc` on each device of `devs`.
     pub fn create_texture(
         &self,
+        devs: &[&'static Device],
         desc: &cl_image_desc,
         format: &cl_image_format,
+        flags: cl_mem_flags,
         user_ptr: *mut c_void,
         copy: bool,
         res_type: ResourceType,
-    ) -> CLResult<HashMap<&'static Device, Arc<PipeResource>>> {
+    ) -> CLResult<PerDevice<&'static Device, Arc<PipeResource>>> {
         let pipe_format = format.to_pipe_format().unwrap();
 
         let width = desc
@@ -118,16 +228,34 @@ impl Context {
             .image_array_size
             .try_into()
             .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
-        let target = cl_mem_type_to_texture_target(desc.image_type);
+        let last_level = (desc.num_levels() - 1)
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
 
-        let mut res = HashMap::new();
-        for &dev in &self.devs {
+        let mut res = PerDevice::new();
+        for &dev in devs {
             let mut resource = None;
-            let enable_bind_as_image =
-                (dev.formats[format][&desc.image_type] as u32 & CL_MEM_WRITE_ONLY) != 0;
+            let target = dev.image_target(desc.image_type, flags);
+            // 3D images created as 2D arrays store their slices as layers
+            let (depth, array_size) = if target == pipe_texture_target::PIPE_TEXTURE_2D_ARRAY
+                && desc.image_type == CL_MEM_OBJECT_IMAGE3D
+            {
+                (1, depth)
+            } else {
+                (depth, array_size)
+            };
+            // without 3D image stores only 3D images created as 2D arrays can be written
+            let enable_bind_as_image = (dev.formats[format][&desc.image_type] as u32
+                & CL_MEM_WRITE_ONLY)
+                != 0
+                && (target != pipe_texture_target::PIPE_TEXTURE_3D || dev.caps.has_3d_image_stores);
 
             // we can't specify custom pitches/slices, so this won't work for non 1D images
-            if !user_ptr.is_null() && !copy && desc.image_type == CL_MEM_OBJECT_IMAGE1D {
+            if !user_ptr.is_null()
+                && !copy
+                && dev.caps.has_user_memory
+                && desc.image_type == CL_MEM_OBJECT_IMAGE1D
+            {
                 resource = dev.screen().resource_create_texture_from_user(
                     width,
                     height,
@@ -146,6 +274,7 @@ impl Context {
                     height,
                     depth,
                     array_size,
+                    last_level,
                     target,
                     pipe_format,
                     res_type,
@@ -153,8 +282,16 @@ impl Context {
                 )
             }
 
-            let resource = resource.ok_or(CL_OUT_OF_RESOURCES);
-            res.insert(dev, Arc::new(resource?));
+            let resource = resource.ok_or_else(|| {
+                self.dump_memory_stats(dev);
+                CLError::new(CL_OUT_OF_RESOURCES, "resource_create_texture")
+                    .device(dev)
+                    .msg(format!(
+                        "failed to allocate a {width}x{height}x{depth} texture with {array_size} \
+                         layers of format {pipe_format:?}"
+                    ))
+            })?;
+            res.insert(dev, Arc::new(resource));
         }
 
         if !user_ptr.is_null() {
@@ -166,7 +303,7 @@ impl Context {
                 .filter(|(_, r)| copy || !r.is_user)
                 .map(|(d, r)| {
                     d.helper_ctx()
-                        .exec(|ctx| ctx.texture_subdata(r, &bx, user_ptr, stride, layer_stride))
+                        .exec(|ctx| ctx.texture_subdata(r, 0, &bx, user_ptr, stride, layer_stride))
                 })
                 .for_each(|f| f.wait());
         }
@@ -183,12 +320,30 @@ impl Context {
             .unwrap()
     }
 
+    /// Returns the base address alignment in pixels required by all devices supporting images
+    /// created from buffers.
+    pub fn image_base_address_alignment(&self) -> cl_uint {
+        self.devs
+            .iter()
+            .map(|dev| dev.image_base_address_alignment())
+            .max()
+            .unwrap()
+    }
+
     pub fn has_svm_devs(&self) -> bool {
         self.devs.iter().any(|dev| dev.svm_supported())
     }
 
-    pub fn add_svm_ptr(&self, ptr: usize, layout: Layout) {
+    /// SVM atomics are only usable if every device in the context supports them.
+    pub fn svm_atomics_supported(&self) -> bool {
+        self.devs.iter().all(|dev| dev.svm_atomics_supported())
+    }
+
+    pub fn add_svm_ptr(&self, ptr: usize, layout: Layout, atomics: bool) {
         self.svm_ptrs.lock().unwrap().insert(ptr, layout);
+        if atomics {
+            self.svm_atomics_ptrs.lock().unwrap().insert(ptr);
+        }
     }
 
     pub fn find_svm_alloc(&self, ptr: usize) -> Option<(*const c_void, Layout)> {
@@ -207,10 +362,79 @@ impl Context {
         None
     }
 
+    /// Whether `ptr` points into an SVM allocation made with `CL_MEM_SVM_ATOMICS`.
+    pub fn is_svm_atomics_ptr(&self, ptr: usize) -> bool {
+        self.find_svm_alloc(ptr).is_some_and(|(base, _)| {
+            self.svm_atomics_ptrs
+                .lock()
+                .unwrap()
+                .contains(&(base as usize))
+        })
+    }
+
     pub fn remove_svm_ptr(&self, ptr: usize) -> Option<Layout> {
+        self.svm_atomics_ptrs.lock().unwrap().remove(&ptr);
         self.svm_ptrs.lock().unwrap().remove(&ptr)
     }
 
+    pub fn add_usm_ptr(&self, ptr: usize, alloc: UsmAlloc) {
+        self.usm_ptrs.lock().unwrap().insert(ptr, alloc);
+    }
+
+    /// Returns the base address and the allocation `ptr` points into.
+    pub fn find_usm_alloc(&self, ptr: usize) -> Option<(usize, UsmAlloc)> {
+        let lock = self.usm_ptrs.lock().unwrap();
+        let (&base, alloc) = lock.range(..=ptr).next_back()?;
+        (ptr - base < alloc.layout.size()).then_some((base, *alloc))
+    }
+
+    pub fn remove_usm_ptr(&self, ptr: usize) -> Option<UsmAlloc> {
+        self.usm_ptrs.lock().unwrap().remove(&ptr)
+    }
+
+    pub fn add_queue(&self, q: &Arc<Queue>) {
+        let mut queues = self.queues.lock().unwrap();
+        queues.retain(|q| q.strong_count() > 0);
+        queues.push(Arc::downgrade(q));
+    }
+
+    /// Drops `res` only once all work submitted to queues on `dev` so far finished, as the GPU
+    /// might still be using them.
+    pub fn destroy_deferred(&self, dev: &Device, res: Vec<Arc<PipeResource>>) {
+        if res.is_empty() {
+            return;
+        }
+
+        let queues: Vec<_> = self
+            .queues
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .filter(|q| ptr::eq(q.device, dev))
+            .collect();
+
+        for q in queues {
+            q.destroy_deferred(&res);
+        }
+    }
+
+    /// Waits on all commands enqueued to any queue of this context so far.
+    pub fn finish_queues(&self) -> CLResult<()> {
+        let queues: Vec<_> = self
+            .queues
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(Weak::upgrade)
+            .collect();
+
+        for q in queues {
+            q.flush(true)?;
+        }
+        Ok(())
+    }
+
     pub fn import_gl_buffer(
         &self,
         handle: u32,
@@ -219,27 +443,95 @@ impl Context {
         gl_target: cl_GLenum,
         format: pipe_format,
         gl_props: GLMemProps,
-    ) -> CLResult<HashMap<&'static Device, Arc<PipeResource>>> {
-        let mut res = HashMap::new();
-        let target = cl_mem_type_to_texture_target_gl(image_type, gl_target);
+    ) -> CLResult<PerDevice<&'static Device, Arc<PipeResource>>> {
+        self.import_dma_buf(
+            &self.devs,
+            handle,
+            modifier,
+            cl_mem_type_to_texture_target_gl(image_type, gl_target),
+            format,
+            gl_props.stride,
+            gl_props.width,
+            gl_props.height,
+            gl_props.depth,
+            gl_props.array_size,
+        )
+    }
+
+    /// Imports the dma-buf `handle` as an image described by `desc` on each device of `devs`.
+    pub fn import_texture(
+        &self,
+        devs: &[&'static Device],
+        handle: u32,
+        modifier: u64,
+        desc: &cl_image_desc,
+        format: &cl_image_format,
+    ) -> CLResult<PerDevice<&'static Device, Arc<PipeResource>>> {
+        let stride = desc
+            .image_row_pitch
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let width = desc
+            .image_width
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let height = desc
+            .image_height
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let depth = desc
+            .image_depth
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let array_size = desc
+            .image_array_size
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+
+        self.import_dma_buf(
+            devs,
+            handle,
+            modifier,
+            cl_mem_type_to_texture_target(desc.image_type),
+            format.to_pipe_format().unwrap(),
+            stride,
+            width,
+            height,
+            depth,
+            array_size,
+        )
+    }
 
-        for dev in &self.devs {
+    /// Imports the dma-buf `handle` on each device of `devs`.
+    pub fn import_dma_buf(
+        &self,
+        devs: &[&'static Device],
+        handle: u32,
+        modifier: u64,
+        target: pipe_texture_target,
+        format: pipe_format,
+        stride: u32,
+        width: u32,
+        height: u16,
+        depth: u16,
+        array_size: u16,
+    ) -> CLResult<PerDevice<&'static Device, Arc<PipeResource>>> {
+        let mut res = PerDevice::new();
+        for &dev in devs {
             let resource = dev
                 .screen()
                 .resource_import_dmabuf(
-                    handle,
-                    modifier,
-                    target,
-                    format,
-                    gl_props.stride,
-                    gl_props.width,
-                    gl_props.height,
-                    gl_props.depth,
-                    gl_props.array_size,
+                    handle, modifier, target, format, stride, width, height, depth, array_size,
                 )
-                .ok_or(CL_OUT_OF_RESOURCES)?;
+                .ok_or_else(|| {
+                    CLError::new(CL_OUT_OF_RESOURCES, "resource_import_dmabuf")
+                        .device(dev)
+                        .msg(format!(
+                            "failed to import dma-buf {handle} with modifier {modifier:#x}"
+                        ))
+                })?;
 
-            res.insert(*dev, Arc::new(resource));
+            res.insert(dev, Arc::new(resource));
         }
 
         Ok(res)
@@ -252,5 +544,11 @@ impl Drop for Context {
         for cb in cbs.into_iter().rev() {
             cb.call(self);
         }
+
+        if Platform::dbg().stats {
+            for dev in &self.devs {
+                log_warn!("stats of {}: {}", dev.screen().name(), dev.stats);
+            }
+        }
     }
 }
@@ -1,7 +1,9 @@
 use crate::api::icd::*;
 use crate::api::util::*;
 use crate::core::format::*;
+use crate::core::kernel::CSOWrapper;
 use crate::core::platform::*;
+use crate::core::stats::*;
 use crate::core::util::*;
 use crate::core::version::*;
 use crate::impl_cl_type_trait_base;
@@ -14,14 +16,18 @@ use mesa_rust::pipe::fence::*;
 use mesa_rust::pipe::resource::*;
 use mesa_rust::pipe::screen::*;
 use mesa_rust::pipe::transfer::*;
+use mesa_rust::util::disk_cache::DiskCacheBorrowed;
 use mesa_rust_gen::*;
+use mesa_rust_util::log_error;
 use mesa_rust_util::math::SetBitIndices;
+use mesa_rust_util::per_device::DeviceIdx;
 use mesa_rust_util::static_assert;
 use rusticl_opencl_gen::*;
 
 use std::cmp::max;
 use std::cmp::min;
 use std::collections::HashMap;
+use std::collections::VecDeque;
 use std::convert::TryInto;
 use std::env;
 use std::ffi::CString;
@@ -30,6 +36,7 @@ use std::os::raw::*;
 use std::sync::Arc;
 use std::sync::Mutex;
 use std::sync::MutexGuard;
+use std::sync::OnceLock;
 
 pub struct Device {
     pub base: CLObjectBase<CL_INVALID_DEVICE>,
@@ -46,12 +53,95 @@ pub struct Device {
     pub formats: HashMap<cl_image_format, HashMap<cl_mem_object_type, cl_mem_flags>>,
     pub lib_clc: NirShader,
     pub caps: DeviceCaps,
+    pub stats: DeviceStats,
     helper_ctx: Mutex<PipeContext>,
+    staging: Mutex<StagingPool>,
+    /// Internal kernels to fill buffers, indexed by the log2 of the pattern size.
+    fill_buffer_csos: [OnceLock<CSOWrapper>; 8],
+    /// Index into the platform's device list, used to look up per device state.
+    idx: usize,
+}
+
+// from drm.h, reported through PIPE_CAP_DMABUF
+const DRM_PRIME_CAP_IMPORT: i32 = 0x1;
+const DRM_PRIME_CAP_EXPORT: i32 = 0x2;
+
+/// Smallest and biggest staging buffers kept in the [StagingPool].
+const STAGING_MIN_SHIFT: u32 = 12;
+const STAGING_MAX_SHIFT: u32 = 24;
+const STAGING_BUCKETS: usize = (STAGING_MAX_SHIFT - STAGING_MIN_SHIFT + 1) as usize;
+
+/// Upper limit of memory held by idle staging buffers of a device.
+const STAGING_POOL_LIMIT: usize = 64 << 20;
+
+struct StagingBuffer {
+    res: PipeResource,
+    /// Signals once the GPU is done with the last use of `res`.
+    fence: PipeFence,
+}
+
+/// Staging buffers used as shadows of mappings which can't be done directly. They are bucketed by
+/// their power of two size so repeated map/unmap cycles can reuse them instead of going through
+/// the allocator every time.
+#[derive(Default)]
+struct StagingPool {
+    buckets: [VecDeque<StagingBuffer>; STAGING_BUCKETS],
+    size: usize,
+}
+
+impl StagingPool {
+    fn bucket(size: u32) -> Option<usize> {
+        let shift = size
+            .checked_next_power_of_two()?
+            .trailing_zeros()
+            .max(STAGING_MIN_SHIFT);
+        (shift <= STAGING_MAX_SHIFT).then(|| (shift - STAGING_MIN_SHIFT) as usize)
+    }
+
+    fn bucket_size(bucket: usize) -> u32 {
+        1 << (bucket as u32 + STAGING_MIN_SHIFT)
+    }
+
+    fn take(&mut self, bucket: usize) -> Option<StagingBuffer> {
+        let buf = self.buckets[bucket].pop_back()?;
+        self.size -= Self::bucket_size(bucket) as usize;
+        Some(buf)
+    }
+
+    fn put(&mut self, bucket: usize, buf: StagingBuffer) {
+        let size = Self::bucket_size(bucket) as usize;
+
+        // make room by dropping the oldest entries of the biggest buckets first.
+        while self.size + size > STAGING_POOL_LIMIT {
+            let Some(victim) = self.buckets.iter().rposition(|b| !b.is_empty()) else {
+                break;
+            };
+            self.buckets[victim].pop_front();
+            self.size -= Self::bucket_size(victim) as usize;
+        }
+
+        self.buckets[bucket].push_back(buf);
+        self.size += size;
+    }
+
+    fn trim(&mut self) {
+        self.buckets.iter_mut().for_each(VecDeque::clear);
+        self.size = 0;
+    }
 }
 
 pub struct DeviceCaps {
     pub has_images: bool,
+    /// Whether 3D images can be bound as shader images. If not, kernel accesses are lowered to
+    /// 2D array accesses of all slices.
+    pub has_3d_image_stores: bool,
+    /// Whether sRGB images can be bound as shader images directly. If not, a linear view is bound
+    /// instead and the conversion is done inside the kernel.
+    pub has_srgb_image_stores: bool,
     pub has_timestamp: bool,
+    /// Whether resources can be created on top of host memory, which lets `CL_MEM_USE_HOST_PTR`
+    /// memory objects use the application's memory directly instead of a shadow copy.
+    pub has_user_memory: bool,
     pub image_2d_size: u32,
     pub max_read_images: u32,
     pub max_write_images: u32,
@@ -68,6 +158,9 @@ impl DeviceCaps {
         let max_read_images =
             Self::shader_param(screen, pipe_shader_cap::PIPE_SHADER_CAP_MAX_SAMPLER_VIEWS) as u32;
         let image_2d_size = screen.param(pipe_cap::PIPE_CAP_MAX_TEXTURE_2D_SIZE) as u32;
+        // we only ever use resources in compute shaders
+        let has_user_memory = screen.param(pipe_cap::PIPE_CAP_RESOURCE_FROM_USER_MEMORY) != 0
+            || screen.param(pipe_cap::PIPE_CAP_RESOURCE_FROM_USER_MEMORY_COMPUTE_ONLY) != 0;
 
         let has_images =
             // The minimum value is 8 if CL_DEVICE_IMAGE_SUPPORT is CL_TRUE
@@ -77,9 +170,49 @@ impl DeviceCaps {
             // The minimum value is 2048 if CL_DEVICE_IMAGE_SUPPORT is CL_TRUE
             image_2d_size >= 2048;
 
+        let has_srgb_image_stores = has_images
+            && FORMATS
+                .iter()
+                .filter(|f| f.is_srgb)
+                .filter(|f| {
+                    screen.is_format_supported(
+                        f.pipe,
+                        pipe_texture_target::PIPE_TEXTURE_2D,
+                        PIPE_BIND_SAMPLER_VIEW,
+                    )
+                })
+                .all(|f| {
+                    screen.is_format_supported(
+                        f.pipe,
+                        pipe_texture_target::PIPE_TEXTURE_2D,
+                        PIPE_BIND_SHADER_IMAGE,
+                    )
+                });
+
+        let has_3d_image_stores = has_images
+            && FORMATS
+                .iter()
+                .filter(|f| {
+                    screen.is_format_supported(
+                        f.pipe,
+                        pipe_texture_target::PIPE_TEXTURE_2D_ARRAY,
+                        PIPE_BIND_SHADER_IMAGE,
+                    )
+                })
+                .all(|f| {
+                    screen.is_format_supported(
+                        f.pipe,
+                        pipe_texture_target::PIPE_TEXTURE_3D,
+                        PIPE_BIND_SHADER_IMAGE,
+                    )
+                });
+
         Self {
             has_images: has_images,
+            has_3d_image_stores: has_3d_image_stores,
+            has_srgb_image_stores: has_srgb_image_stores,
             has_timestamp: cap_timestamp && timer_resolution > 0,
+            has_user_memory: has_user_memory,
             image_2d_size: has_images.then_some(image_2d_size).unwrap_or_default(),
             max_read_images: has_images.then_some(max_read_images).unwrap_or_default(),
             max_write_images: has_images.then_some(max_write_images).unwrap_or_default(),
@@ -98,6 +231,14 @@ pub trait HelperContextWrapper {
     where
         F: Fn(&HelperContext);
 
+    fn buffer_map(
+        &self,
+        res: &PipeResource,
+        offset: i32,
+        size: i32,
+        rw: RWFlags,
+    ) -> Option<PipeTransfer>;
+
     fn buffer_map_directly(
         &self,
         res: &PipeResource,
@@ -114,9 +255,18 @@ pub trait HelperContextWrapper {
         rw: RWFlags,
     ) -> Option<PipeTransfer>;
 
+    fn texture_map(
+        &self,
+        res: &PipeResource,
+        level: u32,
+        bx: &pipe_box,
+        rw: RWFlags,
+    ) -> Option<PipeTransfer>;
+
     fn texture_map_directly(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         rw: RWFlags,
     ) -> Option<PipeTransfer>;
@@ -124,6 +274,7 @@ pub trait HelperContextWrapper {
     fn texture_map_coherent(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         rw: RWFlags,
     ) -> Option<PipeTransfer>;
@@ -167,13 +318,14 @@ impl<'a> HelperContext<'a> {
     pub fn texture_subdata(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         data: *const c_void,
         stride: u32,
         layer_stride: usize,
     ) {
         self.lock
-            .texture_subdata(res, bx, data, stride, layer_stride)
+            .texture_subdata(res, level, bx, data, stride, layer_stride)
     }
 }
 
@@ -186,6 +338,17 @@ impl<'a> HelperContextWrapper for HelperContext<'a> {
         self.lock.flush()
     }
 
+    fn buffer_map(
+        &self,
+        res: &PipeResource,
+        offset: i32,
+        size: i32,
+        rw: RWFlags,
+    ) -> Option<PipeTransfer> {
+        self.lock
+            .buffer_map(res, offset, size, rw, ResourceMapType::Normal)
+    }
+
     fn buffer_map_directly(
         &self,
         res: &PipeResource,
@@ -207,23 +370,36 @@ impl<'a> HelperContextWrapper for HelperContext<'a> {
             .buffer_map(res, offset, size, rw, ResourceMapType::Coherent)
     }
 
+    fn texture_map(
+        &self,
+        res: &PipeResource,
+        level: u32,
+        bx: &pipe_box,
+        rw: RWFlags,
+    ) -> Option<PipeTransfer> {
+        self.lock
+            .texture_map(res, level, bx, rw, ResourceMapType::Normal)
+    }
+
     fn texture_map_directly(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         rw: RWFlags,
     ) -> Option<PipeTransfer> {
-        self.lock.texture_map_directly(res, bx, rw)
+        self.lock.texture_map_directly(res, level, bx, rw)
     }
 
     fn texture_map_coherent(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         rw: RWFlags,
     ) -> Option<PipeTransfer> {
         self.lock
-            .texture_map(res, bx, rw, ResourceMapType::Coherent)
+            .texture_map(res, level, bx, rw, ResourceMapType::Coherent)
     }
 
     fn create_compute_state(&self, nir: &NirShader, static_local_mem: u32) -> *mut c_void {
@@ -257,6 +433,12 @@ impl<'a> HelperContextWrapper for HelperContext<'a> {
 
 impl_cl_type_trait_base!(cl_device_id, Device, [Device], CL_INVALID_DEVICE);
 
+impl DeviceIdx for Device {
+    fn device_idx(&self) -> usize {
+        self.idx
+    }
+}
+
 impl Device {
     fn new(screen: PipeScreen) -> Option<Device> {
         if !Self::check_valid(&screen) {
@@ -269,13 +451,15 @@ impl Device {
         let helper_ctx = screen.create_context()?;
         let lib_clc = spirv::SPIRVBin::get_lib_clc(&screen);
         if lib_clc.is_none() {
-            eprintln!("Libclc failed to load. Please make sure it is installed and provides spirv-mesa3d-.spv and/or spirv64-mesa3d-.spv");
+            log_error!("Libclc failed to load. Please make sure it is installed and provides spirv-mesa3d-.spv and/or spirv64-mesa3d-.spv");
         }
 
         let mut d = Self {
             caps: DeviceCaps::new(&screen),
             base: CLObjectBase::new(RusticlTypes::Device),
             helper_ctx: Mutex::new(helper_ctx),
+            staging: Mutex::default(),
+            fill_buffer_csos: Default::default(),
             screen: screen,
             cl_version: CLVersion::Cl3_0,
             clc_version: CLVersion::Cl3_0,
@@ -288,6 +472,8 @@ impl Device {
             clc_features: Vec::new(),
             formats: HashMap::new(),
             lib_clc: lib_clc?,
+            stats: DeviceStats::default(),
+            idx: 0,
         };
 
         d.fill_format_tables();
@@ -325,43 +511,55 @@ impl Device {
                 // the CTS doesn't test them, so let's not advertize them by accident if they are
                 // broken
                 if t == CL_MEM_OBJECT_IMAGE1D_BUFFER
-                    && [CL_RGB, CL_RGBx].contains(&f.cl_image_format.image_channel_order)
+                    && [CL_RGB, CL_RGBx, CL_sRGB].contains(&f.cl_image_format.image_channel_order)
                     && ![CL_UNORM_SHORT_565, CL_UNORM_SHORT_555]
                         .contains(&f.cl_image_format.image_channel_data_type)
                 {
                     continue;
                 }
 
+                let target = cl_mem_type_to_texture_target(t);
+                let store_target = if t == CL_MEM_OBJECT_IMAGE3D && !self.caps.has_3d_image_stores {
+                    pipe_texture_target::PIPE_TEXTURE_2D_ARRAY
+                } else {
+                    target
+                };
+                let store_format = self.image_store_format(&f.cl_image_format);
                 let mut flags: cl_uint = 0;
-                if self.screen.is_format_supported(
-                    f.pipe,
-                    cl_mem_type_to_texture_target(t),
-                    PIPE_BIND_SAMPLER_VIEW,
-                ) {
+                if self
+                    .screen
+                    .is_format_supported(f.pipe, target, PIPE_BIND_SAMPLER_VIEW)
+                {
                     flags |= CL_MEM_READ_ONLY;
                 }
 
-                // TODO: cl_khr_srgb_image_writes
-                if !f.is_srgb
-                    && self.screen.is_format_supported(
-                        f.pipe,
-                        cl_mem_type_to_texture_target(t),
-                        PIPE_BIND_SHADER_IMAGE,
-                    )
-                {
+                if self.screen.is_format_supported(
+                    store_format,
+                    store_target,
+                    PIPE_BIND_SHADER_IMAGE,
+                ) {
                     flags |= CL_MEM_WRITE_ONLY;
                     // TODO: enable once we support it
                     // flags |= CL_MEM_KERNEL_READ_AND_WRITE;
                 }
 
-                // TODO: cl_khr_srgb_image_writes
-                if !f.is_srgb
-                    && self.screen.is_format_supported(
+                // 3D images with lowered stores are created as 2D arrays if written, which can't be
+                // sampled as 3D images anymore.
+                let read_write = if store_target != target {
+                    false
+                } else if store_format == f.pipe {
+                    self.screen.is_format_supported(
                         f.pipe,
-                        cl_mem_type_to_texture_target(t),
+                        target,
                         PIPE_BIND_SAMPLER_VIEW | PIPE_BIND_SHADER_IMAGE,
                     )
-                {
+                } else {
+                    // with a linear view for stores, both formats are used independently
+                    flags & (CL_MEM_READ_ONLY | CL_MEM_WRITE_ONLY)
+                        == CL_MEM_READ_ONLY | CL_MEM_WRITE_ONLY
+                };
+
+                if read_write {
                     flags |= CL_MEM_READ_WRITE;
                 }
 
@@ -598,6 +796,9 @@ impl Device {
         let mut feats = Vec::new();
         let mut spirv_exts = Vec::new();
         let mut add_ext = |major, minor, patch, ext: &str| {
+            if Platform::conf().is_extension_disabled(ext) {
+                return;
+            }
             exts.push(mk_cl_version_ext(major, minor, patch, ext));
             exts_str.push(ext.to_owned());
         };
@@ -609,6 +810,7 @@ impl Device {
         };
 
         // add extensions all drivers support for now
+        add_ext(0, 9, 4, "cl_khr_command_buffer");
         add_ext(1, 0, 0, "cl_khr_global_int32_base_atomics");
         add_ext(1, 0, 0, "cl_khr_global_int32_extended_atomics");
         add_ext(2, 0, 0, "cl_khr_integer_dot_product");
@@ -621,6 +823,8 @@ impl Device {
         add_feat(2, 0, 0, "__opencl_c_integer_dot_product_input_4x8bit");
         add_ext(1, 0, 0, "cl_khr_local_int32_base_atomics");
         add_ext(1, 0, 0, "cl_khr_local_int32_extended_atomics");
+        add_ext(1, 0, 0, "cl_khr_semaphore");
+        add_ext(1, 0, 0, "cl_mesa_memory_stats");
 
         add_spirv("SPV_KHR_expect_assume");
         add_spirv("SPV_KHR_float_controls");
@@ -640,6 +844,20 @@ impl Device {
             add_ext(1, 0, 0, "cl_khr_gl_sharing");
         }
 
+        if self.dma_buf_import_supported() {
+            add_ext(1, 0, 1, "cl_khr_external_memory");
+            add_ext(1, 0, 0, "cl_khr_external_memory_dma_buf");
+        }
+
+        if self.dma_buf_export_supported() {
+            add_ext(1, 0, 0, "cl_mesa_dma_buf_export");
+        }
+
+        if self.sync_fd_import_supported() || self.sync_fd_export_supported() {
+            add_ext(1, 0, 0, "cl_khr_external_semaphore");
+            add_ext(1, 0, 0, "cl_khr_external_semaphore_sync_fd");
+        }
+
         if self.int64_supported() {
             if self.embedded {
                 add_ext(1, 0, 0, "cles_khr_int64");
@@ -651,6 +869,8 @@ impl Device {
         if self.caps.has_images {
             add_feat(1, 0, 0, "__opencl_c_images");
 
+            add_ext(1, 0, 0, "cl_khr_mipmap_image");
+
             if self.image2d_from_buffer_supported() {
                 add_ext(1, 0, 0, "cl_khr_image2d_from_buffer");
             }
@@ -663,6 +883,10 @@ impl Device {
                 add_ext(1, 0, 0, "cl_khr_3d_image_writes");
                 add_feat(1, 0, 0, "__opencl_c_3d_image_writes");
             }
+
+            if self.srgb_image_writes_supported() {
+                add_ext(1, 0, 0, "cl_khr_srgb_image_writes");
+            }
         }
 
         if self.pci_info().is_some() {
@@ -686,8 +910,13 @@ impl Device {
             add_ext(1, 0, 0, "cl_khr_subgroup_shuffle_relative");
         }
 
+        if self.protected_memory_supported() {
+            add_ext(1, 0, 0, "cl_arm_protected_memory_allocation");
+        }
+
         if self.svm_supported() {
             add_ext(1, 0, 0, "cl_arm_shared_virtual_memory");
+            add_ext(1, 0, 0, "cl_intel_unified_shared_memory");
         }
 
         self.extensions = exts;
@@ -702,7 +931,13 @@ impl Device {
     }
 
     pub fn all() -> impl Iterator<Item = Device> {
-        load_screens().filter_map(Device::new)
+        load_screens()
+            .filter_map(Device::new)
+            .enumerate()
+            .map(|(idx, mut dev)| {
+                dev.idx = idx;
+                dev
+            })
     }
 
     pub fn address_bits(&self) -> cl_uint {
@@ -710,6 +945,12 @@ impl Device {
             .compute_param(pipe_compute_cap::PIPE_COMPUTE_CAP_ADDRESS_BITS)
     }
 
+    /// Alignment of buffer and sub-buffer base addresses in bits.
+    pub fn mem_base_addr_align(&self) -> cl_uint {
+        // TODO proper retrival from devices
+        0x1000
+    }
+
     pub fn const_max_size(&self) -> cl_ulong {
         min(
             // Needed to fix the `api min_max_constant_buffer_size` CL CTS test as it can't really
@@ -768,6 +1009,49 @@ impl Device {
         self.screen.param(pipe_cap::PIPE_CAP_DOUBLES) == 1
     }
 
+    pub fn dma_buf_import_supported(&self) -> bool {
+        self.screen.param(pipe_cap::PIPE_CAP_DMABUF) & DRM_PRIME_CAP_IMPORT != 0
+            && self.screen.is_res_handle_supported()
+    }
+
+    pub fn dma_buf_export_supported(&self) -> bool {
+        self.screen.param(pipe_cap::PIPE_CAP_DMABUF) & DRM_PRIME_CAP_EXPORT != 0
+            && self.screen.is_res_handle_supported()
+    }
+
+    pub fn external_memory_import_handle_types(&self) -> Vec<cl_external_memory_handle_type_khr> {
+        let mut res = Vec::new();
+        if self.dma_buf_import_supported() {
+            res.push(CL_EXTERNAL_MEMORY_HANDLE_DMA_BUF_KHR);
+        }
+        res
+    }
+
+    pub fn sync_fd_import_supported(&self) -> bool {
+        self.screen.param(pipe_cap::PIPE_CAP_NATIVE_FENCE_FD) != 0
+            && self.helper_ctx().is_create_fence_fd_supported()
+    }
+
+    pub fn sync_fd_export_supported(&self) -> bool {
+        self.screen.is_fence_fd_export_supported()
+    }
+
+    pub fn semaphore_import_handle_types(&self) -> Vec<cl_external_semaphore_handle_type_khr> {
+        let mut res = Vec::new();
+        if self.sync_fd_import_supported() {
+            res.push(CL_SEMAPHORE_HANDLE_SYNC_FD_KHR);
+        }
+        res
+    }
+
+    pub fn semaphore_export_handle_types(&self) -> Vec<cl_external_semaphore_handle_type_khr> {
+        let mut res = Vec::new();
+        if self.sync_fd_export_supported() {
+            res.push(CL_SEMAPHORE_HANDLE_SYNC_FD_KHR);
+        }
+        res
+    }
+
     pub fn is_gl_sharing_supported(&self) -> bool {
         self.screen.param(pipe_cap::PIPE_CAP_CL_GL_SHARING) != 0
             && self.screen.param(pipe_cap::PIPE_CAP_DMABUF) != 0
@@ -919,6 +1203,45 @@ impl Device {
                 .any(|f| *f & cl_mem_flags::from(CL_MEM_WRITE_ONLY) == 0)
     }
 
+    /// The format images of `format` have to be bound as shader images with.
+    pub fn image_store_format(&self, format: &cl_image_format) -> pipe_format {
+        if self.caps.has_srgb_image_stores {
+            format.to_pipe_format()
+        } else {
+            format.to_linear_pipe_format()
+        }
+        .unwrap()
+    }
+
+    /// The target images of `image_type` get created with. Without support for binding 3D images
+    /// as shader images, 3D images written by kernels are created as 2D arrays with a layer per
+    /// slice instead, which kernels access through `rusticl_lower_3d_images`.
+    pub fn image_target(
+        &self,
+        image_type: cl_mem_object_type,
+        flags: cl_mem_flags,
+    ) -> pipe_texture_target {
+        if image_type == CL_MEM_OBJECT_IMAGE3D
+            && !self.caps.has_3d_image_stores
+            && bit_check(flags, CL_MEM_WRITE_ONLY)
+        {
+            pipe_texture_target::PIPE_TEXTURE_2D_ARRAY
+        } else {
+            cl_mem_type_to_texture_target(image_type)
+        }
+    }
+
+    pub fn srgb_image_writes_supported(&self) -> bool {
+        let format = cl_image_format {
+            image_channel_order: CL_sRGBA,
+            image_channel_data_type: CL_UNORM_INT8,
+        };
+
+        self.caps.has_images
+            && self.formats[&format][&CL_MEM_OBJECT_IMAGE2D] & cl_mem_flags::from(CL_MEM_WRITE_ONLY)
+                != 0
+    }
+
     pub fn little_endian(&self) -> bool {
         let endianness = self.screen.param(pipe_cap::PIPE_CAP_ENDIANNESS);
         endianness == (pipe_endian::PIPE_ENDIAN_LITTLE as i32)
@@ -936,6 +1259,13 @@ impl Device {
         v.into_iter().map(|v| v as usize).collect()
     }
 
+    /// Returns the internal kernel filling buffers with a `pattern_size` bytes pattern, which gets
+    /// created on first use.
+    pub fn fill_buffer_cso(&'static self, pattern_size: usize) -> &CSOWrapper {
+        self.fill_buffer_csos[pattern_size.trailing_zeros() as usize]
+            .get_or_init(|| CSOWrapper::new_fill_buffer(self, pattern_size))
+    }
+
     pub fn max_grid_size(&self) -> Vec<u64> {
         let v: Vec<u64> = self
             .screen
@@ -1016,6 +1346,15 @@ impl Device {
         &self.screen
     }
 
+    /// Returns the cache compiled kernels are stored in, unless disabled through `RUSTICL_DEBUG`.
+    pub fn kernel_cache(&self) -> Option<DiskCacheBorrowed> {
+        if Platform::dbg().no_cache {
+            return None;
+        }
+
+        self.screen.shader_cache()
+    }
+
     pub fn subgroup_sizes(&self) -> Vec<usize> {
         let subgroup_size = ComputeParam::<u32>::compute_param(
             self.screen.as_ref(),
@@ -1043,10 +1382,34 @@ impl Device {
             && (subgroup_sizes == 1 || (subgroup_sizes > 1 && self.shareable_shaders()))
     }
 
+    /// Whether resources can be allocated in memory the host can't access.
+    pub fn protected_memory_supported(&self) -> bool {
+        self.screen
+            .param(pipe_cap::PIPE_CAP_DEVICE_PROTECTED_SURFACE)
+            != 0
+    }
+
     pub fn svm_supported(&self) -> bool {
         self.screen.param(pipe_cap::PIPE_CAP_SYSTEM_SVM) == 1
     }
 
+    /// Whether atomics on SVM allocations are coherent between the host and the device.
+    pub fn svm_atomics_supported(&self) -> bool {
+        self.svm_supported() && self.screen.param(pipe_cap::PIPE_CAP_SYSTEM_SVM_ATOMICS) == 1
+    }
+
+    /// USM allocations are plain system allocations, so all kinds of them have the same
+    /// capabilities.
+    pub fn usm_caps(&self) -> cl_device_unified_shared_memory_capabilities_intel {
+        if self.svm_supported() {
+            (CL_UNIFIED_SHARED_MEMORY_ACCESS_INTEL
+                | CL_UNIFIED_SHARED_MEMORY_CONCURRENT_ACCESS_INTEL)
+                .into()
+        } else {
+            0
+        }
+    }
+
     pub fn unified_memory(&self) -> bool {
         self.screen.param(pipe_cap::PIPE_CAP_UMA) == 1
     }
@@ -1083,6 +1446,66 @@ impl Device {
         }
     }
 
+    /// Returns a staging buffer of at least `size` bytes, reusing an idle one if possible.
+    pub fn staging_buffer(&self, size: u32) -> Option<PipeResource> {
+        let Some(bucket) = StagingPool::bucket(size) else {
+            return self
+                .screen
+                .resource_create_buffer(size, ResourceType::Staging, 0);
+        };
+
+        // take it out first, so we don't hold the lock while waiting on the fence.
+        let buf = self.staging.lock().unwrap().take(bucket);
+        if let Some(buf) = buf {
+            buf.fence.wait();
+            return Some(buf.res);
+        }
+
+        let size = StagingPool::bucket_size(bucket);
+        self.screen
+            .resource_create_buffer(size, ResourceType::Staging, 0)
+            .or_else(|| {
+                // the memory might just be tied up in the pool
+                self.staging.lock().unwrap().trim();
+                self.screen
+                    .resource_create_buffer(size, ResourceType::Staging, 0)
+            })
+    }
+
+    /// Returns the bytes held by idle staging buffers kept for reuse.
+    pub fn idle_staging_size(&self) -> usize {
+        self.staging.lock().unwrap().size
+    }
+
+    /// Returns the counters documented for `CL_DEVICE_MEMORY_STATS_MESA`.
+    pub fn memory_stats(&self) -> [cl_ulong; 4] {
+        let [buffers, images, shadows] = self.stats.memory.snapshot();
+        [
+            buffers,
+            images,
+            shadows,
+            self.idle_staging_size() as cl_ulong,
+        ]
+    }
+
+    /// Hands a buffer returned by [Device::staging_buffer] back for reuse. `fence` has to signal
+    /// once all pending GPU work on `res` is done.
+    pub fn release_staging_buffer(&self, res: PipeResource, fence: PipeFence) {
+        let size = res.width();
+        if let Some(bucket) = StagingPool::bucket(size) {
+            // buffers bigger than the biggest bucket are never pooled
+            if StagingPool::bucket_size(bucket) == size {
+                self.staging.lock().unwrap().put(
+                    bucket,
+                    StagingBuffer {
+                        res: res,
+                        fence: fence,
+                    },
+                );
+            }
+        }
+    }
+
     pub fn cl_features(&self) -> clc_optional_features {
         let subgroups_supported = self.subgroups_supported();
         clc_optional_features {
@@ -1092,6 +1515,8 @@ impl Device {
             images: self.caps.has_images,
             images_read_write: self.image_read_write_supported(),
             images_write_3d: self.image_3d_write_supported(),
+            images_mipmap: self.caps.has_images,
+            images_write_srgb: self.srgb_image_writes_supported(),
             integer_dot_product: true,
             subgroups: subgroups_supported,
             subgroups_shuffle: subgroups_supported,
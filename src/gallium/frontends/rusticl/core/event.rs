@@ -5,11 +5,13 @@ use crate::core::queue::*;
 use crate::impl_cl_type_trait;
 
 use mesa_rust::pipe::query::*;
+use mesa_rust::util::perfetto;
 use mesa_rust_gen::*;
 use mesa_rust_util::static_assert;
 use rusticl_opencl_gen::*;
 
 use std::collections::HashSet;
+use std::ffi::CString;
 use std::mem;
 use std::sync::Arc;
 use std::sync::Condvar;
@@ -24,6 +26,68 @@ static_assert!(CL_SUBMITTED == 2);
 static_assert!(CL_QUEUED == 3);
 
 pub type EventSig = Box<dyn FnOnce(&Arc<Queue>, &QueueContext) -> CLResult<()> + Send + Sync>;
+/// Work of a command which can be executed multiple times, e.g. when recorded into a command
+/// buffer.
+pub type CommandSig = Box<dyn Fn(&Arc<Queue>, &QueueContext) -> CLResult<()> + Send + Sync>;
+
+/// Host memory a command accesses through pointers passed in by the application.
+#[derive(Clone, Copy)]
+pub enum HostAccess {
+    None,
+    /// `size` bytes starting at `start`.
+    Range {
+        start: usize,
+        size: usize,
+    },
+    /// Unknown host memory, e.g. kernels which might access system SVM.
+    Any,
+}
+
+impl HostAccess {
+    pub fn range<T>(ptr: *const T, offset: usize, size: usize) -> Self {
+        Self::Range {
+            start: ptr as usize + offset,
+            size: size,
+        }
+    }
+
+    /// What a command accesses if its enqueue call doesn't say otherwise.
+    pub fn for_cmd(queue: &Queue, cmd_type: cl_command_type) -> Self {
+        match cmd_type {
+            CL_COMMAND_NDRANGE_KERNEL | CL_COMMAND_TASK | CL_COMMAND_COMMAND_BUFFER_KHR
+                if !queue.device.svm_supported() =>
+            {
+                Self::None
+            }
+            CL_COMMAND_BARRIER
+            | CL_COMMAND_MARKER
+            | CL_COMMAND_COPY_BUFFER
+            | CL_COMMAND_COPY_BUFFER_RECT
+            | CL_COMMAND_FILL_BUFFER
+            | CL_COMMAND_COPY_IMAGE
+            | CL_COMMAND_FILL_IMAGE
+            | CL_COMMAND_COPY_IMAGE_TO_BUFFER
+            | CL_COMMAND_COPY_BUFFER_TO_IMAGE
+            | CL_COMMAND_MIGRATE_MEM_OBJECTS
+            | CL_COMMAND_ACQUIRE_GL_OBJECTS
+            | CL_COMMAND_RELEASE_GL_OBJECTS
+            | CL_COMMAND_SEMAPHORE_WAIT_KHR
+            | CL_COMMAND_SEMAPHORE_SIGNAL_KHR => Self::None,
+            _ => Self::Any,
+        }
+    }
+
+    pub fn overlaps(&self, start: usize, size: usize) -> bool {
+        match *self {
+            Self::None => false,
+            Self::Range {
+                start: own_start,
+                size: own_size,
+            } => own_start < start + size && start < own_start + own_size,
+            Self::Any => true,
+        }
+    }
+}
 
 pub enum EventTimes {
     Queued = CL_PROFILING_COMMAND_QUEUED as isize,
@@ -49,6 +113,9 @@ pub struct Event {
     pub queue: Option<Arc<Queue>>,
     pub cmd_type: cl_command_type,
     pub deps: Vec<Arc<Event>>,
+    pub host_access: HostAccess,
+    /// perfetto flow id connecting the enqueue, submit and GPU slices of this event
+    pub trace_id: u64,
     state: Mutex<EventMutState>,
     cv: Condvar,
 }
@@ -61,6 +128,17 @@ impl Event {
         cmd_type: cl_command_type,
         deps: Vec<Arc<Event>>,
         work: EventSig,
+    ) -> Arc<Event> {
+        let host_access = HostAccess::for_cmd(queue, cmd_type);
+        Self::new_with_host_access(queue, cmd_type, deps, host_access, work)
+    }
+
+    pub fn new_with_host_access(
+        queue: &Arc<Queue>,
+        cmd_type: cl_command_type,
+        deps: Vec<Arc<Event>>,
+        host_access: HostAccess,
+        work: EventSig,
     ) -> Arc<Event> {
         Arc::new(Self {
             base: CLObjectBase::new(RusticlTypes::Event),
@@ -68,6 +146,8 @@ impl Event {
             queue: Some(queue.clone()),
             cmd_type: cmd_type,
             deps: deps,
+            host_access: host_access,
+            trace_id: perfetto::next_id(),
             state: Mutex::new(EventMutState {
                 status: CL_QUEUED as cl_int,
                 work: Some(work),
@@ -84,6 +164,8 @@ impl Event {
             queue: None,
             cmd_type: CL_COMMAND_USER,
             deps: Vec::new(),
+            host_access: HostAccess::None,
+            trace_id: 0,
             state: Mutex::new(EventMutState {
                 status: CL_SUBMITTED as cl_int,
                 ..Default::default()
@@ -185,6 +267,10 @@ impl Event {
         lock.status
     }
 
+    pub fn trace_name(&self) -> CString {
+        CString::new(cmd_type_name(self.cmd_type)).unwrap()
+    }
+
     // We always assume that work here simply submits stuff to the hardware even if it's just doing
     // sw emulation or nothing at all.
     // If anything requets waiting, we will update the status through fencing later.
@@ -193,18 +279,24 @@ impl Event {
         let status = lock.status;
         let queue = self.queue.as_ref().unwrap();
         let profiling_enabled = queue.is_profiling_enabled();
+        let tracing_enabled = perfetto::is_tracing_enabled();
+        let query_timestamps = profiling_enabled || tracing_enabled;
         if status == CL_QUEUED as cl_int {
             if profiling_enabled {
                 // We already have the lock so can't call set_time on the event
                 lock.time_submit = queue.device.screen().get_timestamp();
             }
+            let trace_name = tracing_enabled.then(|| self.trace_name());
+            if let Some(trace_name) = &trace_name {
+                perfetto::trace_begin_flow(trace_name, self.trace_id);
+            }
             let mut query_start = None;
             let mut query_end = None;
             let new = lock.work.take().map_or(
                 // if there is no work
                 CL_SUBMITTED as cl_int,
                 |w| {
-                    if profiling_enabled {
+                    if query_timestamps {
                         query_start =
                             PipeQueryGen::<{ pipe_query_type::PIPE_QUERY_TIMESTAMP }>::new(ctx);
                     }
@@ -212,9 +304,9 @@ impl Event {
                     let res = w(queue, ctx).err().map_or(
                         // if there is an error, negate it
                         CL_SUBMITTED as cl_int,
-                        |e| e,
+                        |e| e.code,
                     );
-                    if profiling_enabled {
+                    if query_timestamps {
                         query_end =
                             PipeQueryGen::<{ pipe_query_type::PIPE_QUERY_TIMESTAMP }>::new(ctx);
                     }
@@ -222,9 +314,21 @@ impl Event {
                 },
             );
 
-            if profiling_enabled {
-                lock.time_start = query_start.unwrap().read_blocked();
-                lock.time_end = query_end.unwrap().read_blocked();
+            if let (Some(mut query_start), Some(mut query_end)) = (query_start, query_end) {
+                let start = query_start.read_blocked();
+                let end = query_end.read_blocked();
+                if profiling_enabled {
+                    lock.time_start = start;
+                    lock.time_end = end;
+                }
+
+                if let Some(trace_name) = &trace_name {
+                    queue.trace_gpu_slice(trace_name, self.trace_id, start, end);
+                }
+            }
+
+            if trace_name.is_some() {
+                perfetto::trace_end();
             }
             self.set_status(lock, new);
         }
@@ -285,6 +389,42 @@ impl Drop for Event {
     }
 }
 
+fn cmd_type_name(cmd_type: cl_command_type) -> &'static str {
+    match cmd_type {
+        CL_COMMAND_ACQUIRE_GL_OBJECTS => "clEnqueueAcquireGLObjects",
+        CL_COMMAND_BARRIER => "clEnqueueBarrier",
+        CL_COMMAND_COPY_BUFFER => "clEnqueueCopyBuffer",
+        CL_COMMAND_COPY_BUFFER_RECT => "clEnqueueCopyBufferRect",
+        CL_COMMAND_COPY_BUFFER_TO_IMAGE => "clEnqueueCopyBufferToImage",
+        CL_COMMAND_COPY_IMAGE => "clEnqueueCopyImage",
+        CL_COMMAND_COPY_IMAGE_TO_BUFFER => "clEnqueueCopyImageToBuffer",
+        CL_COMMAND_FILL_BUFFER => "clEnqueueFillBuffer",
+        CL_COMMAND_FILL_IMAGE => "clEnqueueFillImage",
+        CL_COMMAND_MAP_BUFFER => "clEnqueueMapBuffer",
+        CL_COMMAND_MAP_IMAGE => "clEnqueueMapImage",
+        CL_COMMAND_MARKER => "clEnqueueMarker",
+        CL_COMMAND_MIGRATE_MEM_OBJECTS => "clEnqueueMigrateMemObjects",
+        CL_COMMAND_NDRANGE_KERNEL => "clEnqueueNDRangeKernel",
+        CL_COMMAND_READ_BUFFER => "clEnqueueReadBuffer",
+        CL_COMMAND_READ_BUFFER_RECT => "clEnqueueReadBufferRect",
+        CL_COMMAND_READ_IMAGE => "clEnqueueReadImage",
+        CL_COMMAND_RELEASE_GL_OBJECTS => "clEnqueueReleaseGLObjects",
+        CL_COMMAND_SVM_FREE | CL_COMMAND_SVM_FREE_ARM => "clEnqueueSVMFree",
+        CL_COMMAND_SVM_MAP | CL_COMMAND_SVM_MAP_ARM => "clEnqueueSVMMap",
+        CL_COMMAND_SVM_MEMCPY | CL_COMMAND_SVM_MEMCPY_ARM => "clEnqueueSVMMemcpy",
+        CL_COMMAND_SVM_MEMFILL | CL_COMMAND_SVM_MEMFILL_ARM => "clEnqueueSVMMemFill",
+        CL_COMMAND_SVM_MIGRATE_MEM => "clEnqueueSVMMigrateMem",
+        CL_COMMAND_SVM_UNMAP | CL_COMMAND_SVM_UNMAP_ARM => "clEnqueueSVMUnmap",
+        CL_COMMAND_TASK => "clEnqueueTask",
+        CL_COMMAND_UNMAP_MEM_OBJECT => "clEnqueueUnmapMemObject",
+        CL_COMMAND_USER => "user event",
+        CL_COMMAND_WRITE_BUFFER => "clEnqueueWriteBuffer",
+        CL_COMMAND_WRITE_BUFFER_RECT => "clEnqueueWriteBufferRect",
+        CL_COMMAND_WRITE_IMAGE => "clEnqueueWriteImage",
+        _ => "unknown command",
+    }
+}
+
 // TODO worker thread per device
 // Condvar to wait on new events to work on
 // notify condvar when flushing queue events to worker
@@ -176,10 +176,9 @@ cl_format_table!([
 //  (CL_RGBx,      CL_UNORM_SHORT_555)    => pipe_format::PIPE_FORMAT_R5G5B5X1_UNORM,
 //  (CL_RGBx,      CL_UNORM_INT_101010)   => pipe_format::PIPE_FORMAT_R10G10B10X2_UNORM,
 
-// broken
-//  (CL_sRGB,      CL_UNORM_INT8)         => pipe_format::PIPE_FORMAT_R8G8B8_SRGB,
-//  (CL_sRGBA,     CL_UNORM_INT8)         => pipe_format::PIPE_FORMAT_R8G8B8A8_SRGB,
-//  (CL_sBGRA,     CL_UNORM_INT8)         => pipe_format::PIPE_FORMAT_B8G8R8A8_SRGB,
+    (CL_sRGB,      CL_UNORM_INT8)         => pipe_format::PIPE_FORMAT_R8G8B8_SRGB,
+    (CL_sRGBA,     CL_UNORM_INT8)         => pipe_format::PIPE_FORMAT_R8G8B8A8_SRGB,
+    (CL_sBGRA,     CL_UNORM_INT8)         => pipe_format::PIPE_FORMAT_B8G8R8A8_SRGB,
 // broken
 //  (CL_sRGBx,     CL_UNORM_INT8)         => pipe_format::PIPE_FORMAT_R8G8B8X8_SRGB,
 
@@ -326,6 +325,17 @@ const fn req_for_full_cl2(
         | (CL_sRGBA, CL_UNORM_INT8))
 }
 
+#[allow(non_upper_case_globals)]
+const fn linear_order(ch_order: cl_channel_order) -> cl_channel_order {
+    match ch_order {
+        CL_sBGRA => CL_BGRA,
+        CL_sRGB => CL_RGB,
+        CL_sRGBA => CL_RGBA,
+        CL_sRGBx => CL_RGBx,
+        _ => ch_order,
+    }
+}
+
 #[allow(non_upper_case_globals)]
 const fn is_srgb(ch_order: cl_channel_order) -> bool {
     matches!(ch_order, CL_sBGRA | CL_sRGB | CL_sRGBA | CL_sRGBx)
@@ -359,6 +369,10 @@ pub trait CLFormatInfo {
     fn format_info(&self) -> Option<(u8, bool)>;
     fn to_pipe_format(&self) -> Option<pipe_format>;
 
+    /// Same as [CLFormatInfo::to_pipe_format], but with sRGB formats replaced by their linear
+    /// counterpart.
+    fn to_linear_pipe_format(&self) -> Option<pipe_format>;
+
     fn channel_size(&self) -> Option<u8> {
         if let Some(packed) = self.is_packed() {
             assert!(!packed);
@@ -425,6 +439,13 @@ impl CLFormatInfo for cl_image_format {
     fn to_pipe_format(&self) -> Option<pipe_format> {
         cl_format_to_pipe(self.image_channel_order, self.image_channel_data_type)
     }
+
+    fn to_linear_pipe_format(&self) -> Option<pipe_format> {
+        cl_format_to_pipe(
+            linear_order(self.image_channel_order),
+            self.image_channel_data_type,
+        )
+    }
 }
 
 macro_rules! gl_cl_format_table {
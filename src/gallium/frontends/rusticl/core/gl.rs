@@ -14,6 +14,7 @@ use mesa_rust::pipe::context::*;
 use mesa_rust::pipe::fence::*;
 use mesa_rust::pipe::resource::*;
 use mesa_rust::pipe::screen::*;
+use mesa_rust_util::per_device::PerDevice;
 
 use std::collections::HashMap;
 use std::ffi::CStr;
@@ -21,6 +22,8 @@ use std::ffi::CString;
 use std::mem;
 use std::os::raw::c_void;
 use std::ptr;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 type CLGLMappings = Option<HashMap<Arc<PipeResource>, Arc<PipeResource>>>;
@@ -68,7 +71,7 @@ impl XPlatManager {
     // in theory it should return CLResult<__GLXextFuncPtr> but luckily it's identical
     #[cfg(not(glx))]
     unsafe fn get_func_glx(&self, _: &CStr) -> CLResult<__eglMustCastToProperFunctionPointerType> {
-        Err(CL_INVALID_GL_SHAREGROUP_REFERENCE_KHR)
+        Err(CL_INVALID_GL_SHAREGROUP_REFERENCE_KHR.into())
     }
 
     fn get_func<T>(&self, name: &str) -> CLResult<T> {
@@ -159,7 +162,7 @@ impl GLCtxManager {
         // More than one of the attributes CL_CGL_SHAREGROUP_KHR, CL_EGL_DISPLAY_KHR,
         // CL_GLX_DISPLAY_KHR, and CL_WGL_HDC_KHR is set to a non-default value.
         if !egl_display.is_null() && !glx_display.is_null() {
-            return Err(CL_INVALID_OPERATION);
+            return Err(CL_INVALID_OPERATION.into());
         }
 
         if gl_context.is_null() {
@@ -176,7 +179,7 @@ impl GLCtxManager {
             };
 
             if err != MESA_GLINTEROP_SUCCESS as i32 {
-                return Err(interop_to_cl_error(err));
+                return Err(interop_to_cl_error(err).into());
             }
 
             Ok(Some(GLCtxManager {
@@ -194,7 +197,7 @@ impl GLCtxManager {
             };
 
             if err != MESA_GLINTEROP_SUCCESS as i32 {
-                return Err(interop_to_cl_error(err));
+                return Err(interop_to_cl_error(err).into());
             }
 
             Ok(Some(GLCtxManager {
@@ -203,7 +206,7 @@ impl GLCtxManager {
                 xplat_manager: xplat_manager,
             }))
         } else {
-            Err(CL_INVALID_GL_SHAREGROUP_REFERENCE_KHR)
+            Err(CL_INVALID_GL_SHAREGROUP_REFERENCE_KHR.into())
         }
     }
 
@@ -312,13 +315,13 @@ impl GLCtxManager {
         };
 
         if err != MESA_GLINTEROP_SUCCESS as i32 {
-            return Err(interop_to_cl_error(err));
+            return Err(interop_to_cl_error(err).into());
         }
 
         // CL_INVALID_GL_OBJECT if bufobj is not a GL buffer object or is a GL buffer
         // object but does not have an existing data store or the size of the buffer is 0.
         if [GL_ARRAY_BUFFER, GL_TEXTURE_BUFFER].contains(&target) && export_out.buf_size == 0 {
-            return Err(CL_INVALID_GL_OBJECT);
+            return Err(CL_INVALID_GL_OBJECT.into());
         }
 
         Ok(GLExportManager {
@@ -424,13 +427,40 @@ pub struct GLObject {
     pub gl_object_type: cl_gl_object_type,
     pub gl_object_name: cl_GLuint,
     pub shadow_map: CLGLMappings,
+    /// Whether the shadow slice of a cube map face got written to since it was acquired.
+    shadow_dirty: AtomicBool,
+}
+
+impl GLObject {
+    pub fn new(
+        gl_object_target: cl_GLenum,
+        gl_object_type: cl_gl_object_type,
+        gl_object_name: cl_GLuint,
+        shadow_map: CLGLMappings,
+    ) -> Self {
+        Self {
+            gl_object_target: gl_object_target,
+            gl_object_type: gl_object_type,
+            gl_object_name: gl_object_name,
+            shadow_map: shadow_map,
+            shadow_dirty: AtomicBool::new(false),
+        }
+    }
+
+    /// Records a write to the CL side of the object, so a shadow slice gets copied back into the
+    /// cube map on release.
+    pub fn mark_written(&self) {
+        if self.shadow_map.is_some() {
+            self.shadow_dirty.store(true, Ordering::Relaxed);
+        }
+    }
 }
 
 pub fn create_shadow_slice(
-    cube_map: &HashMap<&'static Device, Arc<PipeResource>>,
+    cube_map: &PerDevice<&'static Device, Arc<PipeResource>>,
     image_format: cl_image_format,
-) -> CLResult<HashMap<&'static Device, Arc<PipeResource>>> {
-    let mut slice = HashMap::new();
+) -> CLResult<PerDevice<&'static Device, Arc<PipeResource>>> {
+    let mut slice = PerDevice::new();
 
     for (dev, imported_gl_res) in cube_map {
         let width = imported_gl_res.width();
@@ -443,6 +473,7 @@ pub fn create_shadow_slice(
                 height,
                 1,
                 1,
+                0,
                 cl_mem_type_to_texture_target(CL_MEM_OBJECT_IMAGE2D),
                 image_format.to_pipe_format().unwrap(),
                 ResourceType::Normal,
@@ -475,10 +506,13 @@ pub fn copy_cube_to_slice(q: &Arc<Queue>, ctx: &PipeContext, mem_objects: &[Mem]
         let region = CLVec::<usize>::new([width, height, 1]);
         let src_bx = create_pipe_box(src_origin, region, CL_MEM_OBJECT_IMAGE2D_ARRAY)?;
 
-        let cl_res = image.get_res_of_dev(q.device)?;
+        let cl_res = image.get_res_of_dev(q.device, RWFlags::WR)?;
         let gl_res = gl_obj.shadow_map.as_ref().unwrap().get(cl_res).unwrap();
 
         ctx.resource_copy_region(gl_res.as_ref(), cl_res.as_ref(), &dst_offset, &src_bx);
+
+        // establishing the shadow isn't a write by the application
+        gl_obj.shadow_dirty.store(false, Ordering::Relaxed);
     }
 
     Ok(())
@@ -490,7 +524,10 @@ pub fn copy_slice_to_cube(q: &Arc<Queue>, ctx: &PipeContext, mem_objects: &[Mem]
             continue;
         };
         let gl_obj = image.gl_obj.as_ref().unwrap();
-        if !is_cube_map_face(gl_obj.gl_object_target) {
+        // nothing to copy back if the face was only read from
+        if !is_cube_map_face(gl_obj.gl_object_target)
+            || !gl_obj.shadow_dirty.swap(false, Ordering::Relaxed)
+        {
             continue;
         }
         let width = image.image_desc.image_width;
@@ -503,7 +540,7 @@ pub fn copy_slice_to_cube(q: &Arc<Queue>, ctx: &PipeContext, mem_objects: &[Mem]
         let region = CLVec::<usize>::new([width, height, 1]);
         let src_bx = create_pipe_box(src_origin, region, CL_MEM_OBJECT_IMAGE2D_ARRAY)?;
 
-        let cl_res = image.get_res_of_dev(q.device)?;
+        let cl_res = image.get_res_of_dev(q.device, RWFlags::RD)?;
         let gl_res = gl_obj.shadow_map.as_ref().unwrap().get(cl_res).unwrap();
 
         ctx.resource_copy_region(cl_res.as_ref(), gl_res.as_ref(), &dst_offset, &src_bx);
@@ -512,7 +549,7 @@ pub fn copy_slice_to_cube(q: &Arc<Queue>, ctx: &PipeContext, mem_objects: &[Mem]
     Ok(())
 }
 
-pub fn interop_to_cl_error(error: i32) -> CLError {
+pub fn interop_to_cl_error(error: i32) -> cl_int {
     match error.try_into().unwrap() {
         MESA_GLINTEROP_OUT_OF_RESOURCES => CL_OUT_OF_RESOURCES,
         MESA_GLINTEROP_OUT_OF_HOST_MEMORY => CL_OUT_OF_HOST_MEMORY,
@@ -554,7 +591,7 @@ pub fn target_from_gl(target: u32) -> CLResult<(u32, u32)> {
         | GL_TEXTURE_RECTANGLE => (CL_MEM_OBJECT_IMAGE2D, CL_GL_OBJECT_TEXTURE2D),
         GL_TEXTURE_2D_ARRAY => (CL_MEM_OBJECT_IMAGE2D_ARRAY, CL_GL_OBJECT_TEXTURE2D_ARRAY),
         GL_TEXTURE_3D => (CL_MEM_OBJECT_IMAGE3D, CL_GL_OBJECT_TEXTURE3D),
-        _ => return Err(CL_INVALID_VALUE),
+        _ => return Err(CL_INVALID_VALUE.into()),
     })
 }
 
@@ -1,4 +1,5 @@
 use crate::api::icd::*;
+use crate::api::util::bit_check;
 use crate::core::device::*;
 use crate::core::event::*;
 use crate::core::memory::*;
@@ -15,7 +16,9 @@ use mesa_rust::pipe::context::ResourceMapType;
 use mesa_rust::pipe::resource::*;
 use mesa_rust::pipe::screen::ResourceType;
 use mesa_rust_gen::*;
+use mesa_rust_util::log_warn;
 use mesa_rust_util::math::*;
+use mesa_rust_util::per_device::PerDevice;
 use mesa_rust_util::serialize::*;
 use rusticl_opencl_gen::*;
 
@@ -271,11 +274,18 @@ pub struct KernelInfo {
     pub num_subgroups: usize,
 }
 
+/// Workgroup size of the internal kernel used to fill buffers.
+pub const FILL_BUFFER_BLOCK_SIZE: u32 = 64;
+
 pub struct CSOWrapper {
     pub cso_ptr: *mut c_void,
     dev: &'static Device,
 }
 
+// SAFETY: compute states are screen objects and can be bound on any context of the device.
+unsafe impl Send for CSOWrapper {}
+unsafe impl Sync for CSOWrapper {}
+
 impl CSOWrapper {
     pub fn new(dev: &'static Device, nir: &NirShader) -> Self {
         let cso_ptr = dev
@@ -288,6 +298,35 @@ impl CSOWrapper {
         }
     }
 
+    /// Creates the internal kernel writing a `pattern_size` bytes pattern into a buffer. See
+    /// [Buffer::fill] for the layout of its input.
+    pub fn new_fill_buffer(dev: &'static Device, pattern_size: usize) -> Self {
+        let nir = unsafe {
+            rusticl_create_fill_buffer_shader(
+                dev.screen
+                    .nir_shader_compiler_options(pipe_shader_type::PIPE_SHADER_COMPUTE),
+                pattern_size as u32,
+                dev.address_bits(),
+                FILL_BUFFER_BLOCK_SIZE,
+            )
+        };
+        let nir = &mut NirShader::new(nir).unwrap();
+        let nir_options = dev.get_nir_options();
+
+        if nir_options.lower_int64_options.0 != 0 {
+            nir_pass!(nir, nir_lower_int64);
+        }
+
+        if nir_options.lower_uniforms_to_ubo {
+            nir_pass!(nir, rusticl_lower_inputs);
+        }
+
+        opt_nir(nir, dev, true);
+        dev.screen.finalize_nir(nir);
+
+        Self::new(dev, nir)
+    }
+
     pub fn get_cso_info(&self) -> pipe_compute_state_object_info {
         self.dev.helper_ctx().compute_state_info(self.cso_ptr)
     }
@@ -309,7 +348,7 @@ pub struct Kernel {
     pub prog: Arc<Program>,
     pub name: String,
     values: Mutex<Vec<Option<KernelArgValue>>>,
-    builds: HashMap<&'static Device, Arc<NirKernelBuild>>,
+    builds: PerDevice<&'static Device, Arc<NirKernelBuild>>,
     pub kernel_info: Arc<KernelInfo>,
 }
 
@@ -704,6 +743,12 @@ fn lower_and_optimize_nir(
         global_address_format,
     );
 
+    if dev.caps.has_images && !dev.caps.has_srgb_image_stores {
+        nir_pass!(nir, rusticl_lower_srgb_images);
+    }
+    if dev.caps.has_images && !dev.caps.has_3d_image_stores {
+        nir_pass!(nir, rusticl_lower_3d_images);
+    }
     nir_pass!(nir, rusticl_lower_intrinsics, &mut lower_state);
     nir_pass!(
         nir,
@@ -780,7 +825,7 @@ pub(super) fn convert_spirv_to_nir(
     args: &[spirv::SPIRVKernelArg],
     dev: &Device,
 ) -> (KernelInfo, NirShader) {
-    let cache = dev.screen().shader_cache();
+    let cache = dev.kernel_cache();
     let key = build.hash_key(dev, name);
 
     let res = if let Some(cache) = &cache {
@@ -792,6 +837,10 @@ pub(super) fn convert_spirv_to_nir(
         None
     };
 
+    if cache.is_some() {
+        dev.stats.add_cache_lookup(res.is_some());
+    }
+
     let (nir, args, internal_args) = if let Some(res) = res {
         res
     } else {
@@ -939,11 +988,12 @@ impl Kernel {
         block: &[usize],
         grid: &[usize],
         offsets: &[usize],
-    ) -> CLResult<EventSig> {
+    ) -> CLResult<CommandSig> {
         // Clone all the data we need to execute this kernel
         let kernel_info = Arc::clone(&self.kernel_info);
         let arg_values = self.arg_values().clone();
         let nir_kernel_build = Arc::clone(&self.builds[q.device]);
+        let name = self.name.clone();
 
         // operations we want to report errors to the clients
         let mut block = create_kernel_arr::<u32>(block, 1)?;
@@ -1010,30 +1060,33 @@ impl Kernel {
                 match val.as_ref().unwrap() {
                     KernelArgValue::Constant(c) => input.extend_from_slice(c),
                     KernelArgValue::Buffer(buffer) => {
-                        let res = buffer.get_res_of_dev(q.device)?;
-                        add_global(q, &mut input, &mut resource_info, res, buffer.offset);
+                        let rw = if arg.kind == KernelArgType::MemConstant
+                            || bit_check(buffer.flags, CL_MEM_READ_ONLY)
+                        {
+                            RWFlags::RD
+                        } else {
+                            RWFlags::RW
+                        };
+                        let view = buffer.view(q.device, rw)?;
+                        add_global(q, &mut input, &mut resource_info, view.res, view.offset);
                     }
                     KernelArgValue::Image(image) => {
-                        let res = image.get_res_of_dev(q.device)?;
-
-                        // If resource is a buffer, the image was created from a buffer. Use strides and
-                        // dimensions of the image then.
-                        let app_img_info = if res.as_ref().is_buffer()
-                            && image.mem_type == CL_MEM_OBJECT_IMAGE2D
-                        {
-                            Some(AppImgInfo::new(
-                                image.image_desc.row_pitch()? / image.image_elem_size as u32,
-                                image.image_desc.width()?,
-                                image.image_desc.height()?,
-                            ))
+                        let rw = if arg.kind == KernelArgType::Texture {
+                            RWFlags::RD
                         } else {
-                            None
+                            RWFlags::RW
                         };
+                        let res = image.get_res_of_dev(q.device, rw)?;
+
+                        // If the image was created from a buffer, view the buffer's resource with
+                        // the offset, strides and dimensions of the image.
+                        let app_img_info = image.app_img_info(res)?;
 
                         let format = image.pipe_format;
+                        let store_format = q.device.image_store_format(&image.image_format);
                         let (formats, orders) = if arg.kind == KernelArgType::Image {
                             iviews.push(res.pipe_image_view(
-                                format,
+                                store_format,
                                 false,
                                 image.pipe_image_host_access(),
                                 app_img_info.as_ref(),
@@ -1041,7 +1094,7 @@ impl Kernel {
                             (&mut img_formats, &mut img_orders)
                         } else if arg.kind == KernelArgType::RWImage {
                             iviews.push(res.pipe_image_view(
-                                format,
+                                store_format,
                                 true,
                                 image.pipe_image_host_access(),
                                 app_img_info.as_ref(),
@@ -1235,6 +1288,23 @@ impl Kernel {
             samplers.iter().for_each(|s| ctx.delete_sampler_state(*s));
             sviews.iter().for_each(|v| ctx.sampler_view_destroy(*v));
 
+            if Platform::dbg().bounds {
+                for (idx, val) in arg_values.iter().enumerate() {
+                    let Some(KernelArgValue::Buffer(buffer)) = val else {
+                        continue;
+                    };
+
+                    if buffer.guard_overwritten(q, ctx)? {
+                        let msg = format!(
+                            "kernel {name} wrote out of bounds of buffer argument {idx} ({} bytes)",
+                            buffer.size
+                        );
+                        log_warn!("{}", msg);
+                        q.context.notify(&msg);
+                    }
+                }
+            }
+
             if let Some(printf_buf) = &printf_buf {
                 let tx = ctx
                     .buffer_map(
@@ -1,26 +1,31 @@
+use crate::api::error::*;
 use crate::api::icd::*;
+use crate::api::strict::StrictState;
 use crate::api::types::*;
 use crate::api::util::*;
 use crate::core::context::*;
 use crate::core::device::*;
 use crate::core::format::*;
 use crate::core::gl::*;
+use crate::core::kernel::FILL_BUFFER_BLOCK_SIZE;
 use crate::core::queue::*;
+use crate::core::stats::MemoryKind;
 use crate::core::util::*;
 use crate::impl_cl_type_trait;
 use crate::impl_cl_type_trait_base;
 
 use mesa_rust::pipe::context::*;
 use mesa_rust::pipe::resource::*;
+use mesa_rust::pipe::screen::DmaBufExport;
 use mesa_rust::pipe::screen::ResourceType;
 use mesa_rust::pipe::transfer::*;
 use mesa_rust_gen::*;
 use mesa_rust_util::math::*;
+use mesa_rust_util::per_device::PerDevice;
 use mesa_rust_util::properties::Properties;
 use rusticl_opencl_gen::*;
 
 use std::cmp;
-use std::collections::hash_map::Entry;
 use std::collections::HashMap;
 use std::convert::TryInto;
 use std::mem;
@@ -28,41 +33,373 @@ use std::mem::size_of;
 use std::ops::Deref;
 use std::os::raw::c_void;
 use std::ptr;
+use std::slice;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::OnceLock;
+
+// from drm_fourcc.h
+const DRM_FORMAT_MOD_LINEAR: u64 = 0;
+const DRM_FORMAT_MOD_INVALID: u64 = 0x00ff_ffff_ffff_ffff;
+
+/// External memory a memory object gets imported from instead of allocating new resources.
+pub struct ExternalMemory {
+    pub handle_type: cl_external_memory_handle_type_khr,
+    pub handle: i32,
+    /// Devices the memory gets imported on.
+    pub devs: Vec<&'static Device>,
+}
+
+/// The resources of a memory object per device of its context. Devices without a resource yet get
+/// theirs allocated on first use.
+type Resources = PerDevice<&'static Device, OnceLock<Arc<PipeResource>>>;
+
+fn lazy_resources(
+    devs: &[&'static Device],
+    mut res: PerDevice<&'static Device, Arc<PipeResource>>,
+) -> Resources {
+    devs.iter()
+        .map(|&dev| {
+            let res = res.remove(dev).map_or_else(OnceLock::new, OnceLock::from);
+            (dev, res)
+        })
+        .collect()
+}
+
+/// Decides which kinds of resources to allocate for a memory object. Flags like
+/// `CL_MEM_ALLOC_HOST_PTR` are only performance hints, so failing to honor them must not fail the
+/// allocation.
+#[derive(Clone, Copy)]
+enum AllocPolicy {
+    /// Device local memory.
+    Device,
+    /// Host visible memory, falling back to device local memory.
+    PreferHost,
+    /// Memory the host accesses while kernels are running, which has no fallback.
+    Coherent,
+    /// Memory protected from the host, which has no fallback either.
+    Protected,
+}
+
+impl AllocPolicy {
+    fn for_buffer(context: &Context, flags: cl_mem_flags, host_ptr: *mut c_void) -> Self {
+        if bit_check(flags, CL_MEM_USE_HOST_PTR) && context.is_svm_atomics_ptr(host_ptr as usize) {
+            // the host might operate atomically on the memory while kernels are running.
+            Self::Coherent
+        } else {
+            Self::for_image(flags)
+        }
+    }
+
+    fn for_image(flags: cl_mem_flags) -> Self {
+        if bit_check(flags, CL_MEM_PROTECTED_ALLOC_ARM) {
+            Self::Protected
+        } else if bit_check(flags, CL_MEM_ALLOC_HOST_PTR) {
+            Self::PreferHost
+        } else {
+            Self::Device
+        }
+    }
+
+    /// Returns the resource types to try allocating in order.
+    fn ladder(self) -> &'static [ResourceType] {
+        match self {
+            Self::Device => &[ResourceType::Normal],
+            Self::PreferHost => &[ResourceType::Staging, ResourceType::Normal],
+            Self::Coherent => &[ResourceType::Coherent],
+            Self::Protected => &[ResourceType::Protected],
+        }
+    }
+}
+
+/// Describes the resources of a memory object allocated by rusticl.
+enum ResourceDesc {
+    Buffer {
+        size: usize,
+        policy: AllocPolicy,
+    },
+    Image {
+        desc: cl_image_desc,
+        format: cl_image_format,
+        flags: cl_mem_flags,
+        policy: AllocPolicy,
+    },
+}
+
+impl ResourceDesc {
+    fn alloc(
+        &self,
+        context: &Context,
+        devs: &[&'static Device],
+        host_ptr: *mut c_void,
+        copy: bool,
+    ) -> CLResult<PerDevice<&'static Device, Arc<PipeResource>>> {
+        let alloc = |res_type| match self {
+            Self::Buffer { size, .. } => {
+                context.create_buffer(devs, *size, host_ptr, copy, res_type)
+            }
+            Self::Image {
+                desc,
+                format,
+                flags,
+                ..
+            } => context.create_texture(devs, desc, format, *flags, host_ptr, copy, res_type),
+        };
+
+        let (Self::Buffer { policy, .. } | Self::Image { policy, .. }) = self;
+        let (&first, fallbacks) = policy.ladder().split_first().unwrap();
+        fallbacks.iter().fold(alloc(first), |res, &res_type| {
+            res.or_else(|_| alloc(res_type))
+        })
+    }
+
+    /// Uploads the content behind `host_ptr` into `res`, which was allocated on `dev`.
+    fn upload(&self, dev: &Device, res: &PipeResource, host_ptr: *const c_void) -> CLResult<()> {
+        let fence = match self {
+            Self::Buffer { size, .. } => {
+                let size = (*size).try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+                dev.helper_ctx()
+                    .exec(|ctx| ctx.buffer_subdata(res, 0, host_ptr, size))
+            }
+            Self::Image { desc, .. } => {
+                let bx = desc.bx()?;
+                let stride = desc.row_pitch()?;
+                let layer_stride = desc.slice_pitch();
+                dev.helper_ctx()
+                    .exec(|ctx| ctx.texture_subdata(res, 0, &bx, host_ptr, stride, layer_stride))
+            }
+        };
+
+        fence.wait();
+        Ok(())
+    }
+}
+
+/// Tracks where the most recent content of a memory object lives across the devices of its
+/// context and the host pointer, so content only gets copied to whoever is stale.
+struct Coherency {
+    /// Devices holding the most recent content.
+    devs: Vec<&'static Device>,
+    /// Whether the host pointer of a `CL_MEM_USE_HOST_PTR` memory object holds it.
+    host: bool,
+}
+
+impl Coherency {
+    fn new(context: &Context, flags: cl_mem_flags, res_desc: &Option<ResourceDesc>) -> Mutex<Self> {
+        // Only the first device gets a resource up front, so it's the only one holding the
+        // content copied from the host pointer. Resources allocated later for
+        // `CL_MEM_USE_HOST_PTR` memory objects get initialized from the host pointer itself.
+        let devs = if res_desc.is_some() && bit_check(flags, CL_MEM_COPY_HOST_PTR) {
+            vec![context.devs[0]]
+        } else {
+            context.devs.clone()
+        };
+
+        Mutex::new(Self {
+            devs: devs,
+            host: bit_check(flags, CL_MEM_USE_HOST_PTR),
+        })
+    }
+
+    fn is_valid(&self, dev: &Device) -> bool {
+        self.devs.iter().any(|&d| ptr::eq(d, dev))
+    }
+}
 
 struct MappingTransfer {
     tx: PipeTransfer,
     shadow: Option<PipeResource>,
-    pending: u32,
+    /// Bytes accounted for `shadow`.
+    shadow_size: usize,
+    /// The mip level mapped by `tx`, buffers only ever use level 0.
+    level: u32,
+    /// The region of `level` mapped by `tx`. Buffers always map their entire parent.
+    origin: CLVec<usize>,
+    region: CLVec<usize>,
 }
 
 impl MappingTransfer {
-    fn new(tx: PipeTransfer, shadow: Option<PipeResource>) -> Self {
+    fn new(
+        tx: PipeTransfer,
+        shadow: Option<PipeResource>,
+        level: u32,
+        origin: CLVec<usize>,
+        region: CLVec<usize>,
+    ) -> Self {
+        let shadow_size = match &shadow {
+            Some(shadow) if shadow.is_buffer() => shadow.width() as usize,
+            Some(shadow) => {
+                tx.slice_pitch() * cmp::max(shadow.depth(), shadow.array_size()) as usize
+            }
+            None => 0,
+        };
+
         MappingTransfer {
             tx: tx,
             shadow: shadow,
-            pending: 1,
+            shadow_size: shadow_size,
+            level: level,
+            origin: origin,
+            region: region,
+        }
+    }
+
+    fn covers(&self, level: u32, origin: &CLVec<usize>, region: &CLVec<usize>) -> bool {
+        self.level == level
+            && (0..3).all(|i| {
+                self.origin[i] <= origin[i]
+                    && origin[i] + region[i] <= self.origin[i] + self.region[i]
+            })
+    }
+}
+
+/// The transfers backing all mappings of a memory object on a single device.
+struct DeviceMappings {
+    txs: Vec<MappingTransfer>,
+    pending: u32,
+}
+
+/// The part of a memory object mapped through a pointer.
+#[derive(Clone, Copy)]
+enum MapRange {
+    /// Range in bytes relative to the parent, as sub-buffers share the mappings of their parent.
+    Buffer { offset: usize, size: usize },
+    /// Region of a mip level of an image.
+    Image {
+        level: u32,
+        origin: CLVec<usize>,
+        region: CLVec<usize>,
+    },
+}
+
+impl MapRange {
+    fn contains(&self, other: &Self) -> bool {
+        match (self, other) {
+            (
+                Self::Buffer { offset, size },
+                Self::Buffer {
+                    offset: other_offset,
+                    size: other_size,
+                },
+            ) => offset <= other_offset && other_offset + other_size <= offset + size,
+            // Regions of images might be mapped through different transfers, so only an identical
+            // mapping is known to use the same one.
+            (
+                Self::Image {
+                    level,
+                    origin,
+                    region,
+                },
+                Self::Image {
+                    level: other_level,
+                    origin: other_origin,
+                    region: other_region,
+                },
+            ) => level == other_level && origin == other_origin && region == other_region,
+            _ => false,
+        }
+    }
+
+    /// Grows the range to cover `other` as well, which has to start at the same place.
+    fn merge(&mut self, other: &Self) {
+        match (self, other) {
+            (
+                Self::Buffer { size, .. },
+                Self::Buffer {
+                    size: other_size, ..
+                },
+            ) => {
+                *size = cmp::max(*size, *other_size);
+            }
+            (
+                Self::Image { region, .. },
+                Self::Image {
+                    region: other_region,
+                    ..
+                },
+            ) => {
+                for i in 0..3 {
+                    region[i] = cmp::max(region[i], other_region[i]);
+                }
+            }
+            _ => {}
         }
     }
 }
 
+/// A pointer handed out to the application by mapping a memory object.
+struct Mapping {
+    /// The range mapped through the pointer, which is the only part getting synced.
+    range: MapRange,
+    refs: u32,
+}
+
 struct Mappings {
-    tx: HashMap<&'static Device, MappingTransfer>,
-    maps: HashMap<usize, u32>,
+    tx: PerDevice<&'static Device, DeviceMappings>,
+    /// Active mappings by the memory object they got created through and their pointer.
+    maps: HashMap<(usize, usize), Mapping>,
 }
 
 impl Mappings {
     fn new() -> Mutex<Self> {
         Mutex::new(Mappings {
-            tx: HashMap::new(),
+            tx: PerDevice::new(),
             maps: HashMap::new(),
         })
     }
 
-    fn contains_ptr(&self, ptr: *mut c_void) -> bool {
-        let ptr = ptr as usize;
-        self.maps.contains_key(&ptr)
+    fn key(mem: &MemBase, ptr: *mut c_void) -> (usize, usize) {
+        (mem as *const MemBase as usize, ptr as usize)
+    }
+
+    fn contains_ptr(&self, mem: &MemBase, ptr: *mut c_void) -> bool {
+        self.maps.contains_key(&Self::key(mem, ptr))
+    }
+
+    fn get_tx(&self, dev: &Device, level: u32) -> Option<&MappingTransfer> {
+        self.tx.get(dev)?.txs.iter().find(|tx| tx.level == level)
+    }
+
+    /// Returns the first transfer on `dev` covering `region` at `origin` of the mip level `level`.
+    /// Transfers only get added until all of them get cleaned up at once, so the same region
+    /// always resolves to the same transfer while it's mapped.
+    fn find_tx(
+        &self,
+        dev: &Device,
+        level: u32,
+        origin: &CLVec<usize>,
+        region: &CLVec<usize>,
+    ) -> Option<&MappingTransfer> {
+        self.tx
+            .get(dev)?
+            .txs
+            .iter()
+            .find(|tx| tx.covers(level, origin, region))
+    }
+
+    fn add_tx(&mut self, context: &Context, dev: &'static Device, tx: MappingTransfer) {
+        if !self.tx.contains_key(dev) {
+            self.tx.insert(
+                dev,
+                DeviceMappings {
+                    txs: Vec::new(),
+                    pending: 0,
+                },
+            );
+        }
+
+        context.add_allocation(dev, MemoryKind::Shadow, tx.shadow_size);
+        self.tx.get_mut(dev).unwrap().txs.push(tx);
+        dev.stats.add_mapping();
+    }
+
+    /// Returns the shadow resources of all transfers on `dev` together with their mip level.
+    fn shadows(&self, dev: &Device) -> impl Iterator<Item = (u32, &PipeResource)> {
+        self.tx
+            .get(dev)
+            .into_iter()
+            .flat_map(|m| &m.txs)
+            .filter_map(|tx| Some((tx.level, tx.shadow.as_ref()?)))
     }
 
     fn mark_pending(&mut self, dev: &Device) {
@@ -75,36 +412,54 @@ impl Mappings {
         }
     }
 
-    fn increase_ref(&mut self, dev: &Device, ptr: *mut c_void) -> bool {
-        let ptr = ptr as usize;
-        let res = self.maps.is_empty();
-        *self.maps.entry(ptr).or_default() += 1;
+    /// Returns whether `range` isn't covered by any active mapping yet, in which case it needs to
+    /// be synced.
+    fn increase_ref(
+        &mut self,
+        dev: &Device,
+        mem: &MemBase,
+        ptr: *mut c_void,
+        range: MapRange,
+    ) -> bool {
+        let res = !self.maps.values().any(|m| m.range.contains(&range));
+        let mapping = self.maps.entry(Self::key(mem, ptr)).or_insert(Mapping {
+            range: range,
+            refs: 0,
+        });
+
+        // mappings of the same pointer start at the same place, so keep the biggest one
+        mapping.range.merge(&range);
+        mapping.refs += 1;
         self.unmark_pending(dev);
         res
     }
 
-    fn decrease_ref(&mut self, ptr: *mut c_void, dev: &Device) -> (bool, Option<&PipeResource>) {
-        let ptr = ptr as usize;
-        if let Some(r) = self.maps.get_mut(&ptr) {
-            *r -= 1;
-
-            if *r == 0 {
-                self.maps.remove(&ptr);
-            }
-
-            if self.maps.is_empty() {
-                let shadow = self.tx.get(dev).and_then(|tx| tx.shadow.as_ref());
-                return (true, shadow);
-            }
+    /// Returns the mapped range if `ptr` got unmapped for the last time, in which case the range
+    /// needs to be synced back.
+    fn decrease_ref(&mut self, mem: &MemBase, ptr: *mut c_void) -> Option<MapRange> {
+        let key = Self::key(mem, ptr);
+        let mapping = self.maps.get_mut(&key)?;
+        mapping.refs -= 1;
+        if mapping.refs > 0 {
+            return None;
         }
-        (false, None)
+
+        self.maps.remove(&key).map(|m| m.range)
     }
 
-    fn clean_up_tx(&mut self, dev: &Device, ctx: &PipeContext) {
+    fn clean_up_tx(&mut self, context: &Context, dev: &Device, ctx: &PipeContext) {
         if self.maps.is_empty() {
-            if let Some(tx) = self.tx.get(&dev) {
-                if tx.pending == 0 {
-                    self.tx.remove(dev).unwrap().tx.with_ctx(ctx);
+            if let Some(m) = self.tx.get(dev) {
+                if m.pending == 0 {
+                    for tx in self.tx.remove(dev).unwrap().txs {
+                        context.remove_allocation(dev, MemoryKind::Shadow, tx.shadow_size);
+                        tx.tx.with_ctx(ctx);
+                        if let Some(shadow) = tx.shadow.filter(PipeResource::is_buffer) {
+                            // the copy out of the shadow might still be in flight.
+                            dev.release_staging_buffer(shadow, ctx.flush());
+                        }
+                        dev.stats.remove_mapping();
+                    }
                 }
             }
         }
@@ -172,7 +527,14 @@ impl Deref for Mem {
 }
 
 impl Mem {
-    pub fn unmap(&self, q: &Queue, ctx: &PipeContext, ptr: MutMemoryPtr) -> CLResult<()> {
+    pub fn is_mapped_ptr(&self, ptr: *mut c_void) -> bool {
+        match self {
+            Self::Buffer(b) => b.is_mapped_ptr(ptr),
+            Self::Image(i) => i.is_mapped_ptr(ptr),
+        }
+    }
+
+    pub fn unmap(&self, q: &Queue, ctx: &QueueContext, ptr: MutMemoryPtr) -> CLResult<()> {
         match self {
             Self::Buffer(b) => b.unmap(q, ctx, ptr),
             Self::Image(i) => i.unmap(q, ctx, ptr),
@@ -193,7 +555,9 @@ impl Mem {
 /// In order to have a compliant implementation of the mapping API we have to consider the following
 /// cases:
 ///   1. Mapping a cl_mem object with CL_MEM_USE_HOST_PTR: We simply return the host_ptr.
-///      Synchronization of shadowed host ptrs are done in `sync_shadow` on demand.
+///      Devices supporting user memory use the host_ptr as storage directly and need no
+///      synchronization at all. For all others the resource shadows the host_ptr and gets synced
+///      in `sync_shadow` and `unmap` on demand.
 ///   2. Mapping linear resources on UMA systems: We simply create the pipe_transfer with
 ///      `PIPE_MAP_DIRECTLY` and `PIPE_MAP_UNSYNCHRONIZED` and return the attached pointer.
 ///   3. On non UMA systems or when 2. fails (e.g. due to the resource being tiled) we
@@ -203,6 +567,10 @@ impl Mem {
 ///        `PIPE_MAP_UNSYNCHRONIZED`
 ///      - sync the shadow buffer like a host_ptr shadow buffer in 1.
 ///
+/// Sub-buffers share the transfers and shadows of their parent. Only the range mapped through a
+/// pointer gets synced on map, unless an active mapping covers it already, and it gets synced back
+/// once the pointer got unmapped for the last time.
+///
 /// Taking this approach we guarentee that we only copy when actually needed while making sure the
 /// content behind the returned pointer is valid until unmapped.
 pub struct MemBase {
@@ -218,13 +586,37 @@ pub struct MemBase {
     pub props: Vec<cl_mem_properties>,
     pub cbs: Mutex<Vec<MemCB>>,
     pub gl_obj: Option<GLObject>,
-    res: Option<HashMap<&'static Device, Arc<PipeResource>>>,
+    external: Option<ExternalMemory>,
+    res: Option<Resources>,
+    /// How to allocate resources on devices using the memory object for the first time, `None`
+    /// for imported memory.
+    res_desc: Option<ResourceDesc>,
+    coherency: Mutex<Coherency>,
     maps: Mutex<Mappings>,
+    strict: StrictState,
 }
 
 pub struct Buffer {
     base: MemBase,
     pub offset: usize,
+    /// Offset of the host pointer within its page. Resources created from the host pointer start
+    /// at the beginning of that page, see [Context::create_buffer].
+    host_ptr_offset: usize,
+}
+
+/// Transfer of a region of an image, see [Image::tx_region].
+struct ImageTransfer<'a> {
+    tx: GuardedPipeTransfer<'a>,
+    /// Linear copy of the region `tx` maps instead of the image itself. It has to outlive `tx`.
+    _staging: Option<PipeResource>,
+}
+
+impl Deref for ImageTransfer<'_> {
+    type Target = PipeTransfer;
+
+    fn deref(&self) -> &Self::Target {
+        &self.tx
+    }
 }
 
 pub struct Image {
@@ -264,6 +656,16 @@ pub trait CLImageDescInfo {
     fn width(&self) -> CLResult<u32>;
     fn height(&self) -> CLResult<u32>;
     fn size(&self) -> CLVec<usize>;
+    fn num_levels(&self) -> u32;
+
+    /// The size of mip level `level`. Array layers are not minified.
+    fn level_size(&self, level: u32) -> CLVec<usize> {
+        let mut size = self.size();
+        for s in size.iter_mut().take(self.dims().into()) {
+            *s = cmp::max(*s >> level, 1);
+        }
+        size
+    }
 
     fn dims(&self) -> u8 {
         self.type_info().0
@@ -311,6 +713,11 @@ impl CLImageDescInfo for cl_image_desc {
             res *= self.image_array_size;
         }
 
+        // mip levels past the first one
+        res += (1..self.num_levels())
+            .map(|level| self.level_size(level).pixels())
+            .sum::<usize>();
+
         res
     }
 
@@ -327,6 +734,10 @@ impl CLImageDescInfo for cl_image_desc {
         CLVec::new([self.image_width, height, depth])
     }
 
+    fn num_levels(&self) -> u32 {
+        cmp::max(self.num_mip_levels, 1)
+    }
+
     fn bx(&self) -> CLResult<pipe_box> {
         create_pipe_box(CLVec::default(), self.size(), self.image_type)
     }
@@ -334,7 +745,7 @@ impl CLImageDescInfo for cl_image_desc {
     fn row_pitch(&self) -> CLResult<u32> {
         self.image_row_pitch
             .try_into()
-            .map_err(|_| CL_OUT_OF_HOST_MEMORY)
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY.into())
     }
 
     fn slice_pitch(&self) -> usize {
@@ -344,17 +755,30 @@ impl CLImageDescInfo for cl_image_desc {
     fn width(&self) -> CLResult<u32> {
         self.image_width
             .try_into()
-            .map_err(|_| CL_OUT_OF_HOST_MEMORY)
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY.into())
     }
 
     fn height(&self) -> CLResult<u32> {
         self.image_height
             .try_into()
-            .map_err(|_| CL_OUT_OF_HOST_MEMORY)
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY.into())
     }
 }
 
+/// Size of the guard area placed behind buffers with `RUSTICL_DEBUG=bounds`.
+pub const BOUNDS_GUARD_SIZE: usize = 4096;
+/// Value the guard area gets filled with.
+pub const BOUNDS_GUARD_PATTERN: u8 = 0xcd;
+
+/// Buffer fills of at least this size with patterns bigger than a dword are done through an
+/// internal kernel.
+const SHADER_FILL_MIN_SIZE: usize = 1 << 20;
+
+/// Copies smaller than this aren't worth splitting up across threads.
+const SW_COPY_PARALLEL_MIN_SIZE: usize = 4 << 20;
+
 fn sw_copy(
+    q: &Queue,
     src: *const c_void,
     dst: *mut c_void,
     region: &CLVec<usize>,
@@ -366,22 +790,80 @@ fn sw_copy(
     dst_slice_pitch: usize,
     pixel_size: u8,
 ) {
-    for z in 0..region[2] {
-        for y in 0..region[1] {
+    let pixel_size = pixel_size as usize;
+    let src = unsafe { src.add(*src_origin * [pixel_size, src_row_pitch, src_slice_pitch]) };
+    let dst = unsafe { dst.add(*dst_origin * [pixel_size, dst_row_pitch, dst_slice_pitch]) };
+
+    // Merge rows and then slices which are contiguous in both source and destination, so we end
+    // up with as few and as big copies as possible.
+    let mut size = region[0] * pixel_size;
+    let mut rows = region[1];
+    let mut slices = region[2];
+    if rows == 1 || src_row_pitch == size && dst_row_pitch == size {
+        size *= rows;
+        rows = 1;
+        if slices == 1 || src_slice_pitch == size && dst_slice_pitch == size {
+            size *= slices;
+            slices = 1;
+        }
+    }
+
+    let copies = rows * slices;
+    let total = size * copies;
+    if total == 0 {
+        return;
+    }
+
+    // ptr::copy handles overlapping memory, but splitting up the copy would break that.
+    let extent = |row_pitch: usize, slice_pitch: usize| {
+        (rows - 1) * row_pitch + (slices - 1) * slice_pitch + size
+    };
+    let src_range = src as usize..src as usize + extent(src_row_pitch, src_slice_pitch);
+    let dst_range = dst as usize..dst as usize + extent(dst_row_pitch, dst_slice_pitch);
+    let overlaps = src_range.start < dst_range.end && dst_range.start < src_range.end;
+
+    let chunks = if total < SW_COPY_PARALLEL_MIN_SIZE || overlaps {
+        1
+    } else {
+        q.copy_pool().threads()
+    };
+
+    // Either split up the list of copies or, if everything got merged, the single copy itself.
+    // Pointers aren't Send, so we pass them on as addresses.
+    let (src, dst) = (src as usize, dst as usize);
+    let copy_chunk = |chunk: usize| {
+        if copies == 1 {
+            let chunk_size = div_round_up(size, chunks);
+            let start = cmp::min(chunk * chunk_size, size);
+            let end = cmp::min(start + chunk_size, size);
             unsafe {
                 ptr::copy(
-                    src.add(
-                        (*src_origin + [0, y, z])
-                            * [pixel_size as usize, src_row_pitch, src_slice_pitch],
-                    ),
-                    dst.add(
-                        (*dst_origin + [0, y, z])
-                            * [pixel_size as usize, dst_row_pitch, dst_slice_pitch],
-                    ),
-                    region[0] * pixel_size as usize,
+                    (src as *const u8).add(start),
+                    (dst as *mut u8).add(start),
+                    end - start,
                 )
             };
+        } else {
+            let chunk_size = div_round_up(copies, chunks);
+            let start = cmp::min(chunk * chunk_size, copies);
+            let end = cmp::min(start + chunk_size, copies);
+            for idx in start..end {
+                let (y, z) = (idx % rows, idx / rows);
+                unsafe {
+                    ptr::copy(
+                        (src as *const u8).add(y * src_row_pitch + z * src_slice_pitch),
+                        (dst as *mut u8).add(y * dst_row_pitch + z * dst_slice_pitch),
+                        size,
+                    )
+                };
+            }
         }
+    };
+
+    if chunks == 1 {
+        copy_chunk(0);
+    } else {
+        q.copy_pool().for_each(chunks, copy_chunk);
     }
 }
 
@@ -403,25 +885,51 @@ impl MemBase {
         size: usize,
         host_ptr: *mut c_void,
         props: Vec<cl_mem_properties>,
+        external: Option<ExternalMemory>,
     ) -> CLResult<Arc<Buffer>> {
-        let res_type = if bit_check(flags, CL_MEM_ALLOC_HOST_PTR) {
-            ResourceType::Staging
+        let policy = AllocPolicy::for_buffer(&context, flags, host_ptr);
+
+        let mut res_desc = None;
+        let buffer = if let Some(external) = &external {
+            let size = size.try_into().map_err(|_| CL_INVALID_BUFFER_SIZE)?;
+            context.import_dma_buf(
+                &external.devs,
+                external.handle as u32,
+                DRM_FORMAT_MOD_INVALID,
+                pipe_texture_target::PIPE_BUFFER,
+                pipe_format::PIPE_FORMAT_NONE,
+                size,
+                size,
+                1,
+                1,
+                1,
+            )?
         } else {
-            ResourceType::Normal
-        };
+            let desc = ResourceDesc::Buffer {
+                size: size,
+                policy: policy,
+            };
 
-        let buffer = context.create_buffer(
-            size,
-            host_ptr,
-            bit_check(flags, CL_MEM_COPY_HOST_PTR),
-            res_type,
-        )?;
+            // the other devices only get a resource once they use the buffer
+            let buffer = desc.alloc(
+                &context,
+                &context.devs[..1],
+                host_ptr,
+                bit_check(flags, CL_MEM_COPY_HOST_PTR),
+            )?;
+            Self::add_allocations(&context, MemoryKind::Buffer, &buffer, size);
+            res_desc = Some(desc);
+            buffer
+        };
+        let res = lazy_resources(&context.devs, buffer);
+        let coherency = Coherency::new(&context, flags, &res_desc);
 
         let host_ptr = if bit_check(flags, CL_MEM_USE_HOST_PTR) {
             host_ptr as usize
         } else {
             0
         };
+        let host_ptr_offset = host_ptr % host_page_size();
 
         Ok(Arc::new(Buffer {
             base: Self {
@@ -434,11 +942,20 @@ impl MemBase {
                 host_ptr: host_ptr,
                 props: props,
                 gl_obj: None,
+                // imported memory comes with its content
+                strict: StrictState::new(
+                    external.is_some()
+                        || bit_check(flags, CL_MEM_COPY_HOST_PTR | CL_MEM_USE_HOST_PTR),
+                ),
+                external: external,
                 cbs: Mutex::new(Vec::new()),
-                res: Some(buffer),
+                res: Some(res),
+                res_desc: res_desc,
                 maps: Mappings::new(),
+                coherency: coherency,
             },
             offset: 0,
+            host_ptr_offset: host_ptr_offset,
         }))
     }
 
@@ -454,6 +971,8 @@ impl MemBase {
             unsafe { parent.host_ptr().add(offset) as usize }
         };
 
+        let coherency = Coherency::new(&parent.context, 0, &None);
+        let host_ptr_offset = parent.host_ptr_offset;
         Arc::new(Buffer {
             base: Self {
                 base: CLObjectBase::new(RusticlTypes::Buffer),
@@ -465,11 +984,16 @@ impl MemBase {
                 host_ptr: host_ptr,
                 props: Vec::new(),
                 gl_obj: None,
+                external: None,
                 cbs: Mutex::new(Vec::new()),
                 res: None,
+                res_desc: None,
                 maps: Mappings::new(),
+                coherency: coherency,
+                strict: StrictState::new(false),
             },
             offset: offset,
+            host_ptr_offset: host_ptr_offset,
         })
     }
 
@@ -483,6 +1007,7 @@ impl MemBase {
         image_elem_size: u8,
         host_ptr: *mut c_void,
         props: Vec<cl_mem_properties>,
+        external: Option<ExternalMemory>,
     ) -> CLResult<Arc<Image>> {
         // we have to sanitize the image_desc a little for internal use
         let api_image_desc = image_desc;
@@ -498,37 +1023,41 @@ impl MemBase {
             image_desc.image_array_size = 1;
         }
 
-        let res_type = if bit_check(flags, CL_MEM_ALLOC_HOST_PTR) {
-            ResourceType::Staging
-        } else {
-            ResourceType::Normal
-        };
+        let policy = AllocPolicy::for_image(flags);
 
-        let texture = if parent.is_none() {
-            let mut texture = context.create_texture(
+        let size = image_desc.pixels() * image_format.pixel_size().unwrap() as usize;
+        let mut res_desc = None;
+        let texture = if let Some(external) = &external {
+            Some(context.import_texture(
+                &external.devs,
+                external.handle as u32,
+                DRM_FORMAT_MOD_LINEAR,
                 &image_desc,
                 image_format,
+            )?)
+        } else if parent.is_none() {
+            let desc = ResourceDesc::Image {
+                desc: image_desc,
+                format: *image_format,
+                flags: flags,
+                policy: policy,
+            };
+
+            // the other devices only get a resource once they use the image
+            let texture = desc.alloc(
+                &context,
+                &context.devs[..1],
                 host_ptr,
                 bit_check(flags, CL_MEM_COPY_HOST_PTR),
-                res_type,
-            );
-
-            // if we error allocating a Staging resource, just try with normal as
-            // `CL_MEM_ALLOC_HOST_PTR` is just a performance hint.
-            if res_type == ResourceType::Staging && texture.is_err() {
-                texture = context.create_texture(
-                    &image_desc,
-                    image_format,
-                    host_ptr,
-                    bit_check(flags, CL_MEM_COPY_HOST_PTR),
-                    ResourceType::Normal,
-                )
-            }
-
-            Some(texture?)
+            )?;
+            Self::add_allocations(&context, MemoryKind::Image, &texture, size);
+            res_desc = Some(desc);
+            Some(texture)
         } else {
             None
         };
+        let res = texture.map(|texture| lazy_resources(&context.devs, texture));
+        let coherency = Coherency::new(&context, flags, &res_desc);
 
         let host_ptr = if bit_check(flags, CL_MEM_USE_HOST_PTR) {
             host_ptr as usize
@@ -544,13 +1073,20 @@ impl MemBase {
                 parent: parent,
                 mem_type: mem_type,
                 flags: flags,
-                size: image_desc.pixels() * image_format.pixel_size().unwrap() as usize,
+                size: size,
                 host_ptr: host_ptr,
                 props: props,
                 gl_obj: None,
+                strict: StrictState::new(
+                    external.is_some()
+                        || bit_check(flags, CL_MEM_COPY_HOST_PTR | CL_MEM_USE_HOST_PTR),
+                ),
+                external: external,
                 cbs: Mutex::new(Vec::new()),
-                res: texture,
+                res: res,
+                res_desc: res_desc,
                 maps: Mappings::new(),
+                coherency: coherency,
             },
             image_format: *image_format,
             pipe_format: pipe_format,
@@ -564,7 +1100,7 @@ impl MemBase {
         match mem.base.get_type()? {
             RusticlTypes::Buffer => Ok(Mem::Buffer(Buffer::arc_from_raw(ptr)?)),
             RusticlTypes::Image => Ok(Mem::Image(Image::arc_from_raw(ptr)?)),
-            _ => Err(CL_INVALID_MEM_OBJECT),
+            _ => Err(CL_INVALID_MEM_OBJECT.into()),
         }
     }
 
@@ -614,8 +1150,8 @@ impl MemBase {
             gl_mem_props.clone(),
         )?;
 
-        // Cube maps faces are not linear in memory, so copy all contents
-        // of desired face into a 2D image and copy it back after gl release.
+        // Cube maps faces are not linear in memory, so copy all contents of the desired face into
+        // a 2D image when acquiring it and copy them back on release if it got written to.
         let (shadow_map, texture) = if is_cube_map_face(export_in.target) {
             let shadow = create_shadow_slice(&imported_gl_tex, image_format)?;
 
@@ -639,6 +1175,8 @@ impl MemBase {
             assert_eq!(gl_mem_props.offset, 0);
         }
 
+        let res = lazy_resources(&context.devs, texture);
+        let coherency = Coherency::new(&context, flags, &None);
         let base = Self {
             base: CLObjectBase::new(rusticl_type),
             context: context,
@@ -648,21 +1186,26 @@ impl MemBase {
             size: gl_mem_props.size(),
             host_ptr: 0,
             props: Vec::new(),
-            gl_obj: Some(GLObject {
-                gl_object_target: gl_export_manager.export_in.target,
-                gl_object_type: gl_object_type,
-                gl_object_name: export_in.obj,
-                shadow_map: shadow_map,
-            }),
+            external: None,
+            gl_obj: Some(GLObject::new(
+                gl_export_manager.export_in.target,
+                gl_object_type,
+                export_in.obj,
+                shadow_map,
+            )),
             cbs: Mutex::new(Vec::new()),
-            res: Some(texture),
+            res: Some(res),
+            res_desc: None,
             maps: Mappings::new(),
+            coherency: coherency,
+            strict: StrictState::new(true),
         };
 
         Ok(if rusticl_type == RusticlTypes::Buffer {
             Arc::new(Buffer {
                 base: base,
                 offset: gl_mem_props.offset as usize,
+                host_ptr_offset: 0,
             })
             .into_cl()
         } else {
@@ -688,6 +1231,28 @@ impl MemBase {
         })
     }
 
+    fn add_allocations(
+        context: &Context,
+        kind: MemoryKind,
+        res: &PerDevice<&'static Device, Arc<PipeResource>>,
+        size: usize,
+    ) {
+        for (d, r) in res {
+            // user pointer imports don't allocate any memory
+            if !r.is_user {
+                context.add_allocation(d, kind, size);
+            }
+        }
+    }
+
+    fn mem_kind(&self) -> MemoryKind {
+        if self.is_buffer() {
+            MemoryKind::Buffer
+        } else {
+            MemoryKind::Image
+        }
+    }
+
     pub fn is_buffer(&self) -> bool {
         self.mem_type == CL_MEM_OBJECT_BUFFER
     }
@@ -704,33 +1269,271 @@ impl MemBase {
             && bit_check(mem.flags, CL_MEM_USE_HOST_PTR)
     }
 
-    pub fn get_res_of_dev(&self, dev: &Device) -> CLResult<&Arc<PipeResource>> {
-        self.get_parent()
+    /// Returns the resource of `dev` for an access of the kind `rw`, after making sure it holds
+    /// the most recent content.
+    pub fn get_res_of_dev(&self, dev: &Device, rw: RWFlags) -> CLResult<&Arc<PipeResource>> {
+        let res = self.res_of_dev(dev)?;
+        if !matches!(rw, RWFlags::RD) {
+            if let Some(gl_obj) = &self.get_parent().gl_obj {
+                gl_obj.mark_written();
+            }
+        }
+
+        self.access(dev, rw).map_err(|err| {
+            CLError::new(err.code, "get_res_of_dev")
+                .object(self)
+                .device(dev)
+                .msg("failed to migrate the content to this device")
+        })?;
+        Ok(res)
+    }
+
+    /// Returns the resource of `dev`, allocating it if `dev` didn't use the memory object so far.
+    fn res_of_dev(&self, dev: &Device) -> CLResult<&Arc<PipeResource>> {
+        let mem = self.get_parent();
+        let no_res = || {
+            CLError::new(CL_OUT_OF_HOST_MEMORY, "get_res_of_dev")
+                .object(self)
+                .device(dev)
+                .msg("memory object has no resource on this device")
+        };
+
+        let cell = mem
             .res
             .as_ref()
             .and_then(|resources| resources.get(dev))
-            .ok_or(CL_OUT_OF_HOST_MEMORY)
+            .ok_or_else(no_res)?;
+        if let Some(res) = cell.get() {
+            return Ok(res);
+        }
+
+        let desc = mem.res_desc.as_ref().ok_or_else(no_res)?;
+        let dev = mem.context_dev(dev).ok_or_else(no_res)?;
+
+        // the content gets migrated over from the device holding it, so we only need to pass on
+        // the host pointer to make use of it as storage.
+        let res = desc
+            .alloc(&mem.context, &[dev], mem.host_ptr(), false)
+            .map_err(|err| err.object(self))?
+            .remove(dev)
+            .ok_or_else(no_res)?;
+
+        // another thread might have raced us allocating it.
+        let is_user = res.is_user;
+        if cell.set(res).is_ok() && !is_user {
+            mem.context.add_allocation(dev, mem.mem_kind(), mem.size);
+        }
+        Ok(cell.get().unwrap())
+    }
+
+    /// Returns the `'static` reference to `dev` out of the devices of the context.
+    fn context_dev(&self, dev: &Device) -> Option<&'static Device> {
+        self.context.devs.iter().copied().find(|&d| ptr::eq(d, dev))
     }
 
     fn get_parent(&self) -> &Self {
         if let Some(parent) = &self.parent {
-            parent
+            parent.get_parent()
         } else {
             self
         }
     }
 
     fn has_user_shadow_buffer(&self, d: &Device) -> CLResult<bool> {
-        let r = self.get_res_of_dev(d)?;
+        let r = self.res_of_dev(d)?;
         Ok(!r.is_user && bit_check(self.flags, CL_MEM_USE_HOST_PTR))
     }
 
+    /// Imported memory shares its storage across devices, so only memory allocated by us has to
+    /// be kept in sync.
+    fn needs_migration(&self) -> bool {
+        self.context.devs.len() > 1 && self.gl_obj.is_none() && self.external.is_none()
+    }
+
+    /// Makes sure `dev` holds the most recent content before accessing the memory object with
+    /// `rw`. Writes leave all other devices and the host pointer stale.
+    fn access(&self, dev: &Device, rw: RWFlags) -> CLResult<()> {
+        let mem = self.get_parent();
+        if !mem.needs_migration() {
+            return Ok(());
+        }
+
+        let dev = mem.context_dev(dev).ok_or(CL_INVALID_DEVICE)?;
+        let mut coherency = mem.coherency.lock().unwrap();
+        if !coherency.is_valid(dev) {
+            let res = mem.res_of_dev(dev)?;
+            if coherency.host && mem.host_ptr != 0 {
+                mem.res_desc.as_ref().ok_or(CL_INVALID_MEM_OBJECT)?.upload(
+                    dev,
+                    res,
+                    mem.host_ptr(),
+                )?;
+            } else {
+                let src = *coherency.devs.first().ok_or(CL_INVALID_MEM_OBJECT)?;
+                mem.copy_content(src, dev)?;
+            }
+            coherency.devs.push(dev);
+        }
+
+        if !matches!(rw, RWFlags::RD) {
+            mem.written_by(&mut coherency, dev)?;
+        }
+        Ok(())
+    }
+
+    /// Makes `dev` the only holder of the most recent content after it wrote to the memory object.
+    fn written_by(&self, coherency: &mut Coherency, dev: &'static Device) -> CLResult<()> {
+        // resources created from the host pointer all share the same memory
+        if self.res_of_dev(dev)?.is_user {
+            coherency.devs = self
+                .res
+                .iter()
+                .flatten()
+                .filter(|(_, r)| r.get().is_some_and(|r| r.is_user))
+                .map(|(&d, _)| d)
+                .collect();
+            coherency.host = true;
+        } else {
+            coherency.devs = vec![dev];
+            coherency.host = false;
+        }
+        Ok(())
+    }
+
+    /// Makes `dev` hold the most recent content of the memory object. With `content_undefined`
+    /// the content doesn't get copied and all other devices are left stale.
+    pub fn migrate(&self, dev: &Device, content_undefined: bool) -> CLResult<()> {
+        let mem = self.get_parent();
+        if !content_undefined || !mem.needs_migration() {
+            return self.access(dev, RWFlags::RD);
+        }
+
+        let dev = mem.context_dev(dev).ok_or(CL_INVALID_DEVICE)?;
+        let mut coherency = mem.coherency.lock().unwrap();
+        mem.written_by(&mut coherency, dev)
+    }
+
+    /// Whether the host pointer already holds the most recent content, so it doesn't have to be
+    /// synced from a device.
+    fn host_is_valid(&self) -> bool {
+        let mem = self.get_parent();
+        mem.needs_migration() && mem.coherency.lock().unwrap().host
+    }
+
+    /// Marks the host pointer as holding the most recent content after the entire memory object
+    /// got synced from or to a device.
+    fn host_synced(&self) {
+        // syncing parts of the parent doesn't make the entire host pointer valid
+        if self.parent.is_none() && self.needs_migration() {
+            self.coherency.lock().unwrap().host = true;
+        }
+    }
+
+    /// Copies the entire content of the resource of `src` into the resource of `dst` through the
+    /// host.
+    fn copy_content(&self, src: &Device, dst: &Device) -> CLResult<()> {
+        let src_res = self.res_of_dev(src)?;
+        let dst_res = self.res_of_dev(dst)?;
+
+        // both wrap the same host memory
+        if src_res.is_user && dst_res.is_user {
+            return Ok(());
+        }
+
+        // Buffers created from the host pointer start at the beginning of its page, so go through
+        // the host pointer directly instead of dealing with different offsets.
+        if src_res.is_buffer() && src_res.is_user {
+            let desc = self.res_desc.as_ref().ok_or(CL_INVALID_MEM_OBJECT)?;
+            return desc.upload(dst, dst_res, self.host_ptr());
+        } else if src_res.is_buffer() && dst_res.is_user {
+            let size = self.size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+            let tx = src
+                .helper_ctx()
+                .buffer_map(src_res, 0, size, RWFlags::RD)
+                .ok_or_else(|| {
+                    CLError::new(CL_OUT_OF_RESOURCES, "buffer_map")
+                        .object(self)
+                        .device(src)
+                        .msg("failed to map the source of a migration")
+                })?;
+            // SAFETY: the host pointer is required to be valid for the size of the buffer and the
+            //         application must not access it while content gets migrated.
+            unsafe { ptr::copy_nonoverlapping(tx.ptr(), self.host_ptr(), self.size) };
+            src.helper_ctx().unmap(tx);
+            return Ok(());
+        }
+
+        for level in 0..=src_res.last_level() {
+            let bx = pipe_box {
+                width: cmp::max(src_res.width() >> level, 1)
+                    .try_into()
+                    .map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                height: cmp::max(src_res.height() >> level, 1).into(),
+                // array layers don't get minified, but arrays have a depth of 1 anyway
+                depth: cmp::max(cmp::max(src_res.depth() >> level, 1), src_res.array_size())
+                    .try_into()
+                    .map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                ..Default::default()
+            };
+
+            let tx = if src_res.is_buffer() {
+                src.helper_ctx()
+                    .buffer_map(src_res, 0, bx.width, RWFlags::RD)
+            } else {
+                src.helper_ctx()
+                    .texture_map(src_res, level, &bx, RWFlags::RD)
+            }
+            .ok_or_else(|| {
+                CLError::new(CL_OUT_OF_RESOURCES, "texture_map")
+                    .object(self)
+                    .device(src)
+                    .msg(format!(
+                        "failed to map level {level} of the source of a migration"
+                    ))
+            })?;
+
+            dst.helper_ctx()
+                .exec(|ctx| {
+                    if dst_res.is_buffer() {
+                        ctx.buffer_subdata(dst_res, 0, tx.ptr(), bx.width as u32);
+                    } else {
+                        ctx.texture_subdata(
+                            dst_res,
+                            level,
+                            &bx,
+                            tx.ptr(),
+                            tx.row_pitch(),
+                            tx.slice_pitch(),
+                        );
+                    }
+                })
+                .wait();
+
+            src.helper_ctx().unmap(tx);
+        }
+
+        Ok(())
+    }
+
     pub fn host_ptr(&self) -> *mut c_void {
         self.host_ptr as *mut c_void
     }
 
-    pub fn is_mapped_ptr(&self, ptr: *mut c_void) -> bool {
-        self.maps.lock().unwrap().contains_ptr(ptr)
+    pub fn strict_state(&self) -> &StrictState {
+        &self.get_parent().strict
+    }
+
+    /// Exports the resource backing this object on `dev` as a dma-buf.
+    pub fn export_dma_buf(&self, dev: &Device) -> CLResult<DmaBufExport> {
+        let res = self.get_res_of_dev(dev, RWFlags::RW)?;
+        dev.screen()
+            .resource_export_dmabuf(res)
+            .ok_or(CL_INVALID_OPERATION.into())
+    }
+
+    /// Returns the external memory this object or its parent got imported from.
+    pub fn external(&self) -> Option<&ExternalMemory> {
+        self.get_parent().external.as_ref()
     }
 }
 
@@ -741,15 +1544,102 @@ impl Drop for MemBase {
             cb.call(self);
         }
 
-        for (d, tx) in self.maps.get_mut().unwrap().tx.drain() {
-            d.helper_ctx().unmap(tx.tx);
+        // Queued commands might still use the resources on the GPU, so hand them over to the
+        // context, which only drops them once the work finished.
+        let mut garbage: PerDevice<&'static Device, Vec<Arc<PipeResource>>> = PerDevice::new();
+        let mut add_garbage = |d: &'static Device, res: Arc<PipeResource>| {
+            if let Some(list) = garbage.get_mut(d) {
+                list.push(res);
+            } else {
+                garbage.insert(d, vec![res]);
+            }
+        };
+
+        for (d, m) in self.maps.get_mut().unwrap().tx.drain() {
+            for tx in m.txs {
+                self.context
+                    .remove_allocation(d, MemoryKind::Shadow, tx.shadow_size);
+                d.helper_ctx().unmap(tx.tx);
+                d.stats.remove_mapping();
+
+                if let Some(shadow) = tx.shadow {
+                    add_garbage(d, Arc::new(shadow));
+                }
+            }
+        }
+
+        // resources of GL objects and imported memory weren't allocated by us
+        if let (Some(res), None, None) = (&self.res, &self.gl_obj, &self.external) {
+            for (d, r) in res {
+                if r.get().is_some_and(|r| !r.is_user) {
+                    self.context
+                        .remove_allocation(d, self.mem_kind(), self.size);
+                }
+            }
+        }
+
+        if let Some(mut res) = self.res.take() {
+            for (d, r) in res.drain() {
+                if let Some(r) = r.into_inner() {
+                    add_garbage(d, r);
+                }
+            }
+        }
+
+        for (d, res) in garbage.drain() {
+            self.context.destroy_deferred(d, res);
         }
     }
 }
 
+/// The part of a resource a buffer occupies on a device. Sub-buffers share the resource of their
+/// parent, so accesses should go through the view to end up in the right place.
+pub struct BufferView<'a> {
+    pub res: &'a Arc<PipeResource>,
+    /// Where the buffer starts within `res` in bytes.
+    pub offset: usize,
+    pub size: usize,
+}
+
+impl BufferView<'_> {
+    /// Translates `offset` within the buffer into an offset within the resource, making sure the
+    /// `size` bytes following it don't leave the buffer.
+    pub fn offset(&self, offset: usize, size: usize) -> CLResult<usize> {
+        if offset.checked_add(size).map_or(true, |end| end > self.size) {
+            return Err(CL_INVALID_VALUE.into());
+        }
+
+        Ok(self.offset + offset)
+    }
+}
+
 impl Buffer {
-    fn apply_offset(&self, offset: usize) -> CLResult<usize> {
-        self.offset.checked_add(offset).ok_or(CL_OUT_OF_HOST_MEMORY)
+    pub fn view(&self, dev: &Device, rw: RWFlags) -> CLResult<BufferView> {
+        let res = self.get_res_of_dev(dev, rw)?;
+        Ok(BufferView {
+            res: res,
+            offset: self.res_offset(res),
+            size: self.size,
+        })
+    }
+
+    /// Sub-buffers share the mappings of their parent, so all of them share a single transfer and
+    /// shadow per device.
+    fn mappings(&self) -> &Mutex<Mappings> {
+        &self.get_parent().maps
+    }
+
+    pub fn is_mapped_ptr(&self, ptr: *mut c_void) -> bool {
+        self.mappings().lock().unwrap().contains_ptr(self, ptr)
+    }
+
+    /// Returns where the buffer starts within `res`, which has to be one of its resources.
+    fn res_offset(&self, res: &PipeResource) -> usize {
+        if res.is_user {
+            self.host_ptr_offset + self.offset
+        } else {
+            self.offset
+        }
     }
 
     pub fn copy_rect(
@@ -775,6 +1665,7 @@ impl Buffer {
 
         // TODO check to use hw accelerated paths (e.g. resource_copy_region or blits)
         sw_copy(
+            q,
             tx_src.ptr(),
             tx_dst.ptr(),
             region,
@@ -799,10 +1690,10 @@ impl Buffer {
         dst_offset: usize,
         size: usize,
     ) -> CLResult<()> {
-        let src_offset = self.apply_offset(src_offset)?;
-        let dst_offset = dst.apply_offset(dst_offset)?;
-        let src_res = self.get_res_of_dev(q.device)?;
-        let dst_res = dst.get_res_of_dev(q.device)?;
+        let src = self.view(q.device, RWFlags::RD)?;
+        let dst = dst.view(q.device, RWFlags::WR)?;
+        let src_offset = src.offset(src_offset, size)?;
+        let dst_offset = dst.offset(dst_offset, size)?;
 
         let bx = create_pipe_box(
             [src_offset, 0, 0].into(),
@@ -815,7 +1706,7 @@ impl Buffer {
             0,
         ];
 
-        ctx.resource_copy_region(src_res, dst_res, &dst_origin, &bx);
+        ctx.resource_copy_region(src.res, dst.res, &dst_origin, &bx);
         Ok(())
     }
 
@@ -826,42 +1717,38 @@ impl Buffer {
         dst: &Image,
         src_offset: usize,
         dst_origin: CLVec<usize>,
+        dst_level: u32,
         region: &CLVec<usize>,
     ) -> CLResult<()> {
-        let src_offset = self.apply_offset(src_offset)?;
         let bpp = dst.image_format.pixel_size().unwrap().into();
         let src_pitch = [bpp, bpp * region[0], bpp * region[0] * region[1]];
         let size = CLVec::calc_size(region, src_pitch);
         let tx_src = self.tx(q, ctx, src_offset, size, RWFlags::RD)?;
 
-        // If image is created from a buffer, use image's slice and row pitch instead
-        let tx_dst;
-        let dst_pitch;
-        if let Some(Mem::Buffer(buffer)) = &dst.parent {
-            dst_pitch = [
-                bpp,
-                dst.image_desc.row_pitch()? as usize,
-                dst.image_desc.slice_pitch(),
-            ];
-
-            let (offset, size) = CLVec::calc_offset_size(dst_origin, region, dst_pitch);
-            tx_dst = buffer.tx(q, ctx, offset, size, RWFlags::WR)?;
-        } else {
-            tx_dst = dst.tx_image(
+        if dst.use_staging_copy(q.device)? {
+            // let the driver upload the data, so the image doesn't have to be mapped
+            return dst.write(
+                // SAFETY: `tx_src` maps the entire region with `src_pitch`
+                unsafe { ConstMemoryPtr::from_ptr(tx_src.ptr()) },
                 q,
                 ctx,
-                &create_pipe_box(dst_origin, *region, dst.mem_type)?,
-                RWFlags::WR,
-            )?;
-
-            dst_pitch = [1, tx_dst.row_pitch() as usize, tx_dst.slice_pitch()];
+                region,
+                src_pitch[1],
+                src_pitch[2],
+                &dst_origin,
+                dst_level,
+            );
         }
 
+        let (tx_dst, dst_row_pitch, dst_slice_pitch) =
+            dst.tx_region(q, ctx, &dst_origin, dst_level, region, RWFlags::WR)?;
+
         // Those pitch values cannot have 0 value in its coordinates
         debug_assert!(src_pitch[0] != 0 && src_pitch[1] != 0 && src_pitch[2] != 0);
-        debug_assert!(dst_pitch[0] != 0 && dst_pitch[1] != 0 && dst_pitch[2] != 0);
+        debug_assert!(dst_row_pitch != 0 && dst_slice_pitch != 0);
 
         sw_copy(
+            q,
             tx_src.ptr(),
             tx_dst.ptr(),
             region,
@@ -869,8 +1756,8 @@ impl Buffer {
             src_pitch[1],
             src_pitch[2],
             &CLVec::default(),
-            dst_pitch[1],
-            dst_pitch[2],
+            dst_row_pitch,
+            dst_slice_pitch,
             bpp as u8,
         );
         Ok(())
@@ -879,15 +1766,26 @@ impl Buffer {
     pub fn fill(
         &self,
         q: &Queue,
-        ctx: &PipeContext,
+        ctx: &QueueContext,
         pattern: &[u8],
         offset: usize,
         size: usize,
     ) -> CLResult<()> {
-        let offset = self.apply_offset(offset)?;
-        let res = self.get_res_of_dev(q.device)?;
-        ctx.clear_buffer(
-            res,
+        let view = self.view(q.device, RWFlags::WR)?;
+        let offset = view.offset(offset, size)?;
+
+        // Drivers are usually fine with dword patterns, but often fall back to the CPU for bigger
+        // ones.
+        if pattern.len() > size_of::<u32>()
+            && size >= SHADER_FILL_MIN_SIZE
+            && q.device.max_threads_per_block() >= FILL_BUFFER_BLOCK_SIZE as usize
+        {
+            Self::shader_fill(q, ctx, view.res, pattern, offset, size);
+            return Ok(());
+        }
+
+        ctx.clear_buffer_batched(
+            view.res,
             pattern,
             offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
             size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
@@ -895,20 +1793,126 @@ impl Buffer {
         Ok(())
     }
 
+    /// Fills `size` bytes at `offset` of `res` with `pattern` through an internal kernel, each
+    /// invocation writes one copy of the pattern.
+    fn shader_fill(
+        q: &Queue,
+        ctx: &QueueContext,
+        res: &PipeResource,
+        pattern: &[u8],
+        offset: usize,
+        size: usize,
+    ) {
+        let cso = q.device.fill_buffer_cso(pattern.len());
+        let block = FILL_BUFFER_BLOCK_SIZE as usize;
+        let count = size / pattern.len();
+        let max_grid = cmp::min(q.device.max_grid_size()[0], u32::MAX as u64) as usize;
+        let max_count = max_grid * block;
+
+        // The kernel input consists of the address to start writing at, the amount of patterns
+        // to write and the pattern, see rusticl_create_fill_buffer_shader.
+        let mut input = vec![0u8; 16 + pattern.len()];
+        input[16..].copy_from_slice(pattern);
+
+        ctx.bind_compute_state(cso.cso_ptr);
+
+        let mut done = 0;
+        while done < count {
+            let this_count = cmp::min(count - done, max_count);
+            let this_offset = offset + done * pattern.len();
+
+            // set_global_binding adds the address of the resource to the offset
+            if q.device.address_bits() == 64 {
+                input[0..8].copy_from_slice(&(this_offset as u64).to_ne_bytes());
+            } else {
+                input[0..4].copy_from_slice(&(this_offset as u32).to_ne_bytes());
+            }
+            input[8..12].copy_from_slice(&(this_count as u32).to_ne_bytes());
+
+            let mut globals: [*mut u32; 1] = [input.as_mut_ptr().cast()];
+            ctx.set_global_binding(&[res], &mut globals);
+            ctx.update_cb0(&input);
+            ctx.launch_grid(
+                1,
+                [FILL_BUFFER_BLOCK_SIZE, 1, 1],
+                [div_round_up(this_count, block) as u32, 1, 1],
+                0,
+            );
+
+            done += this_count;
+        }
+
+        ctx.clear_global_binding(1);
+        ctx.bind_compute_state(ptr::null_mut());
+        ctx.memory_barrier(PIPE_BARRIER_GLOBAL_BUFFER);
+    }
+
+    /// Checks the guard area behind the buffer for writes out of bounds with
+    /// `RUSTICL_DEBUG=bounds`. Returns `true` if it got overwritten, in which case it gets
+    /// restored for the next check.
+    pub fn guard_overwritten(&self, q: &Queue, ctx: &PipeContext) -> CLResult<bool> {
+        // sub buffers share the guard area with their parent
+        let size = self.get_parent().size;
+        let res = self.get_res_of_dev(q.device, RWFlags::RD)?;
+
+        // buffers backed by user memory don't have a guard area
+        if res.is_user || (res.width() as usize) < size + BOUNDS_GUARD_SIZE {
+            return Ok(false);
+        }
+
+        let offset = size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let tx = ctx
+            .buffer_map(
+                res,
+                offset,
+                BOUNDS_GUARD_SIZE as i32,
+                RWFlags::RD,
+                ResourceMapType::Normal,
+            )
+            .ok_or_else(|| {
+                CLError::new(CL_OUT_OF_RESOURCES, "buffer_map")
+                    .object(self)
+                    .device(q.device)
+                    .msg("failed to map the guard area")
+            })?
+            .with_ctx(ctx);
+
+        let guard = unsafe { slice::from_raw_parts(tx.ptr().cast::<u8>(), BOUNDS_GUARD_SIZE) };
+        let overwritten = guard.iter().any(|&b| b != BOUNDS_GUARD_PATTERN);
+        drop(tx);
+
+        if overwritten {
+            let guard = [BOUNDS_GUARD_PATTERN; BOUNDS_GUARD_SIZE];
+            ctx.buffer_subdata(
+                res,
+                offset as u32,
+                guard.as_ptr().cast(),
+                BOUNDS_GUARD_SIZE as u32,
+            );
+        }
+
+        Ok(overwritten)
+    }
+
     pub fn map(&self, dev: &'static Device, offset: usize) -> CLResult<MutMemoryPtr> {
-        let ptr = if self.has_user_shadow_buffer(dev)? {
+        let ptr = if bit_check(self.flags, CL_MEM_USE_HOST_PTR) {
             self.host_ptr()
         } else {
-            let mut lock = self.maps.lock().unwrap();
+            let mut lock = self.mappings().lock().unwrap();
 
-            if let Entry::Vacant(e) = lock.tx.entry(dev) {
+            if lock.get_tx(dev, 0).is_none() {
                 let (tx, res) = self.tx_raw_async(dev, RWFlags::RW)?;
-                e.insert(MappingTransfer::new(tx, res));
-            } else {
-                lock.mark_pending(dev);
+                let region = [self.get_parent().size, 1, 1].into();
+                lock.add_tx(
+                    &self.context,
+                    dev,
+                    MappingTransfer::new(tx, res, 0, CLVec::default(), region),
+                );
             }
 
-            lock.tx.get(dev).unwrap().tx.ptr()
+            lock.mark_pending(dev);
+            // the transfer covers the entire parent buffer
+            unsafe { lock.get_tx(dev, 0).unwrap().tx.ptr().add(self.offset) }
         };
 
         let ptr = unsafe { ptr.add(offset) };
@@ -934,6 +1938,30 @@ impl Buffer {
         Ok(())
     }
 
+    /// Like [Buffer::read], but only submits a copy into the readback ring of `ctx`, so the queue
+    /// thread doesn't have to wait on the GPU. The data arrives at `ptr` before the event gets
+    /// signaled.
+    pub fn read_async(
+        &self,
+        q: &Queue,
+        ctx: &QueueContext,
+        offset: usize,
+        ptr: MutMemoryPtr,
+        size: usize,
+    ) -> CLResult<()> {
+        let view = self.view(q.device, RWFlags::RD)?;
+        let res_offset = view.offset(offset, size)?;
+
+        if let (Ok(res_offset), Ok(res_size)) = (res_offset.try_into(), size.try_into()) {
+            if ctx.read_buffer_async(view.res, res_offset, ptr, res_size) {
+                return Ok(());
+            }
+        }
+
+        // the ring is out of space or the read is larger than the ring
+        self.read(q, ctx, offset, ptr, size)
+    }
+
     pub fn read_rect(
         &self,
         dst: MutMemoryPtr,
@@ -953,6 +1981,7 @@ impl Buffer {
         let tx = self.tx(q, ctx, offset, size, RWFlags::RD)?;
 
         sw_copy(
+            q,
             tx.ptr(),
             dst,
             region,
@@ -968,32 +1997,58 @@ impl Buffer {
         Ok(())
     }
 
+    /// Syncs the `size` bytes at `offset` mapped through `ptr`, unless they are already mapped.
     // TODO: only sync on map when the memory is not mapped with discard
-    pub fn sync_shadow(&self, q: &Queue, ctx: &PipeContext, ptr: MutMemoryPtr) -> CLResult<()> {
+    pub fn sync_shadow(
+        &self,
+        q: &Queue,
+        ctx: &PipeContext,
+        ptr: MutMemoryPtr,
+        offset: usize,
+        size: usize,
+    ) -> CLResult<()> {
         let ptr = ptr.as_ptr();
-        let mut lock = self.maps.lock().unwrap();
-        if !lock.increase_ref(q.device, ptr) {
+        let parent_offset = self.offset + offset;
+        let range = MapRange::Buffer {
+            offset: parent_offset,
+            size: size,
+        };
+
+        let mut lock = self.mappings().lock().unwrap();
+        if !lock.increase_ref(q.device, self, ptr, range) {
             return Ok(());
         }
 
         if self.has_user_shadow_buffer(q.device)? {
+            if self.host_is_valid() {
+                return Ok(());
+            }
+
             self.read(
                 q,
                 ctx,
-                0,
+                offset,
                 // SAFETY: it's required that applications do not cause data races
-                unsafe { MutMemoryPtr::from_ptr(self.host_ptr()) },
-                self.size,
-            )
+                unsafe { MutMemoryPtr::from_ptr(self.host_ptr().add(offset)) },
+                size,
+            )?;
+            if offset == 0 && size == self.size {
+                self.host_synced();
+            }
+            Ok(())
         } else {
-            if let Some(shadow) = lock.tx.get(&q.device).and_then(|tx| tx.shadow.as_ref()) {
-                let res = self.get_res_of_dev(q.device)?;
+            if let Some((_, shadow)) = lock.shadows(q.device).next() {
+                let view = self.view(q.device, RWFlags::RD)?;
+                // the shadow covers the entire parent
+                let dst_offset = parent_offset
+                    .try_into()
+                    .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
                 let bx = create_pipe_box(
-                    [self.offset, 0, 0].into(),
-                    [self.size, 1, 1].into(),
+                    [view.offset(offset, size)?, 0, 0].into(),
+                    [size, 1, 1].into(),
                     CL_MEM_OBJECT_BUFFER,
                 )?;
-                ctx.resource_copy_region(res, shadow, &[0; 3], &bx);
+                ctx.resource_copy_region(view.res, shadow, &[dst_offset, 0, 0], &bx);
             }
             Ok(())
         }
@@ -1007,18 +2062,23 @@ impl Buffer {
         size: usize,
         rw: RWFlags,
     ) -> CLResult<GuardedPipeTransfer<'a>> {
-        let offset = self.apply_offset(offset)?;
-        let r = self.get_res_of_dev(q.device)?;
+        let view = self.view(q.device, rw)?;
+        let offset = view.offset(offset, size)?;
 
         Ok(ctx
             .buffer_map(
-                r,
+                view.res,
                 offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
                 size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
                 rw,
                 ResourceMapType::Normal,
             )
-            .ok_or(CL_OUT_OF_RESOURCES)?
+            .ok_or_else(|| {
+                CLError::new(CL_OUT_OF_RESOURCES, "buffer_map")
+                    .object(self)
+                    .device(q.device)
+                    .msg(format!("failed to map {size} bytes at offset {offset}"))
+            })?
             .with_ctx(ctx))
     }
 
@@ -1027,13 +2087,20 @@ impl Buffer {
         dev: &Device,
         rw: RWFlags,
     ) -> CLResult<(PipeTransfer, Option<PipeResource>)> {
-        let r = self.get_res_of_dev(dev)?;
-        let offset = self.offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
-        let size = self.size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        // sub-buffers share the transfer of their parent, so map all of the parent.
+        let view = self.view(dev, rw)?;
+        let offset = (view.offset - self.offset)
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        let size = self
+            .get_parent()
+            .size
+            .try_into()
+            .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
         let ctx = dev.helper_ctx();
 
-        let tx = if can_map_directly(dev, r) {
-            ctx.buffer_map_directly(r, offset, size, rw)
+        let tx = if can_map_directly(dev, view.res) {
+            ctx.buffer_map_directly(view.res, offset, size, rw)
         } else {
             None
         };
@@ -1041,50 +2108,70 @@ impl Buffer {
         if let Some(tx) = tx {
             Ok((tx, None))
         } else {
-            let shadow = dev
-                .screen()
-                .resource_create_buffer(size as u32, ResourceType::Staging, 0)
-                .ok_or(CL_OUT_OF_RESOURCES)?;
+            let shadow = dev.staging_buffer(size as u32).ok_or_else(|| {
+                CLError::new(CL_OUT_OF_RESOURCES, "staging_buffer")
+                    .object(self)
+                    .device(dev)
+                    .msg(format!(
+                        "failed to allocate a {size} bytes shadow for mapping"
+                    ))
+            })?;
             let tx = ctx
                 .buffer_map_coherent(&shadow, 0, size, rw)
-                .ok_or(CL_OUT_OF_RESOURCES)?;
+                .ok_or_else(|| {
+                    CLError::new(CL_OUT_OF_RESOURCES, "buffer_map_coherent")
+                        .object(self)
+                        .device(dev)
+                        .msg("failed to map the shadow of a mapping")
+                })?;
             Ok((tx, Some(shadow)))
         }
     }
 
     // TODO: only sync on unmap when the memory is not mapped for writing
-    pub fn unmap(&self, q: &Queue, ctx: &PipeContext, ptr: MutMemoryPtr) -> CLResult<()> {
+    pub fn unmap(&self, q: &Queue, ctx: &QueueContext, ptr: MutMemoryPtr) -> CLResult<()> {
         let ptr = ptr.as_ptr();
-        let mut lock = self.maps.lock().unwrap();
-        if !lock.contains_ptr(ptr) {
+        let mut lock = self.mappings().lock().unwrap();
+        if !lock.contains_ptr(self, ptr) {
             return Ok(());
         }
 
-        let (needs_sync, shadow) = lock.decrease_ref(ptr, q.device);
-        if needs_sync {
-            if let Some(shadow) = shadow {
-                let res = self.get_res_of_dev(q.device)?;
-                let offset = self.offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+        if let Some(MapRange::Buffer {
+            offset: parent_offset,
+            size,
+        }) = lock.decrease_ref(self, ptr)
+        {
+            let offset = parent_offset - self.offset;
+            if let Some((_, shadow)) = lock.shadows(q.device).next() {
+                let view = self.view(q.device, RWFlags::WR)?;
+                let dst_offset = view
+                    .offset(offset, size)?
+                    .try_into()
+                    .map_err(|_| CL_OUT_OF_HOST_MEMORY)?;
+                // the shadow covers the entire parent
                 let bx = create_pipe_box(
-                    CLVec::default(),
-                    [self.size, 1, 1].into(),
+                    [parent_offset, 0, 0].into(),
+                    [size, 1, 1].into(),
                     CL_MEM_OBJECT_BUFFER,
                 )?;
 
-                ctx.resource_copy_region(shadow, res, &[offset, 0, 0], &bx);
+                ctx.resource_copy_region(shadow, view.res, &[dst_offset, 0, 0], &bx);
             } else if self.has_user_shadow_buffer(q.device)? {
                 self.write(
                     q,
                     ctx,
-                    0,
+                    offset,
                     // SAFETY: it's required that applications do not cause data races
-                    unsafe { ConstMemoryPtr::from_ptr(self.host_ptr()) },
-                    self.size,
+                    unsafe { ConstMemoryPtr::from_ptr(self.host_ptr().add(offset)) },
+                    size,
                 )?;
+                if offset == 0 && size == self.size {
+                    self.host_synced();
+                }
             }
         }
 
-        lock.clean_up_tx(q.device, ctx);
+        lock.clean_up_tx(&self.context, q.device, ctx);
 
         Ok(())
     }
@@ -1092,16 +2179,16 @@ impl Buffer {
     pub fn write(
         &self,
         q: &Queue,
-        ctx: &PipeContext,
+        ctx: &QueueContext,
         offset: usize,
         ptr: ConstMemoryPtr,
         size: usize,
     ) -> CLResult<()> {
         let ptr = ptr.as_ptr();
-        let offset = self.apply_offset(offset)?;
-        let r = self.get_res_of_dev(q.device)?;
-        ctx.buffer_subdata(
-            r,
+        let view = self.view(q.device, RWFlags::WR)?;
+        let offset = view.offset(offset, size)?;
+        ctx.buffer_subdata_batched(
+            view.res,
             offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
             ptr,
             size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
@@ -1128,6 +2215,7 @@ impl Buffer {
         let tx = self.tx(q, ctx, offset, size, RWFlags::WR)?;
 
         sw_copy(
+            q,
             src,
             tx.ptr(),
             region,
@@ -1151,33 +2239,15 @@ impl Image {
         ctx: &PipeContext,
         dst: &Buffer,
         src_origin: CLVec<usize>,
+        src_level: u32,
         dst_offset: usize,
         region: &CLVec<usize>,
     ) -> CLResult<()> {
-        let dst_offset = dst.apply_offset(dst_offset)?;
         let bpp = self.image_format.pixel_size().unwrap().into();
 
-        let src_pitch;
-        let tx_src;
-        if let Some(Mem::Buffer(buffer)) = &self.parent {
-            src_pitch = [
-                bpp,
-                self.image_desc.row_pitch()? as usize,
-                self.image_desc.slice_pitch(),
-            ];
-            let (offset, size) = CLVec::calc_offset_size(src_origin, region, src_pitch);
-            tx_src = buffer.tx(q, ctx, offset, size, RWFlags::RD)?;
-        } else {
-            tx_src = self.tx_image(
-                q,
-                ctx,
-                &create_pipe_box(src_origin, *region, self.mem_type)?,
-                RWFlags::RD,
-            )?;
-            src_pitch = [1, tx_src.row_pitch() as usize, tx_src.slice_pitch()];
-        }
+        let (tx_src, src_row_pitch, src_slice_pitch) =
+            self.tx_region(q, ctx, &src_origin, src_level, region, RWFlags::RD)?;
 
-        // If image is created from a buffer, use image's slice and row pitch instead
         let dst_pitch = [bpp, bpp * region[0], bpp * region[0] * region[1]];
 
         let dst_origin: CLVec<usize> = [dst_offset, 0, 0].into();
@@ -1185,16 +2255,17 @@ impl Image {
         let tx_dst = dst.tx(q, ctx, offset, size, RWFlags::WR)?;
 
         // Those pitch values cannot have 0 value in its coordinates
-        debug_assert!(src_pitch[0] != 0 && src_pitch[1] != 0 && src_pitch[2] != 0);
+        debug_assert!(src_row_pitch != 0 && src_slice_pitch != 0);
         debug_assert!(dst_pitch[0] != 0 && dst_pitch[1] != 0 && dst_pitch[2] != 0);
 
         sw_copy(
+            q,
             tx_src.ptr(),
             tx_dst.ptr(),
             region,
             &CLVec::default(),
-            src_pitch[1],
-            src_pitch[2],
+            src_row_pitch,
+            src_slice_pitch,
             &CLVec::default(),
             dst_pitch[1],
             dst_pitch[2],
@@ -1209,79 +2280,54 @@ impl Image {
         ctx: &PipeContext,
         dst: &Image,
         src_origin: CLVec<usize>,
+        src_level: u32,
         dst_origin: CLVec<usize>,
+        dst_level: u32,
         region: &CLVec<usize>,
     ) -> CLResult<()> {
         let src_parent = self.get_parent();
         let dst_parent = dst.get_parent();
-        let src_res = src_parent.get_res_of_dev(q.device)?;
-        let dst_res = dst_parent.get_res_of_dev(q.device)?;
-
-        // We just want to use sw_copy if mem objects have different types or if copy can have
-        // custom strides (image2d from buff/images)
-        if src_parent.is_buffer() || dst_parent.is_buffer() {
-            let bpp = self.image_format.pixel_size().unwrap().into();
-
-            let tx_src;
-            let tx_dst;
-            let dst_pitch;
-            let src_pitch;
-            if let Some(Mem::Buffer(buffer)) = &self.parent {
-                src_pitch = [
-                    bpp,
-                    self.image_desc.row_pitch()? as usize,
-                    self.image_desc.slice_pitch(),
-                ];
-
-                let (offset, size) = CLVec::calc_offset_size(src_origin, region, src_pitch);
-                tx_src = buffer.tx(q, ctx, offset, size, RWFlags::RD)?;
-            } else {
-                tx_src = self.tx_image(
-                    q,
-                    ctx,
-                    &create_pipe_box(src_origin, *region, src_parent.mem_type)?,
-                    RWFlags::RD,
-                )?;
-
-                src_pitch = [1, tx_src.row_pitch() as usize, tx_src.slice_pitch()];
-            }
+        let src_res = src_parent.get_res_of_dev(q.device, RWFlags::RD)?;
+        let dst_res = dst_parent.get_res_of_dev(q.device, RWFlags::WR)?;
 
-            if let Some(Mem::Buffer(buffer)) = &dst.parent {
-                // If image is created from a buffer, use image's slice and row pitch instead
-                dst_pitch = [
-                    bpp,
-                    dst.image_desc.row_pitch()? as usize,
-                    dst.image_desc.slice_pitch(),
-                ];
-
-                let (offset, size) = CLVec::calc_offset_size(dst_origin, region, dst_pitch);
-                tx_dst = buffer.tx(q, ctx, offset, size, RWFlags::WR)?;
-            } else {
-                tx_dst = dst.tx_image(
-                    q,
-                    ctx,
-                    &create_pipe_box(dst_origin, *region, dst_parent.mem_type)?,
-                    RWFlags::WR,
-                )?;
-
-                dst_pitch = [1, tx_dst.row_pitch() as usize, tx_dst.slice_pitch()];
-            }
+        // Images created from buffers can't be copied through texture copies, but as long as both
+        // are, the rows can be copied between the buffers directly.
+        if let (Some(src_buf), Some(dst_buf)) = (self.parent_buffer(), dst.parent_buffer()) {
+            self.copy_rows(
+                q,
+                ctx,
+                src_buf,
+                dst,
+                dst_buf,
+                &src_origin,
+                &dst_origin,
+                region,
+            )?;
+        } else if src_parent.is_buffer() || dst_parent.is_buffer() {
+            // We just want to use sw_copy if mem objects have different types or if copy can have
+            // custom strides (image2d from buff/images)
+            let bpp = self.image_format.pixel_size().unwrap();
+            let (tx_src, src_row_pitch, src_slice_pitch) =
+                self.tx_region(q, ctx, &src_origin, src_level, region, RWFlags::RD)?;
+            let (tx_dst, dst_row_pitch, dst_slice_pitch) =
+                dst.tx_region(q, ctx, &dst_origin, dst_level, region, RWFlags::WR)?;
 
             // Those pitch values cannot have 0 value in its coordinates
-            debug_assert!(src_pitch[0] != 0 && src_pitch[1] != 0 && src_pitch[2] != 0);
-            debug_assert!(dst_pitch[0] != 0 && dst_pitch[1] != 0 && dst_pitch[2] != 0);
+            debug_assert!(src_row_pitch != 0 && src_slice_pitch != 0);
+            debug_assert!(dst_row_pitch != 0 && dst_slice_pitch != 0);
 
             sw_copy(
+                q,
                 tx_src.ptr(),
                 tx_dst.ptr(),
                 region,
                 &CLVec::default(),
-                src_pitch[1],
-                src_pitch[2],
+                src_row_pitch,
+                src_slice_pitch,
                 &CLVec::default(),
-                dst_pitch[1],
-                dst_pitch[2],
-                bpp as u8,
+                dst_row_pitch,
+                dst_slice_pitch,
+                bpp,
             )
         } else {
             let bx = create_pipe_box(src_origin, *region, src_parent.mem_type)?;
@@ -1291,8 +2337,69 @@ impl Image {
                 (dst_origin[1], dst_origin[2]) = (dst_origin[2], dst_origin[1]);
             }
 
-            ctx.resource_copy_region(src_res, dst_res, &dst_origin, &bx);
+            ctx.texture_copy_region(src_res, src_level, dst_res, dst_level, &dst_origin, &bx);
+        }
+        Ok(())
+    }
+
+    /// Copies `region` between two images created from the buffers `src_buf` and `dst_buf` on the
+    /// GPU. Only the size of a texel matters for the copy, so it works for any two formats with
+    /// the same texel size.
+    fn copy_rows(
+        &self,
+        q: &Queue,
+        ctx: &PipeContext,
+        src_buf: &Buffer,
+        dst: &Image,
+        dst_buf: &Buffer,
+        src_origin: &CLVec<usize>,
+        dst_origin: &CLVec<usize>,
+        region: &CLVec<usize>,
+    ) -> CLResult<()> {
+        let bpp: usize = self.image_format.pixel_size().unwrap().into();
+        debug_assert_eq!(bpp, usize::from(dst.image_format.pixel_size().unwrap()));
+
+        let src_pitch = [
+            bpp,
+            self.image_desc.image_row_pitch,
+            self.image_desc.image_slice_pitch,
+        ];
+        let dst_pitch = [
+            bpp,
+            dst.image_desc.image_row_pitch,
+            dst.image_desc.image_slice_pitch,
+        ];
+        let src = src_buf.view(q.device, RWFlags::RD)?;
+        let dst = dst_buf.view(q.device, RWFlags::WR)?;
+
+        // rows which are contiguous in both images can be copied at once
+        let row_size = region[0] * bpp;
+        let (rows, copy_size) = if row_size == src_pitch[1] && row_size == dst_pitch[1] {
+            (1, row_size * region[1])
+        } else {
+            (region[1], row_size)
+        };
+
+        for z in 0..region[2] {
+            for y in 0..rows {
+                let src_offset = src.offset((*src_origin + [0, y, z]) * src_pitch, copy_size)?;
+                let dst_offset = dst.offset((*dst_origin + [0, y, z]) * dst_pitch, copy_size)?;
+
+                let bx = create_pipe_box(
+                    [src_offset, 0, 0].into(),
+                    [copy_size, 1, 1].into(),
+                    CL_MEM_OBJECT_BUFFER,
+                )?;
+                let dst_offset: [u32; 3] = [
+                    dst_offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                    0,
+                    0,
+                ];
+
+                ctx.resource_copy_region(src.res, dst.res, &dst_offset, &bx);
+            }
         }
+
         Ok(())
     }
 
@@ -1302,9 +2409,10 @@ impl Image {
         ctx: &PipeContext,
         pattern: &[u32],
         origin: &CLVec<usize>,
+        level: u32,
         region: &CLVec<usize>,
     ) -> CLResult<()> {
-        let res = self.get_res_of_dev(q.device)?;
+        let res = self.get_res_of_dev(q.device, RWFlags::WR)?;
 
         // make sure we allocate multiples of 4 bytes so drivers don't read out of bounds or
         // unaligned.
@@ -1331,66 +2439,120 @@ impl Image {
         }
 
         // If image is created from a buffer, use clear_image_buffer instead
-        if self.is_parent_buffer() {
+        if let Some(buffer) = self.parent_buffer() {
             let strides = (
                 self.image_desc.row_pitch()? as usize,
                 self.image_desc.slice_pitch(),
             );
-            ctx.clear_image_buffer(res, &new_pattern, origin, region, strides, pixel_size);
+            ctx.clear_image_buffer(
+                res,
+                &new_pattern,
+                buffer.res_offset(res),
+                origin,
+                region,
+                strides,
+                pixel_size,
+            );
         } else {
             let bx = create_pipe_box(*origin, *region, self.mem_type)?;
-            ctx.clear_texture(res, &new_pattern, &bx);
+            ctx.clear_texture(res, level, &new_pattern, &bx);
         }
 
         Ok(())
     }
 
+    /// Returns the buffer the image got created from, either directly or through another image.
+    fn parent_buffer(&self) -> Option<&Buffer> {
+        match &self.parent {
+            Some(Mem::Buffer(buffer)) => Some(buffer),
+            Some(Mem::Image(image)) => image.parent_buffer(),
+            None => None,
+        }
+    }
+
+    pub fn is_mapped_ptr(&self, ptr: *mut c_void) -> bool {
+        self.maps.lock().unwrap().contains_ptr(self, ptr)
+    }
+
     pub fn is_parent_buffer(&self) -> bool {
-        matches!(self.parent, Some(Mem::Buffer(_)))
+        self.parent_buffer().is_some()
+    }
+
+    /// Describes how kernels have to view the parent buffer's resource `res` for images created
+    /// from a buffer.
+    pub fn app_img_info(&self, res: &PipeResource) -> CLResult<Option<AppImgInfo>> {
+        let Some(buffer) = self.parent_buffer() else {
+            return Ok(None);
+        };
+
+        let offset = buffer.res_offset(res);
+        let info = if self.mem_type == CL_MEM_OBJECT_IMAGE1D_BUFFER {
+            AppImgInfo::new_buffer(
+                offset.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                self.size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+            )
+        } else {
+            let elem_size = self.image_elem_size as usize;
+            AppImgInfo::new_tex2d(
+                (offset / elem_size)
+                    .try_into()
+                    .map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                self.image_desc.row_pitch()? / elem_size as u32,
+                self.image_desc.width()?,
+                self.image_desc.height()?,
+            )
+        };
+
+        Ok(Some(info))
     }
 
     pub fn map(
         &self,
         dev: &'static Device,
         origin: &CLVec<usize>,
+        level: u32,
+        region: &CLVec<usize>,
         row_pitch: &mut usize,
         slice_pitch: &mut usize,
     ) -> CLResult<*mut c_void> {
         // we might have a host_ptr shadow buffer or image created from buffer
-        let ptr = if self.has_user_shadow_buffer(dev)? {
+        let (ptr, base) = if self.has_user_shadow_buffer(dev)? {
             *row_pitch = self.image_desc.image_row_pitch;
             *slice_pitch = self.image_desc.image_slice_pitch;
-            self.host_ptr()
-        } else if let Some(Mem::Buffer(buffer)) = &self.parent {
+            (self.host_ptr(), CLVec::default())
+        } else if let Some(buffer) = self.parent_buffer() {
             *row_pitch = self.image_desc.image_row_pitch;
             *slice_pitch = self.image_desc.image_slice_pitch;
-            buffer.map(dev, 0)?.as_ptr()
+            (buffer.map(dev, 0)?.as_ptr(), CLVec::default())
         } else {
             let mut lock = self.maps.lock().unwrap();
 
-            if let Entry::Vacant(e) = lock.tx.entry(dev) {
-                let bx = self.image_desc.bx()?;
-                let (tx, res) = self.tx_raw_async(dev, &bx, RWFlags::RW)?;
-                e.insert(MappingTransfer::new(tx, res));
-            } else {
-                lock.mark_pending(dev);
+            if lock.find_tx(dev, level, origin, region).is_none() {
+                let (tx, res) = self.tx_raw_async(dev, level, origin, region, RWFlags::RW)?;
+                lock.add_tx(
+                    &self.context,
+                    dev,
+                    MappingTransfer::new(tx, res, level, *origin, *region),
+                );
             }
 
-            let tx = &lock.tx.get(dev).unwrap().tx;
+            lock.mark_pending(dev);
+            let tx = lock.find_tx(dev, level, origin, region).unwrap();
 
             if self.image_desc.dims() > 1 {
-                *row_pitch = tx.row_pitch() as usize;
+                *row_pitch = tx.tx.row_pitch() as usize;
             }
             if self.image_desc.dims() > 2 || self.image_desc.is_array() {
-                *slice_pitch = tx.slice_pitch();
+                *slice_pitch = tx.tx.slice_pitch();
             }
 
-            tx.ptr()
+            // the transfer might cover a bigger region
+            (tx.tx.ptr(), tx.origin)
         };
 
         let ptr = unsafe {
             ptr.add(
-                *origin
+                (*origin - *base)
                     * [
                         self.image_format.pixel_size().unwrap().into(),
                         *row_pitch,
@@ -1422,34 +2584,18 @@ impl Image {
         ctx: &PipeContext,
         region: &CLVec<usize>,
         src_origin: &CLVec<usize>,
+        src_level: u32,
         dst_row_pitch: usize,
         dst_slice_pitch: usize,
     ) -> CLResult<()> {
         let dst = dst.as_ptr();
         let pixel_size = self.image_format.pixel_size().unwrap();
 
-        let tx;
-        let src_row_pitch;
-        let src_slice_pitch;
-        if let Some(Mem::Buffer(buffer)) = &self.parent {
-            src_row_pitch = self.image_desc.image_row_pitch;
-            src_slice_pitch = self.image_desc.image_slice_pitch;
-
-            let (offset, size) = CLVec::calc_offset_size(
-                src_origin,
-                region,
-                [pixel_size.into(), src_row_pitch, src_slice_pitch],
-            );
-
-            tx = buffer.tx(q, ctx, offset, size, RWFlags::RD)?;
-        } else {
-            let bx = create_pipe_box(*src_origin, *region, self.mem_type)?;
-            tx = self.tx_image(q, ctx, &bx, RWFlags::RD)?;
-            src_row_pitch = tx.row_pitch() as usize;
-            src_slice_pitch = tx.slice_pitch();
-        };
+        let (tx, src_row_pitch, src_slice_pitch) =
+            self.tx_region(q, ctx, src_origin, src_level, region, RWFlags::RD)?;
 
         sw_copy(
+            q,
             tx.ptr(),
             dst,
             region,
@@ -1465,60 +2611,230 @@ impl Image {
         Ok(())
     }
 
+    /// Syncs `region` at `origin` of the mip level `level` mapped through `ptr`, unless it's
+    /// already mapped.
     // TODO: only sync on map when the memory is not mapped with discard
-    pub fn sync_shadow(&self, q: &Queue, ctx: &PipeContext, ptr: MutMemoryPtr) -> CLResult<()> {
+    pub fn sync_shadow(
+        &self,
+        q: &Queue,
+        ctx: &PipeContext,
+        ptr: MutMemoryPtr,
+        origin: CLVec<usize>,
+        level: u32,
+        region: CLVec<usize>,
+    ) -> CLResult<()> {
         let ptr = ptr.as_ptr();
+        let range = MapRange::Image {
+            level: level,
+            origin: origin,
+            region: region,
+        };
+
         let mut lock = self.maps.lock().unwrap();
-        if !lock.increase_ref(q.device, ptr) {
+        if !lock.increase_ref(q.device, self, ptr, range) {
             return Ok(());
         }
 
         if self.has_user_shadow_buffer(q.device)? {
+            if self.host_is_valid() {
+                return Ok(());
+            }
+
             self.read(
                 // SAFETY: it's required that applications do not cause data races
-                unsafe { MutMemoryPtr::from_ptr(self.host_ptr()) },
+                unsafe { MutMemoryPtr::from_ptr(self.host_ptr_at(&origin)) },
                 q,
                 ctx,
-                &self.image_desc.size(),
-                &CLVec::default(),
+                &region,
+                &origin,
+                level,
                 self.image_desc.image_row_pitch,
                 self.image_desc.image_slice_pitch,
-            )
-        } else {
-            if let Some(shadow) = lock.tx.get(q.device).and_then(|tx| tx.shadow.as_ref()) {
-                let res = self.get_res_of_dev(q.device)?;
-                let bx = self.image_desc.bx()?;
-                ctx.resource_copy_region(res, shadow, &[0, 0, 0], &bx);
+            )?;
+            if region == self.image_desc.size() {
+                self.host_synced();
+            }
+        } else if let Some(tx) = lock.find_tx(q.device, level, &origin, &region) {
+            if let Some(shadow) = &tx.shadow {
+                let res = self.get_res_of_dev(q.device, RWFlags::RD)?;
+                let bx = create_pipe_box(origin, region, self.mem_type)?;
+                let dst = self.shadow_offset(tx, &origin)?;
+                ctx.texture_copy_region(res, level, shadow, 0, &dst, &bx);
             }
-            Ok(())
         }
+
+        Ok(())
+    }
+
+    /// Returns the address of the pixel at `origin` within the host pointer.
+    fn host_ptr_at(&self, origin: &CLVec<usize>) -> *mut c_void {
+        let pitches = [
+            self.image_format.pixel_size().unwrap().into(),
+            self.image_desc.image_row_pitch,
+            self.image_desc.image_slice_pitch,
+        ];
+        unsafe { self.host_ptr().add(*origin * pitches) }
+    }
+
+    /// Returns where the pixel at `origin` is located within the shadow of `tx`.
+    fn shadow_offset(&self, tx: &MappingTransfer, origin: &CLVec<usize>) -> CLResult<[u32; 3]> {
+        let bx = create_pipe_box(*origin - *tx.origin, tx.region, self.mem_type)?;
+        Ok([bx.x as u32, bx.y as u32, bx.z as u32])
+    }
+
+    /// Whether copies between buffers and the image should go through a linear staging texture.
+    /// Images which can be mapped directly don't gain anything from it, while images created from
+    /// buffers have custom pitches and are always accessed through the parent buffer.
+    fn use_staging_copy(&self, dev: &Device) -> CLResult<bool> {
+        if self.is_parent_buffer() {
+            return Ok(false);
+        }
+
+        Ok(!can_map_directly(dev, self.res_of_dev(dev)?))
+    }
+
+    /// Creates a linear staging texture on the GPU big enough to hold `bx`.
+    fn create_staging(&self, dev: &Device, bx: &pipe_box) -> CLResult<PipeResource> {
+        // array layers are stored in z, same as the slices of 3D images created as 2D arrays
+        let target = self.res_of_dev(dev)?.target();
+        let (depth, array_size) = if target == pipe_texture_target::PIPE_TEXTURE_1D_ARRAY
+            || target == pipe_texture_target::PIPE_TEXTURE_2D_ARRAY
+        {
+            (1, bx.depth)
+        } else {
+            (bx.depth, 1)
+        };
+
+        dev.screen()
+            .resource_create_texture(
+                bx.width.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                bx.height.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                depth.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                array_size.try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
+                0,
+                target,
+                self.pipe_format,
+                ResourceType::Staging,
+                false,
+            )
+            .ok_or_else(|| {
+                CLError::new(CL_OUT_OF_RESOURCES, "resource_create_texture")
+                    .object(self)
+                    .device(dev)
+                    .msg(format!(
+                        "failed to allocate a {}x{}x{} staging texture",
+                        bx.width, bx.height, bx.depth
+                    ))
+            })
+    }
+
+    /// Copies `bx` of the mip level `level` into a new linear staging texture on the GPU.
+    fn copy_to_staging(
+        &self,
+        q: &Queue,
+        ctx: &PipeContext,
+        level: u32,
+        bx: &pipe_box,
+    ) -> CLResult<PipeResource> {
+        let res = self.get_res_of_dev(q.device, RWFlags::RD)?;
+        let staging = self.create_staging(q.device, bx)?;
+        ctx.texture_copy_region(res, level, &staging, 0, &[0, 0, 0], bx);
+        Ok(staging)
+    }
+
+    /// Maps `region` at `origin` of the mip level `level` and returns the transfer together with its
+    /// row and slice pitch. Images created from a buffer are mapped through the parent buffer with
+    /// the pitches of the image. Reads of images which can't be mapped directly go through a
+    /// linear staging texture.
+    fn tx_region<'a>(
+        &self,
+        q: &Queue,
+        ctx: &'a PipeContext,
+        origin: &CLVec<usize>,
+        level: u32,
+        region: &CLVec<usize>,
+        rw: RWFlags,
+    ) -> CLResult<(ImageTransfer<'a>, usize, usize)> {
+        if let Some(buffer) = self.parent_buffer() {
+            let pixel_size = self.image_format.pixel_size().unwrap().into();
+            let row_pitch = self.image_desc.image_row_pitch;
+            let slice_pitch = self.image_desc.image_slice_pitch;
+            let (offset, size) =
+                CLVec::calc_offset_size(origin, region, [pixel_size, row_pitch, slice_pitch]);
+            let tx = ImageTransfer {
+                tx: buffer.tx(q, ctx, offset, size, rw)?,
+                _staging: None,
+            };
+
+            return Ok((tx, row_pitch, slice_pitch));
+        }
+
+        let (tx, staging) = if matches!(rw, RWFlags::RD) && self.use_staging_copy(q.device)? {
+            let bx = create_pipe_box(*origin, *region, self.mem_type)?;
+            let staging = self.copy_to_staging(q, ctx, level, &bx)?;
+            let bx = create_pipe_box(CLVec::default(), *region, self.mem_type)?;
+            let tx = ctx
+                .texture_map(&staging, 0, &bx, rw, ResourceMapType::Normal)
+                .ok_or_else(|| {
+                    CLError::new(CL_OUT_OF_RESOURCES, "texture_map")
+                        .object(self)
+                        .device(q.device)
+                        .msg("failed to map the staging copy")
+                })?
+                .with_ctx(ctx);
+
+            (tx, Some(staging))
+        } else {
+            let bx = create_pipe_box(*origin, *region, self.mem_type)?;
+            (self.tx_image(q, ctx, level, &bx, rw)?, None)
+        };
+
+        let row_pitch = tx.row_pitch() as usize;
+        let slice_pitch = tx.slice_pitch();
+        let tx = ImageTransfer {
+            tx: tx,
+            _staging: staging,
+        };
+
+        Ok((tx, row_pitch, slice_pitch))
     }
 
     fn tx_image<'a>(
         &self,
         q: &Queue,
         ctx: &'a PipeContext,
+        level: u32,
         bx: &pipe_box,
         rw: RWFlags,
     ) -> CLResult<GuardedPipeTransfer<'a>> {
-        let r = self.get_res_of_dev(q.device)?;
+        let r = self.get_res_of_dev(q.device, rw)?;
         Ok(ctx
-            .texture_map(r, bx, rw, ResourceMapType::Normal)
-            .ok_or(CL_OUT_OF_RESOURCES)?
+            .texture_map(r, level, bx, rw, ResourceMapType::Normal)
+            .ok_or_else(|| {
+                CLError::new(CL_OUT_OF_RESOURCES, "texture_map")
+                    .object(self)
+                    .device(q.device)
+                    .msg(format!("failed to map level {level}"))
+            })?
             .with_ctx(ctx))
     }
 
+    /// Maps `region` at `origin` of the mip level `level` of the resource of `dev`, through a
+    /// shadow resource holding just that region if it can't be mapped directly.
     fn tx_raw_async(
         &self,
         dev: &Device,
-        bx: &pipe_box,
+        level: u32,
+        origin: &CLVec<usize>,
+        region: &CLVec<usize>,
         rw: RWFlags,
     ) -> CLResult<(PipeTransfer, Option<PipeResource>)> {
-        let r = self.get_res_of_dev(dev)?;
+        let r = self.get_res_of_dev(dev, rw)?;
         let ctx = dev.helper_ctx();
+        let bx = create_pipe_box(*origin, *region, self.mem_type)?;
 
         let tx = if can_map_directly(dev, r) {
-            ctx.texture_map_directly(r, bx, rw)
+            ctx.texture_map_directly(r, level, &bx, rw)
         } else {
             None
         };
@@ -1526,22 +2842,16 @@ impl Image {
         if let Some(tx) = tx {
             Ok((tx, None))
         } else {
-            let shadow = dev
-                .screen()
-                .resource_create_texture(
-                    r.width(),
-                    r.height(),
-                    r.depth(),
-                    r.array_size(),
-                    cl_mem_type_to_texture_target(self.image_desc.image_type),
-                    self.pipe_format,
-                    ResourceType::Staging,
-                    false,
-                )
-                .ok_or(CL_OUT_OF_RESOURCES)?;
+            let shadow = self.create_staging(dev, &bx)?;
+            let bx = create_pipe_box(CLVec::default(), *region, self.mem_type)?;
             let tx = ctx
-                .texture_map_coherent(&shadow, bx, rw)
-                .ok_or(CL_OUT_OF_RESOURCES)?;
+                .texture_map_coherent(&shadow, 0, &bx, rw)
+                .ok_or_else(|| {
+                    CLError::new(CL_OUT_OF_RESOURCES, "texture_map_coherent")
+                        .object(self)
+                        .device(dev)
+                        .msg("failed to map the shadow of a mapping")
+                })?;
             Ok((tx, Some(shadow)))
         }
     }
@@ -1550,31 +2860,49 @@ impl Image {
     pub fn unmap(&self, q: &Queue, ctx: &PipeContext, ptr: MutMemoryPtr) -> CLResult<()> {
         let ptr = ptr.as_ptr();
         let mut lock = self.maps.lock().unwrap();
-        if !lock.contains_ptr(ptr) {
+        if !lock.contains_ptr(self, ptr) {
             return Ok(());
         }
 
-        let (needs_sync, shadow) = lock.decrease_ref(ptr, q.device);
-        if needs_sync {
-            if let Some(shadow) = shadow {
-                let res = self.get_res_of_dev(q.device)?;
-                let bx = self.image_desc.bx()?;
-                ctx.resource_copy_region(shadow, res, &[0, 0, 0], &bx);
-            } else if self.has_user_shadow_buffer(q.device)? {
+        if let Some(MapRange::Image {
+            level,
+            origin,
+            region,
+        }) = lock.decrease_ref(self, ptr)
+        {
+            if self.has_user_shadow_buffer(q.device)? {
                 self.write(
                     // SAFETY: it's required that applications do not cause data races
-                    unsafe { ConstMemoryPtr::from_ptr(self.host_ptr()) },
+                    unsafe { ConstMemoryPtr::from_ptr(self.host_ptr_at(&origin)) },
                     q,
                     ctx,
-                    &self.image_desc.size(),
+                    &region,
                     self.image_desc.image_row_pitch,
                     self.image_desc.image_slice_pitch,
-                    &CLVec::default(),
+                    &origin,
+                    level,
                 )?;
+                if region == self.image_desc.size() {
+                    self.host_synced();
+                }
+            } else if let Some(tx) = lock.find_tx(q.device, level, &origin, &region) {
+                if let Some(shadow) = &tx.shadow {
+                    let res = self.get_res_of_dev(q.device, RWFlags::WR)?;
+                    let bx = create_pipe_box(origin - *tx.origin, region, self.mem_type)?;
+                    let dst = create_pipe_box(origin, region, self.mem_type)?;
+                    ctx.texture_copy_region(
+                        shadow,
+                        0,
+                        res,
+                        level,
+                        &[dst.x as u32, dst.y as u32, dst.z as u32],
+                        &bx,
+                    );
+                }
             }
         }
 
-        lock.clean_up_tx(q.device, ctx);
+        lock.clean_up_tx(&self.context, q.device, ctx);
 
         Ok(())
     }
@@ -1588,21 +2916,17 @@ impl Image {
         src_row_pitch: usize,
         mut src_slice_pitch: usize,
         dst_origin: &CLVec<usize>,
+        dst_level: u32,
     ) -> CLResult<()> {
         let src = src.as_ptr();
-        let dst_row_pitch = self.image_desc.image_row_pitch;
-        let dst_slice_pitch = self.image_desc.image_slice_pitch;
 
-        if let Some(Mem::Buffer(buffer)) = &self.parent {
+        if self.is_parent_buffer() {
             let pixel_size = self.image_format.pixel_size().unwrap();
-            let (offset, size) = CLVec::calc_offset_size(
-                dst_origin,
-                region,
-                [pixel_size.into(), dst_row_pitch, dst_slice_pitch],
-            );
-            let tx = buffer.tx(q, ctx, offset, size, RWFlags::WR)?;
+            let (tx, dst_row_pitch, dst_slice_pitch) =
+                self.tx_region(q, ctx, dst_origin, dst_level, region, RWFlags::WR)?;
 
             sw_copy(
+                q,
                 src,
                 tx.ptr(),
                 region,
@@ -1615,7 +2939,7 @@ impl Image {
                 pixel_size,
             );
         } else {
-            let res = self.get_res_of_dev(q.device)?;
+            let res = self.get_res_of_dev(q.device, RWFlags::WR)?;
             let bx = create_pipe_box(*dst_origin, *region, self.mem_type)?;
 
             if self.mem_type == CL_MEM_OBJECT_IMAGE1D_ARRAY {
@@ -1624,6 +2948,7 @@ impl Image {
 
             ctx.texture_subdata(
                 res,
+                dst_level,
                 &bx,
                 src,
                 src_row_pitch
@@ -1642,6 +2967,9 @@ pub struct Sampler {
     pub normalized_coords: bool,
     pub addressing_mode: cl_addressing_mode,
     pub filter_mode: cl_filter_mode,
+    pub mip_filter_mode: cl_filter_mode,
+    pub lod_min: f32,
+    pub lod_max: f32,
     pub props: Option<Properties<cl_sampler_properties>>,
 }
 
@@ -1653,6 +2981,9 @@ impl Sampler {
         normalized_coords: bool,
         addressing_mode: cl_addressing_mode,
         filter_mode: cl_filter_mode,
+        mip_filter_mode: cl_filter_mode,
+        lod_min: f32,
+        lod_max: f32,
         props: Option<Properties<cl_sampler_properties>>,
     ) -> Arc<Sampler> {
         Arc::new(Self {
@@ -1661,6 +2992,9 @@ impl Sampler {
             normalized_coords: normalized_coords,
             addressing_mode: addressing_mode,
             filter_mode: filter_mode,
+            mip_filter_mode: mip_filter_mode,
+            lod_min: lod_min,
+            lod_max: lod_max,
             props: props,
         })
     }
@@ -1723,14 +3057,31 @@ impl Sampler {
         res.set_wrap_s(wrap);
         res.set_wrap_t(wrap);
 
+        // samplers declared in kernels select the nearest mip level without any LOD clamping
+        res.set_min_mip_filter(pipe_tex_mipfilter::PIPE_TEX_MIPFILTER_NEAREST);
+        res.min_lod = 0.0;
+        res.max_lod = PIPE_MAX_TEXTURE_LEVELS as f32;
+
         res
     }
 
     pub fn pipe(&self) -> pipe_sampler_state {
-        Self::cl_to_pipe((
+        let mut res = Self::cl_to_pipe((
             self.addressing_mode,
             self.filter_mode,
             self.normalized_coords,
-        ))
+        ));
+
+        let mip_filter = match self.mip_filter_mode {
+            CL_FILTER_NEAREST => pipe_tex_mipfilter::PIPE_TEX_MIPFILTER_NEAREST,
+            CL_FILTER_LINEAR => pipe_tex_mipfilter::PIPE_TEX_MIPFILTER_LINEAR,
+            _ => panic!("unknown mip_filter_mode"),
+        };
+
+        res.set_min_mip_filter(mip_filter);
+        res.min_lod = self.lod_min;
+        res.max_lod = self.lod_max.min(PIPE_MAX_TEXTURE_LEVELS as f32);
+
+        res
     }
 }
@@ -3,10 +3,14 @@ use crate::api::icd::DISPATCH;
 use crate::core::device::*;
 use crate::core::version::*;
 
+use mesa_rust::util::perfetto;
 use mesa_rust_gen::*;
+use mesa_rust_util::log_warn;
+use mesa_rust_util::string::c_string_to_string;
 use rusticl_opencl_gen::*;
 
 use std::env;
+use std::ptr;
 use std::ptr::addr_of;
 use std::ptr::addr_of_mut;
 use std::sync::Once;
@@ -19,13 +23,29 @@ pub struct Platform {
 
 pub struct PlatformDebug {
     pub allow_invalid_spirv: bool,
-    pub clc: bool,
-    pub program: bool,
+    /// Places a guard area behind buffers and checks it for out of bounds writes after every
+    /// kernel launch.
+    pub bounds: bool,
+    pub leaks: bool,
+    pub memory: bool,
+    /// Disables the disk caches for compiled programs and kernels.
+    pub no_cache: bool,
     pub max_grid_size: u64,
+    /// Logs the runtime counters of the devices of a context once it gets released.
+    pub stats: bool,
     pub sync_every_event: bool,
+    pub strict: bool,
+    pub trace: bool,
     pub validate_spirv: bool,
 }
 
+/// Per application configuration through driconf.
+pub struct PlatformConfig {
+    pub disable_extensions: Vec<String>,
+    pub force_in_order_queue: bool,
+    pub serialize_maps: bool,
+}
+
 pub struct PlatformFeatures {
     pub fp16: bool,
     pub fp64: bool,
@@ -65,12 +85,22 @@ static mut PLATFORM: Platform = Platform {
 };
 static mut PLATFORM_DBG: PlatformDebug = PlatformDebug {
     allow_invalid_spirv: false,
-    clc: false,
-    program: false,
+    bounds: false,
+    leaks: false,
+    memory: false,
+    no_cache: false,
     max_grid_size: 0,
+    stats: false,
     sync_every_event: false,
+    strict: false,
+    trace: false,
     validate_spirv: false,
 };
+static mut PLATFORM_CONF: PlatformConfig = PlatformConfig {
+    disable_extensions: Vec::new(),
+    force_in_order_queue: false,
+    serialize_maps: false,
+};
 static mut PLATFORM_FEATURES: PlatformFeatures = PlatformFeatures {
     fp16: false,
     fp64: false,
@@ -83,12 +113,18 @@ fn load_env() {
         for flag in debug_flags.split(',') {
             match flag {
                 "allow_invalid_spirv" => debug.allow_invalid_spirv = true,
-                "clc" => debug.clc = true,
-                "program" => debug.program = true,
+                "bounds" => debug.bounds = true,
+                "leaks" => debug.leaks = true,
+                "memory" => debug.memory = true,
+                "nocache" => debug.no_cache = true,
+                "stats" => debug.stats = true,
                 "sync" => debug.sync_every_event = true,
+                "trace" => debug.trace = true,
                 "validate" => debug.validate_spirv = true,
+                // aliases for log levels, handled by the logger
+                "clc" | "nir" | "program" => (),
                 "" => (),
-                _ => eprintln!("Unknown RUSTICL_DEBUG flag found: {}", flag),
+                _ => log_warn!("Unknown RUSTICL_DEBUG flag found: {}", flag),
             }
         }
     }
@@ -98,6 +134,8 @@ fn load_env() {
         .and_then(|s| s.parse().ok())
         .unwrap_or(u64::MAX);
 
+    debug.strict = env::var("RUSTICL_STRICT").is_ok_and(|val| !val.is_empty() && val != "0");
+
     // SAFETY: no other references exist at this point
     let features = unsafe { &mut *addr_of_mut!(PLATFORM_FEATURES) };
     if let Ok(feature_flags) = env::var("RUSTICL_FEATURES") {
@@ -106,12 +144,40 @@ fn load_env() {
                 "fp16" => features.fp16 = true,
                 "fp64" => features.fp64 = true,
                 "" => (),
-                _ => eprintln!("Unknown RUSTICL_FEATURES flag found: {}", flag),
+                _ => log_warn!("Unknown RUSTICL_FEATURES flag found: {}", flag),
             }
         }
     }
 }
 
+fn load_driconf() {
+    let mut driconf = rusticl_driconf {
+        force_in_order_queue: false,
+        serialize_maps: false,
+        disable_extensions: ptr::null_mut(),
+    };
+
+    // SAFETY: no other references exist at this point
+    let conf = unsafe { &mut *addr_of_mut!(PLATFORM_CONF) };
+    unsafe {
+        rusticl_driconf_load(&mut driconf);
+        conf.disable_extensions = c_string_to_string(driconf.disable_extensions)
+            .split_whitespace()
+            .map(String::from)
+            .collect();
+        rusticl_driconf_fini(&mut driconf);
+    }
+
+    conf.force_in_order_queue = driconf.force_in_order_queue;
+    conf.serialize_maps = driconf.serialize_maps;
+}
+
+impl PlatformConfig {
+    pub fn is_extension_disabled(&self, ext: &str) -> bool {
+        self.disable_extensions.iter().any(|e| e == ext)
+    }
+}
+
 impl Platform {
     pub fn as_ptr(&self) -> cl_platform_id {
         (self as *const Self) as cl_platform_id
@@ -128,6 +194,11 @@ impl Platform {
         unsafe { &*addr_of!(PLATFORM_DBG) }
     }
 
+    pub fn conf() -> &'static PlatformConfig {
+        debug_assert!(PLATFORM_ENV_ONCE.is_completed());
+        unsafe { &*addr_of!(PLATFORM_CONF) }
+    }
+
     pub fn features() -> &'static PlatformFeatures {
         debug_assert!(PLATFORM_ENV_ONCE.is_completed());
         unsafe { &*addr_of!(PLATFORM_FEATURES) }
@@ -138,11 +209,21 @@ impl Platform {
             glsl_type_singleton_init_or_ref();
         }
 
+        perfetto::init();
         self.devs = Device::all().collect();
     }
 
+    /// Only parses the environment, but doesn't initialize any devices. Used by code paths which
+    /// might get called before the platform got initialized, e.g. API tracing.
+    pub fn init_env_once() {
+        PLATFORM_ENV_ONCE.call_once(|| {
+            load_env();
+            load_driconf();
+        });
+    }
+
     pub fn init_once() {
-        PLATFORM_ENV_ONCE.call_once(load_env);
+        Self::init_env_once();
         // SAFETY: no concurrent static mut access due to std::Once
         PLATFORM_ONCE.call_once(|| unsafe { PLATFORM.init() });
     }
@@ -165,7 +246,7 @@ impl GetPlatformRef for cl_platform_id {
         if !self.is_null() && *self == Platform::get().as_ptr() {
             Ok(Platform::get())
         } else {
-            Err(CL_INVALID_PLATFORM)
+            Err(CL_INVALID_PLATFORM.into())
         }
     }
 }
@@ -10,13 +10,18 @@ use mesa_rust::compiler::clc::*;
 use mesa_rust::compiler::nir::*;
 use mesa_rust::pipe::resource::*;
 use mesa_rust::pipe::screen::ResourceType;
+use mesa_rust::pipe::screen::UUID_SIZE;
 use mesa_rust::util::disk_cache::*;
 use mesa_rust_gen::*;
+use mesa_rust_util::log::LogLevel;
+use mesa_rust_util::log_debug;
+use mesa_rust_util::log_enabled;
 use rusticl_llvm_gen::*;
 use rusticl_opencl_gen::*;
 
 use std::collections::HashMap;
 use std::collections::HashSet;
+use std::ffi::CStr;
 use std::ffi::CString;
 use std::mem::size_of;
 use std::ptr;
@@ -27,21 +32,129 @@ use std::sync::Mutex;
 use std::sync::MutexGuard;
 use std::sync::Once;
 
-const BIN_HEADER_SIZE_V1: usize =
-    // 1. format version
-    size_of::<u32>() +
-    // 2. spirv len
-    size_of::<u32>() +
-    // 3. binary_type
-    size_of::<cl_program_binary_type>();
+/// Version of the program binary container. Binaries of unknown versions are rejected with
+/// CL_INVALID_BINARY, which makes applications fall back to compiling from source.
+const BIN_VERSION: u32 = 2;
+
+/// The only kind of IR stored in program binaries so far. As it's independent of the driver, it
+/// gets compiled for the device whenever a binary is loaded.
+const BIN_IR_SPIRV: u32 = 1;
+
+const SPIRV_MAGIC: u32 = 0x07230203;
+
+// OpenCL C compilation inputs are logged separately from the build logs of programs
+const CLC_LOG_TARGET: &str = "rusticl::clc";
+
+/// Creates the header of a program binary. All fields are stored little endian:
+///
+/// 1. format version
+/// 2. size of the header
+/// 3. kind of IR
+/// 4. binary type
+/// 5. size of the IR
+/// 6. vendor id of the device
+/// 7. UUID of the device, all zero if unknown
+/// 8. size and string of the mesa version the binary was created with
+///
+/// Readers skip fields added to the end of the header they don't know about.
+fn bin_header(dev: &Device, bin_type: cl_program_binary_type, ir_size: usize) -> Vec<u8> {
+    // SAFETY: mesa_version_string returns a static string.
+    let version = unsafe { CStr::from_ptr(mesa_version_string()) }.to_bytes();
+
+    let mut header = Vec::new();
+    header.extend_from_slice(&BIN_VERSION.to_le_bytes());
+    // patched once the header is complete
+    header.extend_from_slice(&0_u32.to_le_bytes());
+    header.extend_from_slice(&BIN_IR_SPIRV.to_le_bytes());
+    header.extend_from_slice(&bin_type.to_le_bytes());
+    header.extend_from_slice(&(ir_size as u32).to_le_bytes());
+    header.extend_from_slice(&dev.vendor_id().to_le_bytes());
+    header.extend_from_slice(&dev.screen().device_uuid().unwrap_or_default());
+    header.extend_from_slice(&(version.len() as u32).to_le_bytes());
+    header.extend_from_slice(version);
+
+    let size = header.len() as u32;
+    header[size_of::<u32>()..2 * size_of::<u32>()].copy_from_slice(&size.to_le_bytes());
+    header
+}
+
+fn split_off<'a>(bin: &mut &'a [u8], len: usize) -> Option<&'a [u8]> {
+    if bin.len() < len {
+        return None;
+    }
+
+    let res;
+    (res, *bin) = (*bin).split_at(len);
+    Some(res)
+}
+
+fn split_u32(bin: &mut &[u8]) -> Option<[u8; 4]> {
+    split_off(bin, size_of::<u32>())?.try_into().ok()
+}
+
+/// Validates a binary returned through CL_PROGRAM_BINARIES and returns its binary type and
+/// SPIR-V if it can be used for `dev`.
+pub fn parse_bin<'a>(
+    dev: &Device,
+    mut bin: &'a [u8],
+) -> Option<(cl_program_binary_type, &'a [u8])> {
+    let version = split_u32(&mut bin)?;
+    let (bin_type, spirv) = if u32::from_le_bytes(version) == BIN_VERSION {
+        let header_size = u32::from_le_bytes(split_u32(&mut bin)?) as usize;
+        let mut header = split_off(&mut bin, header_size.checked_sub(2 * size_of::<u32>())?)?;
+
+        let ir_kind = u32::from_le_bytes(split_u32(&mut header)?);
+        let bin_type = u32::from_le_bytes(split_u32(&mut header)?);
+        let ir_size = u32::from_le_bytes(split_u32(&mut header)?) as usize;
+        let vendor_id = u32::from_le_bytes(split_u32(&mut header)?);
+        let uuid = split_off(&mut header, UUID_SIZE)?;
+        // The mesa version is only informational, because the IR gets compiled on every load.
+
+        if ir_kind != BIN_IR_SPIRV || vendor_id != dev.vendor_id() {
+            return None;
+        }
+
+        // only compare UUIDs if both are known
+        let dev_uuid = dev.screen().device_uuid().unwrap_or_default();
+        let unknown = [0; UUID_SIZE];
+        if uuid != unknown && dev_uuid != unknown && uuid != dev_uuid {
+            return None;
+        }
 
-const BIN_HEADER_SIZE: usize = BIN_HEADER_SIZE_V1;
+        (bin_type, split_off(&mut bin, ir_size)?)
+    } else if u32::from_ne_bytes(version) == 1 {
+        // the first version used native endianess and had no information about the device
+        let spirv_size = u32::from_ne_bytes(split_u32(&mut bin)?) as usize;
+        let bin_type = u32::from_ne_bytes(split_u32(&mut bin)?);
+        (bin_type, split_off(&mut bin, spirv_size)?)
+    } else {
+        return None;
+    };
+
+    let valid_bin_type = [
+        CL_PROGRAM_BINARY_TYPE_COMPILED_OBJECT,
+        CL_PROGRAM_BINARY_TYPE_LIBRARY,
+        CL_PROGRAM_BINARY_TYPE_EXECUTABLE,
+    ]
+    .contains(&bin_type);
+
+    let valid_spirv = spirv.len() % size_of::<u32>() == 0
+        && spirv
+            .get(..size_of::<u32>())
+            .is_some_and(|magic| magic == SPIRV_MAGIC.to_ne_bytes());
+
+    (bin.is_empty() && valid_bin_type && valid_spirv).then_some((bin_type, spirv))
+}
 
 // kernel cache
 static mut DISK_CACHE: Option<DiskCache> = None;
 static DISK_CACHE_ONCE: Once = Once::new();
 
 fn get_disk_cache() -> &'static Option<DiskCache> {
+    if Platform::dbg().no_cache {
+        return &None;
+    }
+
     let func_ptrs = [
         // ourselves
         get_disk_cache as _,
@@ -213,6 +326,18 @@ impl ProgramBuild {
         self.builds.get(dev).unwrap()
     }
 
+    /// Whether there is a compiled object or library for `dev` to link.
+    fn is_linkable(&self, dev: &Device) -> bool {
+        self.builds.get(dev).is_some_and(|b| {
+            b.status == CL_BUILD_SUCCESS as cl_build_status
+                && [
+                    CL_PROGRAM_BINARY_TYPE_COMPILED_OBJECT,
+                    CL_PROGRAM_BINARY_TYPE_LIBRARY,
+                ]
+                .contains(&b.bin_type)
+        })
+    }
+
     fn dev_build_mut(&mut self, dev: &Device) -> &mut ProgramDevBuild {
         self.builds.get_mut(dev).unwrap()
     }
@@ -226,13 +351,23 @@ impl ProgramBuild {
     }
 
     pub fn hash_key(&self, dev: &Device, name: &str) -> Option<cache_key> {
-        if let Some(cache) = dev.screen().shader_cache() {
+        if let Some(cache) = dev.kernel_cache() {
             let info = self.dev_build(dev);
             assert_eq!(info.status, CL_BUILD_SUCCESS as cl_build_status);
 
             let spirv = info.spirv.as_ref().unwrap();
             let mut bin = spirv.to_bin().to_vec();
             bin.extend_from_slice(name.as_bytes());
+            bin.push(0);
+
+            // The cache is per driver, but multiple devices of the same driver might share it.
+            bin.extend_from_slice(&dev.screen().device_uuid().unwrap_or_default());
+            bin.extend_from_slice(dev.screen().name().as_bytes());
+            bin.push(0);
+
+            // build options are only part of the SPIR-V if it got compiled from source
+            bin.extend_from_slice(info.options.as_bytes());
+            bin.push(0);
 
             for (k, v) in &self.spec_constants {
                 bin.extend_from_slice(&k.to_ne_bytes());
@@ -266,7 +401,7 @@ impl ProgramBuild {
         let info = self.dev_build(d);
         assert_eq!(info.status, CL_BUILD_SUCCESS as cl_build_status);
 
-        let mut log = Platform::dbg().program.then(Vec::new);
+        let mut log = log_enabled!(LogLevel::Debug).then(Vec::new);
         let nir = info.spirv.as_ref().unwrap().to_nir(
             kernel,
             d.screen
@@ -279,7 +414,7 @@ impl ProgramBuild {
 
         if let Some(log) = log {
             for line in log {
-                eprintln!("{}", line);
+                log_debug!("{}", line);
             }
         };
 
@@ -378,56 +513,23 @@ impl Program {
         })
     }
 
+    /// Creates a program from binaries already validated through [parse_bin].
     pub fn from_bins(
         context: Arc<Context>,
         devs: Vec<&'static Device>,
-        bins: &[&[u8]],
+        bins: &[(cl_program_binary_type, &[u8])],
     ) -> Arc<Program> {
         let mut builds = HashMap::new();
         let mut kernels = HashSet::new();
 
-        for (&d, b) in devs.iter().zip(bins) {
-            let mut ptr = b.as_ptr();
-            let bin_type;
-            let spirv;
-
-            unsafe {
-                // 1. version
-                let version = ptr.cast::<u32>().read();
-                ptr = ptr.add(size_of::<u32>());
-
-                match version {
-                    1 => {
-                        // 2. size of the spirv
-                        let spirv_size = ptr.cast::<u32>().read();
-                        ptr = ptr.add(size_of::<u32>());
-
-                        // 3. binary_type
-                        bin_type = ptr.cast::<cl_program_binary_type>().read();
-                        ptr = ptr.add(size_of::<cl_program_binary_type>());
-
-                        // 4. the spirv
-                        assert!(b.as_ptr().add(BIN_HEADER_SIZE_V1) == ptr);
-                        assert!(b.len() == BIN_HEADER_SIZE_V1 + spirv_size as usize);
-                        spirv = Some(spirv::SPIRVBin::from_bin(slice::from_raw_parts(
-                            ptr,
-                            spirv_size as usize,
-                        )));
-                    }
-                    _ => panic!("unknown version"),
-                }
-            }
-
-            if let Some(spirv) = &spirv {
-                for k in spirv.kernels() {
-                    kernels.insert(k);
-                }
-            }
+        for (&d, &(bin_type, spirv)) in devs.iter().zip(bins) {
+            let spirv = SPIRVBin::from_bin(spirv);
+            kernels.extend(spirv.kernels());
 
             builds.insert(
                 d,
                 ProgramDevBuild {
-                    spirv: spirv,
+                    spirv: Some(spirv),
                     status: CL_BUILD_SUCCESS as cl_build_status,
                     log: String::from(""),
                     options: String::from(""),
@@ -482,6 +584,10 @@ impl Program {
         self.build_info().dev_build(dev).log.clone()
     }
 
+    pub fn is_linkable(&self, dev: &Device) -> bool {
+        self.build_info().is_linkable(dev)
+    }
+
     pub fn bin_type(&self, dev: &Device) -> cl_program_binary_type {
         self.build_info().dev_build(dev).bin_type
     }
@@ -497,11 +603,10 @@ impl Program {
         for d in &self.devs {
             let info = lock.dev_build(d);
 
-            res.push(
-                info.spirv
-                    .as_ref()
-                    .map_or(0, |s| s.to_bin().len() + BIN_HEADER_SIZE),
-            );
+            res.push(info.spirv.as_ref().map_or(0, |s| {
+                let ir_size = s.to_bin().len();
+                bin_header(d, info.bin_type, ir_size).len() + ir_size
+            }));
         }
         res
     }
@@ -532,21 +637,13 @@ impl Program {
             };
             let spirv = spirv.to_bin();
 
-            unsafe {
-                // 1. binary format version
-                ptr.cast::<u32>().write(1);
-                ptr = ptr.add(size_of::<u32>());
-
-                // 2. size of the spirv
-                ptr.cast::<u32>().write(spirv.len() as u32);
-                ptr = ptr.add(size_of::<u32>());
-
-                // 3. binary_type
-                ptr.cast::<cl_program_binary_type>().write(info.bin_type);
-                ptr = ptr.add(size_of::<cl_program_binary_type>());
+            let header = bin_header(d, info.bin_type, spirv.len());
 
-                // 4. the spirv
-                assert!(ptrs[i].add(BIN_HEADER_SIZE) == ptr);
+            // SAFETY: the application has to provide enough memory as returned through
+            //         CL_PROGRAM_BINARY_SIZES.
+            unsafe {
+                ptr::copy_nonoverlapping(header.as_ptr(), ptr, header.len());
+                ptr = ptr.add(header.len());
                 ptr::copy_nonoverlapping(spirv.as_ptr(), ptr, spirv.len());
             }
         }
@@ -629,14 +726,14 @@ impl Program {
             ProgramSourceType::Src(src) => {
                 let args = prepare_options(&options, dev);
 
-                if Platform::dbg().clc {
+                if log_enabled!(target: CLC_LOG_TARGET, LogLevel::Debug) {
                     let src = src.to_string_lossy();
-                    eprintln!("dumping compilation inputs:");
-                    eprintln!("compilation arguments: {args:?}");
+                    log_debug!(target: CLC_LOG_TARGET, "dumping compilation inputs:");
+                    log_debug!(target: CLC_LOG_TARGET, "compilation arguments: {args:?}");
                     if !headers.is_empty() {
-                        eprintln!("headers: {headers:#?}");
+                        log_debug!(target: CLC_LOG_TARGET, "headers: {headers:#?}");
                     }
-                    eprintln!("source code:\n{src}");
+                    log_debug!(target: CLC_LOG_TARGET, "source code:\n{src}");
                 }
 
                 let (spirv, msgs) = spirv::SPIRVBin::from_clc(
@@ -684,6 +781,9 @@ impl Program {
         self.do_compile(dev, options, headers, &mut self.build_info())
     }
 
+    /// Links `progs` for all devices in `devs` every program has a compiled object or library
+    /// for. Unresolved symbols of one program get resolved against the others. No executable gets
+    /// created for the remaining devices.
     pub fn link(
         context: Arc<Context>,
         devs: &[&'static Device],
@@ -696,6 +796,11 @@ impl Program {
         let lib = options.contains("-create-library");
 
         for &d in devs {
+            if !locks.iter().all(|l| l.is_linkable(d)) {
+                builds.extend(Self::create_default_builds(&[d]));
+                continue;
+            }
+
             let bins: Vec<_> = locks
                 .iter_mut()
                 .map(|l| l.dev_build(d).spirv.as_ref().unwrap())
@@ -737,7 +842,7 @@ impl Program {
                     spirv: spirv,
                     status: status,
                     log: log,
-                    options: String::from(""),
+                    options: options.clone(),
                     bin_type: bin_type,
                     kernels: HashMap::new(),
                 },
@@ -2,32 +2,111 @@ use crate::api::icd::*;
 use crate::core::context::*;
 use crate::core::device::*;
 use crate::core::event::*;
+use crate::core::memory::MutMemoryPtr;
 use crate::core::platform::*;
 use crate::impl_cl_type_trait;
 
-use mesa_rust::pipe::context::PipeContext;
+use mesa_rust::pipe::context::*;
+use mesa_rust::pipe::resource::PipeResource;
+use mesa_rust::pipe::screen::ResourceType;
+use mesa_rust::pipe::transfer::PipeTransfer;
+use mesa_rust::util::perfetto;
+use mesa_rust::util::perfetto::PerfettoTrack;
+use mesa_rust_gen::os_time_get_nano;
+use mesa_rust_gen::pipe_box;
+use mesa_rust_util::math::align;
 use mesa_rust_util::properties::*;
+use mesa_rust_util::thread_pool::ThreadPool;
 use rusticl_opencl_gen::*;
 
+use std::cmp;
+use std::ffi::CStr;
+use std::ffi::CString;
 use std::mem;
 use std::ops::Deref;
+use std::os::raw::c_void;
+use std::ptr;
+use std::slice;
 use std::sync::mpsc;
 use std::sync::Arc;
 use std::sync::Mutex;
+use std::sync::OnceLock;
 use std::sync::Weak;
 use std::thread;
 use std::thread::JoinHandle;
 
+/// Writes and fills smaller than this get batched.
+const BATCH_MAX_SIZE: usize = 64 * 1024;
+
+/// A buffer update not submitted to the driver yet, so following updates of the range right
+/// behind it can be merged into it.
+enum PendingUpdate {
+    Write {
+        res: Arc<PipeResource>,
+        offset: u32,
+        data: Vec<u8>,
+    },
+    Fill {
+        res: Arc<PipeResource>,
+        offset: u32,
+        size: u32,
+        pattern: Vec<u8>,
+    },
+}
+
+impl PendingUpdate {
+    fn submit(self, ctx: &PipeContext) {
+        match self {
+            Self::Write { res, offset, data } => {
+                // the merged size is bounded by BATCH_MAX_SIZE
+                ctx.buffer_subdata(&res, offset, data.as_ptr().cast(), data.len() as u32);
+            }
+            Self::Fill {
+                res,
+                offset,
+                size,
+                pattern,
+            } => {
+                ctx.clear_buffer(&res, &pattern, offset, size);
+            }
+        }
+    }
+}
+
+/// Size of the staging ring non-blocking buffer reads get copied through.
+const READBACK_RING_SIZE: u32 = 4 * 1024 * 1024;
+
+/// A copy into the readback ring, which still has to be copied to `dst` once the GPU is done.
+struct PendingReadback {
+    offset: u32,
+    dst: MutMemoryPtr,
+    size: u32,
+}
+
+/// Persistently mapped staging memory non-blocking buffer reads are copied into on the GPU.
+/// Space is handed out in order and reclaimed once all readbacks got copied out after a flush.
+struct ReadbackRing {
+    res: PipeResource,
+    tx: PipeTransfer,
+    head: u32,
+    pending: Vec<PendingReadback>,
+}
+
 /// State tracking wrapper for [PipeContext]
 ///
 /// Used for tracking bound GPU state to lower CPU overhead and centralize state tracking
 pub struct QueueContext {
     ctx: PipeContext,
+    device: &'static Device,
     use_stream: bool,
+    /// Gets submitted before anything else uses `ctx`.
+    pending: Mutex<Option<PendingUpdate>>,
+    /// Only allocated on the first non-blocking read.
+    readbacks: Mutex<Option<ReadbackRing>>,
 }
 
 impl QueueContext {
-    fn new_for(device: &Device) -> CLResult<Self> {
+    fn new_for(device: &'static Device) -> CLResult<Self> {
         let ctx = device
             .screen()
             .create_context()
@@ -35,10 +114,216 @@ impl QueueContext {
 
         Ok(Self {
             ctx: ctx,
+            device: device,
             use_stream: device.prefers_real_buffer_in_cb0(),
+            pending: Mutex::new(None),
+            readbacks: Mutex::new(None),
         })
     }
 
+    fn create_readback_ring(&self) -> Option<ReadbackRing> {
+        let res = self.device.screen().resource_create_buffer(
+            READBACK_RING_SIZE,
+            ResourceType::Staging,
+            0,
+        )?;
+        let tx = self.ctx.buffer_map(
+            &res,
+            0,
+            READBACK_RING_SIZE as i32,
+            RWFlags::RD,
+            ResourceMapType::Coherent,
+        )?;
+
+        Some(ReadbackRing {
+            res: res,
+            tx: tx,
+            head: 0,
+            pending: Vec::new(),
+        })
+    }
+
+    /// Copies `size` bytes at `offset` of `res` into the readback ring on the GPU. The data gets
+    /// copied to `dst` once the GPU finished, before the events of the flush get signaled and
+    /// before any following command gets processed.
+    ///
+    /// Returns `false` without submitting anything if the ring is out of space, in which case
+    /// the caller has to read back synchronously. This only stalls the queue thread and not the
+    /// application, but reads larger than [READBACK_RING_SIZE] always take that path.
+    pub fn read_buffer_async(
+        &self,
+        res: &PipeResource,
+        offset: u32,
+        dst: MutMemoryPtr,
+        size: u32,
+    ) -> bool {
+        let mut readbacks = self.readbacks.lock().unwrap();
+        if readbacks.is_none() {
+            *readbacks = self.create_readback_ring();
+        }
+
+        let Some(ring) = readbacks.as_mut() else {
+            return false;
+        };
+
+        // keep copies cache line aligned
+        let start = align(ring.head, 64);
+        if start
+            .checked_add(size)
+            .map_or(true, |end| end > READBACK_RING_SIZE)
+        {
+            return false;
+        }
+
+        let bx = pipe_box {
+            x: offset as i32,
+            width: size as i32,
+            height: 1,
+            depth: 1,
+            ..Default::default()
+        };
+
+        // submit pending updates first, they might write to the range we read from
+        self.submit_pending();
+        self.ctx
+            .resource_copy_region(res, &ring.res, &[start, 0, 0], &bx);
+
+        ring.head = start + size;
+        ring.pending.push(PendingReadback {
+            offset: start,
+            dst: dst,
+            size: size,
+        });
+
+        true
+    }
+
+    /// Whether `access` touches the destination of any readback still in the ring.
+    fn readbacks_overlap(&self, access: HostAccess) -> bool {
+        self.readbacks.lock().unwrap().as_ref().is_some_and(|ring| {
+            ring.pending.iter().any(|readback| {
+                access.overlaps(readback.dst.as_ptr() as usize, readback.size as usize)
+            })
+        })
+    }
+
+    /// Copies all readbacks to their destination. Must only be called once all work submitted
+    /// so far finished on the GPU.
+    fn finish_readbacks(&self) {
+        let mut readbacks = self.readbacks.lock().unwrap();
+        let Some(ring) = readbacks.as_mut() else {
+            return;
+        };
+
+        for readback in ring.pending.drain(..) {
+            // SAFETY: the ring is mapped persistently and `readback` is within it. It's required
+            //         that applications don't access `dst` until the read completes.
+            unsafe {
+                ptr::copy_nonoverlapping(
+                    ring.tx.ptr().cast::<u8>().add(readback.offset as usize),
+                    readback.dst.as_ptr().cast(),
+                    readback.size as usize,
+                );
+            }
+        }
+
+        ring.head = 0;
+    }
+
+    /// Writes `size` bytes from `data` at `offset` of `res`. Small writes directly following the
+    /// previous one get merged into a single driver call.
+    pub fn buffer_subdata_batched(
+        &self,
+        res: &Arc<PipeResource>,
+        offset: u32,
+        data: *const c_void,
+        size: u32,
+    ) {
+        // SAFETY: `data` is required to be valid for reads of `size` bytes, same as for
+        //         `buffer_subdata`.
+        let data = unsafe { slice::from_raw_parts(data.cast::<u8>(), size as usize) };
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(PendingUpdate::Write {
+            res: pending_res,
+            offset: pending_offset,
+            data: pending_data,
+        }) = &mut *pending
+        {
+            if Arc::ptr_eq(pending_res, res)
+                && *pending_offset as usize + pending_data.len() == offset as usize
+                && pending_data.len() + data.len() <= BATCH_MAX_SIZE
+            {
+                pending_data.extend_from_slice(data);
+                return;
+            }
+        }
+
+        if let Some(update) = pending.take() {
+            update.submit(&self.ctx);
+        }
+
+        if data.len() <= BATCH_MAX_SIZE {
+            *pending = Some(PendingUpdate::Write {
+                res: Arc::clone(res),
+                offset: offset,
+                data: data.to_vec(),
+            });
+        } else {
+            self.ctx
+                .buffer_subdata(res, offset, data.as_ptr().cast(), size);
+        }
+    }
+
+    /// Fills `size` bytes at `offset` of `res` with `pattern`. Fills with the same pattern
+    /// directly following the previous one get merged into a single driver call.
+    pub fn clear_buffer_batched(
+        &self,
+        res: &Arc<PipeResource>,
+        pattern: &[u8],
+        offset: u32,
+        size: u32,
+    ) {
+        let mut pending = self.pending.lock().unwrap();
+        if let Some(PendingUpdate::Fill {
+            res: pending_res,
+            offset: pending_offset,
+            size: pending_size,
+            pattern: pending_pattern,
+        }) = &mut *pending
+        {
+            if Arc::ptr_eq(pending_res, res)
+                && pending_pattern.as_slice() == pattern
+                && pending_offset.checked_add(*pending_size) == Some(offset)
+            {
+                if let Some(merged_size) = pending_size.checked_add(size) {
+                    *pending_size = merged_size;
+                    return;
+                }
+            }
+        }
+
+        if let Some(update) = pending.take() {
+            update.submit(&self.ctx);
+        }
+
+        if size as usize <= BATCH_MAX_SIZE {
+            *pending = Some(PendingUpdate::Fill {
+                res: Arc::clone(res),
+                offset: offset,
+                size: size,
+                pattern: pattern.to_vec(),
+            });
+        } else {
+            self.ctx.clear_buffer(res, pattern, offset, size);
+        }
+    }
+
+    fn submit_pending(&self) {
+        if let Some(update) = self.pending.lock().unwrap().take() {
+            update.submit(&self.ctx);
+        }
+    }
+
     pub fn update_cb0(&self, data: &[u8]) {
         // only update if we actually bind data
         if !data.is_empty() {
@@ -56,16 +341,26 @@ impl Deref for QueueContext {
     type Target = PipeContext;
 
     fn deref(&self) -> &Self::Target {
+        // everything else using the context has to come after the pending update
+        self.submit_pending();
         &self.ctx
     }
 }
 
 impl Drop for QueueContext {
     fn drop(&mut self) {
+        self.submit_pending();
+        if let Some(ring) = self.readbacks.get_mut().unwrap().take() {
+            drop(ring.tx.with_ctx(&self.ctx));
+        }
         self.ctx.set_constant_buffer(0, &[])
     }
 }
 
+/// Upper limit of threads working on a single CPU side copy, more usually doesn't help as we are
+/// bound by memory bandwidth anyway.
+const MAX_COPY_THREADS: usize = 8;
+
 struct QueueState {
     pending: Vec<Arc<Event>>,
     last: Weak<Event>,
@@ -81,14 +376,29 @@ pub struct Queue {
     pub props: cl_command_queue_properties,
     pub props_v2: Option<Properties<cl_queue_properties>>,
     state: Mutex<QueueState>,
+    track: PerfettoTrack,
+    /// Resources released while work submitted to this queue might still use them. They get
+    /// dropped by the queue thread once the fence of the next flush signaled.
+    destruction: Arc<Mutex<Vec<Arc<PipeResource>>>>,
+    /// Worker threads to split up large CPU side copies, only spawned on first use.
+    copy_pool: OnceLock<ThreadPool>,
     _thrd: JoinHandle<()>,
 }
 
 impl_cl_type_trait!(cl_command_queue, Queue, CL_INVALID_COMMAND_QUEUE);
 
-fn flush_events(evs: &mut Vec<Arc<Event>>, pipe: &PipeContext) {
+fn flush_events(
+    evs: &mut Vec<Arc<Event>>,
+    ctx: &QueueContext,
+    destruction: &Mutex<Vec<Arc<PipeResource>>>,
+) {
     if !evs.is_empty() {
-        pipe.flush().wait();
+        // only resources released before the flush are covered by its fence
+        let resources = mem::take(&mut *destruction.lock().unwrap());
+        ctx.flush().wait();
+        drop(resources);
+        // reads only complete once their data arrived at the destination
+        ctx.finish_readbacks();
         evs.drain(..).for_each(|e| e.signal());
     }
 }
@@ -104,7 +414,10 @@ impl Queue {
         // should be detected earlier (e.g.: checking for CAPs).
         let ctx = QueueContext::new_for(device)?;
         let (tx_q, rx_t) = mpsc::channel::<Vec<Arc<Event>>>();
-        Ok(Arc::new(Self {
+        let track_name = CString::new(format!("rusticl {}", device.screen().name())).unwrap();
+        let destruction = Arc::new(Mutex::new(Vec::new()));
+        let thrd_destruction = Arc::clone(&destruction);
+        let queue = Arc::new(Self {
             base: CLObjectBase::new(RusticlTypes::Queue),
             context: context,
             device: device,
@@ -115,6 +428,9 @@ impl Queue {
                 last: Weak::new(),
                 chan_in: tx_q,
             }),
+            track: PerfettoTrack::new(&track_name),
+            destruction: destruction,
+            copy_pool: OnceLock::new(),
             _thrd: thread::Builder::new()
                 .name("rusticl queue thread".into())
                 .spawn(move || loop {
@@ -127,10 +443,17 @@ impl Queue {
                     let mut flushed = Vec::new();
 
                     for e in new_events {
+                        // Commands accessing the destination of a pending read, e.g. writing
+                        // from the same pointer, have to see its data to keep in-order
+                        // semantics. Everything else waits until the next flush.
+                        if ctx.readbacks_overlap(e.host_access) {
+                            flush_events(&mut flushed, &ctx, &thrd_destruction);
+                        }
+
                         // If we hit any deps from another queue, flush so we don't risk a dead
                         // lock.
                         if e.deps.iter().any(|ev| ev.queue != e.queue) {
-                            flush_events(&mut flushed, &ctx);
+                            flush_events(&mut flushed, &ctx, &thrd_destruction);
                         }
 
                         // We have to wait on user events or events from other queues.
@@ -152,29 +475,37 @@ impl Queue {
                         if e.is_user() {
                             // On each user event we flush our events as application might
                             // wait on them before signaling user events.
-                            flush_events(&mut flushed, &ctx);
+                            flush_events(&mut flushed, &ctx, &thrd_destruction);
 
                             // Wait on user events as they are synchronization points in the
                             // application's control.
                             e.wait();
                         } else if Platform::dbg().sync_every_event {
                             flushed.push(e);
-                            flush_events(&mut flushed, &ctx);
+                            flush_events(&mut flushed, &ctx, &thrd_destruction);
                         } else {
                             flushed.push(e);
                         }
                     }
 
-                    flush_events(&mut flushed, &ctx);
+                    flush_events(&mut flushed, &ctx, &thrd_destruction);
                 })
                 .unwrap(),
-        }))
+        });
+
+        queue.context.add_queue(&queue);
+        Ok(queue)
     }
 
     pub fn queue(&self, e: Arc<Event>) {
+        self.device.stats.add_command();
         if self.is_profiling_enabled() {
             e.set_time(EventTimes::Queued, self.device.screen().get_timestamp());
         }
+        if perfetto::is_tracing_enabled() {
+            perfetto::trace_begin_flow(&e.trace_name(), e.trace_id);
+            perfetto::trace_end();
+        }
         self.state.lock().unwrap().pending.push(e);
     }
 
@@ -217,9 +548,57 @@ impl Queue {
         Ok(())
     }
 
+    /// Whether work submitted to this queue might still be executing.
+    fn is_busy(&self) -> bool {
+        self.state
+            .lock()
+            .unwrap()
+            .last
+            .upgrade()
+            .is_some_and(|e| e.status() > CL_COMPLETE as cl_int)
+    }
+
+    /// Keeps `res` alive until all work submitted to this queue so far finished on the GPU.
+    pub fn destroy_deferred(&self, res: &[Arc<PipeResource>]) {
+        if !self.is_busy() {
+            return;
+        }
+
+        self.destruction.lock().unwrap().extend_from_slice(res);
+
+        // The queue thread might have flushed in the meantime without picking them up. Once the
+        // last event completed, nothing submitted so far can use any of them anymore.
+        if !self.is_busy() {
+            self.destruction.lock().unwrap().clear();
+        }
+    }
+
+    /// Returns the thread pool used for CPU side copies on this queue.
+    pub fn copy_pool(&self) -> &ThreadPool {
+        self.copy_pool.get_or_init(|| {
+            // the queue thread takes part in the copy as well
+            let threads = thread::available_parallelism().map_or(1, |n| n.get());
+            ThreadPool::new(
+                "rusticl copy thread",
+                cmp::min(threads, MAX_COPY_THREADS) - 1,
+            )
+        })
+    }
+
     pub fn is_profiling_enabled(&self) -> bool {
         (self.props & (CL_QUEUE_PROFILING_ENABLE as u64)) != 0
     }
+
+    /// Emits GPU work on the perfetto track of this queue. `start` and `end` are GPU timestamps
+    /// and get converted to CPU time, so they line up with the CPU side slices.
+    pub fn trace_gpu_slice(&self, name: &CStr, flow_id: u64, start: u64, end: u64) {
+        let gpu_now = self.device.screen().get_timestamp();
+        let cpu_now = unsafe { os_time_get_nano() };
+        let to_cpu = |gpu: u64| (gpu as i128 + cpu_now as i128 - gpu_now as i128) as u64;
+
+        self.track.begin(name, flow_id, to_cpu(start));
+        self.track.end(name, to_cpu(end));
+    }
 }
 
 impl Drop for Queue {
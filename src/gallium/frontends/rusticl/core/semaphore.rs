@@ -0,0 +1,168 @@
+use crate::api::icd::*;
+use crate::core::context::*;
+use crate::core::device::*;
+use crate::core::event::*;
+use crate::impl_cl_type_trait;
+
+use mesa_rust::pipe::context::PipeContext;
+use mesa_rust::pipe::fence::FenceFd;
+use mesa_rust::pipe::fence::PipeFence;
+use rusticl_opencl_gen::*;
+
+use std::sync::Arc;
+use std::sync::Condvar;
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Fence of a signal operation, which is only known once the signaling command got executed.
+#[derive(Default)]
+pub struct SignalFence {
+    fence: Mutex<Option<PipeFence>>,
+    cv: Condvar,
+}
+
+impl SignalFence {
+    fn new(fence: PipeFence) -> Self {
+        Self {
+            fence: Mutex::new(Some(fence)),
+            cv: Condvar::new(),
+        }
+    }
+
+    pub fn set(&self, fence: PipeFence) {
+        *self.fence.lock().unwrap() = Some(fence);
+        self.cv.notify_all();
+    }
+}
+
+pub struct SemaphoreSignal {
+    /// The command signaling the semaphore, `None` if the fence was known from the start.
+    pub event: Option<Arc<Event>>,
+    fence: Arc<SignalFence>,
+}
+
+impl SemaphoreSignal {
+    /// Makes all following work on `ctx` wait until the semaphore got signaled.
+    pub fn wait(&self, ctx: &PipeContext) {
+        if let Some(event) = &self.event {
+            event.wait();
+        }
+
+        if let Some(fence) = self.fence.fence.lock().unwrap().as_ref() {
+            ctx.fence_server_sync(fence);
+        }
+    }
+
+    /// Exports the signal operation as a sync file. This only waits until the signaling command
+    /// got submitted, not until it completed.
+    ///
+    /// Returns `None` if there is nothing to wait on anymore, either because the signaling
+    /// command failed, or because the fence can't be exported and we waited on it instead.
+    pub fn export_fd(&self) -> Option<FenceFd> {
+        if let Some(queue) = self.event.as_ref().and_then(|e| e.queue.as_ref()) {
+            // the signaling command might not have been submitted yet.
+            queue.flush(false).ok()?;
+        }
+
+        let mut fence = self.fence.fence.lock().unwrap();
+        while fence.is_none() {
+            // the command can't set the fence if it failed.
+            if self.event.as_ref().is_some_and(|e| e.status() < 0) {
+                return None;
+            }
+            fence = self
+                .fence
+                .cv
+                .wait_timeout(fence, Duration::from_millis(10))
+                .unwrap()
+                .0;
+        }
+
+        let fence = fence.as_ref().unwrap();
+        let fd = fence.export_fd();
+        if fd.is_none() {
+            fence.wait();
+        }
+        fd
+    }
+}
+
+/// Binary semaphore of cl_khr_semaphore. A semaphore is either unsignaled or has one pending
+/// signal operation, which gets consumed by the next wait.
+pub struct Semaphore {
+    pub base: CLObjectBase<CL_INVALID_SEMAPHORE_KHR>,
+    pub context: Arc<Context>,
+    pub devs: Vec<&'static Device>,
+    pub props: Vec<cl_semaphore_properties_khr>,
+    pub export_types: Vec<cl_external_semaphore_handle_type_khr>,
+    signal: Mutex<Option<SemaphoreSignal>>,
+    cv: Condvar,
+}
+
+impl_cl_type_trait!(cl_semaphore_khr, Semaphore, CL_INVALID_SEMAPHORE_KHR);
+
+impl Semaphore {
+    pub fn new(
+        context: Arc<Context>,
+        devs: Vec<&'static Device>,
+        props: Vec<cl_semaphore_properties_khr>,
+        export_types: Vec<cl_external_semaphore_handle_type_khr>,
+    ) -> Arc<Semaphore> {
+        Arc::new(Self {
+            base: CLObjectBase::new(RusticlTypes::Semaphore),
+            context: context,
+            devs: devs,
+            props: props,
+            export_types: export_types,
+            signal: Mutex::new(None),
+            cv: Condvar::new(),
+        })
+    }
+
+    fn set_signal(&self, signal: SemaphoreSignal) {
+        *self.signal.lock().unwrap() = Some(signal);
+        self.cv.notify_all();
+    }
+
+    /// Signals the semaphore through the command `event`, which sets `fence` when executed.
+    pub fn signal(&self, event: Arc<Event>, fence: Arc<SignalFence>) {
+        self.set_signal(SemaphoreSignal {
+            event: Some(event),
+            fence: fence,
+        });
+    }
+
+    /// Signals the semaphore with an already existing fence.
+    pub fn signal_with_fence(&self, fence: PipeFence) {
+        self.set_signal(SemaphoreSignal {
+            event: None,
+            fence: Arc::new(SignalFence::new(fence)),
+        });
+    }
+
+    /// Takes the pending signal operation if there is one, which resets the semaphore to
+    /// unsignaled.
+    pub fn take_signal(&self) -> Option<SemaphoreSignal> {
+        self.signal.lock().unwrap().take()
+    }
+
+    /// Like [Semaphore::take_signal], but blocks until the semaphore gets signaled.
+    pub fn wait_for_signal(&self) -> SemaphoreSignal {
+        let mut signal = self.signal.lock().unwrap();
+        loop {
+            if let Some(signal) = signal.take() {
+                return signal;
+            }
+            signal = self.cv.wait(signal).unwrap();
+        }
+    }
+
+    pub fn is_signaled(&self) -> bool {
+        self.signal.lock().unwrap().is_some()
+    }
+
+    /// Whether signal operations need to create fences which can be exported as sync files.
+    pub fn is_exportable(&self) -> bool {
+        self.export_types.contains(&CL_SEMAPHORE_HANDLE_SYNC_FD_KHR)
+    }
+}
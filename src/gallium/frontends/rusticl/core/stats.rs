@@ -0,0 +1,99 @@
+use rusticl_opencl_gen::*;
+
+use std::fmt;
+use std::sync::atomic::AtomicU64;
+use std::sync::atomic::Ordering;
+
+/// Kinds of memory tracked by [MemoryStats].
+#[derive(Clone, Copy)]
+pub enum MemoryKind {
+    Buffer,
+    Image,
+    /// Shadow resources backing mappings which can't be done directly.
+    Shadow,
+}
+
+/// Bytes of memory currently allocated, split up by [MemoryKind]. Tracked per context and per
+/// device and queryable through `CL_CONTEXT_MEMORY_STATS_MESA` and `CL_DEVICE_MEMORY_STATS_MESA`.
+#[derive(Default)]
+pub struct MemoryStats {
+    bytes: [AtomicU64; 3],
+}
+
+impl MemoryStats {
+    pub fn add(&self, kind: MemoryKind, size: usize) {
+        self.bytes[kind as usize].fetch_add(size as u64, Ordering::Relaxed);
+    }
+
+    pub fn remove(&self, kind: MemoryKind, size: usize) {
+        self.bytes[kind as usize].fetch_sub(size as u64, Ordering::Relaxed);
+    }
+
+    /// Returns the counters in the order of [MemoryKind].
+    pub fn snapshot(&self) -> [cl_ulong; 3] {
+        let [buffers, images, shadows] = &self.bytes;
+        [
+            buffers.load(Ordering::Relaxed),
+            images.load(Ordering::Relaxed),
+            shadows.load(Ordering::Relaxed),
+        ]
+    }
+}
+
+impl fmt::Display for MemoryStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let [buffers, images, shadows] = self.snapshot();
+        write!(
+            f,
+            "buffers: {buffers} bytes, images: {images} bytes, shadows: {shadows} bytes"
+        )
+    }
+}
+
+/// Runtime counters of a device, logged with `RUSTICL_DEBUG=stats`. All counters are only updated
+/// with relaxed ordering, so they aren't guaranteed to be consistent with each other.
+#[derive(Default)]
+pub struct DeviceStats {
+    pub memory: MemoryStats,
+    active_mappings: AtomicU64,
+    submitted_commands: AtomicU64,
+    cache_hits: AtomicU64,
+    cache_misses: AtomicU64,
+}
+
+impl DeviceStats {
+    pub fn add_mapping(&self) {
+        self.active_mappings.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn remove_mapping(&self) {
+        self.active_mappings.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn add_command(&self) {
+        self.submitted_commands.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn add_cache_lookup(&self, hit: bool) {
+        if hit {
+            self.cache_hits.fetch_add(1, Ordering::Relaxed);
+        } else {
+            self.cache_misses.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+}
+
+impl fmt::Display for DeviceStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}, active mappings: {}, submitted commands: {}, shader cache hits: {}, shader cache \
+             misses: {}",
+            self.memory,
+            self.active_mappings.load(Ordering::Relaxed),
+            self.submitted_commands.load(Ordering::Relaxed),
+            self.cache_hits.load(Ordering::Relaxed),
+            self.cache_misses.load(Ordering::Relaxed),
+        )
+    }
+}
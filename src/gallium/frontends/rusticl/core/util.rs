@@ -5,6 +5,8 @@ use rusticl_opencl_gen::*;
 
 use super::gl::is_cube_map_face;
 
+use std::sync::OnceLock;
+
 pub fn cl_mem_type_to_texture_target(mem_type: cl_mem_object_type) -> pipe_texture_target {
     match mem_type {
         CL_MEM_OBJECT_BUFFER => pipe_texture_target::PIPE_BUFFER,
@@ -69,3 +71,16 @@ pub fn create_pipe_box(
         depth: region[2].try_into().map_err(|_| CL_OUT_OF_HOST_MEMORY)?,
     })
 }
+
+/// Returns the size of memory pages on the host.
+pub fn host_page_size() -> usize {
+    static PAGE_SIZE: OnceLock<usize> = OnceLock::new();
+    *PAGE_SIZE.get_or_init(|| {
+        let mut size = 0;
+        if unsafe { os_get_page_size(&mut size) } {
+            size as usize
+        } else {
+            4096
+        }
+    })
+}
@@ -48,12 +48,20 @@ macro_rules! nir_pass_impl {
             let func_str = ::std::stringify!($func);
             let func_cstr = ::std::ffi::CString::new(func_str).unwrap();
             let res = if unsafe { should_skip_nir(func_cstr.as_ptr()) } {
-                println!("skipping {}", func_str);
+                ::mesa_rust_util::log_warn!(
+                    target: "mesa_rust::compiler::nir",
+                    "skipping {}",
+                    func_str
+                );
                 false
             } else {
                 $nir.metadata_set_validation_flag();
                 if $nir.should_print() {
-                    println!("{}", func_str);
+                    ::mesa_rust_util::log_debug!(
+                        target: "mesa_rust::compiler::nir",
+                        "{}",
+                        func_str
+                    );
                 }
                 if $nir.$pass($func $(,$arg)*) {
                     $nir.validate(&format!("after {} in {}:{}", func_str, file!(), line!()));
@@ -93,6 +93,7 @@ impl PipeContext {
     pub fn texture_subdata(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         data: *const c_void,
         stride: u32,
@@ -102,7 +103,7 @@ impl PipeContext {
             self.pipe.as_ref().texture_subdata.unwrap()(
                 self.pipe.as_ptr(),
                 res.pipe(),
-                0,
+                level,
                 pipe_map_flags::PIPE_MAP_WRITE.0, // TODO PIPE_MAP_x
                 bx,
                 data,
@@ -129,6 +130,7 @@ impl PipeContext {
         &self,
         res: &PipeResource,
         pattern: &[u32],
+        base: usize,
         origin: &[usize; 3],
         region: &[usize; 3],
         strides: (usize, usize),
@@ -138,10 +140,11 @@ impl PipeContext {
         for z in 0..region[2] {
             for y in 0..region[1] {
                 let pitch = [pixel_size, row_pitch, slice_pitch];
-                // Convoluted way of doing (origin + [0, y, z]) * pitch
+                // Convoluted way of doing base + (origin + [0, y, z]) * pitch
                 let offset = (0..3)
                     .map(|i| ((origin[i] + [0, y, z][i]) * pitch[i]) as u32)
-                    .sum();
+                    .sum::<u32>()
+                    + base as u32;
 
                 unsafe {
                     self.pipe.as_ref().clear_buffer.unwrap()(
@@ -157,7 +160,7 @@ impl PipeContext {
         }
     }
 
-    pub fn clear_texture(&self, res: &PipeResource, pattern: &[u32], bx: &pipe_box) {
+    pub fn clear_texture(&self, res: &PipeResource, level: u32, pattern: &[u32], bx: &pipe_box) {
         unsafe {
             let clear_texture = self
                 .pipe
@@ -167,7 +170,7 @@ impl PipeContext {
             clear_texture(
                 self.pipe.as_ptr(),
                 res.pipe(),
-                0,
+                level,
                 bx,
                 pattern.as_ptr().cast(),
             )
@@ -180,17 +183,30 @@ impl PipeContext {
         dst: &PipeResource,
         dst_offset: &[u32; 3],
         bx: &pipe_box,
+    ) {
+        self.texture_copy_region(src, 0, dst, 0, dst_offset, bx);
+    }
+
+    /// Like [PipeContext::resource_copy_region], but copies between the given mip levels.
+    pub fn texture_copy_region(
+        &self,
+        src: &PipeResource,
+        src_level: u32,
+        dst: &PipeResource,
+        dst_level: u32,
+        dst_offset: &[u32; 3],
+        bx: &pipe_box,
     ) {
         unsafe {
             self.pipe.as_ref().resource_copy_region.unwrap()(
                 self.pipe.as_ptr(),
                 dst.pipe(),
-                0,
+                dst_level,
                 dst_offset[0],
                 dst_offset[1],
                 dst_offset[2],
                 src.pipe(),
-                0,
+                src_level,
                 bx,
             )
         }
@@ -199,6 +215,7 @@ impl PipeContext {
     fn resource_map(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         flags: pipe_map_flags,
         is_buffer: bool,
@@ -212,7 +229,7 @@ impl PipeContext {
                 self.pipe.as_ref().texture_map
             };
 
-            func.unwrap()(self.pipe.as_ptr(), res.pipe(), 0, flags.0, bx, &mut out)
+            func.unwrap()(self.pipe.as_ptr(), res.pipe(), level, flags.0, bx, &mut out)
         };
 
         if ptr.is_null() {
@@ -237,7 +254,7 @@ impl PipeContext {
             ..Default::default()
         };
 
-        self.resource_map(res, &b, flags, true)
+        self.resource_map(res, 0, &b, flags, true)
     }
 
     pub fn buffer_map(
@@ -272,33 +289,36 @@ impl PipeContext {
     pub fn _texture_map(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         flags: pipe_map_flags,
     ) -> Option<PipeTransfer> {
-        self.resource_map(res, bx, flags, false)
+        self.resource_map(res, level, bx, flags, false)
     }
 
     pub fn texture_map(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         rw: RWFlags,
         map_type: ResourceMapType,
     ) -> Option<PipeTransfer> {
         let mut flags: pipe_map_flags = map_type.into();
         flags |= rw.into();
-        self._texture_map(res, bx, flags)
+        self._texture_map(res, level, bx, flags)
     }
 
     pub fn texture_map_directly(
         &self,
         res: &PipeResource,
+        level: u32,
         bx: &pipe_box,
         rw: RWFlags,
     ) -> Option<PipeTransfer> {
         let flags =
             pipe_map_flags::PIPE_MAP_DIRECTLY | pipe_map_flags::PIPE_MAP_UNSYNCHRONIZED | rw.into();
-        self.resource_map(res, bx, flags, false)
+        self.resource_map(res, level, bx, flags, false)
     }
 
     pub(super) fn texture_unmap(&self, tx: *mut pipe_transfer) {
@@ -610,14 +630,23 @@ impl PipeContext {
         unsafe { self.pipe.as_ref().memory_barrier.unwrap()(self.pipe.as_ptr(), barriers) }
     }
 
-    pub fn flush(&self) -> PipeFence {
+    fn flush_with_flags(&self, flags: u32) -> PipeFence {
         unsafe {
             let mut fence = ptr::null_mut();
-            self.pipe.as_ref().flush.unwrap()(self.pipe.as_ptr(), &mut fence, 0);
+            self.pipe.as_ref().flush.unwrap()(self.pipe.as_ptr(), &mut fence, flags);
             PipeFence::new(fence, &self.screen)
         }
     }
 
+    pub fn flush(&self) -> PipeFence {
+        self.flush_with_flags(0)
+    }
+
+    /// Like [PipeContext::flush], but the returned fence can be exported as a sync file.
+    pub fn flush_exportable(&self) -> PipeFence {
+        self.flush_with_flags(pipe_flush_flags::PIPE_FLUSH_FENCE_FD as u32)
+    }
+
     pub fn import_fence(&self, fence_fd: &FenceFd) -> PipeFence {
         unsafe {
             let mut fence = ptr::null_mut();
@@ -631,6 +660,20 @@ impl PipeContext {
         }
     }
 
+    /// Makes the GPU wait on `fence` before executing any following work. Drivers without
+    /// support for that and fences of other screens wait on the CPU instead.
+    pub fn fence_server_sync(&self, fence: &PipeFence) {
+        if Arc::ptr_eq(fence.screen(), &self.screen) {
+            unsafe {
+                if let Some(fence_server_sync) = self.pipe.as_ref().fence_server_sync {
+                    fence_server_sync(self.pipe.as_ptr(), fence.raw());
+                    return;
+                }
+            }
+        }
+        fence.wait();
+    }
+
     pub fn svm_migrate(
         &self,
         ptrs: &[usize],
@@ -3,12 +3,22 @@ use crate::pipe::screen::*;
 use libc_rust_gen::close;
 use mesa_rust_gen::*;
 
+use std::mem;
 use std::sync::Arc;
 
 pub struct FenceFd {
     pub fd: i32,
 }
 
+impl FenceFd {
+    /// Hands out the file descriptor without closing it.
+    pub fn into_raw(self) -> i32 {
+        let fd = self.fd;
+        mem::forget(self);
+        fd
+    }
+}
+
 impl Drop for FenceFd {
     fn drop(&mut self) {
         unsafe {
@@ -22,6 +32,10 @@ pub struct PipeFence {
     screen: Arc<PipeScreen>,
 }
 
+// SAFETY: fences are screen objects and can be used from any thread.
+unsafe impl Send for PipeFence {}
+unsafe impl Sync for PipeFence {}
+
 impl PipeFence {
     pub fn new(fence: *mut pipe_fence_handle, screen: &Arc<PipeScreen>) -> Self {
         Self {
@@ -30,6 +44,20 @@ impl PipeFence {
         }
     }
 
+    pub(super) fn raw(&self) -> *mut pipe_fence_handle {
+        self.fence
+    }
+
+    pub(super) fn screen(&self) -> &Arc<PipeScreen> {
+        &self.screen
+    }
+
+    /// Exports the fence as a sync file, which requires the fence to be created through
+    /// `PipeContext::flush_exportable` or `PipeContext::import_fence`.
+    pub fn export_fd(&self) -> Option<FenceFd> {
+        self.screen.fence_get_fd(self.fence)
+    }
+
     pub fn wait(&self) {
         self.screen.fence_finish(self.fence);
     }
@@ -46,15 +46,30 @@ unsafe impl Sync for PipeImageView {}
 // Image dimensions provide by application to be used in both
 // image and sampler views when image is created from buffer
 #[derive(PartialEq, Eq)]
-pub struct AppImgInfo {
-    row_stride: u32,
-    width: u32,
-    height: u32,
+/// Layout of images created from a buffer, used to create views over the buffer's resource.
+pub enum AppImgInfo {
+    /// 1D image buffers, `offset` and `size` in bytes.
+    Buffer { offset: u32, size: u32 },
+    /// 2D images created from a buffer, all values in pixels.
+    Tex2D {
+        offset: u32,
+        row_stride: u32,
+        width: u32,
+        height: u32,
+    },
 }
 
 impl AppImgInfo {
-    pub fn new(row_stride: u32, width: u32, height: u32) -> AppImgInfo {
-        AppImgInfo {
+    pub fn new_buffer(offset: u32, size: u32) -> AppImgInfo {
+        AppImgInfo::Buffer {
+            offset: offset,
+            size: size,
+        }
+    }
+
+    pub fn new_tex2d(offset: u32, row_stride: u32, width: u32, height: u32) -> AppImgInfo {
+        AppImgInfo::Tex2D {
+            offset: offset,
             row_stride: row_stride,
             width: width,
             height: height,
@@ -98,6 +113,14 @@ impl PipeResource {
         unsafe { self.pipe.as_ref().unwrap().array_size }
     }
 
+    pub fn last_level(&self) -> u32 {
+        self.as_ref().last_level.into()
+    }
+
+    pub fn target(&self) -> pipe_texture_target {
+        self.as_ref().target()
+    }
+
     pub fn is_buffer(&self) -> bool {
         self.as_ref().target() == pipe_texture_target::PIPE_BUFFER
     }
@@ -118,13 +141,26 @@ impl PipeResource {
         app_img_info: Option<&AppImgInfo>,
     ) -> PipeImageView {
         let pipe = PipeResource::as_ref(self);
-        let u = if let Some(app_img_info) = app_img_info {
+        let u = if let Some(&AppImgInfo::Tex2D {
+            offset,
+            row_stride,
+            width,
+            height,
+        }) = app_img_info
+        {
             pipe_image_view__bindgen_ty_1 {
                 tex2d_from_buf: pipe_image_view__bindgen_ty_1__bindgen_ty_3 {
-                    offset: 0,
-                    row_stride: app_img_info.row_stride as u16,
-                    width: app_img_info.width as u16,
-                    height: app_img_info.height as u16,
+                    offset: offset,
+                    row_stride: row_stride as u16,
+                    width: width as u16,
+                    height: height as u16,
+                },
+            }
+        } else if let Some(&AppImgInfo::Buffer { offset, size }) = app_img_info {
+            pipe_image_view__bindgen_ty_1 {
+                buf: pipe_image_view__bindgen_ty_1__bindgen_ty_2 {
+                    offset: offset,
+                    size: size,
                 },
             }
         } else if self.is_buffer() {
@@ -155,7 +191,7 @@ impl PipeResource {
             PIPE_IMAGE_ACCESS_WRITE
         } as u16;
 
-        let access = if app_img_info.is_some() {
+        let access = if matches!(app_img_info, Some(AppImgInfo::Tex2D { .. })) {
             PIPE_IMAGE_ACCESS_TEX2D_FROM_BUFFER
         } else {
             0
@@ -180,13 +216,22 @@ impl PipeResource {
             u_sampler_view_default_template(&mut res, self.pipe, format);
         }
 
-        if let Some(app_img_info) = app_img_info {
-            res.u.tex2d_from_buf.offset = 0;
-            res.u.tex2d_from_buf.row_stride = app_img_info.row_stride as u16;
-            res.u.tex2d_from_buf.width = app_img_info.width as u16;
-            res.u.tex2d_from_buf.height = app_img_info.height as u16;
+        if let Some(&AppImgInfo::Tex2D {
+            offset,
+            row_stride,
+            width,
+            height,
+        }) = app_img_info
+        {
+            res.u.tex2d_from_buf.offset = offset;
+            res.u.tex2d_from_buf.row_stride = row_stride as u16;
+            res.u.tex2d_from_buf.width = width as u16;
+            res.u.tex2d_from_buf.height = height as u16;
 
             res.set_is_tex2d_from_buf(true);
+        } else if let Some(&AppImgInfo::Buffer { offset, size }) = app_img_info {
+            res.u.buf.offset = offset;
+            res.u.buf.size = size;
         } else if res.target() == pipe_texture_target::PIPE_BUFFER {
             res.u.buf.offset = 0;
             res.u.buf.size = self.as_ref().width0;
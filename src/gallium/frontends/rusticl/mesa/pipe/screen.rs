@@ -1,11 +1,13 @@
 use crate::compiler::nir::NirShader;
 use crate::pipe::context::*;
 use crate::pipe::device::*;
+use crate::pipe::fence::*;
 use crate::pipe::resource::*;
 use crate::util::disk_cache::*;
 
 use mesa_rust_gen::*;
 use mesa_rust_util::has_required_feature;
+use mesa_rust_util::log_debug;
 use mesa_rust_util::ptr::ThreadSafeCPtr;
 use mesa_rust_util::string::*;
 
@@ -70,11 +72,24 @@ impl ComputeParam<Vec<u64>> for PipeScreen {
     }
 }
 
+/// A resource exported as a dma-buf. The file descriptor is owned by whoever receives it.
+pub struct DmaBufExport {
+    pub fd: i32,
+    pub modifier: u64,
+    pub stride: u32,
+    pub offset: u32,
+}
+
 #[derive(Clone, Copy, PartialEq, Eq)]
 pub enum ResourceType {
     Normal,
     Staging,
     Cb0,
+    /// Memory shared with the host which has to stay coherent while the GPU accesses it, e.g.
+    /// for atomics on SVM allocations.
+    Coherent,
+    /// Memory only the GPU can access.
+    Protected,
 }
 
 impl ResourceType {
@@ -89,6 +104,12 @@ impl ResourceType {
                 tmpl.flags |= screen.param(pipe_cap::PIPE_CAP_CONSTBUF0_FLAGS) as u32;
                 tmpl.bind |= PIPE_BIND_CONSTANT_BUFFER;
             }
+            Self::Coherent => {
+                tmpl.flags |= PIPE_RESOURCE_FLAG_MAP_PERSISTENT | PIPE_RESOURCE_FLAG_MAP_COHERENT;
+            }
+            Self::Protected => {
+                tmpl.bind |= PIPE_BIND_PROTECTED;
+            }
             Self::Normal => {}
         }
     }
@@ -127,10 +148,24 @@ impl PipeScreen {
     }
 
     fn resource_create(&self, tmpl: &pipe_resource) -> Option<PipeResource> {
-        PipeResource::new(
+        let res = PipeResource::new(
             unsafe { self.screen().resource_create.unwrap()(self.screen.as_ptr(), tmpl) },
             false,
-        )
+        );
+
+        if res.is_none() {
+            log_debug!(
+                "resource_create failed: target {:?}, format {:?}, size {}x{}x{}x{}",
+                tmpl.target(),
+                tmpl.format(),
+                tmpl.width0,
+                tmpl.height0,
+                tmpl.depth0,
+                tmpl.array_size,
+            );
+        }
+
+        res
     }
 
     fn resource_create_from_user(
@@ -168,6 +203,7 @@ impl PipeScreen {
         &self,
         size: u32,
         mem: *mut c_void,
+        res_type: ResourceType,
         pipe_bind: u32,
     ) -> Option<PipeResource> {
         let mut tmpl = pipe_resource::default();
@@ -179,6 +215,8 @@ impl PipeScreen {
         tmpl.array_size = 1;
         tmpl.bind = pipe_bind;
 
+        res_type.apply(&mut tmpl, self);
+
         self.resource_create_from_user(&tmpl, mem)
     }
 
@@ -188,6 +226,7 @@ impl PipeScreen {
         height: u16,
         depth: u16,
         array_size: u16,
+        last_level: u8,
         target: pipe_texture_target,
         format: pipe_format,
         res_type: ResourceType,
@@ -201,6 +240,7 @@ impl PipeScreen {
         tmpl.height0 = height;
         tmpl.depth0 = depth;
         tmpl.array_size = array_size;
+        tmpl.last_level = last_level;
         tmpl.bind = PIPE_BIND_SAMPLER_VIEW;
 
         if support_image {
@@ -282,6 +322,30 @@ impl PipeScreen {
         }
     }
 
+    pub fn resource_export_dmabuf(&self, res: &PipeResource) -> Option<DmaBufExport> {
+        let mut handle = winsys_handle {
+            type_: WINSYS_HANDLE_TYPE_FD,
+            ..Default::default()
+        };
+
+        let exported = unsafe {
+            self.screen().resource_get_handle?(
+                self.screen.as_ptr(),
+                ptr::null_mut(),
+                res.pipe(),
+                &mut handle,
+                PIPE_HANDLE_USAGE_FRAMEBUFFER_WRITE | PIPE_HANDLE_USAGE_SHADER_WRITE,
+            )
+        };
+
+        exported.then_some(DmaBufExport {
+            fd: handle.handle as i32,
+            modifier: handle.modifier,
+            stride: handle.stride,
+            offset: handle.offset,
+        })
+    }
+
     pub fn param(&self, cap: pipe_cap) -> i32 {
         unsafe { self.screen().get_param.unwrap()(self.screen.as_ptr(), cap) }
     }
@@ -450,6 +514,15 @@ impl PipeScreen {
         }
     }
 
+    pub fn is_fence_fd_export_supported(&self) -> bool {
+        self.screen().fence_get_fd.is_some() && self.param(pipe_cap::PIPE_CAP_NATIVE_FENCE_FD) != 0
+    }
+
+    pub(super) fn fence_get_fd(&self, fence: *mut pipe_fence_handle) -> Option<FenceFd> {
+        let fd = unsafe { self.screen().fence_get_fd?(self.screen.as_ptr(), fence) };
+        (fd >= 0).then_some(FenceFd { fd: fd })
+    }
+
     pub fn query_memory_info(&self) -> Option<pipe_memory_info> {
         let mut info = pipe_memory_info::default();
         unsafe {
@@ -1 +1,2 @@
 pub mod disk_cache;
+pub mod perfetto;
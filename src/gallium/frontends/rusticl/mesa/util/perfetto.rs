@@ -0,0 +1,48 @@
+use mesa_rust_gen::*;
+
+use std::ffi::CStr;
+
+/// Connects to the system tracing service. Safe to call multiple times.
+pub fn init() {
+    unsafe { util_perfetto_init() }
+}
+
+pub fn is_tracing_enabled() -> bool {
+    unsafe { util_perfetto_is_tracing_enabled() }
+}
+
+/// Returns a new process unique id usable to connect slices through flows.
+pub fn next_id() -> u64 {
+    unsafe { util_perfetto_next_id() }
+}
+
+/// Begins a slice on the track of the calling thread, connected to the flow `id`.
+pub fn trace_begin_flow(name: &CStr, id: u64) {
+    unsafe { util_perfetto_trace_begin_flow(name.as_ptr(), id) }
+}
+
+/// Ends the last slice begun on the track of the calling thread.
+pub fn trace_end() {
+    unsafe { util_perfetto_trace_end() }
+}
+
+/// A custom perfetto track to emit slices with explicit timestamps on, e.g. GPU work.
+pub struct PerfettoTrack {
+    id: u64,
+}
+
+impl PerfettoTrack {
+    pub fn new(name: &CStr) -> Self {
+        Self {
+            id: unsafe { util_perfetto_new_track(name.as_ptr()) },
+        }
+    }
+
+    pub fn begin(&self, name: &CStr, flow_id: u64, timestamp: u64) {
+        unsafe { util_perfetto_trace_full_begin(name.as_ptr(), self.id, flow_id, timestamp) }
+    }
+
+    pub fn end(&self, name: &CStr, timestamp: u64) {
+        unsafe { util_perfetto_trace_full_end(name.as_ptr(), self.id, timestamp) }
+    }
+}
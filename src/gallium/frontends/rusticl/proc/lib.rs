@@ -5,6 +5,22 @@ use proc_macro::TokenTree::Group;
 use proc_macro::TokenTree::Ident;
 use proc_macro::TokenTree::Punct;
 
+/// Generates the argument list passed into `crate::api::trace::trace_call`
+fn trace_args(arg_names: &[String]) -> String {
+    arg_names
+        .iter()
+        .map(|arg| format!("(\"{arg}\", &{arg} as &dyn ::std::fmt::Debug),"))
+        .collect()
+}
+
+/// Generates the argument list passed into `crate::api::capture::capture_call`
+fn capture_args(arg_names: &[String]) -> String {
+    arg_names
+        .iter()
+        .map(|arg| format!("crate::api::capture::CaptureArg::capture_value(&{arg}),"))
+        .collect()
+}
+
 /// Macro for generating the C API stubs for normal functions
 #[proc_macro_attribute]
 pub fn cl_entrypoint(attr: TokenStream, item: TokenStream) -> TokenStream {
@@ -93,6 +109,8 @@ pub fn cl_entrypoint(attr: TokenStream, item: TokenStream) -> TokenStream {
         .collect();
 
     let arg_names_str = arg_names.join(",");
+    let trace_args = trace_args(&arg_names);
+    let capture_args = capture_args(&arg_names);
     let mut args = args.to_string();
     if !args.ends_with(',') {
         args.push(',');
@@ -105,10 +123,18 @@ pub fn cl_entrypoint(attr: TokenStream, item: TokenStream) -> TokenStream {
             "pub extern \"C\" fn {attr}(
                 {args}
             ) -> cl_int {{
-                match {name}({arg_names_str}) {{
+                crate::api::error::begin_call();
+                let err = match crate::api::panic::catch(\"{attr}\", || {name}({arg_names_str})) {{
                     Ok(_) => CL_SUCCESS as cl_int,
-                    Err(e) => e,
+                    Err(e) => crate::api::error::finish_call(\"{attr}\", e),
+                }};
+                if crate::api::trace::is_enabled() {{
+                    crate::api::trace::trace_call(\"{attr}\", &[{trace_args}], err, None);
                 }}
+                if crate::api::capture::is_enabled() {{
+                    crate::api::capture::capture_call(\"{attr}\", &[{capture_args}], err, 0);
+                }}
+                err
             }}"
         )
     } else {
@@ -120,10 +146,30 @@ pub fn cl_entrypoint(attr: TokenStream, item: TokenStream) -> TokenStream {
                 {args}
                 errcode_ret: *mut cl_int,
             ) -> {ret_type} {{
-                let (ptr, err) = match {name}({arg_names_str}) {{
+                crate::api::error::begin_call();
+                let (ptr, err) = match crate::api::panic::catch(\"{attr}\", || {name}({arg_names_str})) {{
                     Ok(o) => (o, CL_SUCCESS as cl_int),
-                    Err(e) => (std::ptr::null_mut(), e),
+                    Err(e) => (
+                        std::ptr::null_mut(),
+                        crate::api::error::finish_call(\"{attr}\", e),
+                    ),
                 }};
+                if crate::api::trace::is_enabled() {{
+                    crate::api::trace::trace_call(
+                        \"{attr}\",
+                        &[{trace_args}],
+                        err,
+                        Some(&ptr),
+                    );
+                }}
+                if crate::api::capture::is_enabled() {{
+                    crate::api::capture::capture_call(
+                        \"{attr}\",
+                        &[{capture_args}],
+                        err,
+                        crate::api::capture::CaptureArg::capture_value(&ptr),
+                    );
+                }}
                 if !errcode_ret.is_null() {{
                     unsafe {{
                         *errcode_ret = err;
@@ -175,6 +221,21 @@ pub fn cl_info_entrypoint(attr: TokenStream, item: TokenStream) -> TokenStream {
     // the 1st argument is special as it's the actual property being queried. The remaining
     // arguments are additional input data being passed before the property.
     let arg = &args[0];
+    let arg_names: Vec<_> = ["input".to_string()]
+        .into_iter()
+        .chain((1..args.len()).map(|idx| format!("arg{}", idx - 1)))
+        .chain(
+            [
+                "param_name",
+                "param_value_size",
+                "param_value",
+                "param_value_size_ret",
+            ]
+            .map(str::to_string),
+        )
+        .collect();
+    let trace_args = trace_args(&arg_names);
+    let capture_args = capture_args(&arg_names);
     let (args_values, args) = args[1..]
         .iter()
         .enumerate()
@@ -198,16 +259,26 @@ pub fn cl_info_entrypoint(attr: TokenStream, item: TokenStream) -> TokenStream {
             param_value: *mut ::std::ffi::c_void,
             param_value_size_ret: *mut usize,
         ) -> cl_int {{
-            match input.{method}(
-                {args_values}
-                param_name,
-                param_value_size,
-                param_value,
-                param_value_size_ret,
-            ) {{
+            crate::api::error::begin_call();
+            let err = match crate::api::panic::catch(\"{attr}\", || {{
+                input.{method}(
+                    {args_values}
+                    param_name,
+                    param_value_size,
+                    param_value,
+                    param_value_size_ret,
+                )
+            }}) {{
                 Ok(_) => CL_SUCCESS as cl_int,
-                Err(e) => e,
+                Err(e) => crate::api::error::finish_call(\"{attr}\", e),
+            }};
+            if crate::api::trace::is_enabled() {{
+                crate::api::trace::trace_call(\"{attr}\", &[{trace_args}], err, None);
+            }}
+            if crate::api::capture::is_enabled() {{
+                crate::api::capture::capture_call(\"{attr}\", &[{capture_args}], err, 0);
             }}
+            err
         }}"
     )
     .parse()
@@ -0,0 +1,45 @@
+#include <stdlib.h>
+#include <string.h>
+
+#include "util/driconf.h"
+#include "util/macros.h"
+#include "util/xmlconfig.h"
+
+#include "rusticl_driconf.h"
+
+static const driOptionDescription rusticl_driconf_options[] = {
+    DRI_CONF_SECTION_MISCELLANEOUS
+        DRI_CONF_OPT_S_NODEF(rusticl_disable_extensions,
+                             "Space separated list of device extensions to hide")
+        DRI_CONF_OPT_B(rusticl_force_in_order_queue, false,
+                       "Ignore CL_QUEUE_OUT_OF_ORDER_EXEC_MODE_ENABLE on queue creation")
+        DRI_CONF_OPT_B(rusticl_serialize_maps, false,
+                       "Treat all map operations as blocking")
+    DRI_CONF_SECTION_END
+};
+
+void
+rusticl_driconf_load(struct rusticl_driconf *conf)
+{
+    driOptionCache info;
+    driOptionCache cache;
+
+    driParseOptionInfo(&info, rusticl_driconf_options,
+                       ARRAY_SIZE(rusticl_driconf_options));
+    /* applications are matched by their executable name */
+    driParseConfigFiles(&cache, &info, 0, "rusticl", NULL, NULL, NULL, 0, NULL, 0);
+
+    conf->force_in_order_queue = driQueryOptionb(&cache, "rusticl_force_in_order_queue");
+    conf->serialize_maps = driQueryOptionb(&cache, "rusticl_serialize_maps");
+    conf->disable_extensions = strdup(driQueryOptionstr(&cache, "rusticl_disable_extensions"));
+
+    driDestroyOptionCache(&cache);
+    driDestroyOptionInfo(&info);
+}
+
+void
+rusticl_driconf_fini(struct rusticl_driconf *conf)
+{
+    free(conf->disable_extensions);
+    conf->disable_extensions = NULL;
+}
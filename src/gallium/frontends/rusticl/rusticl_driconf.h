@@ -0,0 +1,11 @@
+#include <stdbool.h>
+
+struct rusticl_driconf {
+    bool force_in_order_queue;
+    bool serialize_maps;
+    /* space separated list, owned by the struct */
+    char *disable_extensions;
+};
+
+void rusticl_driconf_load(struct rusticl_driconf *conf);
+void rusticl_driconf_fini(struct rusticl_driconf *conf);
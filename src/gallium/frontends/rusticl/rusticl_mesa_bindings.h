@@ -18,7 +18,9 @@
 #include "util/blob.h"
 #include "util/disk_cache.h"
 #include "util/hex.h"
+#include "util/os_misc.h"
 #include "util/os_time.h"
+#include "util/perf/u_perfetto.h"
 #include "util/sha1/sha1.h"
 #include "util/u_inlines.h"
 #include "util/u_upload_mgr.h"
@@ -26,4 +28,5 @@
 #include "util/u_sampler.h"
 #include "util/u_surface.h"
 
+#include "rusticl_driconf.h"
 #include "rusticl_nir.h"
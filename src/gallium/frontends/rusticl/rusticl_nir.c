@@ -2,6 +2,7 @@
 
 #include "nir.h"
 #include "nir_builder.h"
+#include "nir_format_convert.h"
 
 #include "rusticl_nir.h"
 
@@ -148,3 +149,166 @@ rusticl_lower_inputs(nir_shader *shader)
    shader->info.first_ubo_is_default_ubo = true;
    return progress;
 }
+
+static nir_def *
+rusticl_is_srgb_image(nir_builder *b, nir_intrinsic_instr *intrins)
+{
+   /* image_deref_order gets lowered to the SPIR-V value later, which is based on CL_R. The sRGB
+    * orders are CL_sRGB, CL_sRGBx, CL_sRGBA and CL_sBGRA in this order.
+    */
+   nir_def *order = nir_image_deref_order(b, 32, intrins->src[0].ssa);
+   order = nir_iadd_imm(b, order, -(CL_sRGB - CL_R));
+   return nir_ult_imm(b, order, CL_sBGRA - CL_sRGB + 1);
+}
+
+static nir_def *
+rusticl_convert_srgb(nir_builder *b, nir_def *val, bool encode)
+{
+   unsigned bit_size = val->bit_size;
+   nir_def *rgb = nir_f2f32(b, nir_trim_vector(b, val, 3));
+
+   rgb = encode ? nir_format_linear_to_srgb(b, rgb) : nir_format_srgb_to_linear(b, rgb);
+   rgb = nir_f2fN(b, rgb, bit_size);
+
+   /* alpha is always linear */
+   nir_def *comps[NIR_MAX_VEC_COMPONENTS];
+   for (unsigned i = 0; i < val->num_components; i++)
+      comps[i] = i < 3 ? nir_channel(b, rgb, i) : nir_channel(b, val, i);
+
+   return nir_vec(b, comps, val->num_components);
+}
+
+static bool
+rusticl_lower_srgb_images_instr(nir_builder *b, nir_intrinsic_instr *intrins, void *_)
+{
+   switch (intrins->intrinsic) {
+   case nir_intrinsic_image_deref_load: {
+      if (nir_alu_type_get_base_type(nir_intrinsic_dest_type(intrins)) != nir_type_float)
+         return false;
+
+      b->cursor = nir_after_instr(&intrins->instr);
+      nir_def *val = &intrins->def;
+      nir_def *linear = rusticl_convert_srgb(b, val, false);
+      nir_def *res = nir_bcsel(b, rusticl_is_srgb_image(b, intrins), linear, val);
+      nir_def_rewrite_uses_after(val, res, res->parent_instr);
+      return true;
+   }
+   case nir_intrinsic_image_deref_store: {
+      if (nir_alu_type_get_base_type(nir_intrinsic_src_type(intrins)) != nir_type_float)
+         return false;
+
+      b->cursor = nir_before_instr(&intrins->instr);
+      nir_def *val = intrins->src[3].ssa;
+      nir_def *srgb = rusticl_convert_srgb(b, val, true);
+      nir_def *res = nir_bcsel(b, rusticl_is_srgb_image(b, intrins), srgb, val);
+      nir_src_rewrite(&intrins->src[3], res);
+      return true;
+   }
+   default:
+      return false;
+   }
+}
+
+bool
+rusticl_lower_srgb_images(nir_shader *nir)
+{
+   return nir_shader_intrinsics_pass(nir, rusticl_lower_srgb_images_instr,
+                                     nir_metadata_block_index | nir_metadata_dominance,
+                                     NULL);
+}
+
+static bool
+rusticl_lower_3d_images_instr(nir_builder *b, nir_intrinsic_instr *intrins, void *_)
+{
+   switch (intrins->intrinsic) {
+   case nir_intrinsic_image_deref_load:
+   case nir_intrinsic_image_deref_store:
+   case nir_intrinsic_image_deref_size:
+      break;
+   default:
+      return false;
+   }
+
+   if (nir_intrinsic_image_dim(intrins) != GLSL_SAMPLER_DIM_3D)
+      return false;
+
+   /* Written 3D images are created as 2D arrays with a layer per slice, so the z coordinate maps
+    * directly to the layer of a 2D array access.
+    */
+   nir_intrinsic_set_image_dim(intrins, GLSL_SAMPLER_DIM_2D);
+   nir_intrinsic_set_image_array(intrins, true);
+   return true;
+}
+
+bool
+rusticl_lower_3d_images(nir_shader *nir)
+{
+   bool progress = false;
+
+   nir_foreach_variable_with_modes(var, nir, nir_var_image) {
+      const struct glsl_type *type = glsl_without_array(var->type);
+      if (glsl_get_sampler_dim(type) != GLSL_SAMPLER_DIM_3D)
+         continue;
+
+      type = glsl_image_type(GLSL_SAMPLER_DIM_2D, true, glsl_get_sampler_result_type(type));
+      var->type = glsl_type_wrap_in_arrays(type, var->type);
+      progress = true;
+   }
+
+   if (progress)
+      nir_fixup_deref_types(nir);
+
+   progress |= nir_shader_intrinsics_pass(nir, rusticl_lower_3d_images_instr, nir_metadata_all,
+                                          NULL);
+   return progress;
+}
+
+nir_shader *
+rusticl_create_fill_buffer_shader(const nir_shader_compiler_options *options,
+                                  unsigned pattern_size, unsigned address_bits,
+                                  unsigned block_size)
+{
+   /* The kernel input is laid out as:
+    *   0: address of the first pattern to write
+    *   8: number of patterns to write as an uint
+    *  16: the pattern
+    */
+   const unsigned count_offset = 8;
+   const unsigned pattern_offset = 16;
+
+   assert(pattern_size % 4 == 0);
+
+   nir_builder b = nir_builder_init_simple_shader(MESA_SHADER_KERNEL, options,
+                                                  "rusticl_fill_buffer");
+   b.shader->info.internal = true;
+   b.shader->info.workgroup_size[0] = block_size;
+   b.shader->info.workgroup_size[1] = 1;
+   b.shader->info.workgroup_size[2] = 1;
+   b.shader->num_uniforms = pattern_offset + pattern_size;
+
+   nir_def *wg_id = nir_channel(&b, nir_load_workgroup_id(&b), 0);
+   nir_def *local_id = nir_channel(&b, nir_load_local_invocation_id(&b), 0);
+   nir_def *id = nir_iadd(&b, nir_imul_imm(&b, wg_id, block_size), local_id);
+   nir_def *count = nir_load_kernel_input(&b, 1, 32, nir_imm_int(&b, count_offset),
+                                          .align_mul = 4, .range = b.shader->num_uniforms);
+
+   nir_push_if(&b, nir_ult(&b, id, count));
+   {
+      nir_def *addr = nir_load_kernel_input(&b, 1, address_bits, nir_imm_int(&b, 0),
+                                            .align_mul = address_bits / 8,
+                                            .range = b.shader->num_uniforms);
+      addr = nir_iadd(&b, addr, nir_imul_imm(&b, nir_u2uN(&b, id, address_bits), pattern_size));
+
+      /* write the pattern in chunks of up to 4 dwords */
+      for (unsigned offset = 0; offset < pattern_size; offset += 16) {
+         unsigned comps = MIN2(pattern_size - offset, 16) / 4;
+         nir_def *val = nir_load_kernel_input(&b, comps, 32, nir_imm_int(&b, pattern_offset + offset),
+                                              .align_mul = 16,
+                                              .range = b.shader->num_uniforms);
+         nir_store_global(&b, nir_iadd_imm(&b, addr, offset), 4, val, nir_component_mask(comps));
+      }
+   }
+   nir_pop_if(&b, NULL);
+
+   return b.shader;
+}
@@ -13,3 +13,8 @@ struct rusticl_lower_state {
 
 bool rusticl_lower_intrinsics(nir_shader *nir, struct rusticl_lower_state *state);
 bool rusticl_lower_inputs(nir_shader *nir);
+bool rusticl_lower_srgb_images(nir_shader *nir);
+bool rusticl_lower_3d_images(nir_shader *nir);
+nir_shader *rusticl_create_fill_buffer_shader(const nir_shader_compiler_options *options,
+                                              unsigned pattern_size, unsigned address_bits,
+                                              unsigned block_size);
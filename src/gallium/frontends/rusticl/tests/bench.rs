@@ -0,0 +1,357 @@
+//! Micro benchmarks of hot paths going through the ICD entry points of rusticl.
+//!
+//! usage: rusticl_bench <path to the rusticl ICD> [benchmark names...]
+//!
+//! Every benchmark runs a couple of samples on the first device of the platform and reports the
+//! median and fastest time per iteration, so changes aimed at performance can be compared against
+//! a baseline. Setup work like creating buffers isn't part of the measured time.
+
+mod common;
+
+use common::*;
+use rusticl_opencl_gen::*;
+
+use std::env;
+use std::ffi::c_char;
+use std::ffi::c_void;
+use std::ffi::CString;
+use std::mem::size_of;
+use std::process::ExitCode;
+use std::ptr;
+use std::thread;
+use std::time::Duration;
+use std::time::Instant;
+
+const SAMPLES: usize = 10;
+
+type BenchResult = Result<Duration, String>;
+
+struct Bench {
+    name: &'static str,
+    iterations: usize,
+    /// Bytes processed per iteration, used to report the throughput.
+    bytes: usize,
+    run: fn(&Env, usize) -> BenchResult,
+}
+
+// SAFETY: all calls only get pointers to memory we own passed in, which is valid for the entire
+//         call and has the size specified.
+fn create_buffer(env: &Env, size: usize) -> Result<cl_mem, String> {
+    let mut err = 0;
+    let res = unsafe {
+        env.d.clCreateBuffer.unwrap()(
+            env.ctx,
+            CL_MEM_READ_WRITE.into(),
+            size,
+            ptr::null_mut(),
+            &mut err,
+        )
+    };
+    check(err, "clCreateBuffer")?;
+    Ok(res)
+}
+
+fn finish(env: &Env) -> Result<(), String> {
+    check(unsafe { env.d.clFinish.unwrap()(env.q) }, "clFinish")
+}
+
+/// Overhead of getting a command through the queue, without doing any actual work.
+fn enqueue_marker(env: &Env, iterations: usize) -> BenchResult {
+    finish(env)?;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let mut event = ptr::null_mut();
+        let err = unsafe {
+            env.d.clEnqueueMarkerWithWaitList.unwrap()(env.q, 0, ptr::null(), &mut event)
+        };
+        check(err, "clEnqueueMarkerWithWaitList")?;
+        unsafe { env.d.clReleaseEvent.unwrap()(event) };
+    }
+    finish(env)?;
+
+    Ok(start.elapsed())
+}
+
+/// Overhead of launching a kernel doing barely anything.
+fn enqueue_kernel(env: &Env, iterations: usize) -> BenchResult {
+    const SRC: &str = "kernel void empty(global int *a) { a[get_global_id(0)] = 0; }";
+
+    let mut err = 0;
+    let mut src = SRC.as_ptr().cast::<c_char>();
+    let prog = unsafe {
+        env.d.clCreateProgramWithSource.unwrap()(env.ctx, 1, &mut src, &SRC.len(), &mut err)
+    };
+    check(err, "clCreateProgramWithSource")?;
+
+    let err = unsafe {
+        env.d.clBuildProgram.unwrap()(prog, 1, &env.dev, ptr::null(), None, ptr::null_mut())
+    };
+    check(err, "clBuildProgram")?;
+
+    let name = CString::new("empty").unwrap();
+    let kernel = unsafe { env.d.clCreateKernel.unwrap()(prog, name.as_ptr(), &mut err) };
+    check(err, "clCreateKernel")?;
+
+    let buf = create_buffer(env, 64)?;
+    let err = unsafe {
+        env.d.clSetKernelArg.unwrap()(kernel, 0, size_of::<cl_mem>(), ptr::addr_of!(buf).cast())
+    };
+    check(err, "clSetKernelArg")?;
+
+    let gws = [16_usize];
+    let launch = || unsafe {
+        env.d.clEnqueueNDRangeKernel.unwrap()(
+            env.q,
+            kernel,
+            1,
+            ptr::null(),
+            gws.as_ptr(),
+            ptr::null(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+
+    // the first launch might still compile variants of the kernel
+    check(launch(), "clEnqueueNDRangeKernel")?;
+    finish(env)?;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        check(launch(), "clEnqueueNDRangeKernel")?;
+    }
+    finish(env)?;
+    let res = start.elapsed();
+
+    unsafe {
+        env.d.clReleaseMemObject.unwrap()(buf);
+        env.d.clReleaseKernel.unwrap()(kernel);
+        env.d.clReleaseProgram.unwrap()(prog);
+    }
+    Ok(res)
+}
+
+/// Latency of a blocking map and the following unmap of a small buffer.
+fn map_unmap(env: &Env, iterations: usize) -> BenchResult {
+    let size = 4096;
+    let buf = create_buffer(env, size)?;
+    finish(env)?;
+
+    let start = Instant::now();
+    for i in 0..iterations {
+        let flags = if i % 2 == 0 {
+            CL_MAP_READ
+        } else {
+            CL_MAP_WRITE
+        };
+
+        let mut err = 0;
+        let ptr = unsafe {
+            env.d.clEnqueueMapBuffer.unwrap()(
+                env.q,
+                buf,
+                CL_TRUE,
+                flags.into(),
+                0,
+                size,
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+                &mut err,
+            )
+        };
+        check(err, "clEnqueueMapBuffer")?;
+
+        let err = unsafe {
+            env.d.clEnqueueUnmapMemObject.unwrap()(env.q, buf, ptr, 0, ptr::null(), ptr::null_mut())
+        };
+        check(err, "clEnqueueUnmapMemObject")?;
+    }
+    finish(env)?;
+    let res = start.elapsed();
+
+    unsafe { env.d.clReleaseMemObject.unwrap()(buf) };
+    Ok(res)
+}
+
+const RECT_ROWS: usize = 1024;
+const RECT_ROW_SIZE: usize = 1024;
+
+/// Throughput of copying between host memory and buffers with differing row pitches, which can't
+/// be done with a single linear copy.
+fn read_rect(env: &Env, iterations: usize) -> BenchResult {
+    let buffer_pitch = RECT_ROW_SIZE + 64;
+    let host_pitch = RECT_ROW_SIZE + 128;
+    let buf = create_buffer(env, buffer_pitch * RECT_ROWS)?;
+    let mut host = vec![0_u8; host_pitch * RECT_ROWS];
+    let origin = [0_usize; 3];
+    let region = [RECT_ROW_SIZE, RECT_ROWS, 1];
+    finish(env)?;
+
+    let start = Instant::now();
+    for _ in 0..iterations {
+        let err = unsafe {
+            env.d.clEnqueueReadBufferRect.unwrap()(
+                env.q,
+                buf,
+                CL_TRUE,
+                origin.as_ptr(),
+                origin.as_ptr(),
+                region.as_ptr(),
+                buffer_pitch,
+                0,
+                host_pitch,
+                0,
+                host.as_mut_ptr().cast::<c_void>(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clEnqueueReadBufferRect")?;
+    }
+    let res = start.elapsed();
+
+    unsafe { env.d.clReleaseMemObject.unwrap()(buf) };
+    Ok(res)
+}
+
+/// Time it takes until a command waiting on a user event completes, after the event got set from
+/// another thread.
+fn event_signal(env: &Env, iterations: usize) -> BenchResult {
+    let mut res = Duration::ZERO;
+    for _ in 0..iterations {
+        let mut err = 0;
+        let user = unsafe { env.d.clCreateUserEvent.unwrap()(env.ctx, &mut err) };
+        check(err, "clCreateUserEvent")?;
+
+        let mut marker = ptr::null_mut();
+        let err =
+            unsafe { env.d.clEnqueueMarkerWithWaitList.unwrap()(env.q, 1, &user, &mut marker) };
+        check(err, "clEnqueueMarkerWithWaitList")?;
+        check(unsafe { env.d.clFlush.unwrap()(env.q) }, "clFlush")?;
+
+        // cl_event isn't Send, but the handle is safe to use from any thread.
+        let user_handle = user as usize;
+        let d = env.d;
+        let setter = thread::spawn(move || {
+            let start = Instant::now();
+            let err = unsafe {
+                d.clSetUserEventStatus.unwrap()(user_handle as cl_event, CL_COMPLETE as cl_int)
+            };
+            (start, err)
+        });
+
+        let err = unsafe { env.d.clWaitForEvents.unwrap()(1, &marker) };
+        let end = Instant::now();
+        let (start, set_err) = setter.join().unwrap();
+        check(set_err, "clSetUserEventStatus")?;
+        check(err, "clWaitForEvents")?;
+        res += end.saturating_duration_since(start);
+
+        unsafe {
+            env.d.clReleaseEvent.unwrap()(marker);
+            env.d.clReleaseEvent.unwrap()(user);
+        }
+    }
+
+    Ok(res)
+}
+
+const BENCHES: &[Bench] = &[
+    Bench {
+        name: "enqueue_marker",
+        iterations: 10000,
+        bytes: 0,
+        run: enqueue_marker,
+    },
+    Bench {
+        name: "enqueue_kernel",
+        iterations: 1000,
+        bytes: 0,
+        run: enqueue_kernel,
+    },
+    Bench {
+        name: "map_unmap",
+        iterations: 1000,
+        bytes: 0,
+        run: map_unmap,
+    },
+    Bench {
+        name: "read_rect",
+        iterations: 20,
+        bytes: RECT_ROWS * RECT_ROW_SIZE,
+        run: read_rect,
+    },
+    Bench {
+        name: "event_signal",
+        iterations: 100,
+        bytes: 0,
+        run: event_signal,
+    },
+];
+
+fn run_bench(env: &Env, bench: &Bench) -> Result<String, String> {
+    // warm up caches and lazily initialized state
+    (bench.run)(env, 1)?;
+
+    let mut samples = Vec::with_capacity(SAMPLES);
+    for _ in 0..SAMPLES {
+        let time = (bench.run)(env, bench.iterations)?;
+        samples.push(time / bench.iterations as u32);
+    }
+    samples.sort();
+
+    let median = samples[SAMPLES / 2];
+    let mut res = format!("median {median:.2?}, min {:.2?}", samples[0]);
+    if bench.bytes > 0 {
+        let mib = bench.bytes as f64 / (1 << 20) as f64;
+        res += &format!(", {:.1} MiB/s", mib / median.as_secs_f64());
+    }
+    Ok(res)
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let Some(icd) = args.next() else {
+        eprintln!("usage: rusticl_bench <path to the rusticl ICD> [benchmark names...]");
+        return ExitCode::FAILURE;
+    };
+    let filter: Vec<_> = args.collect();
+
+    let (d, dev) = match load_first_device(&icd) {
+        Ok(res) => res,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some(dev) = dev else {
+        eprintln!("no devices found, skipping");
+        return ExitCode::from(EXIT_SKIP);
+    };
+
+    let mut failed = false;
+    for bench in BENCHES {
+        if !filter.is_empty() && !filter.iter().any(|name| name == bench.name) {
+            continue;
+        }
+
+        match Env::new(d, dev).and_then(|env| run_bench(&env, bench)) {
+            Ok(res) => println!("{}: {res}", bench.name),
+            Err(err) => {
+                println!("{}: failed ({err})", bench.name);
+                failed = true;
+            }
+        }
+    }
+
+    if failed {
+        ExitCode::FAILURE
+    } else {
+        ExitCode::SUCCESS
+    }
+}
@@ -0,0 +1,183 @@
+//! Helpers shared by the executables in this directory and the replay tool, which all go through
+//! the ICD entry points of rusticl.
+
+use libc_rust_gen::dlopen;
+use libc_rust_gen::dlsym;
+use libc_rust_gen::RTLD_LOCAL;
+use libc_rust_gen::RTLD_NOW;
+use rusticl_opencl_gen::*;
+
+use std::ffi::CString;
+use std::mem;
+use std::ptr;
+
+// meson treats this exit code as a skipped test
+pub const EXIT_SKIP: u8 = 77;
+
+/// A queue on a single device together with its context, which might be shared with other
+/// devices.
+pub struct Env {
+    pub d: &'static cl_icd_dispatch,
+    pub dev: cl_device_id,
+    pub ctx: cl_context,
+    pub q: cl_command_queue,
+}
+
+pub fn check(err: cl_int, what: &str) -> Result<(), String> {
+    if err == CL_SUCCESS as cl_int {
+        Ok(())
+    } else {
+        Err(format!("{what} failed: {err}"))
+    }
+}
+
+fn load_platform(icd: &str) -> Result<cl_platform_id, String> {
+    let icd_name = CString::new(icd).unwrap();
+    let sym_name = CString::new("clIcdGetPlatformIDsKHR").unwrap();
+
+    // SAFETY: all strings are valid and we check the results
+    let get_platforms: clIcdGetPlatformIDsKHR_fn = unsafe {
+        let lib = dlopen(icd_name.as_ptr(), (RTLD_NOW | RTLD_LOCAL) as i32);
+        if lib.is_null() {
+            return Err(format!("failed to load {icd}"));
+        }
+        mem::transmute(dlsym(lib, sym_name.as_ptr()))
+    };
+
+    let get_platforms = get_platforms.ok_or(format!("{icd} is not an OpenCL ICD"))?;
+    let mut platform = ptr::null_mut();
+    // SAFETY: we pass in valid pointers for one platform
+    let err = unsafe { get_platforms(1, &mut platform, ptr::null_mut()) };
+    if err != CL_SUCCESS as cl_int || platform.is_null() {
+        return Err(format!("{icd} didn't return any platform: {err}"));
+    }
+
+    Ok(platform)
+}
+
+/// Returns an empty list if the platform doesn't expose any device.
+fn all_devices(d: &cl_icd_dispatch, platform: cl_platform_id) -> Vec<cl_device_id> {
+    let mut cnt = 0;
+    // SAFETY: we pass in valid pointers for the amount of devices
+    let err = unsafe {
+        d.clGetDeviceIDs.unwrap()(
+            platform,
+            CL_DEVICE_TYPE_ALL.into(),
+            0,
+            ptr::null_mut(),
+            &mut cnt,
+        )
+    };
+    if err != CL_SUCCESS as cl_int || cnt == 0 {
+        return Vec::new();
+    }
+
+    let mut devs = vec![ptr::null_mut(); cnt as usize];
+    // SAFETY: `devs` has space for `cnt` devices
+    let err = unsafe {
+        d.clGetDeviceIDs.unwrap()(
+            platform,
+            CL_DEVICE_TYPE_ALL.into(),
+            cnt,
+            devs.as_mut_ptr(),
+            ptr::null_mut(),
+        )
+    };
+    if err != CL_SUCCESS as cl_int {
+        return Vec::new();
+    }
+
+    devs
+}
+
+/// Loads the ICD at `icd` and returns the dispatch table together with all devices of the
+/// platform.
+pub fn load_devices(icd: &str) -> Result<(&'static cl_icd_dispatch, Vec<cl_device_id>), String> {
+    let platform = load_platform(icd)?;
+
+    // SAFETY: every ICD object starts with a pointer to its dispatch table
+    let d = unsafe { *platform.cast::<&'static cl_icd_dispatch>() };
+    Ok((d, all_devices(d, platform)))
+}
+
+/// Loads the ICD at `icd` and returns the dispatch table together with the first device, or
+/// `None` for the device if the platform doesn't expose any.
+pub fn load_first_device(
+    icd: &str,
+) -> Result<(&'static cl_icd_dispatch, Option<cl_device_id>), String> {
+    let (d, devs) = load_devices(icd)?;
+    Ok((d, devs.first().copied()))
+}
+
+impl Env {
+    pub fn new(d: &'static cl_icd_dispatch, dev: cl_device_id) -> Result<Self, String> {
+        let mut err = 0;
+        // SAFETY: all pointers are valid for the duration of the calls
+        let ctx = unsafe {
+            d.clCreateContext.unwrap()(ptr::null(), 1, &dev, None, ptr::null_mut(), &mut err)
+        };
+        check(err, "clCreateContext")?;
+
+        let res = Self::with_context(d, ctx, dev);
+        // the environment holds its own reference
+        unsafe { d.clReleaseContext.unwrap()(ctx) };
+        res
+    }
+
+    /// Creates a context with all `devs` and returns a queue for each of them.
+    // not every executable runs tests on multiple devices
+    #[allow(dead_code)]
+    pub fn new_shared(
+        d: &'static cl_icd_dispatch,
+        devs: &[cl_device_id],
+    ) -> Result<Vec<Self>, String> {
+        let mut err = 0;
+        // SAFETY: all pointers are valid for the duration of the calls
+        let ctx = unsafe {
+            d.clCreateContext.unwrap()(
+                ptr::null(),
+                devs.len() as cl_uint,
+                devs.as_ptr(),
+                None,
+                ptr::null_mut(),
+                &mut err,
+            )
+        };
+        check(err, "clCreateContext")?;
+
+        let res = devs
+            .iter()
+            .map(|&dev| Self::with_context(d, ctx, dev))
+            .collect();
+        unsafe { d.clReleaseContext.unwrap()(ctx) };
+        res
+    }
+
+    fn with_context(
+        d: &'static cl_icd_dispatch,
+        ctx: cl_context,
+        dev: cl_device_id,
+    ) -> Result<Self, String> {
+        let mut err = 0;
+        let q = unsafe { d.clCreateCommandQueue.unwrap()(ctx, dev, 0, &mut err) };
+        check(err, "clCreateCommandQueue")?;
+        unsafe { d.clRetainContext.unwrap()(ctx) };
+
+        Ok(Self {
+            d: d,
+            dev: dev,
+            ctx: ctx,
+            q: q,
+        })
+    }
+}
+
+impl Drop for Env {
+    fn drop(&mut self) {
+        unsafe {
+            self.d.clFinish.unwrap()(self.q);
+            self.d.clReleaseCommandQueue.unwrap()(self.q);
+            self.d.clReleaseContext.unwrap()(self.ctx);
+        }
+    }
+}
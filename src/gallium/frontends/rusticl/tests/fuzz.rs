@@ -0,0 +1,545 @@
+//! Fuzzing harness for the parameter validation of the ICD entry points of rusticl.
+//!
+//! usage: rusticl_fuzz <path to the rusticl ICD> [input files...]
+//!
+//! Every input is decoded into a sequence of buffer, mapping and kernel related calls with mostly
+//! unchecked parameters, which are run against the first device of the platform. Returned errors
+//! are fine, but crashes, panics or sanitizer reports are bugs in rusticl.
+//!
+//! Without any input files a fixed set of pseudo random inputs is run, which is what the meson
+//! test does. Otherwise every file is run as one input, so this can be used with file based
+//! fuzzers, e.g. `afl-fuzz -i seeds -o findings -- rusticl_fuzz librusticl.so @@`.
+//!
+//! Inputs never pass in invalid object handles or host memory smaller than the size specified,
+//! as the behavior of both is undefined and can't be validated.
+
+mod common;
+
+use common::*;
+use rusticl_opencl_gen::*;
+
+use std::env;
+use std::ffi::c_char;
+use std::ffi::c_void;
+use std::ffi::CString;
+use std::fs;
+use std::mem::size_of;
+use std::process::ExitCode;
+use std::ptr;
+
+/// Upper bound of all sizes used for host memory.
+const MAX_HOST_SIZE: usize = 1 << 16;
+const RANDOM_INPUTS: u64 = 256;
+const RANDOM_INPUT_SIZE: usize = 512;
+
+// the kernel itself must not be able to access memory out of bounds, no matter the arguments.
+const KERNEL_SRC: &str = "
+kernel void fuzz(global uint *a, uint b, uint c, local uint *l) {
+    if (a && get_global_id(0) == 0)
+        a[0] = b + c;
+}";
+
+/// Decodes values from the fuzzer input, returning zeroes once all of it was consumed.
+struct Input<'a> {
+    data: &'a [u8],
+}
+
+impl Input<'_> {
+    fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    fn u8(&mut self) -> u8 {
+        let Some((&val, rest)) = self.data.split_first() else {
+            return 0;
+        };
+        self.data = rest;
+        val
+    }
+
+    fn bool(&mut self) -> bool {
+        self.u8() & 1 != 0
+    }
+
+    fn u32(&mut self) -> u32 {
+        u32::from_le_bytes([self.u8(), self.u8(), self.u8(), self.u8()])
+    }
+
+    /// A size or offset not used to access host memory. Mostly small, but with a good chance of
+    /// hitting edge cases.
+    fn size(&mut self) -> usize {
+        match self.u8() % 8 {
+            0 => 0,
+            1 => usize::MAX - self.u8() as usize,
+            2 => 1 << (self.u8() % usize::BITS as u8),
+            _ => self.host_size(),
+        }
+    }
+
+    /// A size used to access host memory.
+    fn host_size(&mut self) -> usize {
+        self.u32() as usize % MAX_HOST_SIZE
+    }
+
+    /// Picks one of `items`, or `None` once in a while.
+    fn pick<T: Copy>(&mut self, items: &[T]) -> Option<T> {
+        let idx = self.u8() as usize;
+        if items.is_empty() || idx == u8::MAX as usize {
+            None
+        } else {
+            Some(items[idx % items.len()])
+        }
+    }
+
+    fn mem_flags(&mut self) -> cl_mem_flags {
+        // all valid flags, with some invalid ones every now and then
+        let mut flags = self.u32() as cl_mem_flags & 0x3ff;
+        if self.u8() == u8::MAX {
+            flags |= 1 << (self.u8() % 64);
+        }
+        flags
+    }
+}
+
+struct Fuzzer {
+    env: Env,
+    prog: cl_program,
+}
+
+/// Objects created while running a single input.
+struct Run<'a> {
+    f: &'a Fuzzer,
+    q: cl_command_queue,
+    kernel: cl_kernel,
+    mems: Vec<cl_mem>,
+    maps: Vec<(cl_mem, *mut c_void)>,
+    events: Vec<cl_event>,
+    /// Host memory the implementation might still access, only freed once everything finished.
+    host: Vec<Vec<u8>>,
+}
+
+// SAFETY: all calls only get pointers to memory we own passed in, which is valid for the entire
+//         call, or until the queue finished for non blocking calls, and has the size specified.
+impl Fuzzer {
+    fn new(d: &'static cl_icd_dispatch, dev: cl_device_id) -> Result<Self, String> {
+        let env = Env::new(d, dev)?;
+        let mut err = 0;
+        let mut src = KERNEL_SRC.as_ptr().cast::<c_char>();
+        let prog = unsafe {
+            d.clCreateProgramWithSource.unwrap()(env.ctx, 1, &mut src, &KERNEL_SRC.len(), &mut err)
+        };
+        check(err, "clCreateProgramWithSource")?;
+
+        let res = Self {
+            env: env,
+            prog: prog,
+        };
+
+        let err =
+            unsafe { d.clBuildProgram.unwrap()(prog, 1, &dev, ptr::null(), None, ptr::null_mut()) };
+        check(err, "clBuildProgram")?;
+
+        Ok(res)
+    }
+
+    fn run(&self, data: &[u8]) {
+        let mut err = 0;
+        let env = &self.env;
+        let q = unsafe { env.d.clCreateCommandQueue.unwrap()(env.ctx, env.dev, 0, &mut err) };
+        assert_eq!(err, CL_SUCCESS as cl_int, "clCreateCommandQueue failed");
+
+        let name = CString::new("fuzz").unwrap();
+        let kernel = unsafe { env.d.clCreateKernel.unwrap()(self.prog, name.as_ptr(), &mut err) };
+        assert_eq!(err, CL_SUCCESS as cl_int, "clCreateKernel failed");
+
+        let mut run = Run {
+            f: self,
+            q: q,
+            kernel: kernel,
+            mems: Vec::new(),
+            maps: Vec::new(),
+            events: Vec::new(),
+            host: Vec::new(),
+        };
+
+        let mut input = Input { data: data };
+        while !input.is_empty() {
+            run.step(&mut input);
+        }
+    }
+}
+
+impl Drop for Fuzzer {
+    fn drop(&mut self) {
+        unsafe { self.env.d.clReleaseProgram.unwrap()(self.prog) };
+    }
+}
+
+// SAFETY: see Fuzzer
+impl Run<'_> {
+    fn mem(&self, input: &mut Input) -> cl_mem {
+        input.pick(&self.mems).unwrap_or(ptr::null_mut())
+    }
+
+    /// Returns the wait list to pass into enqueue calls, inconsistent ones included.
+    fn wait_list(&self, input: &mut Input) -> (cl_uint, *const cl_event) {
+        match input.u8() % 4 {
+            0 if !self.events.is_empty() => {
+                let cnt = input.u8() as usize % self.events.len() + 1;
+                (cnt as cl_uint, self.events.as_ptr())
+            }
+            1 => (1, ptr::null()),
+            2 => (0, self.events.as_ptr()),
+            _ => (0, ptr::null()),
+        }
+    }
+
+    /// Returns where enqueue calls should return their event.
+    fn event_ptr(&self, input: &mut Input, event: &mut cl_event) -> *mut cl_event {
+        if input.bool() {
+            event
+        } else {
+            ptr::null_mut()
+        }
+    }
+
+    fn add_event(&mut self, event: cl_event) {
+        if !event.is_null() {
+            self.events.push(event);
+        }
+    }
+
+    /// Allocates host memory staying alive until the end of this run.
+    fn host_mem(&mut self, size: usize) -> *mut c_void {
+        self.host.push(vec![0; size]);
+        self.host.last_mut().unwrap().as_mut_ptr().cast()
+    }
+
+    fn step(&mut self, input: &mut Input) {
+        let d = self.f.env.d;
+        let mut err = 0;
+        let mut event = ptr::null_mut();
+
+        match input.u8() % 13 {
+            0 => {
+                let flags = input.mem_flags();
+                let (size, host_ptr) = if input.bool() {
+                    let size = input.host_size();
+                    (size, self.host_mem(size))
+                } else {
+                    (input.size(), ptr::null_mut())
+                };
+                let mem = unsafe {
+                    d.clCreateBuffer.unwrap()(self.f.env.ctx, flags, size, host_ptr, &mut err)
+                };
+                if err == CL_SUCCESS as cl_int {
+                    self.mems.push(mem);
+                }
+            }
+            1 => {
+                let region = cl_buffer_region {
+                    origin: input.size(),
+                    size: input.size(),
+                };
+                let create_type = if input.u8() == u8::MAX {
+                    input.u32()
+                } else {
+                    CL_BUFFER_CREATE_TYPE_REGION
+                };
+                let mem = unsafe {
+                    d.clCreateSubBuffer.unwrap()(
+                        self.mem(input),
+                        input.mem_flags(),
+                        create_type,
+                        ptr::addr_of!(region).cast(),
+                        &mut err,
+                    )
+                };
+                if err == CL_SUCCESS as cl_int {
+                    self.mems.push(mem);
+                }
+            }
+            2 => {
+                if !self.mems.is_empty() {
+                    let mem = self.mems.swap_remove(input.u8() as usize % self.mems.len());
+                    // mappings of released objects are gone for good
+                    self.maps.retain(|&(m, _)| m != mem);
+                    unsafe { d.clReleaseMemObject.unwrap()(mem) };
+                }
+            }
+            3 | 4 => {
+                let write = input.bool();
+                let mem = self.mem(input);
+                let blocking = input.bool().into();
+                let offset = input.size();
+                let size = input.host_size();
+                let host_ptr = self.host_mem(size);
+                let (num_events, events) = self.wait_list(input);
+                let event_ptr = self.event_ptr(input, &mut event);
+                unsafe {
+                    if write {
+                        d.clEnqueueWriteBuffer.unwrap()(
+                            self.q, mem, blocking, offset, size, host_ptr, num_events, events,
+                            event_ptr,
+                        );
+                    } else {
+                        d.clEnqueueReadBuffer.unwrap()(
+                            self.q, mem, blocking, offset, size, host_ptr, num_events, events,
+                            event_ptr,
+                        );
+                    }
+                }
+            }
+            5 => {
+                let (num_events, events) = self.wait_list(input);
+                unsafe {
+                    d.clEnqueueCopyBuffer.unwrap()(
+                        self.q,
+                        self.mem(input),
+                        self.mem(input),
+                        input.size(),
+                        input.size(),
+                        input.size(),
+                        num_events,
+                        events,
+                        self.event_ptr(input, &mut event),
+                    );
+                }
+            }
+            6 => {
+                let pattern: [u8; 256] = [input.u8(); 256];
+                let pattern_size = match input.u8() % 10 {
+                    0 => 0,
+                    1 => 3,
+                    2 => 256,
+                    val => 1 << (val - 3),
+                };
+                let (num_events, events) = self.wait_list(input);
+                unsafe {
+                    d.clEnqueueFillBuffer.unwrap()(
+                        self.q,
+                        self.mem(input),
+                        pattern.as_ptr().cast(),
+                        pattern_size,
+                        input.size(),
+                        input.size(),
+                        num_events,
+                        events,
+                        self.event_ptr(input, &mut event),
+                    );
+                }
+            }
+            7 => {
+                let mem = self.mem(input);
+                let blocking = input.bool().into();
+                let flags = input.u8() as cl_map_flags % 8;
+                let (num_events, events) = self.wait_list(input);
+                let ptr = unsafe {
+                    d.clEnqueueMapBuffer.unwrap()(
+                        self.q,
+                        mem,
+                        blocking,
+                        flags,
+                        input.size(),
+                        input.size(),
+                        num_events,
+                        events,
+                        self.event_ptr(input, &mut event),
+                        &mut err,
+                    )
+                };
+                if err == CL_SUCCESS as cl_int {
+                    self.maps.push((mem, ptr));
+                }
+            }
+            8 => {
+                let (mem, ptr) = if !self.maps.is_empty() && input.bool() {
+                    self.maps.swap_remove(input.u8() as usize % self.maps.len())
+                } else {
+                    // a pointer which was never mapped
+                    (self.mem(input), self.host_mem(1))
+                };
+                let (num_events, events) = self.wait_list(input);
+                unsafe {
+                    d.clEnqueueUnmapMemObject.unwrap()(
+                        self.q,
+                        mem,
+                        ptr,
+                        num_events,
+                        events,
+                        self.event_ptr(input, &mut event),
+                    );
+                }
+            }
+            9 => {
+                let mut value = [0_u8; 16];
+                let (idx, size, value_ptr) = match input.u8() % 3 {
+                    0 => {
+                        let mem = self.mem(input);
+                        value[..size_of::<cl_mem>()].copy_from_slice(&(mem as usize).to_ne_bytes());
+                        let size = if input.bool() {
+                            size_of::<cl_mem>()
+                        } else {
+                            input.u8() as usize % value.len()
+                        };
+                        (0, size, value.as_ptr())
+                    }
+                    // local memory, or no value at all
+                    1 => (input.u8() as cl_uint % 6, input.size(), ptr::null()),
+                    // only for arguments which can't be turned into object handles
+                    _ => {
+                        value.fill_with(|| input.u8());
+                        let idx = [1, 2, 4, 5][input.u8() as usize % 4];
+                        (idx, input.u8() as usize % (value.len() + 1), value.as_ptr())
+                    }
+                };
+                unsafe {
+                    d.clSetKernelArg.unwrap()(self.kernel, idx, size, value_ptr.cast());
+                }
+            }
+            10 => {
+                let work_dim = input.u8() as cl_uint % 5;
+                let offsets: [usize; 4] = [input.size(), input.size(), input.size(), input.size()];
+                let global: [usize; 4] = [0; 4].map(|_| input.u8() as usize % 65);
+                let local: [usize; 4] = [0; 4].map(|_| input.u8() as usize % 65);
+                let offsets = if input.bool() {
+                    offsets.as_ptr()
+                } else {
+                    ptr::null()
+                };
+                let local = if input.bool() {
+                    local.as_ptr()
+                } else {
+                    ptr::null()
+                };
+                let (num_events, events) = self.wait_list(input);
+                unsafe {
+                    d.clEnqueueNDRangeKernel.unwrap()(
+                        self.q,
+                        self.kernel,
+                        work_dim,
+                        offsets,
+                        global.as_ptr(),
+                        local,
+                        num_events,
+                        events,
+                        self.event_ptr(input, &mut event),
+                    );
+                }
+            }
+            11 => {
+                let mut res = [0_u8; 64];
+                let param = if input.bool() {
+                    CL_MEM_TYPE + input.u8() as cl_mem_info % 16
+                } else {
+                    input.u32()
+                };
+                let size = input.u8() as usize % (res.len() + 1);
+                let res_ptr = if input.bool() {
+                    res.as_mut_ptr().cast()
+                } else {
+                    ptr::null_mut()
+                };
+                let mut ret_size = 0;
+                unsafe {
+                    d.clGetMemObjectInfo.unwrap()(
+                        self.mem(input),
+                        param,
+                        size,
+                        res_ptr,
+                        &mut ret_size,
+                    );
+                }
+            }
+            _ => unsafe {
+                if input.bool() {
+                    d.clFinish.unwrap()(self.q);
+                } else {
+                    d.clFlush.unwrap()(self.q);
+                }
+            },
+        }
+
+        self.add_event(event);
+    }
+}
+
+impl Drop for Run<'_> {
+    fn drop(&mut self) {
+        let d = self.f.env.d;
+        unsafe {
+            d.clFinish.unwrap()(self.q);
+            for &event in &self.events {
+                d.clReleaseEvent.unwrap()(event);
+            }
+            for &mem in &self.mems {
+                d.clReleaseMemObject.unwrap()(mem);
+            }
+            d.clReleaseKernel.unwrap()(self.kernel);
+            d.clReleaseCommandQueue.unwrap()(self.q);
+        }
+    }
+}
+
+/// Simple xorshift, so the random inputs are the same on every run.
+fn random_input(seed: u64) -> Vec<u8> {
+    let mut state = seed.wrapping_mul(0x9e3779b97f4a7c15) | 1;
+    let mut next = || {
+        state ^= state << 13;
+        state ^= state >> 7;
+        state ^= state << 17;
+        state
+    };
+
+    let len = next() as usize % RANDOM_INPUT_SIZE;
+    (0..len).map(|_| next() as u8).collect()
+}
+
+fn main() -> ExitCode {
+    let mut args = env::args().skip(1);
+    let Some(icd) = args.next() else {
+        eprintln!("usage: rusticl_fuzz <path to the rusticl ICD> [input files...]");
+        return ExitCode::FAILURE;
+    };
+
+    let (d, dev) = match load_first_device(&icd) {
+        Ok(res) => res,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some(dev) = dev else {
+        eprintln!("no devices found, skipping");
+        return ExitCode::from(EXIT_SKIP);
+    };
+
+    let fuzzer = match Fuzzer::new(d, dev) {
+        Ok(fuzzer) => fuzzer,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let files: Vec<_> = args.collect();
+    if files.is_empty() {
+        for seed in 0..RANDOM_INPUTS {
+            fuzzer.run(&random_input(seed));
+        }
+        println!("ran {RANDOM_INPUTS} random inputs");
+    } else {
+        for file in files {
+            match fs::read(&file) {
+                Ok(data) => fuzzer.run(&data),
+                Err(err) => {
+                    eprintln!("failed to read {file}: {err}");
+                    return ExitCode::FAILURE;
+                }
+            }
+        }
+    }
+
+    ExitCode::SUCCESS
+}
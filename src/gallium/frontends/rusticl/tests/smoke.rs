@@ -0,0 +1,1684 @@
+//! End-to-end smoke tests going through the ICD entry points of rusticl.
+//!
+//! usage: rusticl_smoke <path to the rusticl ICD>
+//!
+//! Every test runs against the first device of the platform with its own context and queue. Tests
+//! of memory shared across devices use a single context with the first two devices and are
+//! skipped if the platform has fewer. This is not meant to replace the CTS, but to catch obvious
+//! regressions without having to run it.
+
+mod common;
+
+use common::*;
+use rusticl_opencl_gen::*;
+
+use std::cmp;
+use std::env;
+use std::ffi::c_char;
+use std::ffi::c_void;
+use std::ffi::CString;
+use std::mem::size_of;
+use std::mem::transmute_copy;
+use std::process::ExitCode;
+use std::ptr;
+
+type TestResult = Result<(), String>;
+
+fn expect_err(err: cl_int, expected: cl_int, what: &str) -> TestResult {
+    if err == expected {
+        Ok(())
+    } else {
+        Err(format!("{what} returned {err} instead of {expected}"))
+    }
+}
+
+fn expect_eq<T: PartialEq>(res: &[T], expected: &[T], what: &str) -> TestResult {
+    if res == expected {
+        Ok(())
+    } else {
+        Err(format!("{what} returned unexpected data"))
+    }
+}
+
+fn pattern(size: usize, seed: u8) -> Vec<u8> {
+    (0..size)
+        .map(|i| (i as u8).wrapping_mul(7) ^ seed)
+        .collect()
+}
+
+// SAFETY: all calls only get pointers to memory we own passed in, which is valid for the entire
+//         call and has the size specified.
+impl Env {
+    fn has_images(&self) -> bool {
+        let mut res: cl_bool = CL_FALSE;
+        let err = unsafe {
+            self.d.clGetDeviceInfo.unwrap()(
+                self.dev,
+                CL_DEVICE_IMAGE_SUPPORT,
+                size_of::<cl_bool>(),
+                ptr::addr_of_mut!(res).cast(),
+                ptr::null_mut(),
+            )
+        };
+        err == CL_SUCCESS as cl_int && res == CL_TRUE
+    }
+
+    fn info_u32(&self, param: cl_device_info) -> Result<cl_uint, String> {
+        let mut res: cl_uint = 0;
+        let err = unsafe {
+            self.d.clGetDeviceInfo.unwrap()(
+                self.dev,
+                param,
+                size_of::<cl_uint>(),
+                ptr::addr_of_mut!(res).cast(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clGetDeviceInfo")?;
+        Ok(res)
+    }
+
+    fn has_extension(&self, ext: &str) -> bool {
+        let mut size = 0;
+        let err = unsafe {
+            self.d.clGetDeviceInfo.unwrap()(
+                self.dev,
+                CL_DEVICE_EXTENSIONS,
+                0,
+                ptr::null_mut(),
+                &mut size,
+            )
+        };
+        if err != CL_SUCCESS as cl_int {
+            return false;
+        }
+
+        let mut res = vec![0u8; size];
+        let err = unsafe {
+            self.d.clGetDeviceInfo.unwrap()(
+                self.dev,
+                CL_DEVICE_EXTENSIONS,
+                size,
+                res.as_mut_ptr().cast(),
+                ptr::null_mut(),
+            )
+        };
+        err == CL_SUCCESS as cl_int
+            && String::from_utf8_lossy(&res)
+                .trim_end_matches('\0')
+                .split(' ')
+                .any(|e| e == ext)
+    }
+
+    /// Looks up the extension function `name` of the platform. `T` has to be its function
+    /// pointer type.
+    fn ext_fn<T>(&self, name: &str) -> Result<T, String> {
+        let mut platform: cl_platform_id = ptr::null_mut();
+        let err = unsafe {
+            self.d.clGetDeviceInfo.unwrap()(
+                self.dev,
+                CL_DEVICE_PLATFORM,
+                size_of::<cl_platform_id>(),
+                ptr::addr_of_mut!(platform).cast(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clGetDeviceInfo")?;
+
+        let c_name = CString::new(name).unwrap();
+        let res = unsafe {
+            self.d.clGetExtensionFunctionAddressForPlatform.unwrap()(platform, c_name.as_ptr())
+        };
+        if res.is_null() {
+            return Err(format!("{name} is not exposed"));
+        }
+
+        Ok(unsafe { transmute_copy(&res) })
+    }
+
+    fn create_buffer(&self, size: usize, data: Option<&[u8]>) -> Result<cl_mem, String> {
+        let mut err = 0;
+        let (flags, host_ptr) = match data {
+            Some(data) => (CL_MEM_COPY_HOST_PTR, data.as_ptr() as *mut c_void),
+            None => (0, ptr::null_mut()),
+        };
+        let flags = cl_mem_flags::from(CL_MEM_READ_WRITE | flags);
+        let res =
+            unsafe { self.d.clCreateBuffer.unwrap()(self.ctx, flags, size, host_ptr, &mut err) };
+        check(err, "clCreateBuffer")?;
+        Ok(res)
+    }
+
+    fn create_image(&self, width: usize, height: usize) -> Result<cl_mem, String> {
+        let mut err = 0;
+        let format = cl_image_format {
+            image_channel_order: CL_RGBA,
+            image_channel_data_type: CL_UNORM_INT8,
+        };
+        let desc = cl_image_desc {
+            image_type: CL_MEM_OBJECT_IMAGE2D,
+            image_width: width,
+            image_height: height,
+            ..Default::default()
+        };
+        let res = unsafe {
+            self.d.clCreateImage.unwrap()(
+                self.ctx,
+                CL_MEM_READ_WRITE.into(),
+                &format,
+                &desc,
+                ptr::null_mut(),
+                &mut err,
+            )
+        };
+        check(err, "clCreateImage")?;
+        Ok(res)
+    }
+
+    fn write_buffer(&self, mem: cl_mem, offset: usize, data: &[u8]) -> TestResult {
+        let err = unsafe {
+            self.d.clEnqueueWriteBuffer.unwrap()(
+                self.q,
+                mem,
+                CL_TRUE,
+                offset,
+                data.len(),
+                data.as_ptr().cast(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clEnqueueWriteBuffer")
+    }
+
+    fn read_buffer(&self, mem: cl_mem, offset: usize, size: usize) -> Result<Vec<u8>, String> {
+        let mut res = vec![0; size];
+        let err = unsafe {
+            self.d.clEnqueueReadBuffer.unwrap()(
+                self.q,
+                mem,
+                CL_TRUE,
+                offset,
+                size,
+                res.as_mut_ptr().cast(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clEnqueueReadBuffer")?;
+        Ok(res)
+    }
+
+    fn map_buffer(
+        &self,
+        mem: cl_mem,
+        flags: u32,
+        offset: usize,
+        size: usize,
+    ) -> Result<*mut c_void, cl_int> {
+        let mut err = 0;
+        let res = unsafe {
+            self.d.clEnqueueMapBuffer.unwrap()(
+                self.q,
+                mem,
+                CL_TRUE,
+                flags.into(),
+                offset,
+                size,
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+                &mut err,
+            )
+        };
+        if err == CL_SUCCESS as cl_int {
+            Ok(res)
+        } else {
+            Err(err)
+        }
+    }
+
+    fn unmap(&self, mem: cl_mem, ptr: *mut c_void) -> cl_int {
+        unsafe {
+            self.d.clEnqueueUnmapMemObject.unwrap()(
+                self.q,
+                mem,
+                ptr,
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        }
+    }
+
+    fn create_sub_buffer(&self, mem: cl_mem, origin: usize, size: usize) -> Result<cl_mem, String> {
+        let region = cl_buffer_region {
+            origin: origin,
+            size: size,
+        };
+        let mut err = 0;
+        let res = unsafe {
+            self.d.clCreateSubBuffer.unwrap()(
+                mem,
+                CL_MEM_READ_WRITE.into(),
+                CL_BUFFER_CREATE_TYPE_REGION,
+                ptr::addr_of!(region).cast(),
+                &mut err,
+            )
+        };
+        check(err, "clCreateSubBuffer")?;
+        Ok(res)
+    }
+
+    fn finish(&self) -> TestResult {
+        check(unsafe { self.d.clFinish.unwrap()(self.q) }, "clFinish")
+    }
+
+    fn release_mem(&self, mem: cl_mem) {
+        unsafe { self.d.clReleaseMemObject.unwrap()(mem) };
+    }
+}
+
+fn buffer_round_trip(env: &Env) -> TestResult {
+    let data = pattern(4096, 0x5a);
+    let src = env.create_buffer(data.len(), Some(&data))?;
+    let dst = env.create_buffer(data.len(), None)?;
+
+    expect_eq(
+        &env.read_buffer(src, 0, data.len())?,
+        &data,
+        "reading COPY_HOST_PTR buffer",
+    )?;
+
+    let update = pattern(256, 0xa5);
+    env.write_buffer(src, 1024, &update)?;
+
+    let err = unsafe {
+        env.d.clEnqueueCopyBuffer.unwrap()(
+            env.q,
+            src,
+            dst,
+            0,
+            0,
+            data.len(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueCopyBuffer")?;
+
+    let mut expected = data.clone();
+    expected[1024..1280].copy_from_slice(&update);
+    expect_eq(
+        &env.read_buffer(dst, 0, data.len())?,
+        &expected,
+        "reading copied buffer",
+    )?;
+
+    env.release_mem(src);
+    env.release_mem(dst);
+    Ok(())
+}
+
+/// A non-blocking read followed by a write from the same host memory has to write the data just
+/// read on in-order queues.
+fn read_then_write(env: &Env) -> TestResult {
+    let data = pattern(4096, 0x3c);
+    let src = env.create_buffer(data.len(), Some(&data))?;
+    let dst = env.create_buffer(data.len(), Some(&[0; 4096]))?;
+    let mut host = vec![0u8; data.len()];
+
+    // two pending reads, the write only overlaps the second one
+    for (i, chunk) in host.chunks_mut(data.len() / 2).enumerate() {
+        let err = unsafe {
+            env.d.clEnqueueReadBuffer.unwrap()(
+                env.q,
+                src,
+                CL_FALSE,
+                i * chunk.len(),
+                chunk.len(),
+                chunk.as_mut_ptr().cast(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clEnqueueReadBuffer")?;
+    }
+
+    let err = unsafe {
+        env.d.clEnqueueWriteBuffer.unwrap()(
+            env.q,
+            dst,
+            CL_FALSE,
+            data.len() / 2,
+            data.len() / 2,
+            host[data.len() / 2..].as_ptr().cast(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueWriteBuffer")?;
+    env.finish()?;
+
+    expect_eq(&host, &data, "non-blocking clEnqueueReadBuffer")?;
+    expect_eq(
+        &env.read_buffer(dst, data.len() / 2, data.len() / 2)?,
+        &data[data.len() / 2..],
+        "writing from memory of a previous read",
+    )?;
+
+    env.release_mem(src);
+    env.release_mem(dst);
+    Ok(())
+}
+
+fn buffer_fill(env: &Env) -> TestResult {
+    let buf = env.create_buffer(1024, Some(&[0; 1024]))?;
+    let fill: u32 = 0xdeadbeef;
+    let err = unsafe {
+        env.d.clEnqueueFillBuffer.unwrap()(
+            env.q,
+            buf,
+            ptr::addr_of!(fill).cast(),
+            size_of::<u32>(),
+            256,
+            512,
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueFillBuffer")?;
+
+    let mut expected = vec![0; 1024];
+    for chunk in expected[256..768].chunks_exact_mut(size_of::<u32>()) {
+        chunk.copy_from_slice(&fill.to_ne_bytes());
+    }
+    expect_eq(
+        &env.read_buffer(buf, 0, 1024)?,
+        &expected,
+        "reading filled buffer",
+    )?;
+
+    env.release_mem(buf);
+    Ok(())
+}
+
+fn map_unmap(env: &Env) -> TestResult {
+    let size = 4096;
+    let buf = env.create_buffer(size, None)?;
+    let data = pattern(size, 0x33);
+
+    let ptr = env
+        .map_buffer(buf, CL_MAP_WRITE_INVALIDATE_REGION, 0, size)
+        .map_err(|err| format!("clEnqueueMapBuffer for writing failed: {err}"))?;
+    unsafe { ptr::copy_nonoverlapping(data.as_ptr(), ptr.cast(), size) };
+    check(env.unmap(buf, ptr), "clEnqueueUnmapMemObject")?;
+
+    expect_eq(
+        &env.read_buffer(buf, 0, size)?,
+        &data,
+        "reading buffer written through map",
+    )?;
+
+    // mapping the same region twice has to work and both mappings need their own unmap
+    let ptr1 = env
+        .map_buffer(buf, CL_MAP_READ, 512, 512)
+        .map_err(|err| format!("clEnqueueMapBuffer for reading failed: {err}"))?;
+    let ptr2 = env
+        .map_buffer(buf, CL_MAP_READ, 512, 512)
+        .map_err(|err| format!("clEnqueueMapBuffer for reading failed: {err}"))?;
+    let mapped = unsafe { std::slice::from_raw_parts(ptr1.cast::<u8>(), 512) };
+    expect_eq(mapped, &data[512..1024], "reading buffer through map")?;
+    check(env.unmap(buf, ptr1), "clEnqueueUnmapMemObject")?;
+    check(env.unmap(buf, ptr2), "clEnqueueUnmapMemObject")?;
+    env.finish()?;
+
+    // invalid use
+    expect_err(
+        env.map_buffer(buf, CL_MAP_READ, 0, 0).err().unwrap_or(0),
+        CL_INVALID_VALUE,
+        "clEnqueueMapBuffer with size 0",
+    )?;
+    expect_err(
+        env.map_buffer(buf, CL_MAP_READ, size, 1).err().unwrap_or(0),
+        CL_INVALID_VALUE,
+        "clEnqueueMapBuffer out of bounds",
+    )?;
+    expect_err(
+        env.unmap(buf, data.as_ptr() as *mut c_void),
+        CL_INVALID_VALUE,
+        "clEnqueueUnmapMemObject with a pointer never mapped",
+    )?;
+
+    env.release_mem(buf);
+    Ok(())
+}
+
+fn sub_buffer(env: &Env) -> TestResult {
+    let data = pattern(4096, 0x77);
+    let buf = env.create_buffer(data.len(), Some(&data))?;
+
+    let mut align: cl_uint = 0;
+    let err = unsafe {
+        env.d.clGetDeviceInfo.unwrap()(
+            env.dev,
+            CL_DEVICE_MEM_BASE_ADDR_ALIGN,
+            size_of::<cl_uint>(),
+            ptr::addr_of_mut!(align).cast(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clGetDeviceInfo(CL_DEVICE_MEM_BASE_ADDR_ALIGN)")?;
+
+    // the alignment is in bits
+    let region = cl_buffer_region {
+        origin: align as usize / 8,
+        size: 1024,
+    };
+    let mut err = 0;
+    let sub = unsafe {
+        env.d.clCreateSubBuffer.unwrap()(
+            buf,
+            CL_MEM_READ_WRITE.into(),
+            CL_BUFFER_CREATE_TYPE_REGION,
+            ptr::addr_of!(region).cast(),
+            &mut err,
+        )
+    };
+    check(err, "clCreateSubBuffer")?;
+
+    let range = region.origin..region.origin + region.size;
+    expect_eq(
+        &env.read_buffer(sub, 0, region.size)?,
+        &data[range],
+        "reading sub-buffer",
+    )?;
+
+    let ptr = env
+        .map_buffer(sub, CL_MAP_READ, 0, region.size)
+        .map_err(|err| format!("clEnqueueMapBuffer on sub-buffer failed: {err}"))?;
+    let mapped = unsafe { std::slice::from_raw_parts(ptr.cast::<u8>(), region.size) };
+    expect_eq(
+        mapped,
+        &data[region.origin..region.origin + region.size],
+        "mapping sub-buffer",
+    )?;
+    check(env.unmap(sub, ptr), "clEnqueueUnmapMemObject")?;
+    env.finish()?;
+
+    env.release_mem(sub);
+    env.release_mem(buf);
+    Ok(())
+}
+
+fn image_copy(env: &Env) -> TestResult {
+    if !env.has_images() {
+        return Ok(());
+    }
+
+    let (width, height) = (64, 32);
+    let size = width * height * 4;
+    let data = pattern(size, 0x11);
+    let src = env.create_image(width, height)?;
+    let dst = env.create_image(width, height)?;
+    let buf = env.create_buffer(size, None)?;
+    let origin = [0_usize; 3];
+    let region = [width, height, 1];
+
+    let err = unsafe {
+        env.d.clEnqueueWriteImage.unwrap()(
+            env.q,
+            src,
+            CL_TRUE,
+            origin.as_ptr(),
+            region.as_ptr(),
+            0,
+            0,
+            data.as_ptr().cast(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueWriteImage")?;
+
+    let err = unsafe {
+        env.d.clEnqueueCopyImage.unwrap()(
+            env.q,
+            src,
+            dst,
+            origin.as_ptr(),
+            origin.as_ptr(),
+            region.as_ptr(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueCopyImage")?;
+
+    let mut res = vec![0_u8; size];
+    let err = unsafe {
+        env.d.clEnqueueReadImage.unwrap()(
+            env.q,
+            dst,
+            CL_TRUE,
+            origin.as_ptr(),
+            region.as_ptr(),
+            0,
+            0,
+            res.as_mut_ptr().cast(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueReadImage")?;
+    expect_eq(&res, &data, "reading copied image")?;
+
+    let err = unsafe {
+        env.d.clEnqueueCopyImageToBuffer.unwrap()(
+            env.q,
+            dst,
+            buf,
+            origin.as_ptr(),
+            region.as_ptr(),
+            0,
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueCopyImageToBuffer")?;
+    expect_eq(
+        &env.read_buffer(buf, 0, size)?,
+        &data,
+        "reading image copied to buffer",
+    )?;
+
+    env.release_mem(src);
+    env.release_mem(dst);
+    env.release_mem(buf);
+    Ok(())
+}
+
+/// Kernels writing to 3D images, which might get lowered to 2D array writes.
+fn image_3d_write(env: &Env) -> TestResult {
+    const SRC: &str = "#pragma OPENCL EXTENSION cl_khr_3d_image_writes : enable\n\
+        kernel void fill(write_only image3d_t img) {\n\
+            int4 coord = (int4)(get_global_id(0), get_global_id(1), get_global_id(2), 0);\n\
+            write_imageui(img, coord, (uint4)(coord.x, coord.y, coord.z, 255));\n\
+        }";
+
+    if !env.has_images() || !env.has_extension("cl_khr_3d_image_writes") {
+        return Ok(());
+    }
+
+    let (width, height, depth) = (16, 8, 4);
+    let format = cl_image_format {
+        image_channel_order: CL_RGBA,
+        image_channel_data_type: CL_UNSIGNED_INT8,
+    };
+    let desc = cl_image_desc {
+        image_type: CL_MEM_OBJECT_IMAGE3D,
+        image_width: width,
+        image_height: height,
+        image_depth: depth,
+        ..Default::default()
+    };
+
+    let mut err = 0;
+    let img = unsafe {
+        env.d.clCreateImage.unwrap()(
+            env.ctx,
+            CL_MEM_WRITE_ONLY.into(),
+            &format,
+            &desc,
+            ptr::null_mut(),
+            &mut err,
+        )
+    };
+    check(err, "clCreateImage")?;
+
+    let mut src = SRC.as_ptr().cast::<c_char>();
+    let prog = unsafe {
+        env.d.clCreateProgramWithSource.unwrap()(env.ctx, 1, &mut src, &SRC.len(), &mut err)
+    };
+    check(err, "clCreateProgramWithSource")?;
+
+    let err = unsafe {
+        env.d.clBuildProgram.unwrap()(prog, 1, &env.dev, ptr::null(), None, ptr::null_mut())
+    };
+    check(err, "clBuildProgram")?;
+
+    let name = CString::new("fill").unwrap();
+    let mut err = 0;
+    let kernel = unsafe { env.d.clCreateKernel.unwrap()(prog, name.as_ptr(), &mut err) };
+    check(err, "clCreateKernel")?;
+
+    let res = (|| {
+        check(
+            unsafe {
+                env.d.clSetKernelArg.unwrap()(
+                    kernel,
+                    0,
+                    size_of::<cl_mem>(),
+                    ptr::addr_of!(img).cast(),
+                )
+            },
+            "clSetKernelArg(0)",
+        )?;
+
+        let gws = [width, height, depth];
+        let err = unsafe {
+            env.d.clEnqueueNDRangeKernel.unwrap()(
+                env.q,
+                kernel,
+                3,
+                ptr::null(),
+                gws.as_ptr(),
+                ptr::null(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clEnqueueNDRangeKernel")?;
+
+        let origin = [0_usize; 3];
+        let mut res = vec![0_u8; width * height * depth * 4];
+        let err = unsafe {
+            env.d.clEnqueueReadImage.unwrap()(
+                env.q,
+                img,
+                CL_TRUE,
+                origin.as_ptr(),
+                gws.as_ptr(),
+                0,
+                0,
+                res.as_mut_ptr().cast(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clEnqueueReadImage")?;
+
+        let expected: Vec<u8> = (0..depth)
+            .flat_map(|z| (0..height).map(move |y| (y, z)))
+            .flat_map(|(y, z)| (0..width).map(move |x| [x as u8, y as u8, z as u8, 255]))
+            .flatten()
+            .collect();
+        expect_eq(&res, &expected, "reading written 3D image")
+    })();
+
+    env.release_mem(img);
+    unsafe {
+        env.d.clReleaseKernel.unwrap()(kernel);
+        env.d.clReleaseProgram.unwrap()(prog);
+    }
+    res
+}
+
+fn kernel_launch(env: &Env) -> TestResult {
+    const SRC: &str = "kernel void add(global int *a, int b) { a[get_global_id(0)] += b; }";
+    const CNT: usize = 1024;
+
+    let mut err = 0;
+    let mut src = SRC.as_ptr().cast::<c_char>();
+    let prog = unsafe {
+        env.d.clCreateProgramWithSource.unwrap()(env.ctx, 1, &mut src, &SRC.len(), &mut err)
+    };
+    check(err, "clCreateProgramWithSource")?;
+
+    let err = unsafe {
+        env.d.clBuildProgram.unwrap()(prog, 1, &env.dev, ptr::null(), None, ptr::null_mut())
+    };
+    check(err, "clBuildProgram")?;
+
+    let name = CString::new("add").unwrap();
+    let mut err = 0;
+    let kernel = unsafe { env.d.clCreateKernel.unwrap()(prog, name.as_ptr(), &mut err) };
+    check(err, "clCreateKernel")?;
+
+    let input: Vec<i32> = (0..CNT as i32).collect();
+    let bytes: Vec<u8> = input.iter().flat_map(|val| val.to_ne_bytes()).collect();
+    let buf = env.create_buffer(bytes.len(), Some(&bytes))?;
+    let inc: i32 = 42;
+
+    unsafe {
+        check(
+            env.d.clSetKernelArg.unwrap()(
+                kernel,
+                0,
+                size_of::<cl_mem>(),
+                ptr::addr_of!(buf).cast(),
+            ),
+            "clSetKernelArg(0)",
+        )?;
+        check(
+            env.d.clSetKernelArg.unwrap()(kernel, 1, size_of::<i32>(), ptr::addr_of!(inc).cast()),
+            "clSetKernelArg(1)",
+        )?;
+    }
+
+    let gws = [CNT];
+    let err = unsafe {
+        env.d.clEnqueueNDRangeKernel.unwrap()(
+            env.q,
+            kernel,
+            1,
+            ptr::null(),
+            gws.as_ptr(),
+            ptr::null(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueNDRangeKernel")?;
+
+    let expected: Vec<u8> = input
+        .iter()
+        .flat_map(|val| (val + inc).to_ne_bytes())
+        .collect();
+    expect_eq(
+        &env.read_buffer(buf, 0, bytes.len())?,
+        &expected,
+        "reading kernel results",
+    )?;
+
+    env.release_mem(buf);
+    unsafe {
+        env.d.clReleaseKernel.unwrap()(kernel);
+        env.d.clReleaseProgram.unwrap()(prog);
+    }
+    Ok(())
+}
+
+fn kernel_arg_info(env: &Env) -> TestResult {
+    const SRC: &str = "kernel void copy(global const float4 *src, local int *tmp, uint cnt) {}";
+
+    let mut err = 0;
+    let mut src = SRC.as_ptr().cast::<c_char>();
+    let prog = unsafe {
+        env.d.clCreateProgramWithSource.unwrap()(env.ctx, 1, &mut src, &SRC.len(), &mut err)
+    };
+    check(err, "clCreateProgramWithSource")?;
+
+    let options = CString::new("-cl-kernel-arg-info").unwrap();
+    let err = unsafe {
+        env.d.clBuildProgram.unwrap()(prog, 1, &env.dev, options.as_ptr(), None, ptr::null_mut())
+    };
+    check(err, "clBuildProgram")?;
+
+    let name = CString::new("copy").unwrap();
+    let mut err = 0;
+    let kernel = unsafe { env.d.clCreateKernel.unwrap()(prog, name.as_ptr(), &mut err) };
+    check(err, "clCreateKernel")?;
+
+    let query_str = |idx: cl_uint, param: cl_kernel_arg_info| -> Result<String, String> {
+        let mut res = [0u8; 64];
+        let mut size = 0;
+        let err = unsafe {
+            env.d.clGetKernelArgInfo.unwrap()(
+                kernel,
+                idx,
+                param,
+                res.len(),
+                res.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        check(err, "clGetKernelArgInfo")?;
+        // strip the terminating NUL
+        Ok(String::from_utf8_lossy(&res[..size.saturating_sub(1)]).into_owned())
+    };
+
+    let query_u32 = |idx: cl_uint, param: cl_kernel_arg_info| -> Result<cl_uint, String> {
+        let mut res: cl_uint = 0;
+        let err = unsafe {
+            env.d.clGetKernelArgInfo.unwrap()(
+                kernel,
+                idx,
+                param,
+                size_of::<cl_uint>(),
+                ptr::addr_of_mut!(res).cast(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clGetKernelArgInfo")?;
+        Ok(res)
+    };
+
+    let res = (|| {
+        let names = [
+            query_str(0, CL_KERNEL_ARG_NAME)?,
+            query_str(1, CL_KERNEL_ARG_NAME)?,
+            query_str(2, CL_KERNEL_ARG_NAME)?,
+        ];
+        expect_eq(
+            &names,
+            &["src", "tmp", "cnt"].map(String::from),
+            "CL_KERNEL_ARG_NAME",
+        )?;
+
+        let type_names = [
+            query_str(0, CL_KERNEL_ARG_TYPE_NAME)?,
+            query_str(1, CL_KERNEL_ARG_TYPE_NAME)?,
+            query_str(2, CL_KERNEL_ARG_TYPE_NAME)?,
+        ];
+        expect_eq(
+            &type_names,
+            &["float4*", "int*", "uint"].map(String::from),
+            "CL_KERNEL_ARG_TYPE_NAME",
+        )?;
+
+        expect_eq(
+            &[
+                query_u32(0, CL_KERNEL_ARG_ADDRESS_QUALIFIER)?,
+                query_u32(1, CL_KERNEL_ARG_ADDRESS_QUALIFIER)?,
+                query_u32(2, CL_KERNEL_ARG_ADDRESS_QUALIFIER)?,
+            ],
+            &[
+                CL_KERNEL_ARG_ADDRESS_GLOBAL,
+                CL_KERNEL_ARG_ADDRESS_LOCAL,
+                CL_KERNEL_ARG_ADDRESS_PRIVATE,
+            ],
+            "CL_KERNEL_ARG_ADDRESS_QUALIFIER",
+        )?;
+
+        expect_eq(
+            &[query_u32(0, CL_KERNEL_ARG_ACCESS_QUALIFIER)?],
+            &[CL_KERNEL_ARG_ACCESS_NONE],
+            "CL_KERNEL_ARG_ACCESS_QUALIFIER",
+        )
+    })();
+
+    unsafe {
+        env.d.clReleaseKernel.unwrap()(kernel);
+        env.d.clReleaseProgram.unwrap()(prog);
+    }
+    res
+}
+
+/// Packs `s` into the words of a NUL terminated SPIR-V literal string.
+fn spirv_string(s: &str) -> Vec<u32> {
+    let mut bytes = s.as_bytes().to_vec();
+    bytes.resize(s.len() / 4 * 4 + 4, 0);
+    bytes
+        .chunks(4)
+        .map(|word| u32::from_le_bytes(word.try_into().unwrap()))
+        .collect()
+}
+
+/// `kernel void k(int x, global float *p) {}` without any names or kernel_arg_type strings. The
+/// type names have to be derived from the SPIR-V types, or from the mangled name of the kernel
+/// where it carries more details, like the signedness of integers.
+fn kernel_arg_info_il(env: &Env) -> TestResult {
+    // k(int x, global uint4 *p) mangled, which carries the signedness of the argument types
+    const MANGLED: &str = "_Z1kiPU3AS1Dv4_j";
+
+    let mut address_bits: cl_uint = 0;
+    let err = unsafe {
+        env.d.clGetDeviceInfo.unwrap()(
+            env.dev,
+            CL_DEVICE_ADDRESS_BITS,
+            size_of::<cl_uint>(),
+            ptr::addr_of_mut!(address_bits).cast(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clGetDeviceInfo")?;
+
+    // Physical32 or Physical64
+    let addressing_model = if address_bits == 64 { 2 } else { 1 };
+    let mangled = spirv_string(MANGLED);
+    #[rustfmt::skip]
+    let mut spirv: Vec<u32> = vec![
+        // magic, version 1.0, generator, bound, schema
+        0x07230203, 0x00010000, 0, 17, 0,
+        // OpCapability Addresses
+        (2 << 16) | 17, 4,
+        // OpCapability Kernel
+        (2 << 16) | 17, 6,
+        // OpMemoryModel <addressing_model> OpenCL
+        (3 << 16) | 14, addressing_model, 2,
+        // OpEntryPoint Kernel %1 "k"
+        (4 << 16) | 15, 6, 1, u32::from(b'k'),
+        // OpEntryPoint Kernel %10 "<MANGLED>"
+        ((3 + mangled.len() as u32) << 16) | 15, 6, 10,
+    ];
+    spirv.extend(mangled);
+    #[rustfmt::skip]
+    spirv.extend([
+        // %2 = OpTypeVoid
+        (2 << 16) | 19, 2,
+        // %3 = OpTypeInt 32 0
+        (4 << 16) | 21, 3, 32, 0,
+        // %4 = OpTypeFloat 32
+        (3 << 16) | 22, 4, 32,
+        // %5 = OpTypePointer CrossWorkgroup %4
+        (4 << 16) | 32, 5, 5, 4,
+        // %6 = OpTypeFunction %2 %3 %5
+        (5 << 16) | 33, 6, 2, 3, 5,
+        // %11 = OpTypeVector %3 4
+        (4 << 16) | 23, 11, 3, 4,
+        // %12 = OpTypePointer CrossWorkgroup %11
+        (4 << 16) | 32, 12, 5, 11,
+        // %13 = OpTypeFunction %2 %3 %12
+        (5 << 16) | 33, 13, 2, 3, 12,
+        // %1 = OpFunction %2 None %6
+        (5 << 16) | 54, 2, 1, 0, 6,
+        // %7 = OpFunctionParameter %3
+        (3 << 16) | 55, 3, 7,
+        // %8 = OpFunctionParameter %5
+        (3 << 16) | 55, 5, 8,
+        // %9 = OpLabel
+        (2 << 16) | 248, 9,
+        // OpReturn
+        (1 << 16) | 253,
+        // OpFunctionEnd
+        (1 << 16) | 56,
+        // %10 = OpFunction %2 None %13
+        (5 << 16) | 54, 2, 10, 0, 13,
+        // %14 = OpFunctionParameter %3
+        (3 << 16) | 55, 3, 14,
+        // %15 = OpFunctionParameter %12
+        (3 << 16) | 55, 12, 15,
+        // %16 = OpLabel
+        (2 << 16) | 248, 16,
+        // OpReturn
+        (1 << 16) | 253,
+        // OpFunctionEnd
+        (1 << 16) | 56,
+    ]);
+
+    let mut err = 0;
+    let prog = unsafe {
+        env.d.clCreateProgramWithIL.unwrap()(
+            env.ctx,
+            spirv.as_ptr().cast(),
+            spirv.len() * size_of::<u32>(),
+            &mut err,
+        )
+    };
+    check(err, "clCreateProgramWithIL")?;
+
+    let err = unsafe {
+        env.d.clBuildProgram.unwrap()(prog, 1, &env.dev, ptr::null(), None, ptr::null_mut())
+    };
+    check(err, "clBuildProgram")?;
+
+    let query = |kernel: cl_kernel, idx: cl_uint, param: cl_kernel_arg_info| -> (cl_int, String) {
+        let mut res = [0u8; 64];
+        let mut size = 0;
+        let err = unsafe {
+            env.d.clGetKernelArgInfo.unwrap()(
+                kernel,
+                idx,
+                param,
+                res.len(),
+                res.as_mut_ptr().cast(),
+                &mut size,
+            )
+        };
+        // strip the terminating NUL
+        let res = String::from_utf8_lossy(&res[..size.saturating_sub(1)]).into_owned();
+        (err, res)
+    };
+
+    let check_kernel = |name: &str, expected: &[&str]| -> TestResult {
+        let c_name = CString::new(name).unwrap();
+        let mut err = 0;
+        let kernel = unsafe { env.d.clCreateKernel.unwrap()(prog, c_name.as_ptr(), &mut err) };
+        check(err, "clCreateKernel")?;
+
+        let res = (|| {
+            // the module doesn't name the arguments
+            let (err, arg_name) = query(kernel, 0, CL_KERNEL_ARG_NAME);
+            check(err, "clGetKernelArgInfo")?;
+            expect_eq(&[arg_name], &[String::new()], "CL_KERNEL_ARG_NAME")?;
+
+            for (idx, &expected) in expected.iter().enumerate() {
+                let (err, type_name) = query(kernel, idx as cl_uint, CL_KERNEL_ARG_TYPE_NAME);
+                check(err, "clGetKernelArgInfo")?;
+                expect_eq(
+                    &[type_name.as_str()],
+                    &[expected],
+                    &format!("CL_KERNEL_ARG_TYPE_NAME of {expected} in {name}"),
+                )?;
+            }
+            Ok(())
+        })();
+
+        unsafe { env.d.clReleaseKernel.unwrap()(kernel) };
+        res
+    };
+
+    let res = check_kernel("k", &["int", "float*"])
+        .and_then(|_| check_kernel(MANGLED, &["int", "uint4*"]));
+
+    unsafe { env.d.clReleaseProgram.unwrap()(prog) };
+    res
+}
+
+fn compile_link(env: &Env) -> TestResult {
+    const HEADER: &str = "int twice(int x);";
+    const SRC_KERNEL: &str = "#include \"util/twice.h\"\n\
+        kernel void run(global int *a) { a[get_global_id(0)] = twice(a[get_global_id(0)]); }";
+    const SRC_LIB: &str = "#include \"util/twice.h\"\nint twice(int x) { return 2 * x; }";
+    const CNT: usize = 64;
+
+    let create = |src: &str| -> Result<cl_program, String> {
+        let mut err = 0;
+        let mut src_ptr = src.as_ptr().cast::<c_char>();
+        let prog = unsafe {
+            env.d.clCreateProgramWithSource.unwrap()(env.ctx, 1, &mut src_ptr, &src.len(), &mut err)
+        };
+        check(err, "clCreateProgramWithSource")?;
+        Ok(prog)
+    };
+
+    let header = create(HEADER)?;
+    let progs = [create(SRC_KERNEL)?, create(SRC_LIB)?];
+    let header_name = CString::new("util/twice.h").unwrap();
+    let mut header_names = [header_name.as_ptr()];
+
+    for prog in progs {
+        let err = unsafe {
+            env.d.clCompileProgram.unwrap()(
+                prog,
+                1,
+                &env.dev,
+                ptr::null(),
+                1,
+                &header,
+                header_names.as_mut_ptr(),
+                None,
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clCompileProgram")?;
+    }
+
+    let mut err = 0;
+    let linked = unsafe {
+        env.d.clLinkProgram.unwrap()(
+            env.ctx,
+            1,
+            &env.dev,
+            ptr::null(),
+            progs.len() as cl_uint,
+            progs.as_ptr(),
+            None,
+            ptr::null_mut(),
+            &mut err,
+        )
+    };
+    check(err, "clLinkProgram")?;
+
+    let name = CString::new("run").unwrap();
+    let mut err = 0;
+    let kernel = unsafe { env.d.clCreateKernel.unwrap()(linked, name.as_ptr(), &mut err) };
+    check(err, "clCreateKernel")?;
+
+    let input: Vec<i32> = (0..CNT as i32).collect();
+    let bytes: Vec<u8> = input.iter().flat_map(|val| val.to_ne_bytes()).collect();
+    let buf = env.create_buffer(bytes.len(), Some(&bytes))?;
+
+    let gws = [CNT];
+    let res = unsafe {
+        check(
+            env.d.clSetKernelArg.unwrap()(
+                kernel,
+                0,
+                size_of::<cl_mem>(),
+                ptr::addr_of!(buf).cast(),
+            ),
+            "clSetKernelArg(0)",
+        )
+        .and_then(|_| {
+            check(
+                env.d.clEnqueueNDRangeKernel.unwrap()(
+                    env.q,
+                    kernel,
+                    1,
+                    ptr::null(),
+                    gws.as_ptr(),
+                    ptr::null(),
+                    0,
+                    ptr::null(),
+                    ptr::null_mut(),
+                ),
+                "clEnqueueNDRangeKernel",
+            )
+        })
+    }
+    .and_then(|_| {
+        let expected: Vec<u8> = input
+            .iter()
+            .flat_map(|val| (val * 2).to_ne_bytes())
+            .collect();
+        expect_eq(
+            &env.read_buffer(buf, 0, bytes.len())?,
+            &expected,
+            "reading results of the linked kernel",
+        )
+    });
+
+    env.release_mem(buf);
+    unsafe {
+        env.d.clReleaseKernel.unwrap()(kernel);
+        env.d.clReleaseProgram.unwrap()(linked);
+        for prog in progs {
+            env.d.clReleaseProgram.unwrap()(prog);
+        }
+        env.d.clReleaseProgram.unwrap()(header);
+    }
+    res
+}
+
+fn program_binary(env: &Env) -> TestResult {
+    const SRC: &str = "kernel void fill(global int *a) { a[get_global_id(0)] = 7; }";
+
+    let mut err = 0;
+    let mut src = SRC.as_ptr().cast::<c_char>();
+    let prog = unsafe {
+        env.d.clCreateProgramWithSource.unwrap()(env.ctx, 1, &mut src, &SRC.len(), &mut err)
+    };
+    check(err, "clCreateProgramWithSource")?;
+
+    let err = unsafe {
+        env.d.clBuildProgram.unwrap()(prog, 1, &env.dev, ptr::null(), None, ptr::null_mut())
+    };
+    check(err, "clBuildProgram")?;
+
+    let mut size = 0_usize;
+    let err = unsafe {
+        env.d.clGetProgramInfo.unwrap()(
+            prog,
+            CL_PROGRAM_BINARY_SIZES,
+            size_of::<usize>(),
+            ptr::addr_of_mut!(size).cast(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clGetProgramInfo(CL_PROGRAM_BINARY_SIZES)")?;
+
+    let mut bin = vec![0_u8; size];
+    let mut bin_ptr = bin.as_mut_ptr();
+    let err = unsafe {
+        env.d.clGetProgramInfo.unwrap()(
+            prog,
+            CL_PROGRAM_BINARIES,
+            size_of::<*mut u8>(),
+            ptr::addr_of_mut!(bin_ptr).cast(),
+            ptr::null_mut(),
+        )
+    };
+    unsafe { env.d.clReleaseProgram.unwrap()(prog) };
+    check(err, "clGetProgramInfo(CL_PROGRAM_BINARIES)")?;
+
+    let create = |bin: &[u8]| -> (cl_program, cl_int, cl_int) {
+        let mut err = 0;
+        let mut status = 0;
+        let mut bin_ptr = bin.as_ptr();
+        let prog = unsafe {
+            env.d.clCreateProgramWithBinary.unwrap()(
+                env.ctx,
+                1,
+                &env.dev,
+                &bin.len(),
+                &mut bin_ptr,
+                &mut status,
+                &mut err,
+            )
+        };
+        (prog, err, status)
+    };
+
+    // a truncated binary has to be rejected, so applications can fall back to the source
+    let (_, err, status) = create(&bin[..bin.len() / 2]);
+    expect_err(
+        err,
+        CL_INVALID_BINARY,
+        "clCreateProgramWithBinary(truncated)",
+    )?;
+    expect_err(status, CL_INVALID_BINARY, "binary_status(truncated)")?;
+
+    let (prog, err, status) = create(&bin);
+    check(err, "clCreateProgramWithBinary")?;
+    check(status, "binary_status")?;
+
+    let res = (|| {
+        let err = unsafe {
+            env.d.clBuildProgram.unwrap()(prog, 1, &env.dev, ptr::null(), None, ptr::null_mut())
+        };
+        check(err, "clBuildProgram(binary)")?;
+
+        let name = CString::new("fill").unwrap();
+        let mut err = 0;
+        let kernel = unsafe { env.d.clCreateKernel.unwrap()(prog, name.as_ptr(), &mut err) };
+        check(err, "clCreateKernel")?;
+        unsafe { env.d.clReleaseKernel.unwrap()(kernel) };
+        Ok(())
+    })();
+
+    unsafe { env.d.clReleaseProgram.unwrap()(prog) };
+    res
+}
+
+/// Content written on one device has to be visible on the other one after migrating it.
+fn migrate_buffer(envs: &[Env]) -> TestResult {
+    let (a, b) = (&envs[0], &envs[1]);
+    let data = pattern(4096, 0x19);
+    let buf = a.create_buffer(data.len(), None)?;
+    a.write_buffer(buf, 0, &data)?;
+
+    let err = unsafe {
+        b.d.clEnqueueMigrateMemObjects.unwrap()(b.q, 1, &buf, 0, 0, ptr::null(), ptr::null_mut())
+    };
+    check(err, "clEnqueueMigrateMemObjects")?;
+    expect_eq(
+        &b.read_buffer(buf, 0, data.len())?,
+        &data,
+        "reading buffer migrated to another device",
+    )?;
+
+    // and back after updating it on the other device
+    let update = pattern(512, 0x91);
+    b.write_buffer(buf, 1024, &update)?;
+    let mut expected = data.clone();
+    expected[1024..1536].copy_from_slice(&update);
+    expect_eq(
+        &a.read_buffer(buf, 0, data.len())?,
+        &expected,
+        "reading buffer written on another device",
+    )?;
+
+    a.release_mem(buf);
+    Ok(())
+}
+
+/// Copies on one device have to see what got written on the other device before.
+fn copy_after_write(envs: &[Env]) -> TestResult {
+    let (a, b) = (&envs[0], &envs[1]);
+    let data = pattern(4096, 0x4e);
+    let src = a.create_buffer(data.len(), Some(&[0; 4096]))?;
+    let dst = a.create_buffer(data.len(), None)?;
+    a.write_buffer(src, 0, &data)?;
+
+    let err = unsafe {
+        b.d.clEnqueueCopyBuffer.unwrap()(
+            b.q,
+            src,
+            dst,
+            0,
+            0,
+            data.len(),
+            0,
+            ptr::null(),
+            ptr::null_mut(),
+        )
+    };
+    check(err, "clEnqueueCopyBuffer")?;
+    b.finish()?;
+
+    expect_eq(
+        &a.read_buffer(dst, 0, data.len())?,
+        &data,
+        "reading buffer copied on another device",
+    )?;
+
+    a.release_mem(src);
+    a.release_mem(dst);
+    Ok(())
+}
+
+/// Writes on one device have to end up in the host pointer once it gets mapped on another device.
+fn use_host_ptr_migration(envs: &[Env]) -> TestResult {
+    let (a, b) = (&envs[0], &envs[1]);
+    let mut host = pattern(4096, 0x62);
+    let mut err = 0;
+    let buf = unsafe {
+        a.d.clCreateBuffer.unwrap()(
+            a.ctx,
+            (CL_MEM_READ_WRITE | CL_MEM_USE_HOST_PTR).into(),
+            host.len(),
+            host.as_mut_ptr().cast(),
+            &mut err,
+        )
+    };
+    check(err, "clCreateBuffer")?;
+
+    let update = pattern(1024, 0x26);
+    let mut expected = host.clone();
+    expected[2048..3072].copy_from_slice(&update);
+
+    let res = (|| {
+        a.write_buffer(buf, 2048, &update)?;
+        expect_eq(
+            &b.read_buffer(buf, 0, expected.len())?,
+            &expected,
+            "reading host pointer buffer written on another device",
+        )?;
+
+        let ptr = b
+            .map_buffer(buf, CL_MAP_READ, 0, expected.len())
+            .map_err(|err| format!("clEnqueueMapBuffer failed: {err}"))?;
+        let mapped = unsafe { std::slice::from_raw_parts(ptr.cast::<u8>(), expected.len()) };
+        let res = expect_eq(mapped, &expected, "reading host pointer buffer through map");
+        check(b.unmap(buf, ptr), "clEnqueueUnmapMemObject")?;
+        b.finish()?;
+        res
+    })();
+
+    a.release_mem(buf);
+    res
+}
+
+/// Overlapping sub-buffers mapped on different devices have to show the same content.
+fn sub_buffer_maps(envs: &[Env]) -> TestResult {
+    let (a, b) = (&envs[0], &envs[1]);
+
+    // the alignment is in bits
+    let align = cmp::max(
+        a.info_u32(CL_DEVICE_MEM_BASE_ADDR_ALIGN)?,
+        b.info_u32(CL_DEVICE_MEM_BASE_ADDR_ALIGN)?,
+    ) as usize
+        / 8;
+    let data = pattern(4 * align, 0x5d);
+    let buf = a.create_buffer(data.len(), Some(&data))?;
+    let sub1 = a.create_sub_buffer(buf, 0, 2 * align)?;
+    let sub2 = a.create_sub_buffer(buf, align, 2 * align)?;
+
+    let res = (|| {
+        // write the overlapping part through the first sub-buffer
+        let update = pattern(align, 0xd5);
+        let ptr = a
+            .map_buffer(sub1, CL_MAP_WRITE, align, align)
+            .map_err(|err| format!("clEnqueueMapBuffer for writing failed: {err}"))?;
+        unsafe { ptr::copy_nonoverlapping(update.as_ptr(), ptr.cast(), align) };
+        check(a.unmap(sub1, ptr), "clEnqueueUnmapMemObject")?;
+        a.finish()?;
+
+        let ptr1 = b
+            .map_buffer(sub1, CL_MAP_READ, align, align)
+            .map_err(|err| format!("clEnqueueMapBuffer for reading failed: {err}"))?;
+        let ptr2 = b
+            .map_buffer(sub2, CL_MAP_READ, 0, align)
+            .map_err(|err| format!("clEnqueueMapBuffer for reading failed: {err}"))?;
+        let mapped1 = unsafe { std::slice::from_raw_parts(ptr1.cast::<u8>(), align) };
+        let mapped2 = unsafe { std::slice::from_raw_parts(ptr2.cast::<u8>(), align) };
+        let res = expect_eq(mapped1, &update, "reading first sub-buffer through map")
+            .and_then(|_| expect_eq(mapped2, &update, "reading second sub-buffer through map"));
+        check(b.unmap(sub1, ptr1), "clEnqueueUnmapMemObject")?;
+        check(b.unmap(sub2, ptr2), "clEnqueueUnmapMemObject")?;
+        b.finish()?;
+        res?;
+
+        let mut expected = data.clone();
+        expected[align..2 * align].copy_from_slice(&update);
+        expect_eq(
+            &a.read_buffer(buf, 0, data.len())?,
+            &expected,
+            "reading parent of sub-buffers",
+        )
+    })();
+
+    a.release_mem(sub1);
+    a.release_mem(sub2);
+    a.release_mem(buf);
+    res
+}
+
+/// Recorded commands have to run again on every enqueue, seeing the current content of the
+/// buffers they use.
+fn command_buffer(env: &Env) -> TestResult {
+    if !env.has_extension("cl_khr_command_buffer") {
+        return Ok(());
+    }
+
+    let create: clCreateCommandBufferKHR_fn = env.ext_fn("clCreateCommandBufferKHR")?;
+    let finalize: clFinalizeCommandBufferKHR_fn = env.ext_fn("clFinalizeCommandBufferKHR")?;
+    let enqueue: clEnqueueCommandBufferKHR_fn = env.ext_fn("clEnqueueCommandBufferKHR")?;
+    let release: clReleaseCommandBufferKHR_fn = env.ext_fn("clReleaseCommandBufferKHR")?;
+    let copy: clCommandCopyBufferKHR_fn = env.ext_fn("clCommandCopyBufferKHR")?;
+
+    let data = pattern(4096, 0x6b);
+    let src = env.create_buffer(data.len(), Some(&data))?;
+    let dst = env.create_buffer(data.len(), Some(&[0; 4096]))?;
+
+    let create_recorded = |flags: cl_command_buffer_flags_khr| {
+        let props = [CL_COMMAND_BUFFER_FLAGS_KHR.into(), flags, 0];
+        let mut err = 0;
+        let cb = unsafe { create.unwrap()(1, &env.q, props.as_ptr(), &mut err) };
+        check(err, "clCreateCommandBufferKHR")?;
+
+        let err = unsafe {
+            copy.unwrap()(
+                cb,
+                ptr::null_mut(),
+                src,
+                dst,
+                0,
+                0,
+                data.len(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        check(err, "clCommandCopyBufferKHR")?;
+        Ok::<_, String>(cb)
+    };
+
+    let cb = create_recorded(0)?;
+    let cb_simultaneous = create_recorded(CL_COMMAND_BUFFER_SIMULTANEOUS_USE_KHR.into())?;
+    let mut user_ev = ptr::null_mut();
+
+    let res = (|| {
+        let err =
+            unsafe { enqueue.unwrap()(0, ptr::null_mut(), cb, 0, ptr::null(), ptr::null_mut()) };
+        expect_err(
+            err,
+            CL_INVALID_OPERATION,
+            "enqueueing a command buffer still recording",
+        )?;
+
+        check(
+            unsafe { finalize.unwrap()(cb) },
+            "clFinalizeCommandBufferKHR",
+        )?;
+        check(
+            unsafe { finalize.unwrap()(cb_simultaneous) },
+            "clFinalizeCommandBufferKHR",
+        )?;
+
+        let err = unsafe {
+            copy.unwrap()(
+                cb,
+                ptr::null_mut(),
+                src,
+                dst,
+                0,
+                0,
+                data.len(),
+                0,
+                ptr::null(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+            )
+        };
+        expect_err(
+            err,
+            CL_INVALID_OPERATION,
+            "recording into a finalized command buffer",
+        )?;
+
+        let err =
+            unsafe { enqueue.unwrap()(0, ptr::null_mut(), cb, 0, ptr::null(), ptr::null_mut()) };
+        check(err, "clEnqueueCommandBufferKHR")?;
+        env.finish()?;
+        expect_eq(
+            &env.read_buffer(dst, 0, data.len())?,
+            &data,
+            "first execution of a command buffer",
+        )?;
+
+        // the replay has to copy the new content
+        let update = pattern(data.len(), 0xa2);
+        env.write_buffer(src, 0, &update)?;
+        let err =
+            unsafe { enqueue.unwrap()(0, ptr::null_mut(), cb, 0, ptr::null(), ptr::null_mut()) };
+        check(err, "clEnqueueCommandBufferKHR")?;
+        env.finish()?;
+        expect_eq(
+            &env.read_buffer(dst, 0, data.len())?,
+            &update,
+            "second execution of a command buffer",
+        )?;
+
+        // keep the executions pending by waiting on a user event
+        let mut err = 0;
+        user_ev = unsafe { env.d.clCreateUserEvent.unwrap()(env.ctx, &mut err) };
+        check(err, "clCreateUserEvent")?;
+
+        let err = unsafe { enqueue.unwrap()(0, ptr::null_mut(), cb, 1, &user_ev, ptr::null_mut()) };
+        check(err, "clEnqueueCommandBufferKHR")?;
+        let err =
+            unsafe { enqueue.unwrap()(0, ptr::null_mut(), cb, 0, ptr::null(), ptr::null_mut()) };
+        expect_err(
+            err,
+            CL_INVALID_OPERATION,
+            "enqueueing a pending command buffer without simultaneous use",
+        )?;
+
+        for _ in 0..2 {
+            let err = unsafe {
+                enqueue.unwrap()(
+                    0,
+                    ptr::null_mut(),
+                    cb_simultaneous,
+                    1,
+                    &user_ev,
+                    ptr::null_mut(),
+                )
+            };
+            check(err, "clEnqueueCommandBufferKHR with simultaneous use")?;
+        }
+
+        let err = unsafe { env.d.clSetUserEventStatus.unwrap()(user_ev, CL_COMPLETE as cl_int) };
+        check(err, "clSetUserEventStatus")?;
+        env.finish()?;
+
+        // and executable again once the previous execution completed
+        let err =
+            unsafe { enqueue.unwrap()(0, ptr::null_mut(), cb, 0, ptr::null(), ptr::null_mut()) };
+        check(err, "clEnqueueCommandBufferKHR after completion")?;
+        env.finish()
+    })();
+
+    unsafe {
+        if !user_ev.is_null() {
+            // unblock the queue in case the test failed before
+            env.d.clSetUserEventStatus.unwrap()(user_ev, CL_COMPLETE as cl_int);
+            env.d.clReleaseEvent.unwrap()(user_ev);
+        }
+        release.unwrap()(cb);
+        release.unwrap()(cb_simultaneous);
+    }
+    env.release_mem(src);
+    env.release_mem(dst);
+    res
+}
+
+const TESTS: &[(&str, fn(&Env) -> TestResult)] = &[
+    ("buffer_round_trip", buffer_round_trip),
+    ("read_then_write", read_then_write),
+    ("buffer_fill", buffer_fill),
+    ("map_unmap", map_unmap),
+    ("sub_buffer", sub_buffer),
+    ("image_copy", image_copy),
+    ("image_3d_write", image_3d_write),
+    ("kernel_launch", kernel_launch),
+    ("kernel_arg_info", kernel_arg_info),
+    ("kernel_arg_info_il", kernel_arg_info_il),
+    ("compile_link", compile_link),
+    ("program_binary", program_binary),
+    ("command_buffer", command_buffer),
+];
+
+/// Tests running on a context with two devices.
+const SHARED_TESTS: &[(&str, fn(&[Env]) -> TestResult)] = &[
+    ("migrate_buffer", migrate_buffer),
+    ("copy_after_write", copy_after_write),
+    ("use_host_ptr_migration", use_host_ptr_migration),
+    ("sub_buffer_maps", sub_buffer_maps),
+];
+
+fn report(name: &str, res: TestResult) -> bool {
+    match &res {
+        Ok(()) => println!("{name}: pass"),
+        Err(err) => println!("{name}: fail ({err})"),
+    }
+    res.is_ok()
+}
+
+fn main() -> ExitCode {
+    let Some(icd) = env::args().nth(1) else {
+        eprintln!("usage: rusticl_smoke <path to the rusticl ICD>");
+        return ExitCode::FAILURE;
+    };
+
+    let (d, devs) = match load_devices(&icd) {
+        Ok(res) => res,
+        Err(err) => {
+            eprintln!("{err}");
+            return ExitCode::FAILURE;
+        }
+    };
+
+    let Some(&dev) = devs.first() else {
+        eprintln!("no devices found, skipping");
+        return ExitCode::from(EXIT_SKIP);
+    };
+
+    let mut total = 0;
+    let mut failed = 0;
+    for (name, test) in TESTS {
+        total += 1;
+        if !report(name, Env::new(d, dev).and_then(|env| test(&env))) {
+            failed += 1;
+        }
+    }
+
+    if devs.len() < 2 {
+        println!("skipping tests across devices, only one device found");
+    } else {
+        for (name, test) in SHARED_TESTS {
+            total += 1;
+            if !report(
+                name,
+                Env::new_shared(d, &devs[..2]).and_then(|envs| test(&envs)),
+            ) {
+                failed += 1;
+            }
+        }
+    }
+
+    println!("{} of {} tests passed", total - failed, total);
+    if failed == 0 {
+        ExitCode::SUCCESS
+    } else {
+        ExitCode::FAILURE
+    }
+}
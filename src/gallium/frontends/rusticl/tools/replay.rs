@@ -0,0 +1,356 @@
+//! Replays API call streams captured through `RUSTICL_CAPTURE_FILE`.
+//!
+//! usage: rusticl_replay <capture> [path to the rusticl ICD]
+//!
+//! Only calls needed to reproduce buffer and kernel workloads are replayed, everything else gets
+//! reported and skipped. Object handles are remapped to the ones created during replay and
+//! captured read back data is compared against the replayed results.
+
+// the replayer only needs the loader
+#[allow(dead_code)]
+#[path = "../tests/common.rs"]
+mod common;
+
+use common::load_devices;
+use mesa_rust_util::capture::*;
+use rusticl_opencl_gen::*;
+
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::env;
+use std::ffi::c_char;
+use std::ffi::c_void;
+use std::ffi::CString;
+use std::fs::File;
+use std::io::BufReader;
+use std::mem::size_of;
+use std::process::ExitCode;
+use std::ptr;
+
+const DEFAULT_ICD: &str = "libRusticlOpenCL.so.1";
+
+struct Replayer {
+    dispatch: &'static cl_icd_dispatch,
+    devices: Vec<cl_device_id>,
+    /// maps captured handles to the ones created during replay
+    handles: HashMap<u64, usize>,
+    skipped: HashSet<String>,
+    replayed: u64,
+    mismatches: u64,
+}
+
+fn handle_list(blob: &[u8]) -> impl Iterator<Item = u64> + '_ {
+    blob.chunks_exact(size_of::<usize>())
+        .map(|handle| usize::from_ne_bytes(handle.try_into().unwrap()) as u64)
+}
+
+impl Replayer {
+    fn new(dispatch: &'static cl_icd_dispatch, devices: Vec<cl_device_id>) -> Result<Self, String> {
+        if devices.is_empty() {
+            return Err("no devices found".to_string());
+        }
+
+        Ok(Self {
+            dispatch: dispatch,
+            devices: devices,
+            handles: HashMap::new(),
+            skipped: HashSet::new(),
+            replayed: 0,
+            mismatches: 0,
+        })
+    }
+
+    fn handle<T>(&self, captured: u64) -> *mut T {
+        self.handles
+            .get(&captured)
+            .map_or(ptr::null_mut(), |&handle| handle as *mut T)
+    }
+
+    fn add_handle<T>(&mut self, captured: u64, handle: *mut T) {
+        if !handle.is_null() {
+            self.handles.insert(captured, handle as usize);
+        }
+    }
+
+    fn check(&self, rec: &CaptureRecord, err: cl_int) {
+        if err != CL_SUCCESS as cl_int {
+            eprintln!("{} failed during replay: {err}", rec.name);
+        }
+    }
+
+    /// Replays a single record, returns false if the call isn't supported.
+    fn replay(&mut self, rec: &CaptureRecord) -> bool {
+        let d = self.dispatch;
+        let a = &rec.args;
+        let mut err = CL_SUCCESS as cl_int;
+        let blob = |arg| rec.blob(arg).unwrap_or_default();
+
+        // SAFETY: all pointers passed in point to memory we own and have the expected size
+        unsafe {
+            match rec.name.as_str() {
+                "clGetDeviceIDs" => {
+                    for (idx, dev) in handle_list(blob(3)).enumerate() {
+                        let replay_dev = self.devices[idx.min(self.devices.len() - 1)];
+                        self.add_handle(dev, replay_dev);
+                    }
+                }
+                "clCreateContext" => {
+                    let devs: Vec<cl_device_id> =
+                        handle_list(blob(2)).map(|dev| self.handle(dev)).collect();
+                    let res = d.clCreateContext.unwrap()(
+                        ptr::null(),
+                        devs.len() as cl_uint,
+                        devs.as_ptr(),
+                        None,
+                        ptr::null_mut(),
+                        &mut err,
+                    );
+                    self.add_handle(rec.ret, res);
+                }
+                "clCreateContextFromType" => {
+                    let res = d.clCreateContextFromType.unwrap()(
+                        ptr::null(),
+                        a[1],
+                        None,
+                        ptr::null_mut(),
+                        &mut err,
+                    );
+                    self.add_handle(rec.ret, res);
+                }
+                "clCreateCommandQueue" => {
+                    let res = d.clCreateCommandQueue.unwrap()(
+                        self.handle(a[0]),
+                        self.handle(a[1]),
+                        a[2],
+                        &mut err,
+                    );
+                    self.add_handle(rec.ret, res);
+                }
+                "clCreateCommandQueueWithProperties" => {
+                    let props: Vec<cl_queue_properties> = handle_list(blob(2)).collect();
+                    let res = d.clCreateCommandQueueWithProperties.unwrap()(
+                        self.handle(a[0]),
+                        self.handle(a[1]),
+                        if props.is_empty() {
+                            ptr::null()
+                        } else {
+                            props.as_ptr()
+                        },
+                        &mut err,
+                    );
+                    self.add_handle(rec.ret, res);
+                }
+                "clCreateBuffer" => {
+                    // the host memory of the application is gone, so always copy
+                    let mut flags = a[1];
+                    let data = blob(3);
+                    if flags & cl_mem_flags::from(CL_MEM_USE_HOST_PTR) != 0 {
+                        flags &= !cl_mem_flags::from(CL_MEM_USE_HOST_PTR);
+                        flags |= cl_mem_flags::from(CL_MEM_COPY_HOST_PTR);
+                    }
+                    let res = d.clCreateBuffer.unwrap()(
+                        self.handle(a[0]),
+                        flags,
+                        a[2] as usize,
+                        if data.is_empty() {
+                            ptr::null_mut()
+                        } else {
+                            data.as_ptr() as *mut c_void
+                        },
+                        &mut err,
+                    );
+                    self.add_handle(rec.ret, res);
+                }
+                "clCreateProgramWithSource" => {
+                    let src = blob(2);
+                    let mut src_ptr = src.as_ptr().cast::<c_char>();
+                    let res = d.clCreateProgramWithSource.unwrap()(
+                        self.handle(a[0]),
+                        1,
+                        &mut src_ptr,
+                        &src.len(),
+                        &mut err,
+                    );
+                    self.add_handle(rec.ret, res);
+                }
+                "clCreateProgramWithIL" => {
+                    let il = blob(1);
+                    let res = d.clCreateProgramWithIL.unwrap()(
+                        self.handle(a[0]),
+                        il.as_ptr().cast(),
+                        il.len(),
+                        &mut err,
+                    );
+                    self.add_handle(rec.ret, res);
+                }
+                "clBuildProgram" => {
+                    let options = CString::new(blob(3)).unwrap_or_default();
+                    err = d.clBuildProgram.unwrap()(
+                        self.handle(a[0]),
+                        0,
+                        ptr::null(),
+                        options.as_ptr(),
+                        None,
+                        ptr::null_mut(),
+                    );
+                }
+                "clCreateKernel" => {
+                    let name = CString::new(blob(1)).unwrap_or_default();
+                    let res = d.clCreateKernel.unwrap()(self.handle(a[0]), name.as_ptr(), &mut err);
+                    self.add_handle(rec.ret, res);
+                }
+                "clSetKernelArg" => {
+                    let value = blob(3);
+                    let mut handle = 0;
+                    // memory objects and samplers are passed in by handle
+                    if rec.blob(KERNEL_ARG_OBJECT_BLOB).is_some() {
+                        if let Some(captured) = handle_list(value).next() {
+                            handle = self.handle::<c_void>(captured) as usize;
+                        }
+                    }
+
+                    let value_ptr = if handle != 0 {
+                        ptr::addr_of!(handle).cast()
+                    } else if value.is_empty() {
+                        ptr::null()
+                    } else {
+                        value.as_ptr().cast()
+                    };
+
+                    err = d.clSetKernelArg.unwrap()(
+                        self.handle(a[0]),
+                        a[1] as cl_uint,
+                        a[2] as usize,
+                        value_ptr,
+                    );
+                }
+                "clEnqueueNDRangeKernel" => {
+                    let sizes = |arg| {
+                        let blob = blob(arg);
+                        if blob.is_empty() {
+                            ptr::null()
+                        } else {
+                            blob.as_ptr().cast::<usize>()
+                        }
+                    };
+                    err = d.clEnqueueNDRangeKernel.unwrap()(
+                        self.handle(a[0]),
+                        self.handle(a[1]),
+                        a[2] as cl_uint,
+                        sizes(3),
+                        sizes(4),
+                        sizes(5),
+                        0,
+                        ptr::null(),
+                        ptr::null_mut(),
+                    );
+                }
+                "clEnqueueWriteBuffer" => {
+                    let data = blob(5);
+                    err = d.clEnqueueWriteBuffer.unwrap()(
+                        self.handle(a[0]),
+                        self.handle(a[1]),
+                        CL_TRUE,
+                        a[3] as usize,
+                        data.len(),
+                        data.as_ptr().cast(),
+                        0,
+                        ptr::null(),
+                        ptr::null_mut(),
+                    );
+                }
+                "clEnqueueReadBuffer" => {
+                    let mut data = vec![0_u8; a[4] as usize];
+                    err = d.clEnqueueReadBuffer.unwrap()(
+                        self.handle(a[0]),
+                        self.handle(a[1]),
+                        CL_TRUE,
+                        a[3] as usize,
+                        data.len(),
+                        data.as_mut_ptr().cast(),
+                        0,
+                        ptr::null(),
+                        ptr::null_mut(),
+                    );
+
+                    if let Some(expected) = rec.blob(5) {
+                        if expected != data {
+                            eprintln!("clEnqueueReadBuffer returned different data than captured");
+                            self.mismatches += 1;
+                        }
+                    }
+                }
+                "clFinish" => err = d.clFinish.unwrap()(self.handle(a[0])),
+                "clFlush" => err = d.clFlush.unwrap()(self.handle(a[0])),
+                "clRetainCommandQueue" => err = d.clRetainCommandQueue.unwrap()(self.handle(a[0])),
+                "clRetainContext" => err = d.clRetainContext.unwrap()(self.handle(a[0])),
+                "clRetainKernel" => err = d.clRetainKernel.unwrap()(self.handle(a[0])),
+                "clRetainMemObject" => err = d.clRetainMemObject.unwrap()(self.handle(a[0])),
+                "clRetainProgram" => err = d.clRetainProgram.unwrap()(self.handle(a[0])),
+                "clReleaseCommandQueue" => {
+                    err = d.clReleaseCommandQueue.unwrap()(self.handle(a[0]))
+                }
+                "clReleaseContext" => err = d.clReleaseContext.unwrap()(self.handle(a[0])),
+                "clReleaseKernel" => err = d.clReleaseKernel.unwrap()(self.handle(a[0])),
+                "clReleaseMemObject" => err = d.clReleaseMemObject.unwrap()(self.handle(a[0])),
+                "clReleaseProgram" => err = d.clReleaseProgram.unwrap()(self.handle(a[0])),
+                // queries don't change any state
+                name if name.starts_with("clGet") => return true,
+                _ => return false,
+            }
+        }
+
+        self.check(rec, err);
+        self.replayed += 1;
+        true
+    }
+}
+
+fn run(capture: &str, icd: &str) -> Result<bool, String> {
+    let mut input = BufReader::new(
+        File::open(capture).map_err(|err| format!("failed to open {capture}: {err}"))?,
+    );
+    read_header(&mut input).map_err(|err| format!("{capture}: {err}"))?;
+
+    let (dispatch, devices) = load_devices(icd)?;
+    let mut replayer = Replayer::new(dispatch, devices)?;
+    while let Some(rec) =
+        CaptureRecord::read_from(&mut input).map_err(|err| format!("{capture}: {err}"))?
+    {
+        // failed calls didn't have any effect
+        if rec.err != CL_SUCCESS as cl_int {
+            continue;
+        }
+
+        if !replayer.replay(&rec) && replayer.skipped.insert(rec.name.clone()) {
+            eprintln!("skipping unsupported call {}", rec.name);
+        }
+    }
+
+    println!(
+        "replayed {} calls, {} read backs didn't match",
+        replayer.replayed, replayer.mismatches
+    );
+    Ok(replayer.mismatches == 0)
+}
+
+fn main() -> ExitCode {
+    let args: Vec<_> = env::args().collect();
+    if args.len() < 2 || args.len() > 3 {
+        eprintln!("usage: {} <capture> [icd]", args[0]);
+        return ExitCode::FAILURE;
+    }
+
+    // don't overwrite the capture we are replaying
+    env::remove_var("RUSTICL_CAPTURE_FILE");
+
+    let icd = args.get(2).map_or(DEFAULT_ICD, String::as_str);
+    match run(&args[1], icd) {
+        Ok(true) => ExitCode::SUCCESS,
+        Ok(false) => ExitCode::FAILURE,
+        Err(err) => {
+            eprintln!("{err}");
+            ExitCode::FAILURE
+        }
+    }
+}
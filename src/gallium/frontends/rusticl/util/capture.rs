@@ -0,0 +1,147 @@
+//! File format for captured API call streams.
+//!
+//! A capture starts with [CAPTURE_MAGIC] followed by [CAPTURE_VERSION], after which the records
+//! follow in the order the calls returned. All integers are stored in native endianness, as
+//! captures contain raw memory contents anyway and aren't meant to be portable between hosts.
+
+use std::io;
+use std::io::Read;
+use std::io::Write;
+
+pub const CAPTURE_MAGIC: [u8; 8] = *b"RCLCAPT\0";
+pub const CAPTURE_VERSION: u32 = 1;
+
+/// `clSetKernelArg` records carry an empty blob at this index if the argument value is a memory
+/// object or sampler handle, which has to be remapped during replay.
+pub const KERNEL_ARG_OBJECT_BLOB: u32 = 4;
+
+/// Memory referenced by an argument of a call, e.g. the host data passed into a write.
+pub struct CaptureBlob {
+    pub arg: u32,
+    pub data: Vec<u8>,
+}
+
+pub struct CaptureRecord {
+    pub name: String,
+    /// Scalar values of all arguments, pointers and object handles are stored as their address.
+    pub args: Vec<u64>,
+    /// The returned object handle or pointer, 0 for calls only returning an error code.
+    pub ret: u64,
+    pub err: i32,
+    pub blobs: Vec<CaptureBlob>,
+}
+
+fn write_u32(out: &mut impl Write, val: u32) -> io::Result<()> {
+    out.write_all(&val.to_ne_bytes())
+}
+
+fn write_u64(out: &mut impl Write, val: u64) -> io::Result<()> {
+    out.write_all(&val.to_ne_bytes())
+}
+
+fn read_u32(input: &mut impl Read) -> io::Result<u32> {
+    let mut buf = [0; 4];
+    input.read_exact(&mut buf)?;
+    Ok(u32::from_ne_bytes(buf))
+}
+
+fn read_u64(input: &mut impl Read) -> io::Result<u64> {
+    let mut buf = [0; 8];
+    input.read_exact(&mut buf)?;
+    Ok(u64::from_ne_bytes(buf))
+}
+
+fn read_vec(input: &mut impl Read, len: u64) -> io::Result<Vec<u8>> {
+    let mut res = Vec::new();
+    input.take(len).read_to_end(&mut res)?;
+    if res.len() as u64 != len {
+        return Err(io::ErrorKind::UnexpectedEof.into());
+    }
+    Ok(res)
+}
+
+pub fn write_header(out: &mut impl Write) -> io::Result<()> {
+    out.write_all(&CAPTURE_MAGIC)?;
+    write_u32(out, CAPTURE_VERSION)
+}
+
+pub fn read_header(input: &mut impl Read) -> io::Result<()> {
+    let mut magic = [0; CAPTURE_MAGIC.len()];
+    input.read_exact(&mut magic)?;
+    if magic != CAPTURE_MAGIC || read_u32(input)? != CAPTURE_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "not a capture file or unsupported version",
+        ));
+    }
+    Ok(())
+}
+
+impl CaptureRecord {
+    pub fn blob(&self, arg: u32) -> Option<&[u8]> {
+        self.blobs
+            .iter()
+            .find(|blob| blob.arg == arg)
+            .map(|blob| blob.data.as_slice())
+    }
+
+    pub fn write_to(&self, out: &mut impl Write) -> io::Result<()> {
+        write_u32(out, self.name.len() as u32)?;
+        out.write_all(self.name.as_bytes())?;
+        write_u64(out, self.ret)?;
+        out.write_all(&self.err.to_ne_bytes())?;
+
+        write_u32(out, self.args.len() as u32)?;
+        for &arg in &self.args {
+            write_u64(out, arg)?;
+        }
+
+        write_u32(out, self.blobs.len() as u32)?;
+        for blob in &self.blobs {
+            write_u32(out, blob.arg)?;
+            write_u64(out, blob.data.len() as u64)?;
+            out.write_all(&blob.data)?;
+        }
+
+        Ok(())
+    }
+
+    /// Returns `None` once the end of the capture was reached.
+    pub fn read_from(input: &mut impl Read) -> io::Result<Option<Self>> {
+        let name_len = match read_u32(input) {
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+            Err(err) => return Err(err),
+        };
+
+        let name = String::from_utf8(read_vec(input, name_len.into())?)
+            .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+        let ret = read_u64(input)?;
+        let err = read_u32(input)? as i32;
+
+        let arg_cnt = read_u32(input)?;
+        let args = (0..arg_cnt)
+            .map(|_| read_u64(input))
+            .collect::<io::Result<_>>()?;
+
+        let blob_cnt = read_u32(input)?;
+        let blobs = (0..blob_cnt)
+            .map(|_| {
+                let arg = read_u32(input)?;
+                let len = read_u64(input)?;
+                Ok(CaptureBlob {
+                    arg: arg,
+                    data: read_vec(input, len)?,
+                })
+            })
+            .collect::<io::Result<_>>()?;
+
+        Ok(Some(Self {
+            name: name,
+            args: args,
+            ret: ret,
+            err: err,
+            blobs: blobs,
+        }))
+    }
+}
@@ -3,7 +3,7 @@ macro_rules! has_required_feature {
     ($object:ident, $feature:ident) => {{
         let has_feature = $object.$feature.is_some();
         if !has_feature {
-            println!(
+            $crate::log_warn!(
                 "Missing {} feature {}",
                 stringify!($object),
                 stringify!($feature)
@@ -0,0 +1,151 @@
+use std::fmt;
+use std::ops::BitAnd;
+use std::ops::BitOr;
+use std::ops::Not;
+use std::ops::Shl;
+use std::ops::Shr;
+
+/// Unsigned integers usable as flag sets or containing bitfields.
+pub trait Bits:
+    Copy
+    + Default
+    + Eq
+    + BitAnd<Output = Self>
+    + BitOr<Output = Self>
+    + Not<Output = Self>
+    + Shl<u32, Output = Self>
+    + Shr<u32, Output = Self>
+    + fmt::LowerHex
+{
+    const BITS: u32;
+
+    fn count_ones(self) -> u32;
+
+    /// Returns a value with the lowest `len` bits set.
+    fn mask(len: u32) -> Self {
+        debug_assert!(len <= Self::BITS);
+        if len == Self::BITS {
+            !Self::default()
+        } else {
+            !(!Self::default() << len)
+        }
+    }
+
+    /// Returns the `len` bits starting at bit `start`.
+    fn get_bits(self, start: u32, len: u32) -> Self {
+        debug_assert!(start + len <= Self::BITS);
+        (self >> start) & Self::mask(len)
+    }
+
+    /// Replaces the `len` bits starting at bit `start` with `val`, which has to fit.
+    fn set_bits(&mut self, start: u32, len: u32, val: Self) {
+        debug_assert!(start + len <= Self::BITS);
+        debug_assert!(val & !Self::mask(len) == Self::default());
+        let mask = Self::mask(len) << start;
+        *self = (*self & !mask) | ((val << start) & mask);
+    }
+}
+
+macro_rules! impl_bits {
+    ($($t:ty),+) => {
+        $(
+            impl Bits for $t {
+                const BITS: u32 = <$t>::BITS;
+
+                fn count_ones(self) -> u32 {
+                    <$t>::count_ones(self)
+                }
+            }
+        )+
+    };
+}
+
+impl_bits!(u8, u16, u32, u64, usize);
+
+/// A set of flags stored in `T`.
+///
+/// All arguments are converted into `T` first, so flags defined with a smaller type, like most CL
+/// enums, can be used directly.
+#[derive(Clone, Copy, Default, PartialEq, Eq)]
+pub struct Flags<T: Bits>(T);
+
+impl<T: Bits> Flags<T> {
+    pub fn new(bits: impl Into<T>) -> Self {
+        Self(bits.into())
+    }
+
+    pub fn bits(self) -> T {
+        self.0
+    }
+
+    pub fn is_empty(self) -> bool {
+        self.0 == T::default()
+    }
+
+    /// Returns true if all of `flags` are set.
+    pub fn contains(self, flags: impl Into<T>) -> bool {
+        let flags = flags.into();
+        self.0 & flags == flags
+    }
+
+    /// Returns true if any of `flags` is set.
+    pub fn intersects(self, flags: impl Into<T>) -> bool {
+        self.0 & flags.into() != T::default()
+    }
+
+    /// Returns true if no flag outside of `valid` is set.
+    pub fn is_subset_of(self, valid: impl Into<T>) -> bool {
+        self.0 & !valid.into() == T::default()
+    }
+
+    /// Returns the number of flags set out of `group`.
+    pub fn count(self, group: impl Into<T>) -> u32 {
+        (self.0 & group.into()).count_ones()
+    }
+
+    pub fn insert(&mut self, flags: impl Into<T>) {
+        self.0 = self.0 | flags.into();
+    }
+
+    pub fn remove(&mut self, flags: impl Into<T>) {
+        self.0 = self.0 & !flags.into();
+    }
+
+    /// Formats the flags with their names, any unknown bits are printed in hex.
+    pub fn names<'a>(self, names: &'a [(T, &'a str)]) -> FlagNames<'a, T> {
+        FlagNames {
+            flags: self,
+            names: names,
+        }
+    }
+}
+
+impl<T: Bits> fmt::Debug for Flags<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+pub struct FlagNames<'a, T: Bits> {
+    flags: Flags<T>,
+    names: &'a [(T, &'a str)],
+}
+
+impl<T: Bits> fmt::Display for FlagNames<'_, T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut rest = self.flags;
+        let mut sep = "";
+        for &(flag, name) in self.names {
+            if flag != T::default() && rest.contains(flag) {
+                write!(f, "{sep}{name}")?;
+                rest.remove(flag);
+                sep = " | ";
+            }
+        }
+
+        if !rest.is_empty() || sep.is_empty() {
+            write!(f, "{sep}{:?}", rest)?;
+        }
+        Ok(())
+    }
+}
@@ -1,8 +1,13 @@
 pub mod assert;
 pub mod bitset;
+pub mod capture;
 pub mod feature;
+pub mod flags;
+pub mod log;
 pub mod math;
+pub mod per_device;
 pub mod properties;
 pub mod ptr;
 pub mod serialize;
 pub mod string;
+pub mod thread_pool;
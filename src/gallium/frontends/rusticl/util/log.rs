@@ -0,0 +1,224 @@
+//! Shared logging for the Rust crates.
+//!
+//! Controlled through the `RUSTICL_LOG` environment variable, which is a comma-separated list of:
+//! - `<level>` sets the default level, one of `off`, `error`, `warn`, `info`, `debug` or `trace`
+//! - `<module>=<level>` sets the level for a module. `<module>` either matches a full module path
+//!   prefix like `rusticl::core` or a single path component like `memory`.
+//! - `timestamps` prefixes every message with the time since the first message
+//! - `threads` prefixes every message with the id of the current thread
+//!
+//! The default level is `warn`.
+//!
+//! The `clc`, `nir` and `program` channels of `RUSTICL_DEBUG` are aliases for enabling the
+//! `debug` level of the module of the same name.
+
+use std::env;
+use std::fmt;
+use std::io::Write;
+use std::sync::OnceLock;
+use std::thread;
+use std::time::Instant;
+
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum LogLevel {
+    Off,
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+}
+
+impl LogLevel {
+    fn parse(level: &str) -> Option<Self> {
+        Some(match level {
+            "off" => Self::Off,
+            "error" => Self::Error,
+            "warn" => Self::Warn,
+            "info" => Self::Info,
+            "debug" => Self::Debug,
+            "trace" => Self::Trace,
+            _ => return None,
+        })
+    }
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Error => "error",
+            Self::Warn => "warning",
+            Self::Info => "info",
+            Self::Debug => "debug",
+            Self::Trace => "trace",
+        }
+    }
+}
+
+struct Logger {
+    default: LogLevel,
+    filters: Vec<(String, LogLevel)>,
+    timestamps: bool,
+    thread_ids: bool,
+    start: Instant,
+}
+
+static LOGGER: OnceLock<Logger> = OnceLock::new();
+
+impl Logger {
+    fn from_env() -> Self {
+        let mut res = Self {
+            default: LogLevel::Warn,
+            filters: Vec::new(),
+            timestamps: false,
+            thread_ids: false,
+            start: Instant::now(),
+        };
+
+        let flags = env::var("RUSTICL_LOG").unwrap_or_default();
+        for flag in flags.split(',') {
+            match flag.split_once('=') {
+                Some((module, level)) => match LogLevel::parse(level) {
+                    Some(level) => res.filters.push((module.to_string(), level)),
+                    None => eprintln!("Unknown RUSTICL_LOG level found: {}", level),
+                },
+                None => match flag {
+                    "timestamps" => res.timestamps = true,
+                    "threads" => res.thread_ids = true,
+                    "" => (),
+                    _ => match LogLevel::parse(flag) {
+                        Some(level) => res.default = level,
+                        None => eprintln!("Unknown RUSTICL_LOG flag found: {}", flag),
+                    },
+                },
+            }
+        }
+
+        // Aliases for the old debug channels, those don't lower the level if it got raised already.
+        let alias_level = res.default.max(LogLevel::Debug);
+        if let Ok(debug_flags) = env::var("RUSTICL_DEBUG") {
+            for flag in debug_flags.split(',') {
+                if matches!(flag, "clc" | "nir" | "program")
+                    && !res.filters.iter().any(|(module, _)| module == flag)
+                {
+                    res.filters.push((flag.to_string(), alias_level));
+                }
+            }
+        }
+
+        // more specific filters should win
+        res.filters.sort_by_key(|(module, _)| module.len());
+        res
+    }
+
+    fn level(&self, module: &str) -> LogLevel {
+        self.filters
+            .iter()
+            .rev()
+            .find(|(filter, _)| {
+                module == filter
+                    || module
+                        .strip_prefix(filter.as_str())
+                        .is_some_and(|rest| rest.starts_with("::"))
+                    || module.split("::").any(|component| component == filter)
+            })
+            .map_or(self.default, |&(_, level)| level)
+    }
+}
+
+fn logger() -> &'static Logger {
+    LOGGER.get_or_init(Logger::from_env)
+}
+
+pub fn enabled(level: LogLevel, module: &str) -> bool {
+    level != LogLevel::Off && level <= logger().level(module)
+}
+
+/// Writes out a message unconditionally, use the `log_*` macros instead.
+pub fn log(level: LogLevel, module: &str, args: fmt::Arguments) {
+    let logger = logger();
+    let mut prefix = String::new();
+
+    if logger.timestamps {
+        let time = logger.start.elapsed();
+        prefix += &format!("[{:5}.{:06}] ", time.as_secs(), time.subsec_micros());
+    }
+
+    if logger.thread_ids {
+        prefix += &format!("[{:?}] ", thread::current().id());
+    }
+
+    // lock once so concurrent messages don't get interleaved
+    let _ = writeln!(
+        std::io::stderr().lock(),
+        "{prefix}{module}: {}: {args}",
+        level.as_str()
+    );
+}
+
+/// Logs a message for the current module or for the module given through `target:`. The latter
+/// is useful inside macros, which otherwise get attributed to the module they are expanded in.
+#[macro_export]
+macro_rules! log {
+    (target: $target:expr, $level:expr, $($arg:tt)+) => {{
+        let level = $level;
+        let target: &str = $target;
+        if $crate::log::enabled(level, target) {
+            $crate::log::log(level, target, ::std::format_args!($($arg)+));
+        }
+    }};
+    ($level:expr, $($arg:tt)+) => {
+        $crate::log!(target: ::std::module_path!(), $level, $($arg)+)
+    };
+}
+
+/// Checks whether messages of the given level would be written out, useful to skip expensive
+/// preparations of them.
+#[macro_export]
+macro_rules! log_enabled {
+    (target: $target:expr, $level:expr) => {
+        $crate::log::enabled($level, $target)
+    };
+    ($level:expr) => {
+        $crate::log::enabled($level, ::std::module_path!())
+    };
+}
+
+#[macro_export]
+macro_rules! log_error {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::log::LogLevel::Error, $($arg)+)
+    };
+    ($($arg:tt)+) => { $crate::log!($crate::log::LogLevel::Error, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_warn {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::log::LogLevel::Warn, $($arg)+)
+    };
+    ($($arg:tt)+) => { $crate::log!($crate::log::LogLevel::Warn, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_info {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::log::LogLevel::Info, $($arg)+)
+    };
+    ($($arg:tt)+) => { $crate::log!($crate::log::LogLevel::Info, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_debug {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::log::LogLevel::Debug, $($arg)+)
+    };
+    ($($arg:tt)+) => { $crate::log!($crate::log::LogLevel::Debug, $($arg)+) };
+}
+
+#[macro_export]
+macro_rules! log_trace {
+    (target: $target:expr, $($arg:tt)+) => {
+        $crate::log!(target: $target, $crate::log::LogLevel::Trace, $($arg)+)
+    };
+    ($($arg:tt)+) => { $crate::log!($crate::log::LogLevel::Trace, $($arg)+) };
+}
@@ -0,0 +1,129 @@
+use std::iter::Flatten;
+use std::iter::FromIterator;
+use std::iter::Map;
+use std::ops::Index;
+use std::slice;
+
+/// Implemented by types with a small and unique index, like the devices of a platform.
+pub trait DeviceIdx {
+    fn device_idx(&self) -> usize;
+}
+
+impl<T: DeviceIdx + ?Sized> DeviceIdx for &T {
+    fn device_idx(&self) -> usize {
+        (**self).device_idx()
+    }
+}
+
+pub type Iter<'a, K, V> =
+    Map<Flatten<slice::Iter<'a, Option<(K, V)>>>, fn(&'a (K, V)) -> (&'a K, &'a V)>;
+
+/// A map from devices to `V`, stored in a vector indexed by [DeviceIdx::device_idx].
+///
+/// There are only ever a few devices, so this avoids hashing on lookups and keeps all entries in
+/// a single small allocation.
+#[derive(Clone)]
+pub struct PerDevice<K, V> {
+    entries: Vec<Option<(K, V)>>,
+}
+
+impl<K: DeviceIdx, V> PerDevice<K, V> {
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+        }
+    }
+
+    pub fn get<Q: DeviceIdx + ?Sized>(&self, key: &Q) -> Option<&V> {
+        self.entries.get(key.device_idx())?.as_ref().map(|(_, v)| v)
+    }
+
+    pub fn get_mut<Q: DeviceIdx + ?Sized>(&mut self, key: &Q) -> Option<&mut V> {
+        self.entries
+            .get_mut(key.device_idx())?
+            .as_mut()
+            .map(|(_, v)| v)
+    }
+
+    pub fn contains_key<Q: DeviceIdx + ?Sized>(&self, key: &Q) -> bool {
+        self.get(key).is_some()
+    }
+
+    /// Inserts `val` for `key` and returns the value previously stored for it.
+    pub fn insert(&mut self, key: K, val: V) -> Option<V> {
+        let idx = key.device_idx();
+        if idx >= self.entries.len() {
+            self.entries.resize_with(idx + 1, || None);
+        }
+
+        self.entries[idx].replace((key, val)).map(|(_, v)| v)
+    }
+
+    pub fn remove<Q: DeviceIdx + ?Sized>(&mut self, key: &Q) -> Option<V> {
+        self.entries
+            .get_mut(key.device_idx())?
+            .take()
+            .map(|(_, v)| v)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.iter().all(Option::is_none)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn iter(&self) -> Iter<'_, K, V> {
+        fn split<K, V>((k, v): &(K, V)) -> (&K, &V) {
+            (k, v)
+        }
+
+        self.entries
+            .iter()
+            .flatten()
+            .map(split as fn(&(K, V)) -> (&K, &V))
+    }
+
+    pub fn values(&self) -> impl Iterator<Item = &V> {
+        self.entries.iter().flatten().map(|(_, v)| v)
+    }
+
+    /// Removes all entries, but keeps the allocation around.
+    pub fn drain(&mut self) -> impl Iterator<Item = (K, V)> + '_ {
+        self.entries.drain(..).flatten()
+    }
+}
+
+impl<K: DeviceIdx, V> Default for PerDevice<K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<K: DeviceIdx, V> FromIterator<(K, V)> for PerDevice<K, V> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let mut res = Self::new();
+        for (k, v) in iter {
+            res.insert(k, v);
+        }
+        res
+    }
+}
+
+impl<'a, K: DeviceIdx, V> IntoIterator for &'a PerDevice<K, V> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = Iter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<K: DeviceIdx, V, Q: DeviceIdx + ?Sized> Index<&Q> for PerDevice<K, V> {
+    type Output = V;
+
+    fn index(&self, key: &Q) -> &V {
+        self.get(key).expect("no entry for device")
+    }
+}
@@ -1,3 +1,5 @@
+use std::slice;
+
 pub struct Properties<T> {
     pub props: Vec<(T, T)>,
 }
@@ -52,3 +54,62 @@ impl<T> Default for Properties<T> {
         Self { props: Vec::new() }
     }
 }
+
+/// Reader for property lists which can't be parsed as plain key value pairs, because some
+/// properties like `CL_DEVICE_HANDLE_LIST_KHR` are followed by a terminated list of values.
+pub struct PropertyReader<T> {
+    p: *const T,
+    len: usize,
+}
+
+impl<T: Copy + PartialEq + Default> PropertyReader<T> {
+    /// # Safety
+    ///
+    /// `p` needs to be NULL or point to a list terminated by `T::default()`, which only gets read
+    /// as far as the caller does.
+    pub unsafe fn new(p: *const T) -> Self {
+        Self { p: p, len: 0 }
+    }
+
+    /// Returns the next property name or `None` once the end of the list is reached.
+    pub fn key(&mut self) -> Option<T> {
+        if self.p.is_null() {
+            return None;
+        }
+
+        let key = self.value();
+        (key != T::default()).then_some(key)
+    }
+
+    /// Returns the next value.
+    pub fn value(&mut self) -> T {
+        // SAFETY: the list is terminated, and callers only read past a key if it's followed by
+        //         values.
+        let val = unsafe { *self.p.add(self.len) };
+        self.len += 1;
+        val
+    }
+
+    /// Returns all values up to `end`, which is consumed, but not part of the result.
+    pub fn list(&mut self, end: T) -> Vec<T> {
+        let mut res = Vec::new();
+        loop {
+            let val = self.value();
+            if val == end {
+                return res;
+            }
+            res.push(val);
+        }
+    }
+
+    /// Returns everything read so far, which is the entire list including the terminating value
+    /// once [PropertyReader::key] returned `None`.
+    pub fn raw(&self) -> Vec<T> {
+        if self.p.is_null() {
+            return Vec::new();
+        }
+
+        // SAFETY: we've already read those values
+        unsafe { slice::from_raw_parts(self.p, self.len) }.to_vec()
+    }
+}
@@ -0,0 +1,107 @@
+use std::mem;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::mpsc;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::thread;
+use std::thread::JoinHandle;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// A fixed set of worker threads to split up CPU heavy work. The threads exit once the pool gets
+/// dropped.
+pub struct ThreadPool {
+    // `Sync` on `Sender` was stabilized in 1.72, until then, put it into a Mutex.
+    sender: Option<Mutex<mpsc::Sender<Job>>>,
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl ThreadPool {
+    pub fn new(name: &str, threads: usize) -> Self {
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        let workers = (0..threads)
+            .filter_map(|_| {
+                let receiver = Arc::clone(&receiver);
+                thread::Builder::new()
+                    .name(name.into())
+                    .spawn(move || loop {
+                        // only hold the lock while waiting for the next job
+                        let job = receiver.lock().unwrap().recv();
+                        match job {
+                            Ok(job) => job(),
+                            Err(_) => break,
+                        }
+                    })
+                    .ok()
+            })
+            .collect();
+
+        Self {
+            sender: Some(Mutex::new(sender)),
+            workers: workers,
+        }
+    }
+
+    /// Number of threads [ThreadPool::for_each] runs on, including the calling thread.
+    pub fn threads(&self) -> usize {
+        self.workers.len() + 1
+    }
+
+    /// Calls `f` for every index in `0..count` on the worker threads and the calling thread and
+    /// returns once all calls finished.
+    pub fn for_each<F>(&self, count: usize, f: F)
+    where
+        F: Fn(usize) + Sync,
+    {
+        let next = AtomicUsize::new(0);
+        let run = || loop {
+            let idx = next.fetch_add(1, Ordering::Relaxed);
+            if idx >= count {
+                break;
+            }
+            f(idx);
+        };
+
+        // Nothing gets ever sent through this channel, we only wait until all jobs dropped their
+        // sender.
+        let (done_sender, done) = mpsc::channel::<()>();
+        let helpers = self.workers.len().min(count.saturating_sub(1));
+        if helpers > 0 {
+            let run: &(dyn Fn() + Sync) = &run;
+            // SAFETY: we wait for all jobs to finish or to get dropped before returning, so `run`
+            //         outlives all of them.
+            let run: &'static (dyn Fn() + Sync) = unsafe { mem::transmute(run) };
+
+            let sender = self.sender.as_ref().unwrap().lock().unwrap();
+            for _ in 0..helpers {
+                let done_sender = done_sender.clone();
+                let job = Box::new(move || {
+                    let _done_sender = done_sender;
+                    run();
+                });
+
+                // if the workers are gone, the job gets dropped right away.
+                let _ = sender.send(job);
+            }
+        }
+
+        drop(done_sender);
+        run();
+
+        // returns an error once all jobs are done
+        let _ = done.recv();
+    }
+}
+
+impl Drop for ThreadPool {
+    fn drop(&mut self) {
+        // disconnect the channel, so the workers stop
+        self.sender.take();
+        for worker in self.workers.drain(..) {
+            let _ = worker.join();
+        }
+    }
+}
@@ -91,7 +91,7 @@ util_perfetto_trace_full_begin(const char *name, uint64_t track_id, uint64_t id,
 }
 
 static inline void
-util_perfetto_trace_full_end(const char *name, uint64_t track_id)
+util_perfetto_trace_full_end(const char *name, uint64_t track_id, uint64_t timestamp)
 {
 }
 